@@ -13,7 +13,8 @@ fn main() {
     let mut stdin = io::stdin();
     let ctx = RenderContext::from_json(&mut stdin).unwrap();
     let cfg: WordcountConfig = ctx.config
-        .get_deserialized("output.wordcount")
+        .get_deserialized_opt("output.wordcount")
+        .expect("Invalid [output.wordcount] table")
         .unwrap_or_default();
 
     let _ = fs::create_dir_all(&ctx.destination);
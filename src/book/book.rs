@@ -1,12 +1,19 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::{self, Display, Formatter};
 use std::fs::{self, File};
 use std::io::{Read, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::vec;
 
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+use super::front_matter::parse_front_matter;
 use super::summary::{parse_summary, Link, SectionNumber, Summary, SummaryItem};
 use config::BuildConfig;
 use errors::*;
+use utils;
 
 /// Load a book into memory from its `src/` directory.
 pub fn load_book<P: AsRef<Path>>(src_dir: P, cfg: &BuildConfig) -> Result<Book> {
@@ -39,7 +46,15 @@ fn create_missing(src_dir: &Path, summary: &Summary) -> Result<()> {
         let next = items.pop().expect("already checked");
 
         if let SummaryItem::Link(ref link) = *next {
-            let filename = src_dir.join(&link.location);
+            // draft chapters don't have a file to create
+            let filename = match link.location {
+                Some(ref location) => src_dir.join(location),
+                None => {
+                    items.extend(&link.nested_items);
+                    continue;
+                }
+            };
+
             if !filename.exists() {
                 if let Some(parent) = filename.parent() {
                     if !parent.exists() {
@@ -88,6 +103,23 @@ impl Book {
         }
     }
 
+    /// Get a depth-first iterator over the items in the book which also says
+    /// where each item is, for when you need to know an item's depth or the
+    /// chapters it's nested in.
+    pub fn iter_with_paths(&self) -> TreeItems {
+        TreeItems {
+            items: self
+                .sections
+                .iter()
+                .enumerate()
+                .map(|(i, item)| TreeItem {
+                    path: vec![i],
+                    parents: Vec::new(),
+                    item: item,
+                }).collect(),
+        }
+    }
+
     /// Recursively apply a closure to each item in the book, allowing you to
     /// mutate them.
     ///
@@ -103,6 +135,36 @@ impl Book {
         for_each_mut(&mut func, &mut self.sections);
     }
 
+    /// The same as `for_each_mut()`, except the closure is also given the
+    /// item's path in the book (see [`TreeItem::path`]).
+    ///
+    /// [`TreeItem::path`]: struct.TreeItem.html#structfield.path
+    pub fn for_each_mut_with_path<F>(&mut self, mut func: F)
+    where
+        F: FnMut(&[usize], &mut BookItem),
+    {
+        for_each_mut_with_path(&mut func, &mut Vec::new(), &mut self.sections);
+    }
+
+    /// Call `func` on every chapter like `for_each_mut()`, except that the
+    /// chapters are spread across several threads. This is for work where each
+    /// chapter can be dealt with by itself, so `func` is given the chapters
+    /// without their `sub_items`. Every chapter is visited even if some fail,
+    /// and then the first error is returned.
+    pub fn par_for_each_chapter_mut<F>(&mut self, func: F) -> Result<()>
+    where
+        F: Fn(&mut Chapter) -> Result<()> + Sync,
+    {
+        let sections = mem::take(&mut self.sections);
+        let mut chapters = Vec::new();
+        let slots = detach_chapters(sections, &mut chapters);
+
+        let results = utils::parallel_map_mut(&mut chapters, func);
+
+        self.sections = reattach_chapters(slots, &mut chapters.into_iter());
+        results.into_iter().collect()
+    }
+
     /// Append a `BookItem` to the `Book`.
     pub fn push_item<I: Into<BookItem>>(&mut self, item: I) -> &mut Self {
         self.sections.push(item.into());
@@ -124,6 +186,41 @@ where
     }
 }
 
+/// Where an item taken apart by `detach_chapters()` goes back.
+enum Slot {
+    /// The next chapter, with these sub-items.
+    Chapter(Vec<Slot>),
+    Other(BookItem),
+}
+
+/// Take every chapter out of `items`, into `chapters` in the order `iter()`
+/// would visit them.
+fn detach_chapters(items: Vec<BookItem>, chapters: &mut Vec<Chapter>) -> Vec<Slot> {
+    items
+        .into_iter()
+        .map(|item| match item {
+            BookItem::Chapter(mut ch) => {
+                let sub_items = mem::take(&mut ch.sub_items);
+                chapters.push(ch);
+                Slot::Chapter(detach_chapters(sub_items, chapters))
+            }
+            other => Slot::Other(other),
+        }).collect()
+}
+
+fn reattach_chapters(slots: Vec<Slot>, chapters: &mut vec::IntoIter<Chapter>) -> Vec<BookItem> {
+    slots
+        .into_iter()
+        .map(|slot| match slot {
+            Slot::Chapter(sub_items) => {
+                let mut ch = chapters.next().expect("Every chapter is put back");
+                ch.sub_items = reattach_chapters(sub_items, chapters);
+                BookItem::Chapter(ch)
+            }
+            Slot::Other(item) => item,
+        }).collect()
+}
+
 /// Enum representing any type of item which can be added to a book.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BookItem {
@@ -131,6 +228,8 @@ pub enum BookItem {
     Chapter(Chapter),
     /// A section separator.
     Separator,
+    /// The title of a part of the book.
+    PartTitle(String),
 }
 
 impl From<Chapter> for BookItem {
@@ -139,6 +238,22 @@ impl From<Chapter> for BookItem {
     }
 }
 
+fn for_each_mut_with_path<F>(func: &mut F, path: &mut Vec<usize>, items: &mut [BookItem])
+where
+    F: FnMut(&[usize], &mut BookItem),
+{
+    for (i, item) in items.iter_mut().enumerate() {
+        path.push(i);
+
+        if let BookItem::Chapter(ref mut ch) = *item {
+            for_each_mut_with_path(func, path, &mut ch.sub_items);
+        }
+
+        func(path, item);
+        path.pop();
+    }
+}
+
 /// The representation of a "chapter", usually mapping to a single file on
 /// disk however it may contain multiple sub-chapters.
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
@@ -151,10 +266,28 @@ pub struct Chapter {
     pub number: Option<SectionNumber>,
     /// Nested items.
     pub sub_items: Vec<BookItem>,
-    /// The chapter's location, relative to the `SUMMARY.md` file.
-    pub path: PathBuf,
+    /// The chapter's location, relative to the `SUMMARY.md` file. Draft
+    /// chapters don't have one.
+    pub path: Option<PathBuf>,
+    /// The file the chapter was loaded from, relative to the `src` directory.
+    /// Unlike `path`, this isn't changed by preprocessors (e.g. when a
+    /// `README.md` is renamed to `index.md`).
+    #[serde(default)]
+    pub source_path: Option<PathBuf>,
     /// An ordered list of the names of each chapter above this one, in the hierarchy.
     pub parent_names: Vec<String>,
+    /// The values from the chapter's front matter (e.g. its authors).
+    #[serde(default, serialize_with = "serialize_sorted")]
+    pub metadata: HashMap<String, Value>,
+}
+
+/// Serialize a chapter's metadata in key order, so the same book always
+/// comes out the same.
+fn serialize_sorted<S: Serializer>(
+    metadata: &HashMap<String, Value>,
+    serializer: S,
+) -> ::std::result::Result<S::Ok, S::Error> {
+    metadata.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
 }
 
 impl Chapter {
@@ -165,21 +298,38 @@ impl Chapter {
         path: P,
         parent_names: Vec<String>,
     ) -> Chapter {
+        let path = path.into();
         Chapter {
             name: name.to_string(),
             content: content,
-            path: path.into(),
+            path: Some(path.clone()),
+            source_path: Some(path),
+            parent_names: parent_names,
+            ..Default::default()
+        }
+    }
+
+    /// Create a new draft chapter, which is listed in `SUMMARY.md` but
+    /// doesn't have a file of its own yet.
+    pub fn new_draft(name: &str, parent_names: Vec<String>) -> Chapter {
+        Chapter {
+            name: name.to_string(),
             parent_names: parent_names,
             ..Default::default()
         }
     }
+
+    /// Check whether this is a draft chapter.
+    pub fn is_draft_chapter(&self) -> bool {
+        self.path.is_none()
+    }
 }
 
 /// Use the provided `Summary` to load a `Book` from disk.
 ///
 /// You need to pass in the book's source directory because all the links in
 /// `SUMMARY.md` give the chapter locations relative to it.
-fn load_book_from_disk<P: AsRef<Path>>(summary: &Summary, src_dir: P) -> Result<Book> {
+pub(crate) fn load_book_from_disk<P: AsRef<Path>>(summary: &Summary, src_dir: P) -> Result<Book> {
     debug!("Loading the book from disk");
     let src_dir = src_dir.as_ref();
 
@@ -209,6 +359,7 @@ fn load_summary_item<P: AsRef<Path>>(
 ) -> Result<BookItem> {
     match *item {
         SummaryItem::Separator => Ok(BookItem::Separator),
+        SummaryItem::PartTitle(ref title) => Ok(BookItem::PartTitle(title.clone())),
         SummaryItem::Link(ref link) => {
             load_chapter(link, src_dir, parent_names).map(|c| BookItem::Chapter(c))
         }
@@ -220,30 +371,47 @@ fn load_chapter<P: AsRef<Path>>(
     src_dir: P,
     parent_names: Vec<String>,
 ) -> Result<Chapter> {
-    debug!("Loading {} ({})", link.name, link.location.display());
     let src_dir = src_dir.as_ref();
 
-    let location = if link.location.is_absolute() {
-        link.location.clone()
-    } else {
-        src_dir.join(&link.location)
-    };
+    let mut ch = match link.location {
+        Some(ref link_location) => {
+            debug!("Loading {} ({})", link.name, link_location.display());
+
+            let location = if link_location.is_absolute() {
+                link_location.clone()
+            } else {
+                src_dir.join(link_location)
+            };
+
+            let mut f = File::open(&location)
+                .chain_err(|| format!("Chapter file not found, {}", link_location.display()))?;
 
-    let mut f = File::open(&location)
-        .chain_err(|| format!("Chapter file not found, {}", link.location.display()))?;
+            let mut content = String::new();
+            f.read_to_string(&mut content).chain_err(|| {
+                format!("Unable to read \"{}\" ({})", link.name, location.display())
+            })?;
 
-    let mut content = String::new();
-    f.read_to_string(&mut content)
-        .chain_err(|| format!("Unable to read \"{}\" ({})", link.name, location.display()))?;
+            let stripped = location
+                .strip_prefix(&src_dir)
+                .expect("Chapters are always inside a book");
 
-    let stripped = location
-        .strip_prefix(&src_dir)
-        .expect("Chapters are always inside a book");
+            let (metadata, content) = parse_front_matter(&content, stripped).chain_err(|| {
+                format!("Unable to load \"{}\" ({})", link.name, location.display())
+            })?;
 
-    let mut sub_item_parents = parent_names.clone();
-    let mut ch = Chapter::new(&link.name, content, stripped, parent_names);
+            let mut ch =
+                Chapter::new(&link.name, content.to_string(), stripped, parent_names.clone());
+            ch.metadata = metadata;
+            ch
+        }
+        None => {
+            debug!("Loading {} (draft)", link.name);
+            Chapter::new_draft(&link.name, parent_names.clone())
+        }
+    };
     ch.number = link.number.clone();
 
+    let mut sub_item_parents = parent_names;
     sub_item_parents.push(link.name.clone());
     let sub_items = link
         .nested_items
@@ -285,6 +453,71 @@ impl<'a> Iterator for BookItems<'a> {
     }
 }
 
+/// An item in a book along with where it is, as returned by
+/// [`Book::iter_with_paths()`].
+///
+/// [`Book::iter_with_paths()`]: struct.Book.html#method.iter_with_paths
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeItem<'a> {
+    /// The item's index at each level of the book, so `[1, 0]` is the first
+    /// item nested in the second top-level item.
+    pub path: Vec<usize>,
+    /// The chapters the item is nested in, outermost first.
+    pub parents: Vec<&'a Chapter>,
+    /// The item itself.
+    pub item: &'a BookItem,
+}
+
+impl<'a> TreeItem<'a> {
+    /// How deeply nested the item is, where top-level items have a depth of
+    /// `0`.
+    pub fn depth(&self) -> usize {
+        self.parents.len()
+    }
+
+    /// The chapter this item is nested in, if it isn't at the top level.
+    pub fn parent(&self) -> Option<&'a Chapter> {
+        self.parents.last().cloned()
+    }
+}
+
+/// A depth-first iterator over the items in a book and where they are.
+///
+/// # Note
+///
+/// This struct shouldn't be created directly, instead prefer the
+/// [`Book::iter_with_paths()`] method.
+///
+/// [`Book::iter_with_paths()`]: struct.Book.html#method.iter_with_paths
+pub struct TreeItems<'a> {
+    items: VecDeque<TreeItem<'a>>,
+}
+
+impl<'a> Iterator for TreeItems<'a> {
+    type Item = TreeItem<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree_item = self.items.pop_front()?;
+
+        if let BookItem::Chapter(ref ch) = *tree_item.item {
+            for (i, sub_item) in ch.sub_items.iter().enumerate().rev() {
+                let mut path = tree_item.path.clone();
+                path.push(i);
+                let mut parents = tree_item.parents.clone();
+                parents.push(ch);
+
+                self.items.push_front(TreeItem {
+                    path: path,
+                    parents: parents,
+                    item: sub_item,
+                });
+            }
+        }
+
+        Some(tree_item)
+    }
+}
+
 impl Display for Chapter {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         if let Some(ref section_number) = self.number {
@@ -360,6 +593,34 @@ And here is some \
         assert_eq!(got, should_be);
     }
 
+    #[test]
+    fn load_a_chapter_with_front_matter() {
+        let temp_dir = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let chapter_path = temp_dir.path().join("chapter_1.md");
+        File::create(&chapter_path)
+            .unwrap()
+            .write_all(b"+++\nauthor = \"Alice\"\n+++\n# Chapter 1\n")
+            .unwrap();
+        let link = Link::new("Chapter 1", &chapter_path);
+
+        let got = load_chapter(&link, temp_dir.path(), Vec::new()).unwrap();
+
+        assert_eq!(got.content, "# Chapter 1\n");
+        assert_eq!(got.metadata["author"], json!("Alice"));
+    }
+
+    #[test]
+    fn metadata_is_serialized_in_key_order() {
+        let mut chapter = Chapter::new("Chapter 1", String::new(), "chapter_1.md", Vec::new());
+        for key in &["zebra", "apple", "mango"] {
+            chapter.metadata.insert(key.to_string(), json!(true));
+        }
+
+        let got = ::serde_json::to_value(&chapter).unwrap().to_string();
+
+        assert!(got.contains(r#""metadata":{"apple":true,"mango":true,"zebra":true}"#));
+    }
+
     #[test]
     fn cant_load_a_nonexistent_chapter() {
         let link = Link::new("Chapter 1", "/foo/bar/baz.md");
@@ -376,16 +637,20 @@ And here is some \
             name: String::from("Nested Chapter 1"),
             content: String::from("Hello World!"),
             number: Some(SectionNumber(vec![1, 2])),
-            path: PathBuf::from("second.md"),
+            path: Some(PathBuf::from("second.md")),
+            source_path: Some(PathBuf::from("second.md")),
             parent_names: vec![String::from("Chapter 1")],
+            metadata: HashMap::new(),
             sub_items: Vec::new(),
         };
         let should_be = BookItem::Chapter(Chapter {
             name: String::from("Chapter 1"),
             content: String::from(DUMMY_SRC),
             number: None,
-            path: PathBuf::from("chapter_1.md"),
+            path: Some(PathBuf::from("chapter_1.md")),
+            source_path: Some(PathBuf::from("chapter_1.md")),
             parent_names: Vec::new(),
+            metadata: HashMap::new(),
             sub_items: vec![
                 BookItem::Chapter(nested.clone()),
                 BookItem::Separator,
@@ -397,6 +662,29 @@ And here is some \
         assert_eq!(got, should_be);
     }
 
+    #[test]
+    fn load_a_draft_chapter_with_nested_chapters() {
+        let (link, temp) = dummy_link();
+        let draft = Link {
+            name: String::from("Draft"),
+            location: None,
+            nested_items: vec![SummaryItem::Link(link)],
+            ..Default::default()
+        };
+
+        let got = load_chapter(&draft, temp.path(), Vec::new()).unwrap();
+
+        assert!(got.is_draft_chapter());
+        assert!(got.content.is_empty());
+        match got.sub_items[0] {
+            BookItem::Chapter(ref ch) => {
+                assert_eq!(ch.path, Some(PathBuf::from("chapter_1.md")));
+                assert_eq!(ch.parent_names, vec![String::from("Draft")]);
+            }
+            ref other => panic!("Expected a chapter, got {:?}", other),
+        }
+    }
+
     #[test]
     fn load_a_book_with_a_single_chapter() {
         let (link, temp) = dummy_link();
@@ -408,7 +696,8 @@ And here is some \
             sections: vec![BookItem::Chapter(Chapter {
                 name: String::from("Chapter 1"),
                 content: String::from(DUMMY_SRC),
-                path: PathBuf::from("chapter_1.md"),
+                path: Some(PathBuf::from("chapter_1.md")),
+                source_path: Some(PathBuf::from("chapter_1.md")),
                 ..Default::default()
             })],
             ..Default::default()
@@ -448,8 +737,10 @@ And here is some \
                     name: String::from("Chapter 1"),
                     content: String::from(DUMMY_SRC),
                     number: None,
-                    path: PathBuf::from("Chapter_1/index.md"),
+                    path: Some(PathBuf::from("Chapter_1/index.md")),
+                    source_path: Some(PathBuf::from("Chapter_1/index.md")),
                     parent_names: Vec::new(),
+                    metadata: HashMap::new(),
                     sub_items: vec![
                         BookItem::Chapter(Chapter::new(
                             "Hello World",
@@ -491,6 +782,82 @@ And here is some \
         assert_eq!(chapter_names, should_be);
     }
 
+    fn nested_book() -> Book {
+        let mut parent = Chapter::new("Parent", String::new(), "parent.md", Vec::new());
+        parent.sub_items = vec![
+            BookItem::Separator,
+            BookItem::Chapter(Chapter::new(
+                "Child",
+                String::new(),
+                "child.md",
+                vec![String::from("Parent")],
+            )),
+        ];
+
+        let mut book = Book::new();
+        book.push_item(BookItem::Separator).push_item(parent);
+        book
+    }
+
+    #[test]
+    fn par_for_each_chapter_mut_puts_the_book_back_together() {
+        let mut book = nested_book();
+
+        let result = book.par_for_each_chapter_mut(|ch| {
+            ch.content = format!("{} was here", ch.name);
+            if ch.name == "Child" {
+                bail!("The child failed");
+            }
+            Ok(())
+        });
+
+        assert_eq!(result.unwrap_err().to_string(), "The child failed");
+        let mut should_be = nested_book();
+        should_be.for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                ch.content = format!("{} was here", ch.name);
+            }
+        });
+        assert_eq!(book, should_be);
+    }
+
+    #[test]
+    fn iterate_over_nested_book_items_with_their_paths() {
+        let book = nested_book();
+
+        let got: Vec<_> = book.iter_with_paths().collect();
+
+        let paths: Vec<_> = got.iter().map(|item| item.path.clone()).collect();
+        assert_eq!(paths, vec![vec![0], vec![1], vec![1, 0], vec![1, 1]]);
+        assert_eq!(got[1].depth(), 0);
+        assert!(got[1].parent().is_none());
+        assert_eq!(got[3].depth(), 1);
+        assert_eq!(got[3].parent().map(|ch| ch.name.as_str()), Some("Parent"));
+        assert_eq!(got[3].item, book.iter().nth(3).unwrap());
+    }
+
+    #[test]
+    fn for_each_mut_with_path_visits_children_first() {
+        let mut book = nested_book();
+        let mut paths = Vec::new();
+
+        book.for_each_mut_with_path(|path, item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                ch.content = format!("depth {}", path.len() - 1);
+            }
+            paths.push(path.to_vec());
+        });
+
+        assert_eq!(paths, vec![vec![0], vec![1, 0], vec![1, 1], vec![1]]);
+        let contents: Vec<_> = book
+            .iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => Some(ch.content.clone()),
+                _ => None,
+            }).collect();
+        assert_eq!(contents, vec!["depth 0", "depth 1"]);
+    }
+
     #[test]
     fn for_each_mut_visits_all_items() {
         let mut book = Book {
@@ -499,8 +866,10 @@ And here is some \
                     name: String::from("Chapter 1"),
                     content: String::from(DUMMY_SRC),
                     number: None,
-                    path: PathBuf::from("Chapter_1/index.md"),
+                    path: Some(PathBuf::from("Chapter_1/index.md")),
+                    source_path: Some(PathBuf::from("Chapter_1/index.md")),
                     parent_names: Vec::new(),
+                    metadata: HashMap::new(),
                     sub_items: vec![
                         BookItem::Chapter(Chapter::new(
                             "Hello World",
@@ -536,7 +905,7 @@ And here is some \
         let summary = Summary {
             numbered_chapters: vec![SummaryItem::Link(Link {
                 name: String::from("Empty"),
-                location: PathBuf::from(""),
+                location: Some(PathBuf::from("")),
                 ..Default::default()
             })],
             ..Default::default()
@@ -555,7 +924,7 @@ And here is some \
         let summary = Summary {
             numbered_chapters: vec![SummaryItem::Link(Link {
                 name: String::from("nested"),
-                location: dir,
+                location: Some(dir),
                 ..Default::default()
             })],
             ..Default::default()
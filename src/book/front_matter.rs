@@ -0,0 +1,290 @@
+//! Parsing the front matter at the top of a chapter.
+//!
+//! Front matter is either TOML between two `+++` lines or YAML between two
+//! `---` lines. Only simple YAML is understood: one `key: value` per line,
+//! where the value is a string, number, boolean or list. A chapter can just as
+//! well start with a `---` thematic break, so a `---` block which isn't valid
+//! YAML is left in the chapter.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde_json::{self, Value};
+use toml;
+
+use diagnostics::Diagnostic;
+use errors::*;
+
+/// Split a chapter's front matter from the rest of its content. `file` is
+/// where the chapter is, for warning about YAML which is left in it.
+pub(crate) fn parse_front_matter<'a>(
+    content: &'a str,
+    file: &Path,
+) -> Result<(HashMap<String, Value>, &'a str)> {
+    let without_bom = content.trim_start_matches('\u{feff}');
+
+    if let Some((front_matter, rest)) = split_front_matter(without_bom, "---") {
+        match parse_yaml(front_matter) {
+            Ok(metadata) => return Ok((metadata, rest)),
+            // only worth mentioning if it starts out looking like front matter
+            Err(e) => match front_matter.lines().find(|line| !line.trim().is_empty()) {
+                Some(line) if yaml_key(line).is_some() => {
+                    let message = format!("{}, so it's left in the chapter", e);
+                    Diagnostic::warning(message).in_file(file).emit();
+                }
+                _ => debug!("{} doesn't start with YAML front matter: {}", file.display(), e),
+            },
+        }
+    }
+    if let Some((front_matter, rest)) = split_front_matter(without_bom, "+++") {
+        return Ok((parse_toml(front_matter)?, rest));
+    }
+
+    Ok((HashMap::new(), content))
+}
+
+fn split_front_matter<'a>(content: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let first_line_end = content.find('\n')?;
+    if content[..first_line_end].trim_end() != delimiter {
+        return None;
+    }
+
+    let body = &content[first_line_end + 1..];
+    let mut offset = 0;
+
+    for line in body.split('\n') {
+        let line_end = offset + line.len();
+
+        if line.trim_end() == delimiter || (delimiter == "---" && line.trim_end() == "...") {
+            let rest = if line_end < body.len() {
+                &body[line_end + 1..]
+            } else {
+                ""
+            };
+            return Some((&body[..offset], rest));
+        }
+
+        offset = line_end + 1;
+    }
+
+    None
+}
+
+fn parse_toml(front_matter: &str) -> Result<HashMap<String, Value>> {
+    let table: toml::value::Table =
+        toml::from_str(front_matter).chain_err(|| "Unable to parse the TOML front matter")?;
+
+    Ok(table
+        .into_iter()
+        .map(|(key, value)| (key, toml_to_json(value)))
+        .collect())
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn parse_yaml(front_matter: &str) -> Result<HashMap<String, Value>> {
+    let mut metadata = HashMap::new();
+    // a key whose value is a list on the following lines
+    let mut list: Option<(String, Vec<Value>)> = None;
+
+    for (i, line) in front_matter.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if trimmed == "-" || trimmed.starts_with("- ") {
+            match list {
+                Some((_, ref mut items)) => items.push(parse_yaml_value(trimmed[1..].trim())),
+                None => bail!("Line {} of the front matter is a list item without a key", i + 1),
+            }
+            continue;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            bail!("Line {} of the front matter is nested, which isn't supported", i + 1);
+        }
+
+        let (key, value) = match yaml_key(trimmed) {
+            Some(pair) => pair,
+            None => bail!("Line {} of the front matter should be a \"key: value\" pair", i + 1),
+        };
+        let key = key.to_string();
+
+        if let Some((key, items)) = list.take() {
+            metadata.insert(key, list_value(items));
+        }
+
+        if value.is_empty() {
+            list = Some((key, Vec::new()));
+        } else {
+            metadata.insert(key, parse_yaml_value(value));
+        }
+    }
+
+    if let Some((key, items)) = list {
+        metadata.insert(key, list_value(items));
+    }
+
+    Ok(metadata)
+}
+
+/// A line's key and value, if it's a `key: value` pair. Unless it's quoted, a
+/// key is a single word, and YAML separates it from its value with a space,
+/// so lines of prose (or URLs) are less likely to be taken for pairs.
+fn yaml_key(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    let colon = line.find(':')?;
+    let key = line[..colon].trim();
+    let after = &line[colon + 1..];
+
+    if is_quoted(key) {
+        return Some((unquote(key), after.trim()));
+    }
+    if key.is_empty()
+        || key.contains(char::is_whitespace)
+        || !(after.is_empty() || after.starts_with(char::is_whitespace))
+    {
+        return None;
+    }
+    Some((key, after.trim()))
+}
+
+/// A key with no value or list items after it is null.
+fn list_value(items: Vec<Value>) -> Value {
+    if items.is_empty() {
+        Value::Null
+    } else {
+        Value::Array(items)
+    }
+}
+
+fn parse_yaml_value(value: &str) -> Value {
+    if value.starts_with('[') && value.ends_with(']') {
+        let items = value[1..value.len() - 1]
+            .split(',')
+            .map(|item| item.trim())
+            .filter(|item| !item.is_empty())
+            .map(parse_yaml_value)
+            .collect();
+        return Value::Array(items);
+    }
+
+    if is_quoted(value) {
+        return Value::String(unquote(value).to_string());
+    }
+
+    // comments can follow unquoted values
+    let value = match value.find(" #") {
+        Some(ix) => value[..ix].trim_end(),
+        None => value,
+    };
+
+    match value {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "null" | "~" => Value::Null,
+        _ => {
+            let float = value.parse().ok().and_then(serde_json::Number::from_f64);
+
+            if let Ok(i) = value.parse::<i64>() {
+                Value::from(i)
+            } else if let Some(n) = float {
+                Value::Number(n)
+            } else {
+                Value::String(value.to_string())
+            }
+        }
+    }
+}
+
+fn is_quoted(s: &str) -> bool {
+    s.len() >= 2
+        && ((s.starts_with('"') && s.ends_with('"')) || (s.starts_with('\'') && s.ends_with('\'')))
+}
+
+fn unquote(s: &str) -> &str {
+    if is_quoted(s) {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chapters_without_front_matter_are_left_alone() {
+        let src = "# Chapter 1\n\n---\n\nSome text\n\n---\n";
+
+        let (metadata, content) = parse_front_matter(src, Path::new("chapter.md")).unwrap();
+
+        assert!(metadata.is_empty());
+        assert_eq!(content, src);
+    }
+
+    #[test]
+    fn parse_yaml_front_matter() {
+        let src = "---\ntitle: \"Hello: World\"\nauthors: [Alice, 'Bob']\n\
+                   tags:\n  - one\n  - 2\ndraft: true # for now\nweight: 1.5\n\
+                   date: 2018-10-01\n---\n# Chapter 1\n";
+
+        let (metadata, content) = parse_front_matter(src, Path::new("chapter.md")).unwrap();
+
+        assert_eq!(content, "# Chapter 1\n");
+        assert_eq!(metadata["title"], json!("Hello: World"));
+        assert_eq!(metadata["authors"], json!(["Alice", "Bob"]));
+        assert_eq!(metadata["tags"], json!(["one", 2]));
+        assert_eq!(metadata["draft"], json!(true));
+        assert_eq!(metadata["weight"], json!(1.5));
+        assert_eq!(metadata["date"], json!("2018-10-01"));
+    }
+
+    #[test]
+    fn parse_toml_front_matter() {
+        let src = "+++\r\nauthors = [\"Alice\"]\r\ndate = 2018-10-01T12:00:00Z\r\n+++\r\nText";
+
+        let (metadata, content) = parse_front_matter(src, Path::new("chapter.md")).unwrap();
+
+        assert_eq!(content, "Text");
+        assert_eq!(metadata["authors"], json!(["Alice"]));
+        assert_eq!(metadata["date"], json!("2018-10-01T12:00:00Z"));
+    }
+
+    #[test]
+    fn invalid_toml_front_matter_is_an_error() {
+        assert!(parse_front_matter("+++\nnot toml\n+++\n", Path::new("chapter.md")).is_err());
+    }
+
+    #[test]
+    fn chapters_starting_with_a_thematic_break_are_left_alone() {
+        let file = Path::new("chapter.md");
+        for src in &[
+            "---\n\nSome text: with a colon.\n\n---\n\nMore text\n",
+            "---\nSee http://example.com\n...\n",
+            "---\nauthor:\n  name: Alice\n---\n",
+        ] {
+            let (metadata, content) = parse_front_matter(src, file).unwrap();
+
+            assert!(metadata.is_empty());
+            assert_eq!(content, *src);
+        }
+    }
+}
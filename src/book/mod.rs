@@ -6,23 +6,37 @@
 //! [1]: ../index.html
 
 mod book;
+mod front_matter;
 mod init;
 mod summary;
+mod testing;
+mod translation;
+mod workspace;
 
-pub use self::book::{load_book, Book, BookItem, BookItems, Chapter};
+pub use self::book::{load_book, Book, BookItem, BookItems, Chapter, TreeItem, TreeItems};
 pub use self::init::BookBuilder;
 pub use self::summary::{parse_summary, Link, SectionNumber, Summary, SummaryItem};
 
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::Command;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use serde_json;
 use tempfile::Builder as TempFileBuilder;
 use toml::Value;
 
+use diagnostics;
 use errors::*;
-use preprocess::{IndexPreprocessor, LinkPreprocessor, Preprocessor,
-    PreprocessorContext, CmdPreprocessor};
-use renderer::{CmdRenderer, HtmlHandlebars, RenderContext, Renderer};
+use preprocess::included_files;
+use preprocess::{AdmonitionsPreprocessor, BibliographyPreprocessor, BookIndexPreprocessor,
+    CaptionsPreprocessor, CmdPreprocessor, ConditionalPreprocessor, DiagramsPreprocessor,
+    EmojiPreprocessor, GettextPreprocessor, GlossaryPreprocessor, HeadingsPreprocessor,
+    HideLinesPreprocessor, IndexPreprocessor, LinkCheckPreprocessor, LinkPreprocessor,
+    MathPreprocessor, Preprocessor, PreprocessorContext, ScalaEvalPreprocessor,
+    ScastiePreprocessor, SmartPunctuationPreprocessor, TabsPreprocessor, VariablesPreprocessor};
+use renderer::{
+    CmdRenderer, EpubRenderer, HtmlHandlebars, JsonRenderer, LatexRenderer, LinkCheckRenderer,
+    ManRenderer, MarkdownRenderer, RenderContext, Renderer,
+};
 use utils;
 
 use config::Config;
@@ -39,12 +53,26 @@ pub struct MDBook {
 
     /// List of pre-processors to be run on the book
     preprocessors: Vec<Box<Preprocessor>>,
+
+    /// The books in this book's workspace, if it has one.
+    pub members: Vec<MDBook>,
 }
 
 impl MDBook {
     /// Load a book from its root directory on disk.
     pub fn load<P: Into<PathBuf>>(book_root: P) -> Result<MDBook> {
         let book_root = book_root.into();
+        let config = MDBook::load_config(&book_root)?;
+
+        MDBook::load_with_config(book_root, config)
+    }
+
+    /// The configuration `load()` uses for the book at `book_root`, which is
+    /// its `book.toml` (if it has one) with any overrides from environment
+    /// variables applied. This can be tweaked further before passing it to
+    /// `load_with_config()`.
+    pub fn load_config<P: AsRef<Path>>(book_root: P) -> Result<Config> {
+        let book_root = book_root.as_ref();
         let config_location = book_root.join("book.toml");
 
         // the book.json file is no longer used, so we should emit a warning to
@@ -72,15 +100,41 @@ impl MDBook {
             }
         }
 
-        MDBook::load_with_config(book_root, config)
+        Ok(config)
     }
 
     /// Load a book from its root directory using a custom config.
+    ///
+    /// A multilingual book is loaded with a translation for each of its
+    /// languages, as its `members`.
     pub fn load_with_config<P: Into<PathBuf>>(book_root: P, config: Config) -> Result<MDBook> {
         let root = book_root.into();
+        if config.book.multilingual {
+            return translation::load_translations(root, config);
+        }
 
         let src_dir = root.join(&config.book.src);
-        let book = book::load_book(&src_dir, &config.build)?;
+        let is_workspace = !workspace::member_dirs(&config)?.is_empty();
+        // a workspace doesn't need chapters of its own
+        let book = if is_workspace && !src_dir.join("SUMMARY.md").exists() {
+            Book::new()
+        } else {
+            book::load_book(&src_dir, &config.build)?
+        };
+
+        let mut book = MDBook::from_book(root, config, book)?;
+        book.members = workspace::load_members(&book)?;
+        Ok(book)
+    }
+
+    /// Create a book from a `Book` which has already been assembled in memory
+    /// (e.g. from a database), instead of loading it from `SUMMARY.md`.
+    ///
+    /// The book's root directory is still used for things like the theme
+    /// directory and as the parent of the build directory.
+    pub fn from_book<P: Into<PathBuf>>(book_root: P, config: Config, book: Book) -> Result<MDBook> {
+        let root = book_root.into();
+        config.book.validate()?;
 
         let renderers = determine_renderers(&config);
         let preprocessors = determine_preprocessors(&config)?;
@@ -91,6 +145,7 @@ impl MDBook {
             book,
             renderers,
             preprocessors,
+            members: Vec::new(),
         })
     }
 
@@ -109,6 +164,7 @@ impl MDBook {
     ///     match *item {
     ///         BookItem::Chapter(ref chapter) => {},
     ///         BookItem::Separator => {},
+    ///         BookItem::PartTitle(ref title) => {},
     ///     }
     /// }
     ///
@@ -147,18 +203,196 @@ impl MDBook {
     }
 
     /// Tells the renderer to build our book and put it in the build directory.
+    ///
+    /// The books in its workspace are built afterwards, since building this
+    /// one clears the directory they go in.
     pub fn build(&self) -> Result<()> {
         info!("Book building has started");
 
+        if self.is_landing_page() && self.config.book.multilingual {
+            translation::write_redirect(self)?;
+        } else if self.is_landing_page() {
+            workspace::write_landing_page(self)?;
+        } else {
+            let emitted = diagnostics::emitted();
+            for renderer in &self.renderers {
+                self.execute_build_process(&**renderer)?;
+                self.check_strict(emitted)?;
+            }
+        }
+
+        for member in &self.members {
+            info!("Building the book in {}", member.source_dir().display());
+            member.build()?;
+        }
+
+        Ok(())
+    }
+
+    /// Only build the translation of a multilingual book into `language`,
+    /// instead of all of them.
+    pub fn select_language(&mut self, language: &str) -> Result<()> {
+        if !self.config.book.multilingual {
+            bail!("Only multilingual books can be built in one language");
+        }
+
+        self.members.retain(|translation| {
+            translation.config.book.language.as_ref().map(|l| &l[..]) == Some(language)
+        });
+        if self.members.is_empty() {
+            bail!("The book doesn't have a [language.{}] table", language);
+        }
+        Ok(())
+    }
+
+    /// Whether this is a workspace (or multilingual book) without any
+    /// chapters, which only gets a page linking to its books.
+    fn is_landing_page(&self) -> bool {
+        !self.members.is_empty() && self.book.sections.is_empty()
+    }
+
+    /// Update an earlier build after `changed` has been modified, only
+    /// re-rendering the chapters it affects where possible. That's the case
+    /// when `changed` is a chapter's source file or a file one of them
+    /// includes. Any other change, like to `SUMMARY.md` or the theme, rebuilds
+    /// the whole book.
+    ///
+    /// The book's preprocessors still run over every chapter, because they
+    /// may depend on each other, and every chapter whose preprocessed content
+    /// differs from the last build is re-rendered as well. That catches a
+    /// figure renumbered, or a glossary entry added, by a change to another
+    /// chapter. The hashes of the last build's chapters are kept in a hidden
+    /// `.mdbook-build-<name>.hashes` file beside its build directory, because
+    /// `watch` and `serve` load the book again for each change. Without one
+    /// to compare with, the whole book is rebuilt. A change inside one of the
+    /// books in its workspace only rebuilds that book, while every
+    /// translation of a multilingual book is rebuilt.
+    ///
+    /// Unlike a full build, re-rendering some chapters writes straight into
+    /// the last build, so while it runs the served book can have some pages
+    /// from each, and a failure can leave it that way until the next build.
+    pub fn rebuild(&self, changed: &Path) -> Result<()> {
+        let changed_file = canonical(changed);
+        // the translations of a multilingual book all share its root
+        let members: Vec<_> = self
+            .members
+            .iter()
+            .filter(|member| changed_file.starts_with(canonical(&member.root)))
+            .collect();
+        if !members.is_empty() {
+            for member in members {
+                member.rebuild(changed)?;
+            }
+            return Ok(());
+        }
+
+        let chapters = self.chapters_affected_by(changed);
+        if chapters.is_empty() {
+            return self.build();
+        }
+        info!("Rebuilding {} chapter(s) using {}", chapters.len(), changed.display());
+
+        let emitted = diagnostics::emitted();
         for renderer in &self.renderers {
-            self.execute_build_process(&**renderer)?;
+            let build_dir = self.build_dir_for(renderer.name());
+            let last_build = match read_last_build(&build_dir) {
+                Some(last_build) => last_build,
+                None => {
+                    self.execute_build_process(&**renderer)?;
+                    self.check_strict(emitted)?;
+                    continue;
+                }
+            };
+
+            let preprocessed_book = self.preprocess_book(&**renderer)?;
+            let hashes = chapter_hashes(&preprocessed_book);
+            let mut wanted = chapters.clone();
+            for (path, hash) in &hashes {
+                if last_build.get(path) != Some(hash) && !wanted.contains(path) {
+                    wanted.push(path.clone());
+                }
+            }
+            debug!("Re-rendering {} chapter(s) for the {} backend", wanted.len(), renderer.name());
+
+            let render_context = RenderContext::new(
+                self.root.clone(),
+                preprocessed_book,
+                self.config.clone(),
+                build_dir.clone(),
+            );
+            // a failure part way through leaves a build nothing's known about
+            forget_last_build(&build_dir);
+            renderer
+                .render_chapters(&render_context, &wanted)
+                .chain_err(|| "Rendering failed")?;
+            remember_last_build(&build_dir, &hashes);
+            self.check_strict(emitted)?;
         }
 
         Ok(())
     }
 
+    /// In strict mode, fail if any diagnostics have been emitted since there
+    /// were `emitted` of them. They've already been logged, so this only says
+    /// how many there were.
+    fn check_strict(&self, emitted: usize) -> Result<()> {
+        let count = diagnostics::emitted() - emitted;
+        if self.config.build.strict && count > 0 {
+            bail!("Found {} problem(s) with the book, which fail the build in strict mode", count);
+        }
+        Ok(())
+    }
+
+    /// The `path`s of the chapters whose source, or something they include,
+    /// is `changed`.
+    fn chapters_affected_by(&self, changed: &Path) -> Vec<PathBuf> {
+        let src_dir = self.source_dir();
+        let changed = canonical(changed);
+        let mut chapters = Vec::new();
+        if changed == canonical(&src_dir.join("SUMMARY.md")) {
+            return chapters;
+        }
+
+        for item in self.iter() {
+            let (ch, path) = match *item {
+                BookItem::Chapter(ref ch) => match ch.path {
+                    Some(ref path) => (ch, path),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let source = src_dir.join(ch.source_path.as_ref().unwrap_or(path));
+            let base = source.parent().unwrap_or(&src_dir);
+
+            let affected = canonical(&source) == changed
+                || included_files(&ch.content, base)
+                    .iter()
+                    .any(|file| canonical(file) == changed);
+            if affected {
+                chapters.push(path.clone());
+            }
+        }
+
+        chapters
+    }
+
     /// Run the entire build process for a particular `Renderer`.
     fn execute_build_process(&self, renderer: &Renderer) -> Result<()> {
+        let preprocessed_book = self.preprocess_book(renderer)?;
+
+        info!("Running the {} backend", renderer.name());
+        self.render(&preprocessed_book, renderer)?;
+        remember_last_build(
+            &self.build_dir_for(renderer.name()),
+            &chapter_hashes(&preprocessed_book),
+        );
+
+        Ok(())
+    }
+
+    /// Run the preprocessors which apply to `renderer` over the book, giving
+    /// the book just as the renderer would see it during a build.
+    pub fn preprocess_book(&self, renderer: &Renderer) -> Result<Book> {
         let mut preprocessed_book = self.book.clone();
         let preprocess_ctx = PreprocessorContext::new(self.root.clone(),
                                      self.config.clone(),
@@ -172,12 +406,14 @@ impl MDBook {
             }
         }
 
-        info!("Running the {} backend", renderer.name());
-        self.render(&preprocessed_book, renderer)?;
-
-        Ok(())
+        Ok(preprocessed_book)
     }
 
+    /// Render the book into a new directory, which only replaces the last
+    /// build once it's finished. A failed build leaves the last one as it
+    /// was, and `mdbook serve` never serves a mix of old and new pages.
+    /// [`rebuild`](#method.rebuild) is the exception: re-rendering only some
+    /// chapters updates the last build in place.
     fn render(
         &self,
         preprocessed_book: &Book,
@@ -185,34 +421,53 @@ impl MDBook {
     ) -> Result<()> {
         let name = renderer.name();
         let build_dir = self.build_dir_for(name);
-        if build_dir.exists() {
-            debug!(
-                "Cleaning build dir for the \"{}\" renderer ({})",
-                name,
-                build_dir.display()
-            );
-
-            utils::fs::remove_dir_content(&build_dir)
-                .chain_err(|| "Unable to clear output directory")?;
-        }
+        let parent = build_dir.parent().unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(parent).chain_err(|| "Unable to create the output directory")?;
+        let staging = TempFileBuilder::new()
+            .prefix(".mdbook-build-")
+            .tempdir_in(parent)
+            .chain_err(|| "Unable to create a directory to render the book in")?;
+        // temporary directories are only readable by their owner
+        let like = if build_dir.exists() { &build_dir } else { parent };
+        fs::set_permissions(staging.path(), fs::metadata(like)?.permissions())?;
+        debug!(
+            "Rendering the book for the \"{}\" renderer in {}",
+            name,
+            staging.path().display()
+        );
 
-        let render_context = RenderContext::new(
+        let mut render_context = RenderContext::new(
             self.root.clone(),
             preprocessed_book.clone(),
             self.config.clone(),
-            build_dir,
+            staging.path(),
         );
+        if build_dir.exists() {
+            render_context.previous_destination = Some(build_dir.clone());
+        }
 
         renderer
             .render(&render_context)
-            .chain_err(|| "Rendering failed")
+            .chain_err(|| "Rendering failed")?;
+        utils::fs::replace_dir(staging, &build_dir)
+            .chain_err(|| format!("Unable to move the book into {}", build_dir.display()))
     }
 
     /// You can change the default renderer to another one by using this method.
     /// The only requirement is for your renderer to implement the [`Renderer`
-    /// trait](../renderer/trait.Renderer.html)
+    /// trait](../renderer/trait.Renderer.html). A renderer with the same name
+    /// as one the book already has, like a customised `HtmlHandlebars`,
+    /// replaces it.
     pub fn with_renderer<R: Renderer + 'static>(&mut self, renderer: R) -> &mut Self {
-        self.renderers.push(Box::new(renderer));
+        let renderer: Box<Renderer> = Box::new(renderer);
+        match self
+            .renderers
+            .iter()
+            .position(|r| r.name() == renderer.name())
+        {
+            Some(i) => self.renderers[i] = renderer,
+            None => self.renderers.push(renderer),
+        }
         self
     }
 
@@ -222,53 +477,65 @@ impl MDBook {
         self
     }
 
-    /// Run `rustdoc` tests on the book, linking against the provided libraries.
+    /// Test the book's code samples, linking its `rust` code blocks against
+    /// the provided libraries. Code blocks in other languages are tested
+    /// with the commands in the `[test]` table.
+    ///
+    /// Every test is run, and whether it passed is logged, before this fails
+    /// if any of them didn't.
     pub fn test(&mut self, library_paths: Vec<&str>) -> Result<()> {
-        let library_args: Vec<&str> = (0..library_paths.len())
-            .map(|_| "-L")
-            .zip(library_paths.into_iter())
-            .flat_map(|x| vec![x.0, x.1])
-            .collect();
+        let cases = self.run_tests(library_paths)?;
+        testing::summarize(&cases)
+    }
 
-        let temp_dir = TempFileBuilder::new().prefix("mdbook-").tempdir()?;
+    /// Like `test()`, but the results are also written to `report` as JUnit
+    /// XML, for CI systems to show.
+    pub fn test_with_junit_report<P: AsRef<Path>>(
+        &mut self,
+        library_paths: Vec<&str>,
+        report: P,
+    ) -> Result<()> {
+        let cases = self.run_tests(library_paths)?;
+        let report = report.as_ref();
+        fs::write(report, testing::junit_report(&cases))
+            .chain_err(|| format!("Unable to write the test report to {}", report.display()))?;
+        testing::summarize(&cases)
+    }
 
+    fn run_tests(&mut self, library_paths: Vec<&str>) -> Result<Vec<testing::TestCase>> {
         // FIXME: Is "test" the proper renderer name to use here?
         let preprocess_context = PreprocessorContext::new(self.root.clone(),
                                                           self.config.clone(),
                                                           "test".to_string());
 
-        let book = LinkPreprocessor::new().run(&preprocess_context, self.book.clone())?;
+        let mut book = LinkPreprocessor::new().run(&preprocess_context, self.book.clone())?;
         // Index Preprocessor is disabled so that chapter paths continue to point to the
         // actual markdown files.
 
+        // so tests see code blocks the way they'd be compiled, like after a
+        // preprocessor has wrapped them into complete programs
+        for name in testing::test_preprocessors(&self.config)? {
+            if name == LinkPreprocessor::NAME {
+                continue;
+            }
+            match self.preprocessors.iter().find(|p| p.name() == name) {
+                Some(preprocessor) => {
+                    debug!("Running the {} preprocessor.", name);
+                    book = preprocessor.run(&preprocess_context, book)?;
+                }
+                None => warn!("test.preprocessors contains \"{}\", which isn't enabled", name),
+            }
+        }
+
+        let tester = testing::Tester::new(&self.config, &library_paths)?;
+        let mut cases = Vec::new();
         for item in book.iter() {
             if let BookItem::Chapter(ref ch) = *item {
-                if !ch.path.as_os_str().is_empty() {
-                    let path = self.source_dir().join(&ch.path);
-                    let content = utils::fs::file_to_string(&path)?;
-                    info!("Testing file: {:?}", path);
-
-                    // write preprocessed file to tempdir
-                    let path = temp_dir.path().join(&ch.path);
-                    let mut tmpf = utils::fs::create_file(&path)?;
-                    tmpf.write_all(content.as_bytes())?;
-
-                    let output = Command::new("rustdoc")
-                        .arg(&path)
-                        .arg("--test")
-                        .args(&library_args)
-                        .output()?;
-
-                    if !output.status.success() {
-                        bail!(ErrorKind::Subprocess(
-                            "Rustdoc returned an error".to_string(),
-                            output
-                        ));
-                    }
-                }
+                cases.extend(tester.test_chapter(ch)?);
             }
         }
-        Ok(())
+
+        Ok(cases)
     }
 
     /// The logic for determining where a backend should put its build
@@ -319,6 +586,64 @@ impl MDBook {
     }
 }
 
+/// The path without any `..` or symlinks, so it can be compared to others,
+/// or as it is if it doesn't exist any more.
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// A hash of the content of each of `book`'s chapters, by its path.
+fn chapter_hashes(book: &Book) -> HashMap<PathBuf, String> {
+    book.iter()
+        .filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) => ch
+                .path
+                .as_ref()
+                .map(|path| (path.clone(), utils::stable_hash(ch.content.as_bytes()))),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Where the hashes of the chapters in the last build into `build_dir` are
+/// kept. It's beside the build rather than in it, so it isn't served or
+/// deployed with the book, and it outlives the `MDBook`, which `watch` and
+/// `serve` load again for every change.
+fn hashes_file(build_dir: &Path) -> PathBuf {
+    let name = build_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    build_dir.with_file_name(format!(".mdbook-build-{}.hashes", name))
+}
+
+/// The hashes of the chapters in the last build into `build_dir`, if there
+/// is one and they're known.
+fn read_last_build(build_dir: &Path) -> Option<HashMap<PathBuf, String>> {
+    if !build_dir.exists() {
+        return None;
+    }
+    let file = File::open(hashes_file(build_dir)).ok()?;
+    serde_json::from_reader(file).ok()
+}
+
+/// Save the hashes of the chapters just built into `build_dir`. That only
+/// saves work later, so the build doesn't fail if they can't be saved.
+fn remember_last_build(build_dir: &Path, hashes: &HashMap<PathBuf, String>) {
+    let path = hashes_file(build_dir);
+    let saved = serde_json::to_vec(hashes)
+        .map_err(Error::from)
+        .and_then(|json| fs::write(&path, json).map_err(Error::from));
+    if let Err(e) = saved {
+        warn!("Unable to save the chapters' hashes in {}: {}", path.display(), e);
+        forget_last_build(build_dir);
+    }
+}
+
+fn forget_last_build(build_dir: &Path) {
+    let _ = fs::remove_file(hashes_file(build_dir));
+}
+
 /// Look at the `Config` and try to figure out what renderers to use.
 fn determine_renderers(config: &Config) -> Vec<Box<Renderer>> {
     let mut renderers: Vec<Box<Renderer>> = Vec::new();
@@ -328,6 +653,18 @@ fn determine_renderers(config: &Config) -> Vec<Box<Renderer>> {
             // the "html" backend has its own Renderer
             if key == "html" {
                 renderers.push(Box::new(HtmlHandlebars::new()));
+            } else if key == "markdown" {
+                renderers.push(Box::new(MarkdownRenderer::new()));
+            } else if key == "epub" {
+                renderers.push(Box::new(EpubRenderer::new()));
+            } else if key == "latex" {
+                renderers.push(Box::new(LatexRenderer::new()));
+            } else if key == "man" {
+                renderers.push(Box::new(ManRenderer::new()));
+            } else if key == "json" {
+                renderers.push(Box::new(JsonRenderer::new()));
+            } else if key == "linkcheck" {
+                renderers.push(Box::new(LinkCheckRenderer::new()));
             } else {
                 let renderer = interpret_custom_renderer(key, table);
                 renderers.push(renderer);
@@ -367,6 +704,11 @@ fn determine_preprocessors(config: &Config) -> Result<Vec<Box<Preprocessor>>> {
         config.get("preprocessor").and_then(|v| v.as_table())
     {
         for key in preprocessor_table.keys() {
+            // the default preprocessors may already have been added
+            if preprocessors.iter().any(|p| p.name() == key) {
+                continue;
+            }
+
             match key.as_ref() {
                 "links" => {
                     preprocessors.push(Box::new(LinkPreprocessor::new()))
@@ -374,6 +716,54 @@ fn determine_preprocessors(config: &Config) -> Result<Vec<Box<Preprocessor>>> {
                 "index" => {
                     preprocessors.push(Box::new(IndexPreprocessor::new()))
                 }
+                "admonitions" => {
+                    preprocessors.push(Box::new(AdmonitionsPreprocessor::new()))
+                }
+                "bibliography" => {
+                    preprocessors.push(Box::new(BibliographyPreprocessor::new()))
+                }
+                "book-index" => {
+                    preprocessors.push(Box::new(BookIndexPreprocessor::new()))
+                }
+                "captions" => {
+                    preprocessors.push(Box::new(CaptionsPreprocessor::new()))
+                }
+                "conditional" => {
+                    preprocessors.push(Box::new(ConditionalPreprocessor::new()))
+                }
+                "diagrams" => {
+                    preprocessors.push(Box::new(DiagramsPreprocessor::new()))
+                }
+                "emoji" => preprocessors.push(Box::new(EmojiPreprocessor::new())),
+                "gettext" => {
+                    preprocessors.push(Box::new(GettextPreprocessor::new()))
+                }
+                "glossary" => {
+                    preprocessors.push(Box::new(GlossaryPreprocessor::new()))
+                }
+                "headings" => {
+                    preprocessors.push(Box::new(HeadingsPreprocessor::new()))
+                }
+                "hide-lines" => {
+                    preprocessors.push(Box::new(HideLinesPreprocessor::new()))
+                }
+                "link-check" => {
+                    preprocessors.push(Box::new(LinkCheckPreprocessor::new()))
+                }
+                "math" => preprocessors.push(Box::new(MathPreprocessor::new())),
+                "scala-eval" => {
+                    preprocessors.push(Box::new(ScalaEvalPreprocessor::new()))
+                }
+                "scastie" => {
+                    preprocessors.push(Box::new(ScastiePreprocessor::new()))
+                }
+                "smart-punctuation" => {
+                    preprocessors.push(Box::new(SmartPunctuationPreprocessor::new()))
+                }
+                "tabs" => preprocessors.push(Box::new(TabsPreprocessor::new())),
+                "variables" => {
+                    preprocessors.push(Box::new(VariablesPreprocessor::new()))
+                }
                 name => preprocessors.push(interpret_custom_preprocessor(
                     name,
                     &preprocessor_table[name],
@@ -382,7 +772,85 @@ fn determine_preprocessors(config: &Config) -> Result<Vec<Box<Preprocessor>>> {
         }
     }
 
-    Ok(preprocessors)
+    // strict mode fails on broken links and duplicate headers, which only
+    // `link-check` looks for
+    let checking_links = preprocessors
+        .iter()
+        .any(|p| p.name() == LinkCheckPreprocessor::NAME);
+    if config.build.strict && !checking_links {
+        preprocessors.push(Box::new(LinkCheckPreprocessor::new()));
+    }
+
+    sort_preprocessors(preprocessors, config)
+}
+
+/// Reorder the preprocessors so each one runs after the preprocessors listed
+/// in its `after` key and before those in its `before` key. Otherwise they
+/// keep their original order.
+fn sort_preprocessors(
+    preprocessors: Vec<Box<Preprocessor>>,
+    config: &Config,
+) -> Result<Vec<Box<Preprocessor>>> {
+    let names: Vec<String> = preprocessors.iter().map(|p| p.name().to_string()).collect();
+    // each `(first, then)` pair means `first` has to run before `then`
+    let mut edges = Vec::new();
+
+    for name in &names {
+        for other in preprocessor_ordering(config, name, "before")? {
+            if names.contains(&other) {
+                edges.push((name.clone(), other));
+            } else {
+                warn!("preprocessor.{}.before contains \"{}\", which isn't enabled", name, other);
+            }
+        }
+
+        for other in preprocessor_ordering(config, name, "after")? {
+            if names.contains(&other) {
+                edges.push((other, name.clone()));
+            } else {
+                warn!("preprocessor.{}.after contains \"{}\", which isn't enabled", name, other);
+            }
+        }
+    }
+
+    let mut remaining = preprocessors;
+    let mut sorted = Vec::with_capacity(remaining.len());
+
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|p| {
+            !edges.iter().any(|(first, then)| {
+                then == p.name() && remaining.iter().any(|q| q.name() == first)
+            })
+        });
+
+        match ready {
+            Some(ix) => sorted.push(remaining.remove(ix)),
+            None => {
+                let names: Vec<_> = remaining.iter().map(|p| p.name()).collect();
+                bail!(
+                    "The preprocessors {} have a cycle in their `before` and `after` keys",
+                    names.join(", ")
+                );
+            }
+        }
+    }
+
+    Ok(sorted)
+}
+
+fn preprocessor_ordering(config: &Config, name: &str, key: &str) -> Result<Vec<String>> {
+    let key = format!("preprocessor.{}.{}", name, key);
+
+    match config.get(&key) {
+        Some(Value::Array(ref names)) => names
+            .iter()
+            .map(|name| match name.as_str() {
+                Some(name) => Ok(name.to_string()),
+                None => bail!("\"{}\" should only contain preprocessor names", key),
+            }).collect(),
+        Some(_) => bail!("\"{}\" should be a list of preprocessor names", key),
+        None => Ok(Vec::new()),
+    }
 }
 
 fn interpret_custom_preprocessor(
@@ -528,6 +996,99 @@ mod tests {
         assert!(got.into_iter().any(|p| p.name() == "random"));
     }
 
+    #[test]
+    fn hide_lines_is_a_builtin_preprocessor() {
+        let cfg_str = r#"
+        [preprocessor.hide-lines]
+        scala = "// hide"
+        "#;
+
+        let cfg = Config::from_str(cfg_str).unwrap();
+
+        let got = determine_preprocessors(&cfg).unwrap();
+        let hide_lines: Vec<_> = got.iter()
+            .filter(|p| p.name() == "hide-lines")
+            .collect();
+
+        assert_eq!(hide_lines.len(), 1);
+    }
+
+    #[test]
+    fn default_preprocessors_are_only_added_once() {
+        let cfg_str = r#"
+        [preprocessor.links]
+        "#;
+
+        let cfg = Config::from_str(cfg_str).unwrap();
+
+        let got = determine_preprocessors(&cfg).unwrap();
+        let names: Vec<_> = got.iter().map(|p| p.name()).collect();
+
+        assert_eq!(names, vec!["links", "index"]);
+    }
+
+    #[test]
+    fn strict_mode_checks_links_once() {
+        let mut cfg = Config::default();
+        cfg.build.strict = true;
+        let got = determine_preprocessors(&cfg).unwrap();
+        let names: Vec<_> = got.iter().map(|p| p.name()).collect();
+        assert_eq!(names, vec!["links", "index", "link-check"]);
+
+        cfg.set("preprocessor.link-check.warn-only", true).unwrap();
+        let got = determine_preprocessors(&cfg).unwrap();
+        let names: Vec<_> = got.iter().map(|p| p.name()).collect();
+        assert_eq!(names, vec!["links", "index", "link-check"]);
+    }
+
+    #[test]
+    fn preprocessors_can_be_reordered() {
+        let cfg_str = r#"
+        [preprocessor.index]
+        before = ["links"]
+
+        [preprocessor.random]
+        before = ["index"]
+
+        [preprocessor.hide-lines]
+        after = ["links", "not-enabled"]
+        "#;
+
+        let cfg = Config::from_str(cfg_str).unwrap();
+
+        let got = determine_preprocessors(&cfg).unwrap();
+        let names: Vec<_> = got.iter().map(|p| p.name()).collect();
+
+        assert_eq!(names, vec!["random", "index", "links", "hide-lines"]);
+    }
+
+    #[test]
+    fn cyclic_preprocessor_ordering_is_an_error() {
+        let cfg_str = r#"
+        [preprocessor.index]
+        before = ["links"]
+
+        [preprocessor.links]
+        before = ["index"]
+        "#;
+
+        let cfg = Config::from_str(cfg_str).unwrap();
+
+        assert!(determine_preprocessors(&cfg).is_err());
+    }
+
+    #[test]
+    fn preprocessor_ordering_must_be_a_list_of_names() {
+        let cfg_str = r#"
+        [preprocessor.index]
+        before = "links"
+        "#;
+
+        let cfg = Config::from_str(cfg_str).unwrap();
+
+        assert!(determine_preprocessors(&cfg).is_err());
+    }
+
     #[test]
     fn preprocessors_can_provide_their_own_commands() {
         let cfg_str = r#"
@@ -37,6 +37,25 @@ use std::path::{Path, PathBuf};
 /// You can either use - or * to indicate a numbered chapter, the parser doesn't
 /// care but you'll probably want to stay consistent.
 ///
+/// **Part Title:** Numbered chapters can be split into parts with a level 1
+/// heading before each list of chapters. Parts are shown as headings in the
+/// table of contents, and the chapters' numbering carries on from one part to
+/// the next.
+///
+/// ```markdown
+/// # Part II: Advanced Topics
+///
+/// - [Title of the Chapter](relative/path/to/markdown.md)
+/// ```
+///
+/// **Draft Chapter:** A chapter with an empty link is a draft. It's shown in
+/// the table of contents so readers can see what's planned, but it doesn't
+/// have a file (or a page) of its own yet.
+///
+/// ```markdown
+/// - [Title of a future chapter]()
+/// ```
+///
 /// **Suffix Chapter:** After the numbered chapters you can add a couple of
 /// non-numbered chapters. They are the same as prefix chapters but come after
 /// the numbered chapters instead of before.
@@ -70,8 +89,8 @@ pub struct Link {
     /// The name of the chapter.
     pub name: String,
     /// The location of the chapter's source file, taking the book's `src`
-    /// directory as the root.
-    pub location: PathBuf,
+    /// directory as the root. Draft chapters don't have one.
+    pub location: Option<PathBuf>,
     /// The section number, if this chapter is in the numbered section.
     pub number: Option<SectionNumber>,
     /// Any nested items this chapter may contain.
@@ -83,7 +102,7 @@ impl Link {
     pub fn new<S: Into<String>, P: AsRef<Path>>(name: S, location: P) -> Link {
         Link {
             name: name.into(),
-            location: location.as_ref().to_path_buf(),
+            location: Some(location.as_ref().to_path_buf()),
             number: None,
             nested_items: Vec::new(),
         }
@@ -94,7 +113,7 @@ impl Default for Link {
     fn default() -> Self {
         Link {
             name: String::new(),
-            location: PathBuf::new(),
+            location: None,
             number: None,
             nested_items: Vec::new(),
         }
@@ -108,6 +127,8 @@ pub enum SummaryItem {
     Link(Link),
     /// A separator (`---`).
     Separator,
+    /// The title of a part of the book (`# Part II`).
+    PartTitle(String),
 }
 
 impl SummaryItem {
@@ -139,7 +160,9 @@ impl From<Link> for SummaryItem {
 ///                     | EPSILON
 /// prefix_chapters   ::= item*
 /// suffix_chapters   ::= item*
-/// numbered_chapters ::= dotted_item+
+/// numbered_chapters ::= part+
+/// part              ::= part_title? dotted_item+
+/// part_title        ::= "# " TEXT
 /// dotted_item       ::= INDENT* DOT_POINT item
 /// item              ::= link
 ///                     | separator
@@ -154,6 +177,8 @@ impl From<Link> for SummaryItem {
 struct SummaryParser<'a> {
     src: &'a str,
     stream: pulldown_cmark::Parser<'a>,
+    /// A part title found just before the first numbered chapter.
+    first_part_title: Option<String>,
 }
 
 /// Reads `Events` from the provided stream until the corresponding
@@ -201,6 +226,7 @@ impl<'a> SummaryParser<'a> {
         SummaryParser {
             src: text,
             stream: pulldown_parser,
+            first_part_title: None,
         }
     }
 
@@ -263,6 +289,18 @@ impl<'a> SummaryParser<'a> {
                     let link = self.parse_link(href.to_string())?;
                     items.push(SummaryItem::Link(link));
                 }
+                Some(Event::Start(Tag::Header(1))) => {
+                    if !is_prefix {
+                        bail!(self.parse_error("Suffix chapters cannot be followed by a part"));
+                    }
+
+                    // the first part starts the numbered section
+                    self.first_part_title = Some(self.parse_part_title());
+                    if !self.starts_list() {
+                        bail!(self.parse_error("A part title must be followed by a list"));
+                    }
+                    break;
+                }
                 Some(Event::Start(Tag::Rule)) => items.push(SummaryItem::Separator),
                 Some(_) => {}
                 None => break,
@@ -276,22 +314,30 @@ impl<'a> SummaryParser<'a> {
         let link_content = collect_events!(self.stream, end Tag::Link(..));
         let name = stringify_events(link_content);
 
-        if href.is_empty() {
-            Err(self.parse_error("You can't have an empty link."))
+        // an empty link is a draft chapter
+        let location = if href.is_empty() {
+            None
         } else {
-            Ok(Link {
-                name: name,
-                location: PathBuf::from(href.to_string()),
-                number: None,
-                nested_items: Vec::new(),
-            })
-        }
+            Some(PathBuf::from(href))
+        };
+
+        Ok(Link {
+            name: name,
+            location: location,
+            number: None,
+            nested_items: Vec::new(),
+        })
     }
 
     /// Parse the numbered chapters. This assumes the opening list tag has
     /// already been consumed by a previous parser.
     fn parse_numbered(&mut self) -> Result<Vec<SummaryItem>> {
-        let mut items = Vec::new();
+        let mut items: Vec<_> = self
+            .first_part_title
+            .take()
+            .map(SummaryItem::PartTitle)
+            .into_iter()
+            .collect();
         let mut root_items = 0;
         let root_number = SectionNumber::default();
 
@@ -319,6 +365,13 @@ impl<'a> SummaryParser<'a> {
                     // we're starting the suffix chapters
                     break;
                 }
+                Some(Event::Start(Tag::Header(1))) => {
+                    items.push(SummaryItem::PartTitle(self.parse_part_title()));
+
+                    if !self.starts_list() {
+                        bail!(self.parse_error("A part title must be followed by a list"));
+                    }
+                }
                 Some(Event::Start(other_tag)) => {
                     if other_tag == Tag::Rule {
                         items.push(SummaryItem::Separator);
@@ -352,6 +405,24 @@ impl<'a> SummaryParser<'a> {
         Ok(items)
     }
 
+    /// Parse a part title, assuming the opening heading tag has already been
+    /// consumed.
+    fn parse_part_title(&mut self) -> String {
+        let tags = collect_events!(self.stream, end Tag::Header(1));
+        let title = stringify_events(tags);
+        debug!("Found the \"{}\" part", title);
+
+        title
+    }
+
+    /// Check that the next thing is the start of a list.
+    fn starts_list(&mut self) -> bool {
+        match self.next_event() {
+            Some(Event::Start(Tag::List(..))) => true,
+            _ => false,
+        }
+    }
+
     fn next_event(&mut self) -> Option<Event<'a>> {
         let next = self.stream.next();
         trace!("Next event: {:?}", next);
@@ -407,7 +478,10 @@ impl<'a> SummaryParser<'a> {
                         "Found chapter: {} {} ({})",
                         number,
                         link.name,
-                        link.location.display()
+                        link.location
+                            .as_ref()
+                            .map(|p| p.display().to_string())
+                            .unwrap_or_else(|| "draft".to_string())
                     );
 
                     link.number = Some(number);
@@ -577,12 +651,12 @@ mod tests {
         let should_be = vec![
             SummaryItem::Link(Link {
                 name: String::from("First"),
-                location: PathBuf::from("./first.md"),
+                location: Some(PathBuf::from("./first.md")),
                 ..Default::default()
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
-                location: PathBuf::from("./second.md"),
+                location: Some(PathBuf::from("./second.md")),
                 ..Default::default()
             }),
         ];
@@ -621,7 +695,7 @@ mod tests {
         let src = "[First](./first.md)";
         let should_be = Link {
             name: String::from("First"),
-            location: PathBuf::from("./first.md"),
+            location: Some(PathBuf::from("./first.md")),
             ..Default::default()
         };
 
@@ -642,7 +716,7 @@ mod tests {
         let src = "- [First](./first.md)\n";
         let link = Link {
             name: String::from("First"),
-            location: PathBuf::from("./first.md"),
+            location: Some(PathBuf::from("./first.md")),
             number: Some(SectionNumber(vec![1])),
             ..Default::default()
         };
@@ -663,18 +737,18 @@ mod tests {
         let should_be = vec![
             SummaryItem::Link(Link {
                 name: String::from("First"),
-                location: PathBuf::from("./first.md"),
+                location: Some(PathBuf::from("./first.md")),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: vec![SummaryItem::Link(Link {
                     name: String::from("Nested"),
-                    location: PathBuf::from("./nested.md"),
+                    location: Some(PathBuf::from("./nested.md")),
                     number: Some(SectionNumber(vec![1, 1])),
                     nested_items: Vec::new(),
                 })],
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
-                location: PathBuf::from("./second.md"),
+                location: Some(PathBuf::from("./second.md")),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -698,13 +772,13 @@ mod tests {
         let should_be = vec![
             SummaryItem::Link(Link {
                 name: String::from("First"),
-                location: PathBuf::from("./first.md"),
+                location: Some(PathBuf::from("./first.md")),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: Vec::new(),
             }),
             SummaryItem::Link(Link {
                 name: String::from("Second"),
-                location: PathBuf::from("./second.md"),
+                location: Some(PathBuf::from("./second.md")),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
@@ -719,13 +793,20 @@ mod tests {
     }
 
     #[test]
-    fn an_empty_link_location_is_an_error() {
+    fn an_empty_link_location_is_a_draft_chapter() {
         let src = "- [Empty]()\n";
         let mut parser = SummaryParser::new(src);
         parser.stream.next();
 
-        let got = parser.parse_numbered();
-        assert!(got.is_err());
+        let got = parser.parse_numbered().unwrap();
+        let should_be = vec![SummaryItem::Link(Link {
+            name: String::from("Empty"),
+            location: None,
+            number: Some(SectionNumber(vec![1])),
+            nested_items: Vec::new(),
+        })];
+
+        assert_eq!(got, should_be);
     }
 
     /// Regression test for https://github.com/rust-lang-nursery/mdBook/issues/779
@@ -736,21 +817,21 @@ mod tests {
         let should_be = vec![
             SummaryItem::Link(Link {
                 name: String::from("First"),
-                location: PathBuf::from("./first.md"),
+                location: Some(PathBuf::from("./first.md")),
                 number: Some(SectionNumber(vec![1])),
                 nested_items: Vec::new(),
             }),
             SummaryItem::Separator,
             SummaryItem::Link(Link {
                 name: String::from("Second"),
-                location: PathBuf::from("./second.md"),
+                location: Some(PathBuf::from("./second.md")),
                 number: Some(SectionNumber(vec![2])),
                 nested_items: Vec::new(),
             }),
             SummaryItem::Separator,
             SummaryItem::Link(Link {
                 name: String::from("Third"),
-                location: PathBuf::from("./third.md"),
+                location: Some(PathBuf::from("./third.md")),
                 number: Some(SectionNumber(vec![3])),
                 nested_items: Vec::new(),
             }),
@@ -763,4 +844,39 @@ mod tests {
 
         assert_eq!(got, should_be);
     }
+
+    #[test]
+    fn parse_part_titles() {
+        let src = "# Summary\n\n[Intro](intro.md)\n\n# Part I\n\n- [First](./first.md)\n\n\
+                   # Part II: Advanced\n\n- [Second](./second.md)\n";
+        let link = |name: &str, location: &str, number: u32| {
+            SummaryItem::Link(Link {
+                name: String::from(name),
+                location: Some(PathBuf::from(location)),
+                number: Some(SectionNumber(vec![number])),
+                nested_items: Vec::new(),
+            })
+        };
+        let should_be = vec![
+            SummaryItem::PartTitle(String::from("Part I")),
+            link("First", "./first.md", 1),
+            SummaryItem::PartTitle(String::from("Part II: Advanced")),
+            link("Second", "./second.md", 2),
+        ];
+
+        let got = parse_summary(src).unwrap();
+
+        assert_eq!(got.title, Some(String::from("Summary")));
+        assert_eq!(got.prefix_chapters.len(), 1);
+        assert_eq!(got.numbered_chapters, should_be);
+    }
+
+    #[test]
+    fn part_titles_must_be_followed_by_a_list() {
+        let src = "# Summary\n\n- [First](./first.md)\n\n# Part II\n\n[Suffix](./suffix.md)\n";
+
+        let got = parse_summary(src);
+
+        assert!(got.is_err());
+    }
 }
@@ -0,0 +1,717 @@
+//! Testing the code samples in a book's chapters, for `mdbook test`.
+//!
+//! A chapter's `rust` code blocks are tested together with `rustdoc --test`.
+//! Code blocks in other languages are tested if the `[test]` table has a
+//! command for the language, like `[test.scala] command = "scala-cli compile
+//! -"`. Each of those is piped to the command on its own, and passes if the
+//! command exits successfully.
+//!
+//! A code block can be followed by an `output` code block with what it
+//! should print. Then it's piped to the language's `run` command instead, and
+//! only passes if what it prints to `stdout` matches.
+//!
+//! Code blocks can be skipped with the `ignore` attribute, and whole chapters
+//! with `test: false` in their front matter. `should_fail` turns a test
+//! around, and `compile_only` only checks a code block, even if it has an
+//! expected output.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+use tempfile::{Builder as TempFileBuilder, TempDir};
+use toml::Value;
+
+use book::Chapter;
+use config::Config;
+use diagnostics::{self, Diagnostic};
+use errors::*;
+use utils::{self, run_with_input, split_code_blocks, Block, CodeBlock};
+
+/// One test, which is either all of a chapter's `rust` code blocks or a
+/// single code block in another language.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TestCase {
+    /// The chapter's file, relative to the `src` directory.
+    pub file: PathBuf,
+    /// The line the code block starts on, for languages other than Rust.
+    pub line: Option<usize>,
+    pub language: String,
+    pub outcome: Outcome,
+    /// How long the test took.
+    pub time: Duration,
+}
+
+/// How a test went.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Outcome {
+    Passed,
+    /// The test failed, for this reason.
+    Failed(String),
+    Skipped,
+}
+
+impl TestCase {
+    pub fn is_failure(&self) -> bool {
+        matches!(self.outcome, Outcome::Failed(_))
+    }
+
+    /// Log how the test went, with an error diagnostic if it failed.
+    fn report(&self) {
+        match self.outcome {
+            Outcome::Passed => info!("{} ... ok", self),
+            Outcome::Skipped => info!("{} ... skipped", self),
+            Outcome::Failed(ref failure) => {
+                let message = format!("{} test failed\n{}", self.language, failure.trim_end());
+                let mut diagnostic = Diagnostic::error(message).in_file(self.file.clone());
+                if let Some(line) = self.line {
+                    diagnostic = diagnostic.at_line(line);
+                }
+                diagnostic.emit();
+            }
+        }
+    }
+}
+
+impl fmt::Display for TestCase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(f, " ({})", self.language)
+    }
+}
+
+/// Runs the tests for each chapter.
+pub(crate) struct Tester {
+    /// Where chapters are written for `rustdoc` to read.
+    temp_dir: TempDir,
+    library_args: Vec<String>,
+    /// The commands code blocks in each language are tested with.
+    commands: BTreeMap<String, Commands>,
+}
+
+/// How code blocks in one language are tested. At least one of the commands
+/// is set.
+struct Commands {
+    /// Checks a code block, like by compiling it.
+    command: Option<String>,
+    /// Runs a code block, so what it prints can be compared with the output
+    /// it's expected to have.
+    run: Option<String>,
+}
+
+impl Commands {
+    /// The command for a code block, depending on whether it has an expected
+    /// output. Either command will do if there's only one.
+    fn for_block(&self, has_output: bool) -> &str {
+        let (preferred, fallback) = if has_output {
+            (&self.run, &self.command)
+        } else {
+            (&self.command, &self.run)
+        };
+        preferred
+            .as_ref()
+            .or(fallback.as_ref())
+            .expect("Languages always have a command")
+    }
+}
+
+impl Tester {
+    pub fn new(config: &Config, library_paths: &[&str]) -> Result<Tester> {
+        let mut commands = BTreeMap::new();
+        if let Some(table) = config.get("test").and_then(|v| v.as_table()) {
+            for (language, value) in table {
+                // other keys, like `preprocessors`, aren't tables
+                if !value.is_table() {
+                    continue;
+                }
+                let get = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+                let (command, run) = (get("command"), get("run"));
+                if command.is_none() && run.is_none() {
+                    warn!("test.{} doesn't have a command, so it's ignored", language);
+                    continue;
+                }
+                commands.insert(language.clone(), Commands { command, run });
+            }
+        }
+
+        Ok(Tester {
+            temp_dir: TempFileBuilder::new().prefix("mdbook-").tempdir()?,
+            library_args: library_paths
+                .iter()
+                .flat_map(|path| vec![String::from("-L"), path.to_string()])
+                .collect(),
+            commands,
+        })
+    }
+
+    /// Test a chapter's code blocks, logging each result as it comes in.
+    pub fn test_chapter(&self, ch: &Chapter) -> Result<Vec<TestCase>> {
+        let chapter_path = match ch.path {
+            Some(ref path) if !path.as_os_str().is_empty() => path,
+            _ => return Ok(Vec::new()),
+        };
+        let file = diagnostics::chapter_file(ch);
+        let skip_chapter = ch.metadata.get("test").and_then(|v| v.as_bool()) == Some(false);
+        let blocks = code_blocks(&ch.content);
+        let mut cases = Vec::new();
+        let mut push = |line, language: &str, outcome, started: Instant| {
+            let case = TestCase {
+                file: file.clone(),
+                line,
+                language: language.to_string(),
+                outcome,
+                time: started.elapsed(),
+            };
+            case.report();
+            cases.push(case);
+        };
+
+        if let Some(content) = rust_tests(&ch.content) {
+            let started = Instant::now();
+            let outcome = if skip_chapter {
+                Outcome::Skipped
+            } else {
+                debug!("Testing the Rust in {}", file.display());
+                self.rustdoc(chapter_path, &content)?
+            };
+            push(None, "rust", outcome, started);
+        }
+        // rustdoc skips these itself, but they're counted like any others
+        for block in &blocks {
+            if block.language() == Some("rust") && (skip_chapter || block.has("ignore")) {
+                push(Some(block.start + 1), "rust", Outcome::Skipped, Instant::now());
+            }
+        }
+
+        for (i, block) in blocks.iter().enumerate() {
+            let language = match block.language() {
+                Some(language) if language != "rust" && language != "output" => language,
+                _ => continue,
+            };
+            let commands = match self.commands.get(language) {
+                Some(commands) => commands,
+                None => continue,
+            };
+
+            let started = Instant::now();
+            let outcome = if skip_chapter || block.has("ignore") {
+                Outcome::Skipped
+            } else {
+                let expected = blocks
+                    .get(i + 1)
+                    .filter(|next| is_expected_output(&ch.content, block, next));
+                test_block(commands, block, expected)
+            };
+            push(Some(block.start + 1), language, outcome, started);
+        }
+
+        Ok(cases)
+    }
+
+    /// Test a chapter's `rust` code blocks, returning why they failed if
+    /// they did.
+    fn rustdoc(&self, chapter_path: &Path, content: &str) -> Result<Outcome> {
+        // rustdoc names the file in its output, so it's given the same
+        // relative path the chapter has
+        utils::fs::write_file(self.temp_dir.path(), chapter_path, content.as_bytes())?;
+        let output = Command::new("rustdoc")
+            .arg(chapter_path)
+            .arg("--test")
+            .args(&self.library_args)
+            .current_dir(self.temp_dir.path())
+            .output()
+            .chain_err(|| "Unable to run rustdoc")?;
+
+        if output.status.success() {
+            Ok(Outcome::Passed)
+        } else {
+            Ok(Outcome::Failed(printed(&output)))
+        }
+    }
+}
+
+/// Test a code block in a language other than Rust, which might have the
+/// output it's `expected` to print.
+fn test_block(commands: &Commands, block: &CodeBlock, expected: Option<&CodeBlock>) -> Outcome {
+    let should_fail = block.has("should_fail");
+    // what a code block which fails prints can't be checked
+    let expected = expected.filter(|_| !should_fail && !block.has("compile_only"));
+    let command = if block.has("compile_only") {
+        match commands.command {
+            Some(ref command) => command,
+            None => return Outcome::Skipped,
+        }
+    } else {
+        commands.for_block(expected.is_some())
+    };
+
+    match (run_snippet(command, &block.code()), expected) {
+        (Err(_), _) if should_fail => Outcome::Passed,
+        (Err(failure), _) => Outcome::Failed(failure),
+        (Ok(_), _) if should_fail => {
+            Outcome::Failed(format!("\"{}\" was expected to fail, but it didn't", command))
+        }
+        (Ok(ref printed), Some(expected)) if !same_output(printed, &expected.code()) => {
+            Outcome::Failed(format!(
+                "It didn't print the output on line {}\n{}",
+                expected.start + 1,
+                diff(&expected.code(), printed)
+            ))
+        }
+        _ => Outcome::Passed,
+    }
+}
+
+/// Pipe a code block to `command`, returning what it printed, or why it
+/// failed if it did.
+fn run_snippet(command: &str, code: &str) -> ::std::result::Result<String, String> {
+    match run_with_input(command, code) {
+        Ok(output) => Ok(output),
+        Err(Error(ErrorKind::Subprocess(_, output), _)) => Err(printed(&output)),
+        Err(e) => {
+            let causes: Vec<_> = e.iter().map(|cause| cause.to_string()).collect();
+            Err(causes.join(": "))
+        }
+    }
+}
+
+/// Is `next` an `output` code block right after `block`, with nothing but
+/// blank lines between them?
+fn is_expected_output(content: &str, block: &CodeBlock, next: &CodeBlock) -> bool {
+    next.language() == Some("output")
+        && content
+            .split('\n')
+            .skip(block.end() + 1)
+            .take(next.start - block.end() - 1)
+            .all(|line| line.trim().is_empty())
+}
+
+/// Outputs are compared ignoring whitespace at the end of each line, and
+/// blank lines at the end.
+fn same_output(printed: &str, expected: &str) -> bool {
+    output_lines(printed) == output_lines(expected)
+}
+
+fn output_lines(output: &str) -> Vec<&str> {
+    let mut lines: Vec<_> = output.lines().map(|line| line.trim_end()).collect();
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines
+}
+
+/// A line by line diff from the `expected` output to what was `printed`,
+/// with `-` in front of expected lines which are missing and `+` in front of
+/// ones which were printed instead.
+fn diff(expected: &str, printed: &str) -> String {
+    let expected = output_lines(expected);
+    let printed = output_lines(printed);
+
+    // common[i][j] is the length of the longest common subsequence of
+    // expected[i..] and printed[j..]
+    let mut common = vec![vec![0; printed.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..printed.len()).rev() {
+            common[i][j] = if expected[i] == printed[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < printed.len() {
+        if i < expected.len() && j < printed.len() && expected[i] == printed[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == printed.len() || (i < expected.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", printed[j]));
+            j += 1;
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Everything a failed command printed.
+fn printed(output: &Output) -> String {
+    let mut printed = format!("{}\n", output.status);
+    for stream in &[&output.stdout, &output.stderr] {
+        let stream = String::from_utf8_lossy(stream);
+        if !stream.trim().is_empty() {
+            printed.push_str(stream.trim_end());
+            printed.push('\n');
+        }
+    }
+    printed
+}
+
+/// Log how many tests passed, failed and were skipped in each language,
+/// failing if any of them failed.
+pub(crate) fn summarize(cases: &[TestCase]) -> Result<()> {
+    let mut languages: BTreeMap<&str, (usize, usize, usize)> = BTreeMap::new();
+    for case in cases {
+        let counts = languages.entry(&case.language).or_insert((0, 0, 0));
+        match case.outcome {
+            Outcome::Passed => counts.0 += 1,
+            Outcome::Failed(_) => counts.1 += 1,
+            Outcome::Skipped => counts.2 += 1,
+        }
+    }
+    for (language, &(passed, failed, skipped)) in &languages {
+        info!(
+            "{}: {} passed, {} failed, {} skipped",
+            language, passed, failed, skipped
+        );
+    }
+
+    let failed: Vec<_> = cases
+        .iter()
+        .filter(|case| case.is_failure())
+        .map(|case| case.to_string())
+        .collect();
+    let run = cases
+        .iter()
+        .filter(|case| case.outcome != Outcome::Skipped)
+        .count();
+    if !failed.is_empty() {
+        bail!(
+            "{} of {} test(s) failed: {}",
+            failed.len(),
+            run,
+            failed.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The results as JUnit XML, with a test suite for each language.
+pub(crate) fn junit_report(cases: &[TestCase]) -> String {
+    let mut languages: BTreeMap<&str, Vec<&TestCase>> = BTreeMap::new();
+    for case in cases {
+        languages.entry(&case.language).or_default().push(case);
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let all: Vec<_> = cases.iter().collect();
+    xml.push_str(&format!("<testsuites{}>\n", junit_counts(&all)));
+    for (language, cases) in languages {
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\"{}>\n",
+            escape_xml(language),
+            junit_counts(&cases)
+        ));
+        for case in cases {
+            xml.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+                escape_xml(&case.to_string()),
+                escape_xml(&case.file.display().to_string()),
+                seconds(case.time)
+            ));
+            match case.outcome {
+                Outcome::Passed => xml.push_str("/>\n"),
+                Outcome::Skipped => xml.push_str(">\n      <skipped/>\n    </testcase>\n"),
+                Outcome::Failed(ref failure) => xml.push_str(&format!(
+                    ">\n      <failure message=\"{} test failed\">{}</failure>\n    </testcase>\n",
+                    escape_xml(&case.language),
+                    escape_xml(failure)
+                )),
+            }
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn junit_counts(cases: &[&TestCase]) -> String {
+    let failures = cases.iter().filter(|case| case.is_failure()).count();
+    let skipped = cases
+        .iter()
+        .filter(|case| case.outcome == Outcome::Skipped)
+        .count();
+    let time: Duration = cases.iter().map(|case| case.time).sum();
+    format!(
+        " tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\"",
+        cases.len(),
+        failures,
+        skipped,
+        seconds(time)
+    )
+}
+
+fn seconds(time: Duration) -> f64 {
+    time.as_secs() as f64 + f64::from(time.subsec_millis()) / 1000.0
+}
+
+/// Escape text for an XML attribute or element, dropping the control
+/// characters XML can't have.
+fn escape_xml(s: &str) -> String {
+    s.chars()
+        .filter(|&c| !c.is_control() || c == '\n' || c == '\t')
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The names of the preprocessors in `test.preprocessors`, which should run
+/// before the book is tested.
+pub(crate) fn test_preprocessors(config: &Config) -> Result<Vec<String>> {
+    match config.get("test.preprocessors") {
+        Some(Value::Array(ref names)) => names
+            .iter()
+            .map(|name| match name.as_str() {
+                Some(name) => Ok(name.to_string()),
+                None => bail!("\"test.preprocessors\" should only contain preprocessor names"),
+            }).collect(),
+        Some(_) => bail!("\"test.preprocessors\" should be a list of preprocessor names"),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Every fenced code block in a chapter, in order.
+pub(crate) fn code_blocks<'a>(content: &'a str) -> Vec<CodeBlock<'a>> {
+    split_code_blocks(content)
+        .into_iter()
+        .filter_map(|block| match block {
+            // a fence indented by four spaces is part of an indented code
+            // block, which might be showing how to write one
+            Block::Code(ref block)
+                if block.opening_fence.len() - block.opening_fence.trim_start().len() >= 4 =>
+            {
+                None
+            }
+            Block::Code(block) => Some(block),
+            Block::Text { .. } => None,
+        }).collect()
+}
+
+/// The chapter with everything but its `rust` code blocks blanked out, ready
+/// to be given to `rustdoc --test`, or `None` if it doesn't have any.
+///
+/// Lines are blanked rather than removed so the line numbers `rustdoc`
+/// reports are the chapter's own. Everything else about the code blocks is
+/// left to `rustdoc`, like hiding lines starting with `#` and attributes such
+/// as `ignore` and `no_run`. `compile_only` and `should_fail` are turned into
+/// the `no_run` and `should_panic` it understands.
+pub(crate) fn rust_tests(content: &str) -> Option<String> {
+    let blocks: Vec<_> = code_blocks(content)
+        .into_iter()
+        .filter(|block| block.language() == Some("rust"))
+        .collect();
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let lines: Vec<_> = content
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            if blocks.iter().any(|block| block.start == i) {
+                line.replace("compile_only", "no_run")
+                    .replace("should_fail", "should_panic")
+            } else if blocks
+                .iter()
+                .any(|block| (block.start..=block.end()).contains(&i))
+            {
+                line.to_string()
+            } else {
+                String::new()
+            }
+        }).collect();
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_rust_code_blocks_are_kept() {
+        let content = "# Interop\n\n\
+                       ```scala\nval x = 1\n```\n\n\
+                       Some text.\n\n\
+                       ```rust,no_run\n# fn main() {\nlet x = 1;\n# }\n```\n\n\
+                       ```\nnot rust\n```\n";
+        assert_eq!(
+            rust_tests(content).unwrap(),
+            "\n\n\n\n\n\n\n\n```rust,no_run\n# fn main() {\nlet x = 1;\n# }\n```\n\n\n\n\n"
+        );
+    }
+
+    #[test]
+    fn chapters_without_rust_have_nothing_to_test() {
+        assert_eq!(rust_tests("# Scala\n\n```scala\nval x = 1\n```\n"), None);
+        assert_eq!(rust_tests("# Text\n\n    indented code\n"), None);
+        assert_eq!(rust_tests("# Fences\n\n    ```rust\n    fn main() {}\n    ```\n"), None);
+    }
+
+    // the tests running code blocks use `sh`, so they're only run where it exists
+    #[test]
+    #[cfg(not(windows))]
+    fn configured_languages_are_tested_with_their_commands() {
+        let mut config = Config::default();
+        config.set("test.sh.command", "sh").unwrap();
+        config.set("test.preprocessors", vec!["links"]).unwrap();
+        let tester = Tester::new(&config, &[]).unwrap();
+        assert_eq!(test_preprocessors(&config).unwrap(), vec!["links"]);
+
+        let content = "# Shell\n\n```sh\ntrue\n```\n\n```sh\nexit 1\n```\n\n```python\nnope\n```\n";
+        let ch = Chapter::new("Shell", content.to_string(), "shell.md", Vec::new());
+        let cases = tester.test_chapter(&ch).unwrap();
+
+        let got: Vec<_> = cases
+            .iter()
+            .map(|case| (case.to_string(), case.is_failure()))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (String::from("shell.md:3 (sh)"), false),
+                (String::from("shell.md:7 (sh)"), true),
+            ]
+        );
+        assert!(summarize(&cases).is_err());
+        assert!(summarize(&cases[..1]).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn code_blocks_are_run_and_compared_with_their_expected_output() {
+        let mut config = Config::default();
+        config.set("test.sh.command", "false").unwrap();
+        config.set("test.sh.run", "sh").unwrap();
+        let tester = Tester::new(&config, &[]).unwrap();
+
+        let content = "# Shell\n\n\
+                       ```sh\necho one\necho two\n```\n\n```output\none\ntwo  \n\n```\n\n\
+                       ```sh\necho one\necho three\n```\n```output\none\ntwo\n```\n\n\
+                       ```sh\necho unchecked\n```\n\nSome text.\n\n```output\nunchecked\n```\n";
+        let ch = Chapter::new("Shell", content.to_string(), "shell.md", Vec::new());
+        let cases = tester.test_chapter(&ch).unwrap();
+
+        let got: Vec<_> = cases.into_iter().map(|case| case.outcome).collect();
+        assert_eq!(
+            got,
+            vec![
+                Outcome::Passed,
+                Outcome::Failed(String::from(
+                    "It didn't print the output on line 18\n  one\n- two\n+ three"
+                )),
+                Outcome::Failed(String::from("exit status: 1\n")),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn code_blocks_can_be_skipped_or_expected_to_fail() {
+        let mut config = Config::default();
+        config.set("test.sh.run", "sh").unwrap();
+        let tester = Tester::new(&config, &[]).unwrap();
+
+        let content = "# Shell\n\n\
+                       ```sh,ignore\nexit 1\n```\n\n\
+                       ```sh,should_fail\nexit 1\n```\n\n\
+                       ```sh,should_fail\ntrue\n```\n\n\
+                       ```sh,compile_only\necho one\n```\n```output\ntwo\n```\n\n\
+                       ```rust,ignore\nnope\n```\n";
+        let mut ch = Chapter::new("Shell", content.to_string(), "shell.md", Vec::new());
+        let got: Vec<_> = tester
+            .test_chapter(&ch)
+            .unwrap()
+            .into_iter()
+            .map(|case| (case.line, case.language, case.outcome))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (None, String::from("rust"), Outcome::Passed),
+                (Some(22), String::from("rust"), Outcome::Skipped),
+                (Some(3), String::from("sh"), Outcome::Skipped),
+                (Some(7), String::from("sh"), Outcome::Passed),
+                (
+                    Some(11),
+                    String::from("sh"),
+                    Outcome::Failed(String::from("\"sh\" was expected to fail, but it didn't"))
+                ),
+                // there's no command to only check it with
+                (Some(15), String::from("sh"), Outcome::Skipped),
+            ]
+        );
+
+        ch.metadata.insert(String::from("test"), json!(false));
+        let cases = tester.test_chapter(&ch).unwrap();
+        assert_eq!(cases.len(), 6);
+        assert!(cases.iter().all(|case| case.outcome == Outcome::Skipped));
+        assert!(summarize(&cases).is_ok());
+    }
+
+    #[test]
+    fn rustdoc_is_told_about_the_attributes_it_knows_differently() {
+        assert_eq!(
+            rust_tests("```rust,compile_only\nloop {}\n```\n\n```rust,should_fail\npanic!()\n```")
+                .unwrap(),
+            "```rust,no_run\nloop {}\n```\n\n```rust,should_panic\npanic!()\n```"
+        );
+    }
+
+    #[test]
+    fn results_can_be_reported_as_junit_xml() {
+        let case = |line, language: &str, outcome| TestCase {
+            file: PathBuf::from("guide/usage.md"),
+            line,
+            language: language.to_string(),
+            outcome,
+            time: Duration::from_millis(1500),
+        };
+        let cases = vec![
+            case(None, "rust", Outcome::Passed),
+            case(Some(7), "sh", Outcome::Failed(String::from("exit status: 1\n<oops>\u{1b}"))),
+            case(Some(12), "sh", Outcome::Skipped),
+        ];
+
+        assert_eq!(
+            junit_report(&cases),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="3" failures="1" skipped="1" time="4.500">
+  <testsuite name="rust" tests="1" failures="0" skipped="0" time="1.500">
+    <testcase name="guide/usage.md (rust)" classname="guide/usage.md" time="1.500"/>
+  </testsuite>
+  <testsuite name="sh" tests="2" failures="1" skipped="1" time="3.000">
+    <testcase name="guide/usage.md:7 (sh)" classname="guide/usage.md" time="1.500">
+      <failure message="sh test failed">exit status: 1
+&lt;oops&gt;</failure>
+    </testcase>
+    <testcase name="guide/usage.md:12 (sh)" classname="guide/usage.md" time="1.500">
+      <skipped/>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn diffs_show_the_lines_which_changed() {
+        assert_eq!(diff("a\nb\nc", "a\nc\nd"), "  a\n- b\n  c\n+ d");
+        assert_eq!(diff("", "a"), "+ a");
+        assert!(same_output("a  \nb\n\n", "a\nb"));
+        assert!(!same_output("a\n\nb", "a\nb"));
+    }
+}
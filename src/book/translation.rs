@@ -0,0 +1,215 @@
+//! Multilingual books, whose translations are built side by side.
+//!
+//! Each language's chapters go in the directory of the same name in `src`,
+//! like `src/en` and `src/ja`, and are built into the directory of the same
+//! name in the book's HTML output. Translations use the default language's
+//! `SUMMARY.md` unless they have their own, and chapters which haven't been
+//! translated yet are taken from the default language.
+
+use std::path::{Path, PathBuf};
+
+use super::book::{load_book, load_book_from_disk};
+use super::summary::{parse_summary, SummaryItem};
+use super::{Book, BookItem, MDBook};
+use errors::*;
+use handlebars;
+use utils;
+
+use config::Config;
+
+/// The language a multilingual book falls back to.
+pub(crate) fn default_language(config: &Config) -> String {
+    config.book.language().to_string()
+}
+
+/// Load a multilingual book, with a translation for each of its languages.
+pub(crate) fn load_translations(root: PathBuf, config: Config) -> Result<MDBook> {
+    let languages = config.languages()?;
+    let default = default_language(&config);
+    if !languages.contains_key(&default) {
+        bail!(
+            "The book's default language, \"{0}\", needs a [language.{0}] table",
+            default
+        );
+    }
+
+    let mut book = MDBook::from_book(root, config, Book::new())?;
+    let build_dir = book.build_dir_for("html");
+
+    for (code, language) in languages {
+        let mut config = book.config.clone();
+        config.book.language = Some(code.clone());
+        config.book.src = config.book.src.join(&code);
+        config.build.build_dir = build_dir.join(&code);
+        if language.title.is_some() {
+            config.book.title = language.title;
+        }
+        if language.description.is_some() {
+            config.book.description = language.description;
+        }
+
+        let src_dir = book.root.join(&config.book.src);
+        let chapters = if code == default {
+            load_book(&src_dir, &config.build)
+        } else {
+            load_translation(&src_dir, &default)
+        };
+        let chapters =
+            chapters.chain_err(|| format!("Unable to load the \"{}\" translation", code))?;
+
+        let translation = MDBook::from_book(book.root.clone(), config, chapters)?;
+        book.members.push(translation);
+    }
+
+    Ok(book)
+}
+
+/// Load the translation in `src_dir`, taking `SUMMARY.md` (if it doesn't
+/// have its own) and any chapters it doesn't have from the `fallback`
+/// language's directory next to it.
+fn load_translation(src_dir: &Path, fallback: &str) -> Result<Book> {
+    // untranslated chapters are loaded through the other directory, so their
+    // source paths are still relative to this one
+    let fallback_dir = Path::new("..").join(fallback);
+
+    let mut summary_md = src_dir.join("SUMMARY.md");
+    if !summary_md.exists() {
+        summary_md = src_dir.join(&fallback_dir).join("SUMMARY.md");
+    }
+    let summary =
+        utils::fs::file_to_string(&summary_md).chain_err(|| "Couldn't open SUMMARY.md")?;
+    let mut summary = parse_summary(&summary).chain_err(|| "Summary parsing failed")?;
+
+    let mut untranslated = 0;
+    for items in &mut [
+        &mut summary.prefix_chapters,
+        &mut summary.numbered_chapters,
+        &mut summary.suffix_chapters,
+    ] {
+        untranslated += fall_back(items, src_dir, &fallback_dir);
+    }
+    if untranslated > 0 {
+        info!(
+            "{} chapter(s) in {} haven't been translated, so the \"{}\" ones are used",
+            untranslated,
+            src_dir.display(),
+            fallback
+        );
+    }
+
+    let mut book = load_book_from_disk(&summary, src_dir)?;
+    book.for_each_mut(|item| {
+        if let BookItem::Chapter(ref mut ch) = *item {
+            let path = match ch.path {
+                Some(ref path) => path.strip_prefix(&fallback_dir).ok().map(Path::to_path_buf),
+                None => None,
+            };
+            if path.is_some() {
+                ch.path = path;
+            }
+        }
+    });
+
+    Ok(book)
+}
+
+/// Point the chapters in `items` which `src_dir` doesn't have at those in
+/// `fallback_dir` instead, returning how many of them there were.
+fn fall_back(items: &mut [SummaryItem], src_dir: &Path, fallback_dir: &Path) -> usize {
+    let mut count = 0;
+    for item in items {
+        if let SummaryItem::Link(ref mut link) = *item {
+            if let Some(ref mut location) = link.location {
+                let fallback = fallback_dir.join(&location);
+                if !src_dir.join(&location).exists() && src_dir.join(&fallback).exists() {
+                    debug!("Using {} for {}", fallback.display(), location.display());
+                    *location = fallback;
+                    count += 1;
+                }
+            }
+            count += fall_back(&mut link.nested_items, src_dir, fallback_dir);
+        }
+    }
+    count
+}
+
+/// Write the `index.html` which sends readers on to the default language, or
+/// the first one which was built if that wasn't.
+pub(crate) fn write_redirect(book: &MDBook) -> Result<()> {
+    let default = default_language(&book.config);
+    let languages: Vec<_> = book
+        .members
+        .iter()
+        .filter_map(|translation| translation.config.book.language.as_ref())
+        .collect();
+    let language = match languages.iter().find(|language| ***language == default) {
+        Some(language) => language,
+        None => languages[0],
+    };
+
+    let url = handlebars::html_escape(&format!("{}/index.html", language));
+    let page = format!(
+        "<!DOCTYPE HTML>\n\
+         <html>\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>Redirecting...</title>\n\
+         <meta http-equiv=\"refresh\" content=\"0; URL={url}\">\n\
+         </head>\n\
+         <body>\n\
+         <p>Redirecting to <a href=\"{url}\">{url}</a>...</p>\n\
+         </body>\n\
+         </html>\n",
+        url = url
+    );
+    utils::fs::write_file(&book.build_dir_for("html"), "index.html", page.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder as TempFileBuilder;
+
+    #[test]
+    fn untranslated_chapters_are_taken_from_the_fallback_language() {
+        let temp = TempFileBuilder::new().prefix("book").tempdir().unwrap();
+        let src = temp.path().join("src");
+        utils::fs::write_file(
+            &src,
+            "en/SUMMARY.md",
+            b"- [Intro](intro.md)\n    - [Usage](guide/usage.md)\n",
+        )
+        .unwrap();
+        utils::fs::write_file(&src, "en/intro.md", b"# Intro").unwrap();
+        utils::fs::write_file(&src, "en/guide/usage.md", b"# Usage").unwrap();
+        utils::fs::write_file(&src, "ja/intro.md", "# はじめに".as_bytes()).unwrap();
+
+        let book = load_translation(&src.join("ja"), "en").unwrap();
+        let chapters: Vec<_> = book
+            .iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => Some((
+                    ch.content.clone(),
+                    ch.path.clone().unwrap(),
+                    ch.source_path.clone().unwrap(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            chapters,
+            vec![
+                (
+                    String::from("# はじめに"),
+                    PathBuf::from("intro.md"),
+                    PathBuf::from("intro.md")
+                ),
+                (
+                    String::from("# Usage"),
+                    PathBuf::from("guide/usage.md"),
+                    PathBuf::from("../en/guide/usage.md")
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Workspaces, which build several books together.
+//!
+//! A book becomes a workspace by listing other books in its `book.toml`:
+//!
+//! ```toml
+//! [workspace]
+//! books = ["guide", "reference"]
+//! ```
+//!
+//! Each of them is built into the directory of the same name in the
+//! workspace's HTML output, so they're served under subpaths and can link to
+//! each other with relative links like `../reference/index.md`. A workspace
+//! without a `SUMMARY.md` of its own gets a landing page listing its books
+//! instead.
+
+use std::path::PathBuf;
+
+use super::MDBook;
+use errors::*;
+use utils;
+
+use config::Config;
+
+/// The directories of the books in a workspace, relative to its root.
+pub(crate) fn member_dirs(config: &Config) -> Result<Vec<PathBuf>> {
+    let dirs = config
+        .get_deserialized_opt("workspace.books")
+        .chain_err(|| "workspace.books should be a list of directories")?;
+    Ok(dirs.unwrap_or_default())
+}
+
+/// Load the books in `workspace`, each of them set up to be built into its
+/// own directory inside the workspace's HTML output.
+pub(crate) fn load_members(workspace: &MDBook) -> Result<Vec<MDBook>> {
+    let build_dir = workspace.build_dir_for("html");
+    let mut members = Vec::new();
+
+    for dir in member_dirs(&workspace.config)? {
+        let root = workspace.root.join(&dir);
+        let mut config = MDBook::load_config(&root)?;
+        config.build.build_dir = build_dir.join(&dir);
+        config.build.strict |= workspace.config.build.strict;
+
+        let member = MDBook::load_with_config(root, config)
+            .chain_err(|| format!("Unable to load the book in {}", dir.display()))?;
+        members.push(member);
+    }
+
+    Ok(members)
+}
+
+/// Write the page listing the books in `workspace`, for when it doesn't have
+/// any chapters of its own.
+pub(crate) fn write_landing_page(workspace: &MDBook) -> Result<()> {
+    let build_dir = workspace.build_dir_for("html");
+    info!("Writing the workspace's landing page to {}", build_dir.display());
+
+    let html = landing_page(workspace, &member_dirs(&workspace.config)?);
+    utils::fs::write_file(&build_dir, "index.html", html.as_bytes())
+}
+
+fn landing_page(workspace: &MDBook, dirs: &[PathBuf]) -> String {
+    let title = workspace.config.book.title.as_ref().map_or("Books", |t| &t[..]);
+    let mut html = format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"UTF-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{0}</title>\n\
+         <style>\n\
+         body {{ font-family: \"Open Sans\", sans-serif; max-width: 750px; margin: 0 auto; \
+         padding: 0 15px; color: #333; }}\n\
+         .books {{ list-style: none; padding: 0; }}\n\
+         .books li {{ margin: 1em 0; }}\n\
+         .books a {{ font-size: 1.25em; }}\n\
+         .books p {{ margin: 0.25em 0; }}\n\
+         </style>\n\
+         </head>\n\
+         <body>\n\
+         <h1>{0}</h1>\n",
+        utils::escape_html(title)
+    );
+    if let Some(ref description) = workspace.config.book.description {
+        html.push_str(&format!("<p>{}</p>\n", utils::escape_html(description)));
+    }
+
+    html.push_str("<ul class=\"books\">\n");
+    for (member, dir) in workspace.members.iter().zip(dirs) {
+        let dir = utils::fs::normalize_path(&dir.display().to_string());
+        let name = member.config.book.title.clone().unwrap_or_else(|| dir.clone());
+        html.push_str(&format!(
+            "<li><a href=\"{}/index.html\">{}</a>",
+            utils::escape_html(dir.trim_end_matches('/')),
+            utils::escape_html(&name)
+        ));
+        if let Some(ref description) = member.config.book.description {
+            html.push_str(&format!("<p>{}</p>", utils::escape_html(description)));
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n");
+
+    if let Some(url) = workspace.config.html_config().and_then(|cfg| cfg.livereload_url) {
+        html.push_str(&format!(
+            "<script type=\"text/javascript\">\n\
+             var socket = new WebSocket(\"{}\");\n\
+             socket.onmessage = function (event) {{\n\
+             if (event.data === \"reload\") {{ socket.close(); location.reload(true); }}\n\
+             }};\n\
+             </script>\n",
+            url
+        ));
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::Book;
+
+    #[test]
+    fn books_in_the_workspace_are_listed() {
+        let mut cfg = Config::default();
+        cfg.set("workspace.books", vec!["core", "http/client"]).unwrap();
+        assert_eq!(
+            member_dirs(&cfg).unwrap(),
+            vec![PathBuf::from("core"), PathBuf::from("http/client")]
+        );
+        assert!(member_dirs(&Config::default()).unwrap().is_empty());
+
+        cfg.set("workspace.books", "core").unwrap();
+        assert!(member_dirs(&cfg).is_err());
+    }
+
+    #[test]
+    fn the_landing_page_links_to_every_book() {
+        let mut cfg = Config::default();
+        cfg.book.title = Some(String::from("Docs & Guides"));
+        let mut workspace = MDBook::from_book("/tmp/docs", cfg, Book::new()).unwrap();
+
+        let mut core = Config::default();
+        core.book.title = Some(String::from("The Core Guide"));
+        core.book.description = Some(String::from("Where to <start>"));
+        workspace.members.push(MDBook::from_book("/tmp/docs/core", core, Book::new()).unwrap());
+        let http = Config::default();
+        workspace.members.push(MDBook::from_book("/tmp/docs/http", http, Book::new()).unwrap());
+
+        let html = landing_page(&workspace, &[PathBuf::from("core"), PathBuf::from("http")]);
+        assert!(html.contains("<title>Docs &amp; Guides</title>"));
+        assert!(html.contains(
+            "<li><a href=\"core/index.html\">The Core Guide</a><p>Where to &lt;start&gt;</p></li>"
+        ));
+        assert!(html.contains("<li><a href=\"http/index.html\">http</a></li>"));
+        assert!(!html.contains("WebSocket"));
+    }
+}
@@ -1,7 +1,11 @@
-use clap::{App, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use mdbook::config::Config;
+use mdbook::diagnostics::{self, Diagnostic};
 use mdbook::errors::Result;
 use mdbook::MDBook;
-use {get_book_dir, open};
+use serde_json;
+use std::path::PathBuf;
+use {book_config, get_book_dir, open, set_arg};
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -10,21 +14,87 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
         .arg_from_usage(
             "-d, --dest-dir=[dest-dir] 'Output directory for the book{n}\
              (If omitted, uses build.build-dir from book.toml or defaults to ./book)'",
+        ).arg_from_usage(
+            "-s, --source-dir=[source-dir] 'Source directory for the book{n}\
+             (If omitted, uses book.src from book.toml or defaults to ./src)'",
         ).arg_from_usage(
             "[dir] 'Root directory for the book{n}\
              (Defaults to the Current Directory when omitted)'",
         ).arg_from_usage("-o, --open 'Opens the compiled book in a web browser'")
+        .arg_from_usage(
+            "--strict 'Fails the build if there are any warnings about the{n}\
+             book, like broken links or failed includes'",
+        ).arg_from_usage(
+            "-l, --language=[language] 'Only builds the translation of a multilingual{n}\
+             book into this language'",
+        ).arg(Arg::with_name("features")
+            .long("features")
+            .value_name("features")
+            .takes_value(true)
+            .require_delimiter(true)
+            .multiple(true)
+            .empty_values(false)
+            .help("A comma-separated list of features to enable for the{n}conditional preprocessor"))
+        .arg(
+            Arg::with_name("message-format")
+                .long("message-format")
+                .value_name("format")
+                .takes_value(true)
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .help("Also print warnings and errors to stdout as JSON, one per line"),
+        )
+        .arg(set_arg())
 }
 
 // Build command implementation
 pub fn execute(args: &ArgMatches) -> Result<()> {
-    let book_dir = get_book_dir(args);
-    let mut book = MDBook::load(&book_dir)?;
+    let json = args.value_of("message-format") == Some("json");
+    if json {
+        diagnostics::collect();
+    }
 
-    if let Some(dest_dir) = args.value_of("dest-dir") {
-        book.config.build.build_dir = dest_dir.into();
+    let mut src = PathBuf::new();
+    let result = book_config(args).and_then(|config| {
+        src = config.book.src.clone();
+        build(args, config)
+    });
+
+    if json {
+        let mut diagnostics = diagnostics::take_collected();
+        if let Err(ref e) = result {
+            let causes: Vec<_> = e.iter().map(|cause| cause.to_string()).collect();
+            diagnostics.push(Diagnostic::error(causes.join(": ")));
+        }
+
+        for mut diagnostic in diagnostics {
+            // editors and CI want paths relative to the book's root
+            diagnostic.file = diagnostic.file.map(|file| src.join(file));
+            println!("{}", serde_json::to_string(&diagnostic)?);
+        }
     }
 
+    result
+}
+
+fn build(args: &ArgMatches, mut config: Config) -> Result<()> {
+    // preprocessors are chosen when the book is loaded, so these have to be
+    // done first
+    if let Some(features) = args.values_of("features") {
+        let key = "preprocessor.conditional.features";
+        let mut enabled: Vec<String> = config.get_deserialized(key).unwrap_or_default();
+        enabled.extend(features.map(|f| f.to_string()));
+        config.set(key, &enabled)?;
+    }
+
+    if args.is_present("strict") {
+        config.build.strict = true;
+    }
+
+    let mut book = MDBook::load_with_config(get_book_dir(args), config)?;
+    if let Some(language) = args.value_of("language") {
+        book.select_language(language)?;
+    }
     book.build()?;
 
     if args.is_present("open") {
@@ -3,11 +3,12 @@ use get_book_dir;
 use mdbook::errors::*;
 use mdbook::MDBook;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("clean")
-        .about("Deletes a built book")
+        .about("Deletes a built book and its page cache")
         .arg_from_usage(
             "-d, --dest-dir=[dest-dir] 'Output directory for the book{n}\
              (If omitted, uses build.build-dir from book.toml or defaults to ./book)'",
@@ -26,7 +27,44 @@ pub fn execute(args: &ArgMatches) -> ::mdbook::errors::Result<()> {
         Some(dest_dir) => dest_dir.into(),
         None => book.root.join(&book.config.build.build_dir),
     };
-    fs::remove_dir_all(&dir_to_remove).chain_err(|| "Unable to remove the build directory")?;
+    remove(&book, &dir_to_remove).chain_err(|| "Unable to remove the build directory")?;
+
+    let cache_dir = book
+        .config
+        .html_config()
+        .and_then(|html| html.cache_dir)
+        .map(|dir| book.root.join(dir));
+    if let Some(cache_dir) = cache_dir {
+        remove(&book, &cache_dir).chain_err(|| "Unable to remove the page cache")?;
+    }
 
     Ok(())
 }
+
+/// Remove a directory, unless it's the book's root or has the book's source
+/// in it, which can happen when `build-dir` or `--dest-dir` is set to the
+/// wrong thing.
+fn remove(book: &MDBook, dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        debug!("{} doesn't exist, so there's nothing to remove", dir.display());
+        return Ok(());
+    }
+
+    let dir = canonical(dir);
+    let protected = [canonical(&book.root), canonical(&book.source_dir())];
+    if protected.iter().any(|path| path.starts_with(&dir)) {
+        return Err(format!(
+            "Refusing to remove {}, because the book's source is inside it",
+            dir.display()
+        )
+        .into());
+    }
+
+    info!("Removing {}", dir.display());
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,24 @@
+use clap::{App, Arg, ArgMatches, Shell, SubCommand};
+use create_clap_app;
+use mdbook::errors::Result;
+use std::io;
+
+// Create clap subcommand arguments
+pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("completions")
+        .about("Prints a completion script for your shell")
+        .arg(
+            Arg::with_name("shell")
+                .required(true)
+                .possible_values(&Shell::variants())
+                .help("The shell to print the completion script for"),
+        )
+}
+
+// Completions command implementation
+pub fn execute(args: &ArgMatches) -> Result<()> {
+    // clap has already checked it's one of the shells it knows about
+    let shell = value_t!(args, "shell", Shell).unwrap_or_else(|e| e.exit());
+    create_clap_app().gen_completions_to("mdbook", shell, &mut io::stdout());
+    Ok(())
+}
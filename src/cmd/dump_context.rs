@@ -0,0 +1,42 @@
+use clap::{App, ArgMatches, SubCommand};
+use get_book_dir;
+use mdbook::errors::Result;
+use mdbook::renderer::{HtmlHandlebars, RenderContext};
+use mdbook::MDBook;
+use serde_json;
+use std::path::Path;
+
+// Create clap subcommand arguments
+pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("dump-context")
+        .about("Prints the data a chapter's page is rendered with, for theme authors")
+        .arg_from_usage(
+            "<chapter> 'The chapter, by its source file (e.g. guide/usage.md){n}\
+             or its page (e.g. guide/usage.html)'",
+        ).arg_from_usage(
+            "[dir] 'Root directory for the book{n}\
+             (Defaults to the Current Directory when omitted)'",
+        )
+}
+
+// Dump-context command implementation
+pub fn execute(args: &ArgMatches) -> Result<()> {
+    let book_dir = get_book_dir(args);
+    let book = MDBook::load(&book_dir)?;
+
+    // the chapter goes through the same preprocessors as in a real build
+    let html = HtmlHandlebars::new();
+    let preprocessed_book = book.preprocess_book(&html)?;
+    let ctx = RenderContext::new(
+        book.root.clone(),
+        preprocessed_book,
+        book.config.clone(),
+        book.build_dir_for("html"),
+    );
+
+    let chapter = Path::new(args.value_of("chapter").unwrap());
+    let data = html.template_context(&ctx, chapter)?;
+    println!("{}", serde_json::to_string_pretty(&data)?);
+
+    Ok(())
+}
@@ -15,6 +15,8 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
         .arg_from_usage("[dir] 'Directory to create the book in{n}\
             (Defaults to the Current Directory when omitted)'")
         .arg_from_usage("--theme 'Copies the default theme into your source folder'")
+        .arg_from_usage("--title [title] 'Sets the title of the book instead of asking for it'")
+        .arg_from_usage("--gitignore 'Creates a .gitignore without asking'")
         .arg_from_usage("--force 'Skips confirmation prompts'")
 }
 
@@ -47,19 +49,26 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
         }
     }
 
-    println!("\nDo you want a .gitignore to be created? (y/n)");
-
-    if confirm() {
+    if args.is_present("gitignore") {
         builder.create_gitignore(true);
+    } else if !args.is_present("force") {
+        println!("\nDo you want a .gitignore to be created? (y/n)");
+
+        if confirm() {
+            builder.create_gitignore(true);
+        }
     }
 
-    config.book.title = request_book_title();
+    config.book.title = match args.value_of("title") {
+        Some(title) => Some(title.to_string()),
+        None => request_book_title(),
+    };
 
     if let Some(author) = get_author_name() {
         debug!("Obtained user name from gitconfig: {:?}", author);
         config.book.authors.push(author);
-        builder.with_config(config);
     }
+    builder.with_config(config);
 
     builder.build()?;
     println!("\nAll done, no errors...");
@@ -2,9 +2,12 @@
 
 pub mod build;
 pub mod clean;
+pub mod completions;
+pub mod dump_context;
 pub mod init;
 #[cfg(feature = "serve")]
 pub mod serve;
 pub mod test;
 #[cfg(feature = "watch")]
 pub mod watch;
+pub mod xgettext;
@@ -12,7 +12,7 @@ use mdbook::errors::*;
 use mdbook::utils;
 use mdbook::MDBook;
 use std;
-use {get_book_dir, open};
+use {load_book, open, set_arg};
 
 struct ErrorRecover;
 
@@ -24,6 +24,10 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
             "-d, --dest-dir=[dest-dir] 'Output directory for the book{n}\
              (If omitted, uses build.build-dir from book.toml or defaults to ./book)'",
         )
+        .arg_from_usage(
+            "-s, --source-dir=[source-dir] 'Source directory for the book{n}\
+             (If omitted, uses book.src from book.toml or defaults to ./src)'",
+        )
         .arg_from_usage(
             "[dir] 'Root directory for the book{n}\
              (Defaults to the Current Directory when omitted)'",
@@ -65,12 +69,12 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
                 .help("Port to use for WebSockets livereload connections"),
         )
         .arg_from_usage("-o, --open 'Opens the book server in a web browser'")
+        .arg(set_arg())
 }
 
 // Watch command implementation
 pub fn execute(args: &ArgMatches) -> Result<()> {
-    let book_dir = get_book_dir(args);
-    let mut book = MDBook::load(&book_dir)?;
+    let mut book = load_book(args)?;
 
     let port = args.value_of("port").unwrap();
     let ws_port = args.value_of("websocket-port").unwrap();
@@ -82,12 +86,7 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     let ws_address = format!("{}:{}", hostname, ws_port);
 
     let livereload_url = format!("ws://{}:{}", public_address, ws_port);
-    book.config
-        .set("output.html.livereload-url", &livereload_url)?;
-
-    if let Some(dest_dir) = args.value_of("dest-dir") {
-        book.config.build.build_dir = dest_dir.into();
-    }
+    set_livereload_url(&mut book, &livereload_url)?;
 
     book.build()?;
 
@@ -114,18 +113,17 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     }
 
     #[cfg(feature = "watch")]
-    watch::trigger_on_change(&mut book, move |path, book_dir| {
+    watch::trigger_on_change(&book, &[], move |path| {
         info!("File changed: {:?}", path);
         info!("Building book...");
 
         // FIXME: This area is really ugly because we need to re-set livereload :(
 
-        let result = MDBook::load(&book_dir)
+        let result = load_book(args)
             .and_then(|mut b| {
-                b.config
-                    .set("output.html.livereload-url", &livereload_url)?;
+                set_livereload_url(&mut b, &livereload_url)?;
                 Ok(b)
-            }).and_then(|b| b.build());
+            }).and_then(|b| b.rebuild(path));
 
         if let Err(e) = result {
             error!("Unable to load the book");
@@ -138,6 +136,15 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// Point livereloading at `url`, in every book of the workspace too.
+fn set_livereload_url(book: &mut MDBook, url: &str) -> Result<()> {
+    book.config.set("output.html.livereload-url", url)?;
+    for member in &mut book.members {
+        set_livereload_url(member, url)?;
+    }
+    Ok(())
+}
+
 impl AfterMiddleware for ErrorRecover {
     fn catch(&self, _: &mut Request, err: IronError) -> IronResult<Response> {
         match err.response.status {
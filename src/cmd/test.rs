@@ -1,7 +1,6 @@
 use clap::{App, Arg, ArgMatches, SubCommand};
-use get_book_dir;
+use {load_book, set_arg};
 use mdbook::errors::Result;
-use mdbook::MDBook;
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -11,6 +10,10 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
             "-d, --dest-dir=[dest-dir] 'Output directory for the book{n}\
              (If omitted, uses build.build-dir from book.toml or defaults to ./book)'",
         )
+        .arg_from_usage(
+            "-s, --source-dir=[source-dir] 'Source directory for the book{n}\
+             (If omitted, uses book.src from book.toml or defaults to ./src)'",
+        )
         .arg_from_usage(
             "[dir] 'Root directory for the book{n}\
              (Defaults to the Current Directory when omitted)'",
@@ -24,6 +27,13 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
             .multiple(true)
             .empty_values(false)
             .help("A comma-separated list of directories to add to {n}the crate search path when building tests"))
+        .arg(Arg::with_name("report")
+            .long("report")
+            .value_name("format=path")
+            .takes_value(true)
+            .empty_values(false)
+            .help("Also write the results to a file, like{n}junit=results.xml (the only format)"))
+        .arg(set_arg())
 }
 
 // test command implementation
@@ -32,14 +42,20 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
         .values_of("library-path")
         .map(|v| v.collect())
         .unwrap_or_default();
-    let book_dir = get_book_dir(args);
-    let mut book = MDBook::load(&book_dir)?;
-
-    if let Some(dest_dir) = args.value_of("dest-dir") {
-        book.config.build.build_dir = dest_dir.into();
+    let mut book = load_book(args)?;
+    match args.value_of("report") {
+        Some(report) => {
+            let path = match report.find('=') {
+                Some(i) if &report[..i] == "junit" => &report[i + 1..],
+                _ => {
+                    let msg = format!("Unknown report \"{}\", expected junit=<path>", report);
+                    return Err(msg.into());
+                }
+            };
+            book.test_with_junit_report(library_paths, path)?;
+        }
+        None => book.test(library_paths)?,
     }
 
-    book.test(library_paths)?;
-
     Ok(())
 }
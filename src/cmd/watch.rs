@@ -1,14 +1,16 @@
 extern crate notify;
+extern crate regex;
 
 use self::notify::Watcher;
-use clap::{App, ArgMatches, SubCommand};
+use self::regex::Regex;
+use clap::{App, Arg, ArgMatches, SubCommand};
 use mdbook::errors::Result;
 use mdbook::utils;
 use mdbook::MDBook;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::channel;
 use std::time::Duration;
-use {get_book_dir, open};
+use {load_book, open, set_arg};
 
 // Create clap subcommand arguments
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
@@ -17,25 +19,42 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
         .arg_from_usage(
             "-d, --dest-dir=[dest-dir] 'Output directory for the book{n}\
              (If omitted, uses build.build-dir from book.toml or defaults to ./book)'",
+        ).arg_from_usage(
+            "-s, --source-dir=[source-dir] 'Source directory for the book{n}\
+             (If omitted, uses book.src from book.toml or defaults to ./src)'",
         ).arg_from_usage(
             "[dir] 'Root directory for the book{n}\
              (Defaults to the Current Directory when omitted)'",
         ).arg_from_usage("-o, --open 'Open the compiled book in a web browser'")
+        .arg(
+            Arg::with_name("ignore")
+                .short("i")
+                .long("ignore")
+                .value_name("pattern")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Don't rebuild when files matching a .gitignore-style pattern{n}\
+                     change, on top of those ignored by the book's .gitignore",
+                ),
+        )
+        .arg(set_arg())
 }
 
 // Watch command implementation
 pub fn execute(args: &ArgMatches) -> Result<()> {
-    let book_dir = get_book_dir(args);
-    let book = MDBook::load(&book_dir)?;
+    let book = load_book(args)?;
 
     if args.is_present("open") {
         book.build()?;
         open(book.build_dir_for("html").join("index.html"));
     }
 
-    trigger_on_change(&book, |path, book_dir| {
+    let ignore: Vec<&str> = args.values_of("ignore").map(|v| v.collect()).unwrap_or_default();
+    trigger_on_change(&book, &ignore, |path| {
         info!("File changed: {:?}\nBuilding book...\n", path);
-        let result = MDBook::load(&book_dir).and_then(|b| b.build());
+        let result = load_book(args).and_then(|b| b.rebuild(path));
 
         if let Err(e) = result {
             error!("Unable to build the book");
@@ -47,12 +66,13 @@ pub fn execute(args: &ArgMatches) -> Result<()> {
 }
 
 /// Calls the closure when a book source file is changed, blocking indefinitely.
-pub fn trigger_on_change<F>(book: &MDBook, closure: F)
+/// Changes to files ignored by the book's `.gitignore`, or matching one of the
+/// `ignore` patterns, are skipped.
+pub fn trigger_on_change<F>(book: &MDBook, ignore: &[&str], closure: F)
 where
-    F: Fn(&Path, &Path),
+    F: Fn(&Path),
 {
     use self::notify::DebouncedEvent::*;
-    use self::notify::RecursiveMode::*;
 
     // Create a channel to receive the events.
     let (tx, rx) = channel();
@@ -65,16 +85,9 @@ where
         }
     };
 
-    // Add the source directory to the watcher
-    if let Err(e) = watcher.watch(book.source_dir(), Recursive) {
-        error!("Error while watching {:?}:\n    {:?}", book.source_dir(), e);
-        ::std::process::exit(1);
-    };
-
-    let _ = watcher.watch(book.theme_dir(), Recursive);
+    watch_book(&mut watcher, book);
 
-    // Add the book.toml file to the watcher if it exists
-    let _ = watcher.watch(book.root.join("book.toml"), NonRecursive);
+    let ignore = IgnorePatterns::new(&book.root, ignore);
 
     info!("Listening for changes...");
 
@@ -82,9 +95,223 @@ where
         debug!("Received filesystem event: {:?}", event);
         match event {
             Create(path) | Write(path) | Remove(path) | Rename(_, path) => {
-                closure(&path, &book.root);
+                if ignore.is_ignored(&path) {
+                    debug!("Ignoring the change to {}", path.display());
+                } else {
+                    closure(&path);
+                }
             }
             _ => {}
         }
     }
 }
+
+/// Watch the files `book` is built from, along with those of the books in
+/// its workspace.
+fn watch_book<W: Watcher>(watcher: &mut W, book: &MDBook) {
+    use self::notify::RecursiveMode::*;
+
+    // Add the source directory to the watcher, which a workspace doesn't need
+    if book.members.is_empty() || book.source_dir().exists() {
+        if let Err(e) = watcher.watch(book.source_dir(), Recursive) {
+            error!("Error while watching {:?}:\n    {:?}", book.source_dir(), e);
+            ::std::process::exit(1);
+        };
+    }
+
+    let _ = watcher.watch(book.theme_dir(), Recursive);
+
+    // Add the book.toml file to the watcher if it exists
+    let _ = watcher.watch(book.root.join("book.toml"), NonRecursive);
+
+    for member in &book.members {
+        watch_book(watcher, member);
+    }
+}
+
+/// Patterns, in the style of `.gitignore`, for files whose changes don't need
+/// a rebuild.
+struct IgnorePatterns {
+    root: PathBuf,
+    patterns: Vec<IgnorePattern>,
+}
+
+struct IgnorePattern {
+    regex: Regex,
+    /// The pattern started with a `!`, so it un-ignores whatever it matches.
+    negated: bool,
+    /// The pattern ended with a `/`, so it only matches directories.
+    dir_only: bool,
+}
+
+impl IgnorePatterns {
+    /// The patterns from the `.gitignore` in the book's root, followed by
+    /// `extra` ones.
+    fn new(root: &Path, extra: &[&str]) -> IgnorePatterns {
+        let gitignore = utils::fs::file_to_string(root.join(".gitignore")).unwrap_or_default();
+        let patterns = gitignore
+            .lines()
+            .chain(extra.iter().cloned())
+            .filter_map(IgnorePattern::parse)
+            .collect();
+
+        IgnorePatterns {
+            root: root.to_path_buf(),
+            patterns,
+        }
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+
+        // a file is ignored if it, or any directory it's in, is
+        let depth = relative.components().count();
+        let mut candidate = PathBuf::new();
+        for (i, component) in relative.components().enumerate() {
+            candidate.push(component);
+            let is_dir = i + 1 < depth || path.is_dir();
+            let candidate = utils::fs::normalize_path(&candidate.display().to_string());
+
+            // the last pattern to match wins
+            let mut ignored = false;
+            for pattern in &self.patterns {
+                if (is_dir || !pattern.dir_only) && pattern.regex.is_match(&candidate) {
+                    ignored = !pattern.negated;
+                }
+            }
+            if ignored {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+impl IgnorePattern {
+    fn parse(line: &str) -> Option<IgnorePattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let negated = line.starts_with('!');
+        let line = line.trim_start_matches('!');
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        // patterns with a slash are relative to the root, the rest can match
+        // at any depth
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+
+        let mut regex = String::from(if anchored { "^" } else { "^(.*/)?" });
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        regex.push_str("(.*/)?");
+                    } else {
+                        regex.push_str(".*");
+                    }
+                }
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                '[' => {
+                    // a class's ranges are kept, so only what's special to
+                    // the regex inside a class is escaped
+                    let mut raw = String::new();
+                    let mut class = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        match c {
+                            ']' if !raw.is_empty() => {
+                                closed = true;
+                                break;
+                            }
+                            '!' if raw.is_empty() => class.push('^'),
+                            '\\' | '[' => {
+                                class.push('\\');
+                                class.push(c);
+                            }
+                            '^' if !raw.is_empty() => class.push_str("\\^"),
+                            c => class.push(c),
+                        }
+                        raw.push(c);
+                    }
+
+                    if closed {
+                        regex.push_str(&format!("[{}]", class));
+                    } else {
+                        regex.push_str(&regex::escape(&format!("[{}", raw)));
+                    }
+                }
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+
+        match Regex::new(&regex) {
+            Ok(regex) => Some(IgnorePattern {
+                regex,
+                negated,
+                dir_only,
+            }),
+            Err(_) => {
+                warn!("Skipping the invalid ignore pattern \"{}\"", line);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(lines: &[&str]) -> IgnorePatterns {
+        IgnorePatterns {
+            root: PathBuf::from("/book"),
+            patterns: lines.iter().cloned().filter_map(IgnorePattern::parse).collect(),
+        }
+    }
+
+    #[test]
+    fn patterns_without_a_slash_match_at_any_depth() {
+        let ignore = patterns(&["*.swp", "# a comment", "", "drafts/"]);
+
+        assert!(ignore.is_ignored(Path::new("/book/src/.chapter.md.swp")));
+        assert!(ignore.is_ignored(Path::new("/book/src/drafts/idea.md")));
+        assert!(!ignore.is_ignored(Path::new("/book/src/chapter.md")));
+        assert!(!ignore.is_ignored(Path::new("/elsewhere/chapter.swp")));
+    }
+
+    #[test]
+    fn patterns_with_a_slash_are_relative_to_the_root() {
+        let ignore = patterns(&["/src/generated", "theme/**/*.css", "!theme/keep.css"]);
+
+        assert!(ignore.is_ignored(Path::new("/book/src/generated/api.md")));
+        assert!(!ignore.is_ignored(Path::new("/book/src/nested/src/generated")));
+        assert!(ignore.is_ignored(Path::new("/book/theme/css/chrome.css")));
+        assert!(ignore.is_ignored(Path::new("/book/theme/general.css")));
+        assert!(!ignore.is_ignored(Path::new("/book/theme/keep.css")));
+    }
+
+    #[test]
+    fn classes_keep_their_ranges() {
+        let ignore = patterns(&["*.[oa]", "[a-z]*.tmp", "[!0-9]x"]);
+
+        assert!(ignore.is_ignored(Path::new("/book/src/main.o")));
+        assert!(ignore.is_ignored(Path::new("/book/src/lib.a")));
+        assert!(!ignore.is_ignored(Path::new("/book/src/lib.so")));
+        assert!(ignore.is_ignored(Path::new("/book/scratch.tmp")));
+        assert!(!ignore.is_ignored(Path::new("/book/Scratch.tmp")));
+        assert!(ignore.is_ignored(Path::new("/book/ax")));
+        assert!(!ignore.is_ignored(Path::new("/book/1x")));
+    }
+}
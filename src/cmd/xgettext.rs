@@ -0,0 +1,54 @@
+use clap::{App, ArgMatches, SubCommand};
+use mdbook::errors::Result;
+use mdbook::preprocess::GettextPreprocessor;
+use mdbook::utils;
+use std::path::Path;
+use {load_book, set_arg};
+
+// Create clap subcommand arguments
+pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("xgettext")
+        .about("Extracts a book's text into a .pot template for translators")
+        .arg_from_usage(
+            "-s, --source-dir=[source-dir] 'Source directory for the book{n}\
+             (If omitted, uses book.src from book.toml or defaults to ./src)'",
+        ).arg_from_usage(
+            "-o, --output=[file] 'Where to write the template, relative to the{n}\
+             book's root (Defaults to po/messages.pot)'",
+        ).arg_from_usage(
+            "[dir] 'Root directory for the book{n}\
+             (Defaults to the Current Directory when omitted)'",
+        ).arg(set_arg())
+}
+
+// xgettext command implementation
+pub fn execute(args: &ArgMatches) -> Result<()> {
+    let book = load_book(args)?;
+
+    // a multilingual book's messages are the ones in its default language
+    let default = book.config.book.language().to_string();
+    let source = if book.config.book.multilingual {
+        match book
+            .members
+            .iter()
+            .find(|translation| translation.config.book.language.as_ref() == Some(&default))
+        {
+            Some(translation) => translation,
+            None => return Err(format!("The book isn't written in \"{}\"", default).into()),
+        }
+    } else {
+        &book
+    };
+
+    let output = book
+        .root
+        .join(args.value_of("output").unwrap_or("po/messages.pot"));
+    let root = output.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = output
+        .file_name()
+        .ok_or_else(|| format!("{} isn't a file name", output.display()))?;
+
+    info!("Writing the messages in the book to {}", output.display());
+    let pot = GettextPreprocessor::extract(&source.book);
+    utils::fs::write_file(root, file_name, pot.as_bytes())
+}
@@ -52,6 +52,7 @@
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::File;
 use std::io::Read;
@@ -64,6 +65,9 @@ use toml_query::read::TomlValueReadExt;
 
 use errors::*;
 
+/// Where the Rust playground runs code.
+const RUST_PLAYGROUND: &str = "https://play.rust-lang.org/evaluate.json";
+
 /// The overall configuration object for MDBook, essentially an in-memory
 /// representation of `book.toml`.
 #[derive(Debug, Clone, PartialEq)]
@@ -77,19 +81,29 @@ pub struct Config {
 
 impl Config {
     /// Load a `Config` from some string.
+    ///
+    /// Values of the wrong type in the tables mdBook knows about are errors
+    /// which point at their line, and keys it doesn't recognise in them are
+    /// warned about, with a suggestion if they look like a typo.
     pub fn from_str(src: &str) -> Result<Config> {
-        toml::from_str(src).chain_err(|| Error::from("Invalid configuration file"))
+        let raw = parse_checked(src)?;
+        if raw.get("extends").is_some() {
+            bail!("Only a configuration file loaded from disk can use `extends`");
+        }
+
+        raw.try_into()
+            .chain_err(|| Error::from("Invalid configuration file"))
     }
 
     /// Load the configuration file from disk.
+    ///
+    /// If it has an `extends` key, the file that names (relative to this one)
+    /// is loaded first, and this file's values are laid over the top of it.
     pub fn from_disk<P: AsRef<Path>>(config_file: P) -> Result<Config> {
-        let mut buffer = String::new();
-        File::open(config_file)
-            .chain_err(|| "Unable to open the configuration file")?
-            .read_to_string(&mut buffer)
-            .chain_err(|| "Couldn't read the file")?;
+        let raw = load_extended(config_file.as_ref(), &mut Vec::new())?;
 
-        Config::from_str(&buffer)
+        raw.try_into()
+            .chain_err(|| Error::from("Invalid configuration file"))
     }
 
     /// Updates the `Config` from the available environment variables.
@@ -138,7 +152,9 @@ impl Config {
             let parsed_value = serde_json::from_str(&value)
                 .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
 
-            self.set(key, parsed_value).expect("unreachable");
+            if let Err(e) = self.set(&key, parsed_value) {
+                warn!("Ignoring the {} environment variable: {}", key, e);
+            }
         }
     }
 
@@ -173,40 +189,77 @@ impl Config {
         self.get_deserialized("output.html").ok()
     }
 
+    /// The languages a multilingual book is written in, from its
+    /// `[language.<code>]` tables, by their codes.
+    pub fn languages(&self) -> Result<BTreeMap<String, LanguageConfig>> {
+        let languages = self
+            .get_deserialized_opt("language")
+            .chain_err(|| "Each language of the book should have a [language.<code>] table")?;
+        Ok(languages.unwrap_or_default())
+    }
+
     /// Convenience function to fetch a value from the config and deserialize it
     /// into some arbitrary type.
+    ///
+    /// Unlike `get()`, this also reaches into the `book` and `build` tables,
+    /// so `get_deserialized::<PathBuf, _>("book.src")` works too.
     pub fn get_deserialized<'de, T: Deserialize<'de>, S: AsRef<str>>(&self, name: S) -> Result<T> {
         let name = name.as_ref();
 
-        if let Some(value) = self.get(name) {
-            value
-                .clone()
-                .try_into()
-                .chain_err(|| "Couldn't deserialize the value")
+        match self.get_deserialized_opt(name)? {
+            Some(value) => Ok(value),
+            None => bail!("Key not found, {:?}", name),
+        }
+    }
+
+    /// Like `get_deserialized()`, except a missing key is `Ok(None)`, so only a
+    /// value of the wrong type is an error.
+    ///
+    /// This is the way for a renderer or preprocessor to read its own table,
+    /// since a typo in `book.toml` is reported instead of quietly replaced by
+    /// the defaults.
+    pub fn get_deserialized_opt<'de, T: Deserialize<'de>, S: AsRef<str>>(
+        &self,
+        name: S,
+    ) -> Result<Option<T>> {
+        let name = name.as_ref();
+
+        let value = if let Some(key) = name.strip_prefix("book.") {
+            read_field(&self.book, key)
+        } else if let Some(key) = name.strip_prefix("build.") {
+            read_field(&self.build, key)
         } else {
-            bail!("Key not found, {:?}", name)
+            self.get(name).cloned()
+        };
+
+        match value {
+            Some(value) => value
+                .try_into()
+                .map(Some)
+                .chain_err(|| format!("Couldn't deserialize the value of {}", name)),
+            None => Ok(None),
         }
     }
 
     /// Set a config key, clobbering any existing values along the way.
     ///
-    /// The only way this can fail is if we can't serialize `value` into a
-    /// `toml::Value`.
+    /// This fails if `value` can't be serialized into a `toml::Value`, or if
+    /// it's the wrong type for one of the `book` and `build` tables' fields.
     pub fn set<S: Serialize, I: AsRef<str>>(&mut self, index: I, value: S) -> Result<()> {
         let index = index.as_ref();
 
         let value =
             Value::try_from(value).chain_err(|| "Unable to represent the item as a JSON Value")?;
 
-        if index.starts_with("book.") {
-            self.book.update_value(&index[5..], value);
-        } else if index.starts_with("build.") {
-            self.build.update_value(&index[6..], value);
+        if let Some(key) = index.strip_prefix("book.") {
+            self.book.update_value(key, value)
+        } else if let Some(key) = index.strip_prefix("build.") {
+            self.build.update_value(key, value)
         } else {
             self.rest.insert(index, value)?;
+            Ok(())
         }
-
-        Ok(())
+        .chain_err(|| format!("Unable to set {}", index))
     }
 
     /// Get the table associated with a particular renderer.
@@ -290,10 +343,13 @@ impl<'de> Deserialize<'de> for Config {
             }
         };
 
-        let book: BookConfig = table
-            .remove("book")
-            .and_then(|value| value.try_into().ok())
-            .unwrap_or_default();
+        let book: BookConfig = match table.remove("book") {
+            Some(value) => value.try_into().map_err(|e| {
+                use serde::de::Error;
+                D::Error::custom(format!("Invalid [book] table: {}", e))
+            })?,
+            None => BookConfig::default(),
+        };
 
         let build: BuildConfig = table
             .remove("build")
@@ -321,7 +377,15 @@ impl Serialize for Config {
             }
         };
 
+        let build_config = match Value::try_from(self.build.clone()) {
+            Ok(cfg) => cfg,
+            Err(_) => {
+                return Err(S::Error::custom("Unable to serialize the BuildConfig"));
+            }
+        };
+
         table.insert("book", book_config).expect("unreachable");
+        table.insert("build", build_config).expect("unreachable");
         table.serialize(s)
     }
 }
@@ -338,6 +402,185 @@ fn parse_env(key: &str) -> Option<String> {
     }
 }
 
+/// The tables of `book.toml` which mdBook knows the shape of, for checking
+/// their values' types.
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct Schema {
+    book: Option<BookConfig>,
+    build: Option<BuildConfig>,
+    output: Option<OutputSchema>,
+    language: Option<BTreeMap<String, LanguageConfig>>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct OutputSchema {
+    html: Option<HtmlConfig>,
+}
+
+/// Parse `src`, making sure the tables mdBook knows about have the right types.
+fn parse_checked(src: &str) -> Result<Value> {
+    let raw: Value = toml::from_str(src).chain_err(|| "Invalid configuration file")?;
+    if let Err(e) = toml::from_str::<Schema>(src) {
+        let message = e.to_string();
+        let line = key_in_error(&message).and_then(|key| line_of_key(src, key));
+        let err = match line {
+            Some(line) => Error::from(format!("{} at line {}", message, line)),
+            None => Error::from(message),
+        };
+        return Err(err).chain_err(|| "Invalid configuration file");
+    }
+    warn_about_unknown_keys(&raw);
+
+    Ok(raw)
+}
+
+/// Load the configuration file at `path` along with the ones it `extends`,
+/// where `chain` is the files which extend this one.
+fn load_extended(path: &Path, chain: &mut Vec<PathBuf>) -> Result<Value> {
+    let mut buffer = String::new();
+    File::open(path)
+        .chain_err(|| "Unable to open the configuration file")?
+        .read_to_string(&mut buffer)
+        .chain_err(|| "Couldn't read the file")?;
+    let mut raw = parse_checked(&buffer)?;
+
+    let base = match raw.as_table_mut().and_then(|table| table.remove("extends")) {
+        Some(Value::String(base)) => base,
+        Some(_) => bail!("`extends` in {} should be the path of a file", path.display()),
+        None => return Ok(raw),
+    };
+    let base = path.parent().unwrap_or_else(|| Path::new(".")).join(base);
+
+    chain.push(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    if base.canonicalize().map(|base| chain.contains(&base)).unwrap_or(false) {
+        bail!("{} ends up extending itself", base.display());
+    }
+    let base_raw = load_extended(&base, chain).chain_err(|| {
+        format!("Couldn't load {}, which {} extends", base.display(), path.display())
+    })?;
+
+    Ok(merge(base_raw, raw))
+}
+
+/// Lay `over` on top of `base`, merging the tables they both have and
+/// otherwise taking `over`'s values.
+fn merge(base: Value, over: Value) -> Value {
+    match (base, over) {
+        (Value::Table(mut base), Value::Table(over)) => {
+            for (key, value) in over {
+                let value = match base.remove(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => value,
+                };
+                base.insert(key, value);
+            }
+            Value::Table(base)
+        }
+        (_, over) => over,
+    }
+}
+
+/// The dotted key a deserialization error is about, like `output.html.theme`.
+fn key_in_error(message: &str) -> Option<&str> {
+    let start = message.find("for key `")? + "for key `".len();
+    let end = message[start..].find('`')?;
+    Some(&message[start..start + end])
+}
+
+/// The (1-based) line of `src` which sets `key`.
+fn line_of_key(src: &str, key: &str) -> Option<usize> {
+    let mut table = String::new();
+    for (i, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            let header = line.trim_start_matches('[').split(']').next().unwrap_or_default();
+            table = header.trim().to_string();
+        } else if let Some(equals) = line.find('=') {
+            let name = line[..equals].trim().trim_matches('"');
+            if table.is_empty() && name == key || format!("{}.{}", table, name) == key {
+                return Some(i + 1);
+            }
+        }
+    }
+    None
+}
+
+/// Warn about the keys of mdBook's own tables which it doesn't know, since
+/// they're most likely typos.
+fn warn_about_unknown_keys(raw: &Value) {
+    let mut tables = vec![
+        ("book".to_string(), known_keys::<BookConfig>()),
+        ("build".to_string(), known_keys::<BuildConfig>()),
+        ("output.html".to_string(), known_keys::<HtmlConfig>()),
+        ("output.html.analytics".to_string(), known_keys::<Analytics>()),
+        ("output.html.fold".to_string(), known_keys::<Fold>()),
+        ("output.html.playpen".to_string(), known_keys::<Playpen>()),
+        ("output.html.playground".to_string(), known_keys::<Playground>()),
+        ("output.html.images".to_string(), known_keys::<Images>()),
+        ("output.html.search".to_string(), known_keys::<Search>()),
+    ];
+    if let Ok(Some(Value::Table(languages))) = raw.read("language") {
+        for code in languages.keys() {
+            tables.push((format!("language.{}", code), known_keys::<LanguageConfig>()));
+        }
+    }
+
+    for (name, known) in tables {
+        let table = match raw.read(&name) {
+            Ok(Some(Value::Table(table))) => table,
+            _ => continue,
+        };
+        for key in table.keys().filter(|key| !known.contains(key)) {
+            match closest_key(key, &known) {
+                Some(suggestion) => warn!(
+                    "Unknown key `{}` in [{}], did you mean `{}`?",
+                    key, name, suggestion
+                ),
+                None => warn!("Unknown key `{}` in [{}], which is ignored", key, name),
+            }
+        }
+    }
+}
+
+/// The keys of one of the config's tables.
+fn known_keys<T: Default + Serialize>() -> Vec<String> {
+    // unlike TOML, JSON keeps the fields which are `None`
+    match serde_json::to_value(T::default()) {
+        Ok(serde_json::Value::Object(fields)) => fields.into_iter().map(|(key, _)| key).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The known key `key` is most likely a typo of, if any.
+fn closest_key<'a>(key: &str, known: &'a [String]) -> Option<&'a str> {
+    let max_distance = ::std::cmp::max(1, key.chars().count() / 3);
+    known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
 fn is_legacy_format(table: &Value) -> bool {
     let legacy_items = [
         "title",
@@ -371,6 +614,14 @@ pub struct BookConfig {
     pub src: PathBuf,
     /// Does this book support more than one language?
     pub multilingual: bool,
+    /// The language the book is written in, like `en`. A multilingual book
+    /// falls back to this language for chapters which haven't been
+    /// translated, and each translation is built with it set to its own
+    /// language.
+    pub language: Option<String>,
+    /// Which way the book's text runs. If `None`, it's right-to-left for
+    /// languages which are written that way, and left-to-right otherwise.
+    pub text_direction: Option<TextDirection>,
 }
 
 impl Default for BookConfig {
@@ -381,6 +632,91 @@ impl Default for BookConfig {
             description: None,
             src: PathBuf::from("src"),
             multilingual: false,
+            language: None,
+            text_direction: None,
+        }
+    }
+}
+
+impl BookConfig {
+    /// The language the book is written in, which is `en` unless it says
+    /// otherwise.
+    pub fn language(&self) -> &str {
+        self.language.as_ref().map_or("en", String::as_str)
+    }
+
+    /// Check the book's metadata makes sense, so renderers can put it
+    /// straight into their output.
+    pub fn validate(&self) -> Result<()> {
+        if self.authors.iter().any(|author| author.trim().is_empty()) {
+            bail!("book.authors has a blank name in it");
+        }
+        if let Some(ref description) = self.description {
+            if description.trim().is_empty() {
+                bail!("book.description is blank, so it should be left out instead");
+            }
+        }
+        if let Some(ref language) = self.language {
+            if !is_language_tag(language) {
+                bail!(
+                    "book.language should be a language tag like \"en\" or \"pt-BR\", not {:?}",
+                    language
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Which way the book's text runs, taking it from the book's language if
+    /// `text-direction` isn't set.
+    pub fn text_direction(&self) -> TextDirection {
+        if let Some(direction) = self.text_direction {
+            return direction;
+        }
+
+        // the primary language subtag, so "ar-EG" counts as Arabic
+        let language = match self.language {
+            Some(ref language) => language.split(&['-', '_'][..]).next().unwrap(),
+            None => return TextDirection::LeftToRight,
+        };
+        match &language.to_lowercase()[..] {
+            "ar" | "dv" | "fa" | "he" | "ku" | "ps" | "sd" | "ug" | "ur" | "yi" => {
+                TextDirection::RightToLeft
+            }
+            _ => TextDirection::LeftToRight,
+        }
+    }
+}
+
+/// Is `tag` shaped like a BCP 47 language tag, such as `en`, `zh-Hant` or
+/// `pt-BR`?
+fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    (2..=8).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Which way a book's text runs.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TextDirection {
+    /// Left to right, like English.
+    #[serde(rename = "ltr")]
+    LeftToRight,
+    /// Right to left, like Arabic and Hebrew.
+    #[serde(rename = "rtl")]
+    RightToLeft,
+}
+
+impl TextDirection {
+    /// The value of the HTML `dir` attribute for this direction.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TextDirection::LeftToRight => "ltr",
+            TextDirection::RightToLeft => "rtl",
         }
     }
 }
@@ -397,6 +733,9 @@ pub struct BuildConfig {
     /// Should the default preprocessors always be used when they are
     /// compatible with the renderer?
     pub use_default_preprocessors: bool,
+    /// Should warnings about the book's content, like broken links and failed
+    /// includes, fail the build?
+    pub strict: bool,
 }
 
 impl Default for BuildConfig {
@@ -405,29 +744,80 @@ impl Default for BuildConfig {
             build_dir: PathBuf::from("book"),
             create_missing: true,
             use_default_preprocessors: true,
+            strict: false,
         }
     }
 }
 
+/// One of a multilingual book's languages, from its `[language.<code>]`
+/// table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LanguageConfig {
+    /// The language's name, as shown in the language picker.
+    pub name: Option<String>,
+    /// The book's title in this language.
+    pub title: Option<String>,
+    /// The book's description in this language.
+    pub description: Option<String>,
+}
+
 /// Configuration for the HTML renderer.
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct HtmlConfig {
     /// The theme directory, if specified.
     pub theme: Option<PathBuf>,
+    /// The color scheme readers see until they pick one themselves. If
+    /// `None`, the "light" scheme is used.
+    pub default_theme: Option<String>,
     /// Use "smart quotes" instead of the usual `"` character.
     pub curly_quotes: bool,
+    /// The language of the theme's own text, like the search bar's
+    /// placeholder and the navigation buttons' tooltips. If `None`, the
+    /// book's language is used.
+    pub language: Option<String>,
+    /// Replacements for the theme's text, by the names of its strings, for
+    /// languages it hasn't been translated into or wording the book prefers.
+    pub ui_strings: BTreeMap<String, String>,
     /// Should mathjax be enabled?
     pub mathjax_support: bool,
-    /// An optional google analytics code.
+    /// Show an "on this page" panel listing the current chapter's headings?
+    pub page_toc: bool,
+    /// An optional google analytics code. This is a shorthand for an
+    /// `analytics` table with the `google` provider.
     pub google_analytics: Option<String>,
+    /// Analytics settings. If `None`, no analytics are added to the pages.
+    pub analytics: Option<Analytics>,
+    /// The page icon, relative to the book's root, instead of the theme's
+    /// `favicon.png`.
+    pub favicon: Option<PathBuf>,
+    /// An image shown at the top of the sidebar, relative to the book's root,
+    /// which links to the start of the book.
+    pub logo: Option<PathBuf>,
     /// Additional CSS stylesheets to include in the rendered page's `<head>`.
     pub additional_css: Vec<PathBuf>,
     /// Additional JS scripts to include at the bottom of the rendered page's
     /// `<body>`.
     pub additional_js: Vec<PathBuf>,
+    /// Files of HTML, relative to the book's root, to add to the end of each
+    /// page's `<head>`.
+    pub additional_head: Vec<PathBuf>,
+    /// Files of HTML, relative to the book's root, to add to the end of each
+    /// page's `<body>`.
+    pub additional_footer: Vec<PathBuf>,
+    /// Additional files and directories, relative to the book's root, which
+    /// are copied into the rendered book as they are.
+    pub additional_resources: Vec<PathBuf>,
+    /// Extra highlight.js languages to load, on top of the ones bundled with
+    /// `highlight.js`. Each is read from `languages/<name>.js` in the theme
+    /// directory.
+    pub additional_languages: Vec<String>,
     /// Playpen settings.
     pub playpen: Playpen,
+    /// The playgrounds which code blocks are sent to when their play button
+    /// is clicked.
+    pub playground: Playground,
     /// This is used as a bit of a workaround for the `mdbook serve` command.
     /// Basically, because you set the websocket port from the command line, the
     /// `mdbook serve` command needs a way to let the HTML renderer know where
@@ -438,8 +828,47 @@ pub struct HtmlConfig {
     pub livereload_url: Option<String>,
     /// Should section labels be rendered?
     pub no_section_label: bool,
+    /// How many levels of chapters get section labels. Deeper chapters are
+    /// left unnumbered, and `0` turns off numbering altogether. If `None`,
+    /// every level is numbered.
+    pub section_numbering_depth: Option<usize>,
     /// Search settings. If `None`, the default will be used.
     pub search: Option<Search>,
+    /// Making smaller versions of the book's images. If `None`, images are
+    /// copied as they are.
+    pub images: Option<Images>,
+    /// Folding of sections in the sidebar.
+    pub fold: Fold,
+    /// A URL for editing a chapter's source, with `{path}` standing in for
+    /// the chapter's path relative to the `src` directory. If set, each
+    /// chapter links to it from the menu bar.
+    pub edit_url_template: Option<String>,
+    /// Show the date and hash of the last commit to change each chapter,
+    /// taken from `git log`.
+    pub git_metadata: bool,
+    /// The path the book is served from, like `/my-book/`, or its full URL.
+    /// The 404 page uses it to find the book's assets whichever URL it's
+    /// shown for, and a full URL also gets the book a `sitemap.xml`. If
+    /// `None`, the book is assumed to be at the root of its site.
+    pub site_url: Option<String>,
+    /// An image shown when a page is shared on social media, either a full
+    /// URL or a path relative to `site_url`.
+    pub social_image: Option<String>,
+    /// Write a `robots.txt` allowing everything (and pointing at the sitemap,
+    /// if there is one), unless the `src` directory has its own.
+    pub robots_txt: bool,
+    /// Pages which have moved, from their old path (relative to the root of
+    /// the book) to where they are now. A page redirecting to the new
+    /// location is written at each old path.
+    pub redirect: BTreeMap<String, String>,
+    /// Where pages are cached between builds, relative to the book's root,
+    /// so chapters which haven't changed aren't rendered again. If `None`,
+    /// every page is rendered on every build.
+    pub cache_dir: Option<PathBuf>,
+    /// Also write each stylesheet and script under a name with a hash of its
+    /// contents in it, and link pages to that, so hosts can have browsers
+    /// cache them indefinitely.
+    pub hash_files: bool,
 }
 
 impl HtmlConfig {
@@ -473,6 +902,90 @@ impl Default for Playpen {
     }
 }
 
+/// Configuration for the online playgrounds which runnable code blocks are
+/// sent to, to be compiled and run.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Playground {
+    /// The URL of the playground for each language's code blocks, besides the
+    /// Rust playground for `rust`. An empty URL turns a language's play
+    /// buttons off.
+    pub languages: BTreeMap<String, String>,
+}
+
+impl Playground {
+    /// The playground for each language which has one.
+    pub fn endpoints(&self) -> BTreeMap<String, String> {
+        let mut endpoints = BTreeMap::new();
+        endpoints.insert(String::from("rust"), String::from(RUST_PLAYGROUND));
+
+        for (language, url) in &self.languages {
+            if url.is_empty() {
+                endpoints.remove(language);
+            } else {
+                endpoints.insert(language.clone(), url.clone());
+            }
+        }
+        endpoints
+    }
+}
+
+/// Configuration for making smaller, modern versions of the PNG and JPEG
+/// images in the book's pages, which browsers choose between.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Images {
+    /// The formats to convert images to, in order of preference. Default:
+    /// `["webp"]`.
+    pub formats: Vec<String>,
+    /// The widths, in pixels, to make each image at. Images are never made
+    /// wider than they are. If empty, images are converted at their own size.
+    pub widths: Vec<u32>,
+    /// The `sizes` attribute telling browsers how wide images are shown, for
+    /// picking between their widths.
+    pub sizes: Option<String>,
+    /// ImageMagick's command. Default: `convert`.
+    pub command: String,
+}
+
+impl Default for Images {
+    fn default() -> Images {
+        Images {
+            formats: vec![String::from("webp")],
+            widths: Vec::new(),
+            sizes: None,
+            command: String::from("convert"),
+        }
+    }
+}
+
+/// Configuration for folding the sections of the sidebar's table of contents.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Fold {
+    /// Can sections be folded? Default: `false`.
+    pub enable: bool,
+    /// How many levels of chapters start out unfolded, with `0` folding every
+    /// section. The chapter being read is always unfolded. Default: `0`.
+    pub level: usize,
+}
+
+/// Configuration of the analytics service added to every page of the HTML
+/// renderer.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Analytics {
+    /// The analytics service, either `google` or `plausible`.
+    pub provider: String,
+    /// The tracking ID, for Google Analytics.
+    pub id: Option<String>,
+    /// The domain the site is registered as, for Plausible.
+    pub domain: Option<String>,
+    /// Where to load Plausible's script from, for self-hosted instances.
+    /// Default: `https://plausible.io/js/plausible.js`.
+    pub script_url: Option<String>,
+}
+
 /// Configuration of the search functionality of the HTML renderer.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
@@ -504,6 +1017,9 @@ pub struct Search {
     /// Copy JavaScript files for the search functionality to the output directory?
     /// Default: `true`.
     pub copy_js: bool,
+    /// Also write the searchable text to `search-export.json`, for other search
+    /// tools to use. Default: `false`.
+    pub export: bool,
 }
 
 impl Default for Search {
@@ -520,6 +1036,7 @@ impl Default for Search {
             expand: true,
             heading_split_level: 3,
             copy_js: true,
+            export: false,
         }
     }
 }
@@ -530,20 +1047,26 @@ impl Default for Search {
 /// This is definitely not the most performant way to do things, which means you
 /// should probably keep it away from tight loops...
 trait Updateable<'de>: Serialize + Deserialize<'de> {
-    fn update_value<S: Serialize>(&mut self, key: &str, value: S) {
+    fn update_value<S: Serialize>(&mut self, key: &str, value: S) -> Result<()> {
         let mut raw = Value::try_from(&self).expect("unreachable");
 
-        {
-            if let Ok(value) = Value::try_from(value) {
-                let _ = raw.insert(key, value);
-            } else {
-                return;
-            }
-        }
+        let value =
+            Value::try_from(value).chain_err(|| "Unable to represent the item as a TOML Value")?;
+        raw.insert(key, value)?;
 
-        if let Ok(updated) = raw.try_into() {
-            *self = updated;
-        }
+        *self = raw
+            .try_into()
+            .chain_err(|| format!("{} can't be set to a value of that type", key))?;
+        Ok(())
+    }
+}
+
+/// Read `key` from one of the config's tables.
+fn read_field<T: Serialize>(table: &T, key: &str) -> Option<Value> {
+    let raw = Value::try_from(table).expect("unreachable");
+    match raw.read(key) {
+        Ok(value) => value.cloned(),
+        Err(_) => None,
     }
 }
 
@@ -552,6 +1075,8 @@ impl<'de, T> Updateable<'de> for T where T: Serialize + Deserialize<'de> {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
+    use tempfile::TempDir;
 
     const COMPLEX_CONFIG: &'static str = r#"
         [book]
@@ -565,12 +1090,14 @@ mod tests {
         build-dir = "outputs"
         create-missing = false
         use-default-preprocessors = true
+        strict = true
 
         [output.html]
         theme = "./themedir"
         curly-quotes = true
         google-analytics = "123456"
         additional-css = ["./foo/bar/baz.css"]
+        section-numbering-depth = 2
 
         [output.html.playpen]
         editable = true
@@ -597,6 +1124,7 @@ mod tests {
             build_dir: PathBuf::from("outputs"),
             create_missing: false,
             use_default_preprocessors: true,
+            strict: true,
         };
         let playpen_should_be = Playpen {
             editable: true,
@@ -608,6 +1136,7 @@ mod tests {
             additional_css: vec![PathBuf::from("./foo/bar/baz.css")],
             theme: Some(PathBuf::from("./themedir")),
             playpen: playpen_should_be,
+            section_numbering_depth: Some(2),
             ..Default::default()
         };
 
@@ -679,10 +1208,14 @@ mod tests {
         [output.html]
         destination = "my-book" # the output files will be generated in `root/my-book` instead of `root/book`
         theme = "my-theme"
+        default-theme = "Navy"
         curly-quotes = true
         google-analytics = "123456"
         additional-css = ["custom.css", "custom2.css"]
         additional-js = ["custom.js"]
+        additional-resources = ["assets/"]
+        additional-languages = ["protobuf"]
+        edit-url-template = "https://github.com/org/repo/edit/master/src/{path}"
         "#;
 
         let book_should_be = BookConfig {
@@ -699,14 +1232,21 @@ mod tests {
             build_dir: PathBuf::from("my-book"),
             create_missing: true,
             use_default_preprocessors: true,
+            strict: false,
         };
 
         let html_should_be = HtmlConfig {
             theme: Some(PathBuf::from("my-theme")),
+            default_theme: Some(String::from("Navy")),
             curly_quotes: true,
             google_analytics: Some(String::from("123456")),
             additional_css: vec![PathBuf::from("custom.css"), PathBuf::from("custom2.css")],
             additional_js: vec![PathBuf::from("custom.js")],
+            additional_resources: vec![PathBuf::from("assets/")],
+            additional_languages: vec![String::from("protobuf")],
+            edit_url_template: Some(String::from(
+                "https://github.com/org/repo/edit/master/src/{path}",
+            )),
             ..Default::default()
         };
 
@@ -729,6 +1269,149 @@ mod tests {
         assert_eq!(got, value);
     }
 
+    #[test]
+    fn typed_values_can_be_read_from_any_table() {
+        let mut cfg = Config::from_str(COMPLEX_CONFIG).unwrap();
+
+        let src: PathBuf = cfg.get_deserialized("book.src").unwrap();
+        assert_eq!(src, PathBuf::from("source"));
+        let build_dir: PathBuf = cfg.get_deserialized("build.build-dir").unwrap();
+        assert_eq!(build_dir, PathBuf::from("outputs"));
+        let theme: Option<PathBuf> = cfg.get_deserialized_opt("output.html.theme").unwrap();
+        assert_eq!(theme, Some(PathBuf::from("./themedir")));
+
+        let missing: Option<String> = cfg.get_deserialized_opt("output.html.missing").unwrap();
+        assert_eq!(missing, None);
+        assert!(cfg.get_deserialized::<String, _>("output.html.missing").is_err());
+        let err = cfg.get_deserialized_opt::<u32, _>("output.html.theme").unwrap_err();
+        assert!(err.to_string().contains("output.html.theme"), "{}", err);
+
+        assert!(cfg.set("book.authors", "Jane Doe").is_err());
+        assert_eq!(cfg.book.authors.len(), 1);
+        cfg.set("build.create-missing", true).unwrap();
+        assert!(cfg.build.create_missing);
+    }
+
+    #[test]
+    fn every_table_survives_serialization() {
+        let mut cfg = Config::from_str(COMPLEX_CONFIG).unwrap();
+        cfg.set("preprocessor.my-preprocessor.level", 3).unwrap();
+
+        let got: Config = serde_json::from_str(&serde_json::to_string(&cfg).unwrap()).unwrap();
+        assert_eq!(got, cfg);
+        assert_eq!(got.build.build_dir, PathBuf::from("outputs"));
+        let level: i64 = got.get_deserialized("preprocessor.my-preprocessor.level").unwrap();
+        assert_eq!(level, 3);
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_point_at_their_line() {
+        let src = "[book]\ntitle = \"Example\"\n\n[output.html]\ncurly-quotes = \"yes\"\n";
+        let err = Config::from_str(src).unwrap_err();
+        let cause = err.iter().skip(1).next().unwrap().to_string();
+        assert!(cause.contains("curly-quotes"), "{}", cause);
+        assert!(cause.contains("line 5"), "{}", cause);
+
+        assert_eq!(line_of_key(src, "book.title"), Some(2));
+        assert_eq!(line_of_key(src, "output.html.theme"), None);
+
+        assert!(Config::from_str("[build]\nbuild-dir = 5\n").is_err());
+        assert!(Config::from_str("[output.html.search]\nenable = 1\n").is_err());
+    }
+
+    #[test]
+    fn typos_of_known_keys_are_spotted() {
+        let html = known_keys::<HtmlConfig>();
+        assert!(html.contains(&String::from("default-theme")));
+        assert!(html.contains(&String::from("site-url")));
+        assert!(known_keys::<BookConfig>().contains(&String::from("title")));
+
+        assert_eq!(closest_key("defualt-theme", &html), Some("default-theme"));
+        assert_eq!(closest_key("default_theme", &html), Some("default-theme"));
+        assert_eq!(closest_key("no-such-thing", &html), None);
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn languages_are_read_from_their_tables() {
+        let src = r#"
+        [book]
+        multilingual = true
+        language = "ja"
+
+        [language.en]
+        name = "English"
+
+        [language.ja]
+        name = "日本語"
+        title = "サンプル"
+        "#;
+
+        let cfg = Config::from_str(src).unwrap();
+        assert_eq!(cfg.book.language, Some(String::from("ja")));
+        let languages = cfg.languages().unwrap();
+        assert_eq!(languages.keys().collect::<Vec<_>>(), vec!["en", "ja"]);
+        assert_eq!(languages["ja"].title, Some(String::from("サンプル")));
+        assert_eq!(languages["en"].title, None);
+
+        assert!(Config::default().languages().unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_text_direction_follows_the_language_unless_set() {
+        let mut book = BookConfig::default();
+        assert_eq!(book.text_direction(), TextDirection::LeftToRight);
+
+        book.language = Some(String::from("ar-EG"));
+        assert_eq!(book.text_direction(), TextDirection::RightToLeft);
+        book.language = Some(String::from("ja"));
+        assert_eq!(book.text_direction(), TextDirection::LeftToRight);
+
+        let src = r#"
+        [book]
+        language = "ja"
+        text-direction = "rtl"
+        "#;
+        let cfg = Config::from_str(src).unwrap();
+        assert_eq!(cfg.book.text_direction, Some(TextDirection::RightToLeft));
+        assert_eq!(cfg.book.text_direction(), TextDirection::RightToLeft);
+    }
+
+    #[test]
+    fn the_books_metadata_is_checked() {
+        let mut book = BookConfig {
+            authors: vec![String::from("Jane Doe")],
+            description: Some(String::from("A book about books")),
+            ..Default::default()
+        };
+        for language in &["en", "pt-BR", "zh-Hant-TW", "sgn-BE-FR"] {
+            book.language = Some(language.to_string());
+            assert!(book.validate().is_ok(), "{}", language);
+        }
+        for language in &["", "english language", "en_US", "e", "pt-"] {
+            book.language = Some(language.to_string());
+            assert!(book.validate().is_err(), "{}", language);
+        }
+
+        let book = BookConfig {
+            authors: vec![String::from("Jane Doe"), String::from(" ")],
+            ..Default::default()
+        };
+        assert!(book.validate().is_err());
+        let book = BookConfig {
+            description: Some(String::new()),
+            ..Default::default()
+        };
+        assert!(book.validate().is_err());
+
+        assert_eq!(BookConfig::default().language(), "en");
+        assert!(Config::from_str("[book]\nauthors = \"Jane Doe\"\n").is_err());
+        assert!(Config::from_str("[book]\ntext-direction = \"up\"\n").is_err());
+    }
+
     #[test]
     fn parse_env_vars() {
         let inputs = vec![
@@ -801,4 +1484,55 @@ mod tests {
 
         assert_eq!(cfg.book.title, Some(should_be));
     }
+
+    #[test]
+    fn a_config_can_extend_a_shared_one() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join("common.toml"),
+            r#"
+            [book]
+            authors = ["The Docs Team"]
+            src = "shared-src"
+
+            [output.html]
+            theme = "../theme"
+            curly-quotes = true
+
+            [output.html.playpen]
+            editable = true
+            "#,
+        )
+        .unwrap();
+        fs::create_dir(temp.path().join("guide")).unwrap();
+        let book_toml = temp.path().join("guide").join("book.toml");
+        fs::write(
+            &book_toml,
+            r#"
+            extends = "../common.toml"
+
+            [book]
+            title = "The Guide"
+            src = "src"
+
+            [output.html]
+            curly-quotes = false
+            "#,
+        )
+        .unwrap();
+
+        let cfg = Config::from_disk(&book_toml).unwrap();
+        assert_eq!(cfg.book.title, Some(String::from("The Guide")));
+        assert_eq!(cfg.book.authors, vec![String::from("The Docs Team")]);
+        assert_eq!(cfg.book.src, PathBuf::from("src"));
+        let html = cfg.html_config().unwrap();
+        assert_eq!(html.theme, Some(PathBuf::from("../theme")));
+        assert!(!html.curly_quotes);
+        assert!(html.playpen.editable);
+        assert!(cfg.get("extends").is_none());
+
+        fs::write(temp.path().join("common.toml"), "extends = \"guide/book.toml\"").unwrap();
+        assert!(Config::from_disk(&book_toml).is_err());
+        assert!(Config::from_str("extends = \"../common.toml\"").is_err());
+    }
 }
@@ -0,0 +1,187 @@
+//! Warnings and errors about a book's content, along with the file and line
+//! they're about.
+//!
+//! Diagnostics are logged as soon as they're emitted, like any other message.
+//! Programs which want to do something else with them, like `mdbook build
+//! --message-format json`, can also call `collect()` before building and
+//! `take_collected()` afterwards, and strict mode (`build.strict`) fails the
+//! build if any are emitted while it runs.
+//!
+//! Line numbers count lines in the chapter as the preprocessor saw it, which
+//! is the same as its source file unless an earlier preprocessor (like
+//! `links`, when it expands an `{{#include}}`) added or removed lines above.
+
+use std::fmt;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use book::Chapter;
+
+lazy_static! {
+    static ref COLLECTED: Mutex<Option<Vec<Diagnostic>>> = Mutex::new(None);
+}
+
+static EMITTED: AtomicUsize = AtomicUsize::new(0);
+
+/// How bad a `Diagnostic` is.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Something looks wrong, but the book can still be built.
+    Warning,
+    /// Something is wrong, and part of the book won't come out as intended.
+    Error,
+}
+
+/// A problem with the book's content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// How bad the problem is.
+    pub severity: Severity,
+    /// What's wrong.
+    pub message: String,
+    /// The file the problem is in, relative to the book's `src` directory.
+    pub file: Option<PathBuf>,
+    /// The line the problem is on, counting from 1.
+    pub line: Option<usize>,
+}
+
+impl Diagnostic {
+    /// Create a warning.
+    pub fn warning<S: Into<String>>(message: S) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, message.into())
+    }
+
+    /// Create an error.
+    pub fn error<S: Into<String>>(message: S) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message.into())
+    }
+
+    fn new(severity: Severity, message: String) -> Diagnostic {
+        Diagnostic {
+            severity,
+            message,
+            file: None,
+            line: None,
+        }
+    }
+
+    /// Say which file the problem is in.
+    pub fn in_file<P: Into<PathBuf>>(mut self, file: P) -> Diagnostic {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Say which line the problem is on.
+    pub fn at_line(mut self, line: usize) -> Diagnostic {
+        self.line = Some(line);
+        self
+    }
+
+    /// Say which line of `content` the problem is on, from the byte `offset`
+    /// it starts at.
+    pub fn at_offset(self, content: &str, offset: usize) -> Diagnostic {
+        self.at_line(line_at(content, offset))
+    }
+
+    /// Log the diagnostic, and keep it if diagnostics are being collected.
+    pub fn emit(self) {
+        match self.severity {
+            Severity::Warning => warn!("{}", self),
+            Severity::Error => error!("{}", self),
+        }
+        EMITTED.fetch_add(1, Ordering::SeqCst);
+
+        if let Some(ref mut collected) = *COLLECTED.lock().unwrap() {
+            collected.push(self);
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            match self.line {
+                Some(line) => write!(f, "{}:{}: ", file.display(), line)?,
+                None => write!(f, "{}: ", file.display())?,
+            }
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Start keeping every diagnostic which is emitted, on top of logging them.
+pub fn collect() {
+    let mut collected = COLLECTED.lock().unwrap();
+    if collected.is_none() {
+        *collected = Some(Vec::new());
+    }
+}
+
+/// Take the diagnostics emitted since `collect()` was called (or since this
+/// was last called), in the order they were emitted.
+pub fn take_collected() -> Vec<Diagnostic> {
+    match *COLLECTED.lock().unwrap() {
+        Some(ref mut collected) => mem::take(collected),
+        None => Vec::new(),
+    }
+}
+
+/// How many diagnostics have been emitted so far. This counts every
+/// diagnostic in the process, so books built at the same time (on different
+/// threads) are counted together.
+pub fn emitted() -> usize {
+    EMITTED.load(Ordering::SeqCst)
+}
+
+/// The line the byte `offset` is on in `content`, counting from 1.
+pub fn line_at(content: &str, offset: usize) -> usize {
+    let end = offset.min(content.len());
+    content.as_bytes()[..end].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+/// The file diagnostics about a chapter should point at, which is the one it
+/// was loaded from. Chapters made in memory might not have one, so they're
+/// named instead.
+pub(crate) fn chapter_file(ch: &Chapter) -> PathBuf {
+    match ch.source_path.as_ref().or(ch.path.as_ref()) {
+        Some(path) => path.clone(),
+        None => Path::new(&ch.name).to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_show_where_they_are() {
+        let diagnostic = Diagnostic::warning("Oops")
+            .in_file("guide/usage.md")
+            .at_offset("# Usage\n\nSome text\n", 12);
+        assert_eq!(diagnostic.line, Some(3));
+        assert_eq!(diagnostic.to_string(), "guide/usage.md:3: Oops");
+        assert_eq!(
+            Diagnostic::error("Oops").in_file("a.md").to_string(),
+            "a.md: Oops"
+        );
+        assert_eq!(Diagnostic::error("Oops").to_string(), "Oops");
+    }
+
+    #[test]
+    fn emitted_diagnostics_are_collected() {
+        collect();
+        Diagnostic::warning("A collected warning").emit();
+
+        // other tests might be emitting diagnostics at the same time
+        let collected = take_collected();
+        assert!(collected
+            .iter()
+            .any(|d| d.message == "A collected warning" && d.severity == Severity::Warning));
+        assert!(take_collected()
+            .iter()
+            .all(|d| d.message != "A collected warning"));
+    }
+}
@@ -57,6 +57,27 @@
 //! md.build().expect("Building failed");
 //! ```
 //!
+//! Or put a book together in memory and build that, without needing a
+//! `SUMMARY.md` or any chapters on disk.
+//!
+//! ```rust,no_run
+//! use mdbook::book::{Book, Chapter};
+//! use mdbook::config::Config;
+//! use mdbook::MDBook;
+//!
+//! let mut book = Book::new();
+//! book.push_item(Chapter::new(
+//!     "Introduction",
+//!     String::from("# Introduction\n\nHello, World!"),
+//!     "intro.md",
+//!     Vec::new(),
+//! ));
+//!
+//! let md = MDBook::from_book("/path/to/book/root", Config::default(), book)
+//!     .expect("Unable to create the book");
+//! md.build().expect("Building failed");
+//! ```
+//!
 //! ## Implementing a new Backend
 //!
 //! `mdbook` has a fairly flexible mechanism for creating additional backends
@@ -82,15 +103,20 @@
 
 #![deny(missing_docs)]
 
+extern crate chrono;
+extern crate crossbeam_utils;
 #[macro_use]
 extern crate error_chain;
-extern crate handlebars;
+/// The Handlebars crate used by the HTML renderer, re-exported for writing
+/// helpers to register with `HtmlHandlebars::with_helper`.
+pub extern crate handlebars;
 extern crate itertools;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
 extern crate memchr;
+extern crate num_cpus;
 extern crate pulldown_cmark;
 extern crate regex;
 extern crate serde;
@@ -98,6 +124,7 @@ extern crate serde;
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
+extern crate sha1;
 extern crate shlex;
 extern crate tempfile;
 extern crate toml;
@@ -109,6 +136,7 @@ extern crate pretty_assertions;
 
 pub mod book;
 pub mod config;
+pub mod diagnostics;
 pub mod preprocess;
 pub mod renderer;
 pub mod theme;
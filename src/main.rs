@@ -7,12 +7,16 @@ extern crate error_chain;
 extern crate log;
 extern crate mdbook;
 extern crate open;
+extern crate serde_json;
 
 use chrono::Local;
-use clap::{App, AppSettings, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches};
 use env_logger::Builder;
 use log::LevelFilter;
+use mdbook::config::Config;
+use mdbook::errors::Result;
 use mdbook::utils;
+use mdbook::MDBook;
 use std::env;
 use std::ffi::OsStr;
 use std::io::Write;
@@ -24,15 +28,57 @@ const NAME: &'static str = "mdBook";
 const VERSION: &'static str = concat!("v", crate_version!());
 
 fn main() {
-    init_logger();
+    let matches = create_clap_app().get_matches();
+    init_logger(&matches);
 
-    // Create a list of valid arguments and sub-commands
+    // Check which subcomamnd the user ran...
+    let res = match matches.subcommand() {
+        ("init", Some(sub_matches)) => cmd::init::execute(sub_matches),
+        ("build", Some(sub_matches)) => cmd::build::execute(sub_matches),
+        ("clean", Some(sub_matches)) => cmd::clean::execute(sub_matches),
+        ("dump-context", Some(sub_matches)) => cmd::dump_context::execute(sub_matches),
+        #[cfg(feature = "watch")]
+        ("watch", Some(sub_matches)) => cmd::watch::execute(sub_matches),
+        #[cfg(feature = "serve")]
+        ("serve", Some(sub_matches)) => cmd::serve::execute(sub_matches),
+        ("test", Some(sub_matches)) => cmd::test::execute(sub_matches),
+        ("completions", Some(sub_matches)) => cmd::completions::execute(sub_matches),
+        ("xgettext", Some(sub_matches)) => cmd::xgettext::execute(sub_matches),
+        (_, _) => unreachable!(),
+    };
+
+    if let Err(e) = res {
+        utils::log_backtrace(&e);
+
+        ::std::process::exit(101);
+    }
+}
+
+/// Create a list of valid arguments and sub-commands
+fn create_clap_app<'a, 'b>() -> App<'a, 'b> {
     let app = App::new(NAME)
         .about("Creates a book from markdown files")
         .author("Mathieu David <mathieudavid@mathieudavid.org>")
         .version(VERSION)
         .setting(AppSettings::GlobalVersion)
         .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .global(true)
+                .help("Logs more about what's going on (-vv for even more)"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .multiple(true)
+                .global(true)
+                .conflicts_with("verbose")
+                .help("Only logs warnings and errors (-qq for only errors)"),
+        )
         .after_help(
             "For more information about a specific command, try `mdbook <command> --help`\n\
              The source code for mdBook is available at: https://github.com/rust-lang-nursery/mdBook",
@@ -40,34 +86,23 @@ fn main() {
         .subcommand(cmd::init::make_subcommand())
         .subcommand(cmd::build::make_subcommand())
         .subcommand(cmd::test::make_subcommand())
-        .subcommand(cmd::clean::make_subcommand());
+        .subcommand(cmd::clean::make_subcommand())
+        .subcommand(cmd::dump_context::make_subcommand())
+        .subcommand(cmd::completions::make_subcommand())
+        .subcommand(cmd::xgettext::make_subcommand());
 
     #[cfg(feature = "watch")]
     let app = app.subcommand(cmd::watch::make_subcommand());
     #[cfg(feature = "serve")]
     let app = app.subcommand(cmd::serve::make_subcommand());
 
-    // Check which subcomamnd the user ran...
-    let res = match app.get_matches().subcommand() {
-        ("init", Some(sub_matches)) => cmd::init::execute(sub_matches),
-        ("build", Some(sub_matches)) => cmd::build::execute(sub_matches),
-        ("clean", Some(sub_matches)) => cmd::clean::execute(sub_matches),
-        #[cfg(feature = "watch")]
-        ("watch", Some(sub_matches)) => cmd::watch::execute(sub_matches),
-        #[cfg(feature = "serve")]
-        ("serve", Some(sub_matches)) => cmd::serve::execute(sub_matches),
-        ("test", Some(sub_matches)) => cmd::test::execute(sub_matches),
-        (_, _) => unreachable!(),
-    };
-
-    if let Err(e) = res {
-        utils::log_backtrace(&e);
-
-        ::std::process::exit(101);
-    }
+    app
 }
 
-fn init_logger() {
+/// Log at the level picked with `-v` or `-q`, or `info` by default. `RUST_LOG`
+/// can change that further, e.g. `RUST_LOG=mdbook::preprocess::scala_eval=debug`
+/// to see more from just one preprocessor.
+fn init_logger(matches: &ArgMatches) {
     let mut builder = Builder::new();
 
     builder.format(|formatter, record| {
@@ -81,13 +116,20 @@ fn init_logger() {
         )
     });
 
+    let level = match (matches.occurrences_of("verbose"), matches.occurrences_of("quiet")) {
+        (0, 0) => LevelFilter::Info,
+        (0, 1) => LevelFilter::Warn,
+        (0, _) => LevelFilter::Error,
+        (1, _) => LevelFilter::Debug,
+        (_, _) => LevelFilter::Trace,
+    };
+    builder.filter(None, level);
+    // Filter extraneous html5ever not-implemented messages
+    builder.filter(Some("html5ever"), LevelFilter::Error);
+
+    // the last matching directive wins, so these take precedence
     if let Ok(var) = env::var("RUST_LOG") {
         builder.parse(&var);
-    } else {
-        // if no RUST_LOG provided, default to logging at the Info level
-        builder.filter(None, LevelFilter::Info);
-        // Filter extraneous html5ever not-implemented messages
-        builder.filter(Some("html5ever"), LevelFilter::Error);
     }
 
     builder.init();
@@ -107,8 +149,81 @@ fn get_book_dir(args: &ArgMatches) -> PathBuf {
     }
 }
 
+/// The `--set key=value` option, for overriding items in `book.toml`.
+fn set_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("set")
+        .long("set")
+        .value_name("key=value")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help(
+            "Overrides an item in book.toml, like output.html.site-url=/docs/{n}\
+             (The value is parsed as JSON, or else used as a string)",
+        )
+}
+
+/// Split a `--set` option into the config item and its value, which is parsed
+/// the same way as environment variable overrides are.
+fn parse_setting(setting: &str) -> Result<(&str, serde_json::Value)> {
+    let mut parts = setting.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) if !key.trim().is_empty() => {
+            let value = serde_json::from_str(value)
+                .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+            Ok((key.trim(), value))
+        }
+        _ => Err(format!("Expected key=value, but got \"{}\"", setting).into()),
+    }
+}
+
+/// The book's config, with any `--set` options (then the `--dest-dir` and
+/// `--source-dir` flags) taking precedence over `book.toml`, for the
+/// subcommands which have them.
+fn book_config(args: &ArgMatches) -> Result<Config> {
+    let mut config = MDBook::load_config(get_book_dir(args))?;
+
+    for setting in args.values_of("set").into_iter().flatten() {
+        let (key, value) = parse_setting(setting)?;
+        config.set(key, value)?;
+    }
+
+    if let Some(dest_dir) = args.value_of("dest-dir") {
+        config.build.build_dir = dest_dir.into();
+    }
+    if let Some(source_dir) = args.value_of("source-dir") {
+        config.book.src = source_dir.into();
+    }
+
+    Ok(config)
+}
+
+/// Load the book, using `book_config()`.
+fn load_book(args: &ArgMatches) -> Result<MDBook> {
+    MDBook::load_with_config(get_book_dir(args), book_config(args)?)
+}
+
 fn open<P: AsRef<OsStr>>(path: P) {
     if let Err(e) = open::that(path) {
         error!("Error opening web browser: {}", e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_are_parsed_like_environment_variables() {
+        let (key, value) = parse_setting("output.html.git-metadata=true").unwrap();
+        assert_eq!(key, "output.html.git-metadata");
+        assert_eq!(value, serde_json::Value::Bool(true));
+
+        let (key, value) = parse_setting("book.title=A = B").unwrap();
+        assert_eq!(key, "book.title");
+        assert_eq!(value, serde_json::Value::from("A = B"));
+
+        assert!(parse_setting("book.title").is_err());
+        assert!(parse_setting("=value").is_err());
+    }
+}
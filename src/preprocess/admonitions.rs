@@ -0,0 +1,258 @@
+use regex::Regex;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use utils::{escape_html, lines_outside_code};
+
+/// The kinds of admonition which can be written as a block quote starting
+/// with e.g. `**Note:**`.
+const QUOTE_KINDS: &[&str] = &[
+    "note",
+    "tip",
+    "info",
+    "important",
+    "warning",
+    "caution",
+    "danger",
+];
+
+/// A preprocessor for turning admonitions into styled callout boxes.
+///
+/// An admonition is either a block quote starting with something like
+/// `**Note:**` or `**Warning:**`, or a `!!! kind "Optional title"` line
+/// followed by an indented body. Either way it becomes a
+/// `<div class="admonition kind">`, which the default theme gives a coloured
+/// border and an icon.
+pub struct AdmonitionsPreprocessor;
+
+impl AdmonitionsPreprocessor {
+    pub(crate) const NAME: &'static str = "admonitions";
+
+    /// Create a new `AdmonitionsPreprocessor`.
+    pub fn new() -> Self {
+        AdmonitionsPreprocessor
+    }
+}
+
+impl Preprocessor for AdmonitionsPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, _ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                ch.content = render_admonitions(&ch.content);
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+fn render_admonitions(content: &str) -> String {
+    let mut lines = Vec::new();
+    let mut input = lines_outside_code(content).into_iter().peekable();
+
+    while let Some((line, outside_code)) = input.next() {
+        if !outside_code {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        if let Some((kind, title)) = parse_bang_header(line) {
+            let mut body = Vec::new();
+            while let Some(&(next, _)) = input.peek() {
+                if next.trim().is_empty() {
+                    body.push("");
+                } else if let Some(stripped) = strip_indent(next) {
+                    body.push(stripped);
+                } else {
+                    break;
+                }
+                input.next();
+            }
+
+            // blank lines between the admonition and whatever comes next
+            // belong to neither
+            let mut trailing_blank_lines = 0;
+            while body.last() == Some(&"") {
+                body.pop();
+                trailing_blank_lines += 1;
+            }
+
+            lines.push(render(&kind, title.as_ref().map(|t| t.as_str()), &body));
+            lines.extend((0..trailing_blank_lines).map(|_| String::new()));
+            continue;
+        }
+
+        if let Some((kind, title, first_line)) = parse_quote_header(line) {
+            let mut body = vec![first_line];
+            while let Some(stripped) = input.peek().and_then(|&(next, _)| strip_quote(next)) {
+                body.push(stripped);
+                input.next();
+            }
+
+            lines.push(render(&kind, Some(title), &body));
+            continue;
+        }
+
+        lines.push(line.to_string());
+    }
+
+    lines.join("\n")
+}
+
+/// Parse a `!!! kind "Optional title"` line, returning the kind and the title.
+fn parse_bang_header(line: &str) -> Option<(String, Option<String>)> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r#"^!!!\s+([a-zA-Z][a-zA-Z0-9_\-]*)(?:\s+"([^"]*)")?\s*$"#).unwrap();
+    }
+
+    RE.captures(line).map(|caps| {
+        let kind = caps[1].to_lowercase();
+        let title = match caps.get(2) {
+            // an explicitly empty title means the admonition doesn't have one
+            Some(title) if title.as_str().is_empty() => None,
+            Some(title) => Some(title.as_str().to_string()),
+            None => Some(capitalize(&kind)),
+        };
+
+        (kind, title)
+    })
+}
+
+/// Parse the first line of a `> **Note:** ...` block quote, returning the
+/// kind, the title as it was written and the rest of the line.
+fn parse_quote_header(line: &str) -> Option<(String, &str, &str)> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r"^\s{0,3}>\s?\*\*([a-zA-Z]+)(?::\*\*|\*\*:)\s*(.*)$").unwrap();
+    }
+
+    let caps = RE.captures(line)?;
+    let title = caps.get(1).unwrap().as_str();
+    let kind = title.to_lowercase();
+
+    if QUOTE_KINDS.contains(&kind.as_str()) {
+        Some((kind, title, caps.get(2).unwrap().as_str()))
+    } else {
+        None
+    }
+}
+
+fn strip_indent(line: &str) -> Option<&str> {
+    if line.starts_with("    ") {
+        Some(&line[4..])
+    } else if line.starts_with('\t') {
+        Some(&line[1..])
+    } else {
+        None
+    }
+}
+
+/// Remove one level of block quoting from a line.
+fn strip_quote(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    if !trimmed.starts_with('>') || line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let rest = &trimmed[1..];
+    Some(if rest.starts_with(' ') { &rest[1..] } else { rest })
+}
+
+fn render(kind: &str, title: Option<&str>, body: &[&str]) -> String {
+    let mut html = format!("<div class=\"admonition {}\">\n", kind);
+
+    if let Some(title) = title {
+        html.push_str(&format!("<p class=\"admonition-title\">{}</p>\n", escape_html(title)));
+    }
+
+    // the blank lines let the body be parsed as markdown instead of HTML
+    let body = render_admonitions(&body.join("\n"));
+    if !body.trim().is_empty() {
+        html.push('\n');
+        html.push_str(&body);
+        html.push_str("\n\n");
+    }
+
+    html.push_str("</div>");
+    html
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_quote_admonitions() {
+        let src = "Text\n\n> **Warning:** Mind\n> the *gap*.\n\nMore";
+        let should_be = "Text\n\n<div class=\"admonition warning\">\n\
+                         <p class=\"admonition-title\">Warning</p>\n\n\
+                         Mind\nthe *gap*.\n\n</div>\n\nMore";
+
+        assert_eq!(render_admonitions(src), should_be);
+    }
+
+    #[test]
+    fn ordinary_block_quotes_are_left_alone() {
+        let src = "> **Bob:** Hello\n\n> Just a quote";
+
+        assert_eq!(render_admonitions(src), src);
+    }
+
+    #[test]
+    fn bang_admonitions() {
+        let src = "!!! tip \"Did you know?\"\n    First\n\n    ```\n    code\n    ```\n\nAfter";
+        let should_be = "<div class=\"admonition tip\">\n\
+                         <p class=\"admonition-title\">Did you know?</p>\n\n\
+                         First\n\n```\ncode\n```\n\n</div>\n\nAfter";
+
+        assert_eq!(render_admonitions(src), should_be);
+    }
+
+    #[test]
+    fn bang_admonition_titles() {
+        assert_eq!(
+            parse_bang_header("!!! danger"),
+            Some(("danger".to_string(), Some("Danger".to_string())))
+        );
+        assert_eq!(
+            parse_bang_header("!!! note \"\""),
+            Some(("note".to_string(), None))
+        );
+        assert_eq!(parse_bang_header("!!!not-an-admonition"), None);
+    }
+
+    #[test]
+    fn admonitions_in_code_blocks_are_left_alone() {
+        let src = "```\n> **Note:** Hi\n!!! note\n```";
+
+        assert_eq!(render_admonitions(src), src);
+    }
+
+    #[test]
+    fn admonitions_are_rendered_as_markdown() {
+        let src = render_admonitions("> **Note:** Some *text*");
+
+        let got = ::utils::render_markdown(&src, false);
+
+        assert_eq!(
+            got,
+            "<div class=\"admonition note\">\n<p class=\"admonition-title\">Note</p>\n\
+             <p>Some <em>text</em></p>\n</div>"
+        );
+    }
+}
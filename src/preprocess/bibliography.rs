@@ -0,0 +1,792 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use regex::{Captures, Regex};
+use serde_json::{self, Value};
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem, Chapter};
+use diagnostics::{chapter_file, Diagnostic};
+use utils::fs::{file_to_string, normalize_path, path_to_root};
+use utils::replace_outside_code;
+
+const DEFAULT_CHAPTER: &str = "bibliography.md";
+const DEFAULT_TITLE: &str = "Bibliography";
+
+/// A preprocessor which resolves `[@key]` citations against a BibTeX or
+/// CSL-JSON file, and lists the works cited in a bibliography.
+///
+/// A citation becomes an author-date reference like "(Knuth 1984)", linking
+/// to the work's entry in the bibliography. Several works can be cited at
+/// once, separated by semicolons, and each can have a locator after a comma,
+/// as in `[@knuth84, p. 97; @lamport94]`.
+///
+/// The bibliography is added to the end of the chapter at `chapter`
+/// (`bibliography.md` unless configured otherwise), or to a new chapter at
+/// the end of the book if there's no such chapter in `SUMMARY.md`.
+pub struct BibliographyPreprocessor;
+
+impl BibliographyPreprocessor {
+    pub(crate) const NAME: &'static str = "bibliography";
+
+    /// Create a new `BibliographyPreprocessor`.
+    pub fn new() -> Self {
+        BibliographyPreprocessor
+    }
+}
+
+impl Preprocessor for BibliographyPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let table = ctx.config.get_preprocessor(Self::NAME);
+        let get = |key: &str| table.and_then(|t| t.get(key)).and_then(|v| v.as_str());
+        let file = match get("file") {
+            Some(file) => ctx.root.join(file),
+            None => bail!("The bibliography preprocessor needs a `file` to read references from"),
+        };
+        let chapter_path = PathBuf::from(get("chapter").unwrap_or(DEFAULT_CHAPTER));
+        let title = get("title").unwrap_or(DEFAULT_TITLE).to_string();
+
+        let references = load_references(&file)
+            .chain_err(|| format!("Unable to read the bibliography {}", file.display()))?;
+        let bibliography = normalize_path(&chapter_path.display().to_string());
+        let mut cited = HashSet::new();
+        let mut chapter_exists = false;
+
+        book.for_each_mut(|item: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                if let Some(path) = ch.path.clone() {
+                    chapter_exists |= path == chapter_path;
+                    let citations = Citations {
+                        references: &references,
+                        bibliography: format!("{}{}", path_to_root(&path), bibliography),
+                    };
+                    ch.content = citations.cite(&ch.content, &chapter_file(ch), &mut cited);
+                }
+            }
+        });
+
+        if cited.is_empty() {
+            warn!("Nothing in the book cites the bibliography");
+            return Ok(book);
+        }
+        let list = render_bibliography(&references, &cited);
+
+        if chapter_exists {
+            book.for_each_mut(|item: &mut BookItem| {
+                if let BookItem::Chapter(ref mut ch) = *item {
+                    if ch.path.as_ref() == Some(&chapter_path) {
+                        if !ch.content.trim().is_empty() {
+                            ch.content.push_str("\n\n");
+                        }
+                        ch.content.push_str(&list);
+                    }
+                }
+            });
+        } else {
+            let content = format!("# {}\n\n{}", title, list);
+            book.push_item(Chapter::new(&title, content, chapter_path, Vec::new()));
+        }
+
+        Ok(book)
+    }
+}
+
+/// A work which can be cited.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Reference {
+    key: String,
+    authors: Vec<Name>,
+    year: Option<String>,
+    title: Option<String>,
+    /// The journal, book or proceedings it was published in.
+    container: Option<String>,
+    volume: Option<String>,
+    issue: Option<String>,
+    pages: Option<String>,
+    publisher: Option<String>,
+    doi: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Name {
+    family: String,
+    given: Option<String>,
+}
+
+impl Reference {
+    /// How the work is referred to in a citation, like "Knuth 1984".
+    fn label(&self) -> String {
+        let authors = match self.authors.len() {
+            0 => self.title.clone().unwrap_or_else(|| self.key.clone()),
+            1 => self.authors[0].family.clone(),
+            2 => format!("{} and {}", self.authors[0].family, self.authors[1].family),
+            _ => format!("{} et al.", self.authors[0].family),
+        };
+
+        match self.year {
+            Some(ref year) => format!("{} {}", authors, year),
+            None => authors,
+        }
+    }
+
+    /// The work's entry in the bibliography, in an author-date style.
+    fn render(&self) -> String {
+        let mut sentences = Vec::new();
+
+        let names: Vec<_> = self
+            .authors
+            .iter()
+            .enumerate()
+            .map(|(i, name)| match name.given {
+                // only the first author is listed surname first
+                Some(ref given) if i == 0 => format!("{}, {}", name.family, given),
+                Some(ref given) => format!("{} {}", given, name.family),
+                None => name.family.clone(),
+            }).collect();
+        match names.len() {
+            0 => {}
+            1 => sentences.push(sentence(&names[0])),
+            2 => sentences.push(sentence(&names.join(" and "))),
+            n => sentences.push(sentence(&format!(
+                "{}, and {}",
+                names[..n - 1].join(", "),
+                names[n - 1]
+            ))),
+        }
+        if let Some(ref year) = self.year {
+            sentences.push(sentence(year));
+        }
+
+        // the title of an article goes in quotes, and that of a book in italics
+        match (self.title.as_ref(), self.container.is_some()) {
+            (Some(title), true) => sentences.push(format!("\"{}\"", sentence(title))),
+            (Some(title), false) => sentences.push(emphasised_sentence(title)),
+            _ => {}
+        }
+        if let Some(ref container) = self.container {
+            let mut source = format!("*{}*", container.trim());
+            if let Some(ref volume) = self.volume {
+                source.push_str(&format!(" {}", volume));
+            }
+            if let Some(ref issue) = self.issue {
+                source.push_str(&format!(" ({})", issue));
+            }
+            if let Some(ref pages) = self.pages {
+                source.push_str(&format!(": {}", pages));
+            }
+            sentences.push(sentence(&source));
+        }
+
+        if let Some(ref publisher) = self.publisher {
+            sentences.push(sentence(publisher));
+        }
+        if let Some(ref doi) = self.doi {
+            sentences.push(format!("<https://doi.org/{}>", doi));
+        } else if let Some(ref url) = self.url {
+            sentences.push(format!("<{}>", url));
+        }
+
+        sentences.join(" ")
+    }
+}
+
+/// Something ending in a full stop, unless it already ends in punctuation.
+fn sentence(text: &str) -> String {
+    let text = text.trim();
+    if text.ends_with(&['.', '?', '!'][..]) {
+        text.to_string()
+    } else {
+        format!("{}.", text)
+    }
+}
+
+/// `text` in italics, with a full stop after it.
+fn emphasised_sentence(text: &str) -> String {
+    let text = text.trim();
+    if text.ends_with(&['.', '?', '!'][..]) {
+        format!("*{}*", text)
+    } else {
+        format!("*{}*.", text)
+    }
+}
+
+fn load_references(path: &Path) -> Result<HashMap<String, Reference>> {
+    let src = file_to_string(path)?;
+    let references = match path.extension().and_then(|e| e.to_str()) {
+        Some("bib") => parse_bibtex(&src)?,
+        Some("json") => parse_csl_json(&src)?,
+        _ => bail!("A bibliography has to be a BibTeX (`.bib`) or CSL-JSON (`.json`) file"),
+    };
+
+    Ok(references
+        .into_iter()
+        .map(|reference| (reference.key.clone(), reference))
+        .collect())
+}
+
+/// Read the entries of a BibTeX file. `@comment`, `@preamble` and `@string`
+/// entries are skipped, so `@string` abbreviations aren't expanded.
+fn parse_bibtex(src: &str) -> Result<Vec<Reference>> {
+    let mut parser = BibtexParser {
+        chars: src.chars().collect(),
+        pos: 0,
+    };
+    let mut references = Vec::new();
+
+    while let Some(kind) = parser.next_entry() {
+        let kind = kind.to_lowercase();
+        if kind == "comment" || kind == "preamble" || kind == "string" {
+            parser.skip_group()?;
+            continue;
+        }
+
+        let (key, fields) = parser
+            .entry()
+            .chain_err(|| format!("Unable to read the @{} entry", kind))?;
+        references.push(bibtex_reference(key, &fields));
+    }
+
+    Ok(references)
+}
+
+struct BibtexParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl BibtexParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => bail!("Expected \"{}\" but found \"{}\"", expected, c),
+            None => bail!("Expected \"{}\" but the file ended", expected),
+        }
+    }
+
+    /// Move to the next `@type{` or `@type(`, returning the entry type.
+    /// Anything before it, like an e-mail address in a comment, is skipped.
+    fn next_entry(&mut self) -> Option<String> {
+        loop {
+            while self.peek()? != '@' {
+                self.pos += 1;
+            }
+            self.pos += 1;
+
+            let start = self.pos;
+            while matches!(self.peek(), Some(c) if c.is_alphanumeric() || c == '_') {
+                self.pos += 1;
+            }
+            let kind: String = self.chars[start..self.pos].iter().collect();
+            self.skip_whitespace();
+            if !kind.is_empty() && matches!(self.peek(), Some('{') | Some('(')) {
+                return Some(kind);
+            }
+        }
+    }
+
+    /// The letters, digits and punctuation up to the next space or delimiter.
+    fn word(&mut self) -> String {
+        self.skip_whitespace();
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || "{}(),=#\"".contains(c) {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn skip_group(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        let close = match self.peek() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => return Ok(()),
+        };
+        self.pos += 1;
+        self.braced(close).map(|_| ())
+    }
+
+    /// Everything up to `close`, keeping any nested braces.
+    fn braced(&mut self, close: char) -> Result<String> {
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Some(c) if c == close && depth == 0 => break,
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                Some(_) => {}
+                None => bail!("An entry isn't closed"),
+            }
+            self.pos += 1;
+        }
+        let text = self.chars[start..self.pos].iter().collect();
+        self.pos += 1;
+        Ok(text)
+    }
+
+    /// An entry's key and fields, with field names lowercased.
+    fn entry(&mut self) -> Result<(String, HashMap<String, String>)> {
+        self.skip_whitespace();
+        let close = match self.peek() {
+            Some('(') => ')',
+            _ => '}',
+        };
+        self.expect(if close == ')' { '(' } else { '{' })?;
+        let key = self.word();
+        let mut fields = HashMap::new();
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    break;
+                }
+                None => bail!("The entry for \"{}\" isn't closed", key),
+                _ => {
+                    let name = self.word().to_lowercase();
+                    self.expect('=')
+                        .chain_err(|| format!("Unable to read \"{}\"'s {}", key, name))?;
+                    let value = self.value()?;
+                    fields.insert(name, value);
+                }
+            }
+        }
+
+        Ok((key, fields))
+    }
+
+    /// A field's value, joining the parts of one concatenated with `#`.
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    value.push_str(&self.braced('}')?);
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    value.push_str(&self.braced('"')?);
+                }
+                _ => value.push_str(&self.word()),
+            }
+
+            self.skip_whitespace();
+            if self.peek() != Some('#') {
+                return Ok(value);
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+fn bibtex_reference(key: String, fields: &HashMap<String, String>) -> Reference {
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .filter_map(|name| fields.get(*name))
+            .next()
+            .map(|value| clean_latex(value))
+    };
+    let authors = fields
+        .get("author")
+        .or_else(|| fields.get("editor"))
+        .map_or_else(Vec::new, |names| split_names(names));
+    let year = field(&["year"]).or_else(|| field(&["date"]).map(|d| d.chars().take(4).collect()));
+
+    Reference {
+        key,
+        authors,
+        year,
+        title: field(&["title"]),
+        container: field(&["journal", "journaltitle", "booktitle"]),
+        volume: field(&["volume"]),
+        issue: field(&["number", "issue"]),
+        pages: field(&["pages"]).map(|pages| pages.replace('-', "–")),
+        publisher: field(&["publisher", "institution", "school", "organization"]),
+        doi: field(&["doi"]),
+        url: field(&["url"]),
+    }
+}
+
+/// Split a BibTeX list of names on the `and`s which aren't in braces.
+fn split_names(names: &str) -> Vec<Name> {
+    let mut split = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in names.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ if depth == 0 && names[i..].starts_with(" and ") => {
+                split.push(&names[start..i]);
+                start = i + " and ".len();
+            }
+            _ => {}
+        }
+    }
+    split.push(&names[start..]);
+
+    split
+        .into_iter()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(parse_name)
+        .collect()
+}
+
+/// A name written as "First Last" or "Last, First". A name in braces, like
+/// `{World Health Organization}`, is kept whole.
+fn parse_name(name: &str) -> Name {
+    if name.starts_with('{') && name.ends_with('}') {
+        return Name {
+            family: clean_latex(name),
+            given: None,
+        };
+    }
+
+    let parts: Vec<_> = name.split(',').map(str::trim).collect();
+    if parts.len() > 1 {
+        // "Last, Jr, First" keeps its first and last parts
+        return Name {
+            family: clean_latex(parts[0]),
+            given: Some(clean_latex(parts[parts.len() - 1])).filter(|g| !g.is_empty()),
+        };
+    }
+
+    let mut words: Vec<_> = name.split_whitespace().collect();
+    let family = words.pop().map(clean_latex).unwrap_or_default();
+    let given = if words.is_empty() {
+        None
+    } else {
+        Some(clean_latex(&words.join(" ")))
+    };
+    Name { family, given }
+}
+
+/// Turn the LaTeX in a BibTeX value into plain text: accents are combined
+/// with their letters, escaped characters are unescaped, other commands lose
+/// their backslash, dashes are made, and braces are dropped.
+fn clean_latex(value: &str) -> String {
+    lazy_static! {
+        static ref ACCENT: Regex = Regex::new(r#"\\(["'`^~])\{?([A-Za-z])\}?"#).unwrap();
+        static ref ESCAPE: Regex = Regex::new(r"\\([&%$#_])").unwrap();
+        static ref COMMAND: Regex = Regex::new(r"\\([A-Za-z]+)").unwrap();
+        static ref SPACE: Regex = Regex::new(r"\s+").unwrap();
+    }
+
+    let value = ACCENT.replace_all(value, |caps: &Captures| {
+        let accent = match &caps[1] {
+            "\"" => '\u{308}',
+            "'" => '\u{301}',
+            "`" => '\u{300}',
+            "^" => '\u{302}',
+            _ => '\u{303}',
+        };
+        format!("{}{}", &caps[2], accent)
+    });
+    let value = ESCAPE.replace_all(&value, "$1");
+    let value = COMMAND.replace_all(&value, "$1");
+    let value = value
+        .replace("---", "—")
+        .replace("--", "–")
+        .replace('~', "\u{a0}")
+        .replace(&['{', '}'][..], "");
+
+    SPACE.replace_all(value.trim(), " ").into_owned()
+}
+
+/// Read the items of a CSL-JSON file.
+fn parse_csl_json(src: &str) -> Result<Vec<Reference>> {
+    let items: Vec<Value> =
+        serde_json::from_str(src).chain_err(|| "A CSL-JSON file has to be an array of items")?;
+    let mut references = Vec::new();
+
+    for item in &items {
+        let key = match item.get("id").and_then(text) {
+            Some(id) => id,
+            None => bail!("Every item in a CSL-JSON file needs an \"id\""),
+        };
+        let field = |name: &str| item.get(name).and_then(text);
+        let authors = item
+            .get("author")
+            .or_else(|| item.get("editor"))
+            .and_then(Value::as_array)
+            .map_or_else(Vec::new, |names| names.iter().filter_map(csl_name).collect());
+        let issued = item.get("issued");
+        let year = issued
+            .and_then(|i| i.pointer("/date-parts/0/0"))
+            .and_then(text)
+            .or_else(|| issued.and_then(|i| i.get("literal")).and_then(text));
+
+        references.push(Reference {
+            key,
+            authors,
+            year,
+            title: field("title"),
+            container: field("container-title"),
+            volume: field("volume"),
+            issue: field("issue"),
+            pages: field("page").map(|pages| pages.replace('-', "–")),
+            publisher: field("publisher"),
+            doi: field("DOI"),
+            url: field("URL"),
+        });
+    }
+
+    Ok(references)
+}
+
+fn csl_name(name: &Value) -> Option<Name> {
+    match name.get("family").and_then(text) {
+        Some(family) => Some(Name {
+            family,
+            given: name.get("given").and_then(text),
+        }),
+        None => name.get("literal").and_then(text).map(|family| Name {
+            family,
+            given: None,
+        }),
+    }
+}
+
+/// A string or number as text.
+fn text(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(ref n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Everything needed to resolve one chapter's citations.
+struct Citations<'a> {
+    references: &'a HashMap<String, Reference>,
+    /// The bibliography chapter, relative to the chapter being cited from.
+    bibliography: String,
+}
+
+impl<'a> Citations<'a> {
+    /// Replace each `[@key]` citation with links to the works it cites,
+    /// recording their keys in `cited`.
+    fn cite(&self, content: &str, file: &Path, cited: &mut HashSet<String>) -> String {
+        lazy_static! {
+            static ref CITATION: Regex = Regex::new(r"\[\s*(@[^\[\]]*)\]([(\[])?").unwrap();
+            static ref WORK: Regex = Regex::new(r"^@(\w[\w:./-]*)\s*(?:,\s*(.+))?$").unwrap();
+        }
+
+        replace_outside_code(content, |text| {
+            CITATION
+                .replace_all(text, |caps: &Captures| {
+                    // `[@handle](url)` is a link, not a citation
+                    if caps.get(2).is_some() {
+                        return caps[0].to_string();
+                    }
+
+                    let mut works = Vec::new();
+                    for work in caps[1].split(';') {
+                        let work = match WORK.captures(work.trim()) {
+                            Some(work) => work,
+                            None => return caps[0].to_string(),
+                        };
+                        let key = &work[1];
+                        let reference = match self.references.get(key) {
+                            Some(reference) => reference,
+                            None => {
+                                let message = format!("There's no \"{}\" in the bibliography", key);
+                                Diagnostic::warning(message).in_file(file).emit();
+                                return caps[0].to_string();
+                            }
+                        };
+
+                        let label = match work.get(2) {
+                            Some(locator) => format!("{}, {}", reference.label(), locator.as_str()),
+                            None => reference.label(),
+                        };
+                        works.push(format!("[{}]({}#ref-{})", label, self.bibliography, key));
+                        cited.insert(key.to_string());
+                    }
+
+                    format!("({})", works.join("; "))
+                }).into_owned()
+        })
+    }
+}
+
+/// The cited works' entries, sorted by author and year, each with an anchor
+/// for citations to link to.
+fn render_bibliography(references: &HashMap<String, Reference>, cited: &HashSet<String>) -> String {
+    let mut works: Vec<_> = cited.iter().filter_map(|key| references.get(key)).collect();
+    works.sort_by_key(|work| {
+        let author = work.authors.first().map(|name| name.family.to_lowercase());
+        (author, work.year.clone(), work.title.clone(), work.key.clone())
+    });
+
+    let entries: Vec<_> = works
+        .iter()
+        .map(|work| format!("<a id=\"ref-{}\"></a>{}", work.key, work.render()))
+        .collect();
+    format!("{}\n", entries.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BIBTEX: &str = r#"
+        Comments go anywhere.
+
+        @article{knuth84,
+          author = {Donald E. Knuth},
+          title = {Literate {P}rogramming},
+          journal = "The Computer Journal",
+          year = 1984, volume = {27}, number = {2}, pages = {97--111},
+          doi = {10.1093/comjnl/27.2.97}
+        }
+
+        @string{ acm = "ACM" }
+
+        @book(lamport94,
+          author = {Lamport, Leslie and G{\"o}del, Kurt and {The \LaTeX3 Project}},
+          title = {\LaTeX: A Document Preparation System},
+          publisher = "Addison" # "-Wesley",
+          year = {1994},
+        )
+    "#;
+
+    fn references() -> HashMap<String, Reference> {
+        parse_bibtex(BIBTEX)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.key.clone(), r))
+            .collect()
+    }
+
+    #[test]
+    fn bibtex_entries_are_read() {
+        let references = references();
+
+        assert_eq!(references.len(), 2);
+        assert_eq!(
+            references["knuth84"],
+            Reference {
+                key: String::from("knuth84"),
+                authors: vec![Name {
+                    family: String::from("Knuth"),
+                    given: Some(String::from("Donald E.")),
+                }],
+                year: Some(String::from("1984")),
+                title: Some(String::from("Literate Programming")),
+                container: Some(String::from("The Computer Journal")),
+                volume: Some(String::from("27")),
+                issue: Some(String::from("2")),
+                pages: Some(String::from("97–111")),
+                doi: Some(String::from("10.1093/comjnl/27.2.97")),
+                ..Default::default()
+            }
+        );
+
+        let lamport = &references["lamport94"];
+        let families: Vec<_> = lamport.authors.iter().map(|a| a.family.as_str()).collect();
+        assert_eq!(families, ["Lamport", "Go\u{308}del", "The LaTeX3 Project"]);
+        assert_eq!(lamport.publisher, Some(String::from("Addison-Wesley")));
+    }
+
+    #[test]
+    fn an_at_outside_an_entry_is_a_comment() {
+        let src = "Written by someone@example.com, see @comment below.\n\
+                   @comment{an @article{old} in a comment}\n\
+                   @book{knuth84, title = {The TeXbook}}";
+
+        let references = parse_bibtex(src).unwrap();
+
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].key, "knuth84");
+        assert_eq!(references[0].title, Some(String::from("The TeXbook")));
+    }
+
+    #[test]
+    fn csl_json_items_are_read() {
+        let src = r#"[{
+            "id": "knuth84",
+            "type": "article-journal",
+            "author": [{"family": "Knuth", "given": "Donald E."}, {"literal": "Others"}],
+            "issued": {"date-parts": [[1984, 5]]},
+            "title": "Literate Programming",
+            "container-title": "The Computer Journal",
+            "page": "97-111"
+        }]"#;
+
+        let references = parse_csl_json(src).unwrap();
+
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].label(), "Knuth and Others 1984");
+        assert_eq!(references[0].pages, Some(String::from("97–111")));
+    }
+
+    #[test]
+    fn citations_link_to_the_bibliography() {
+        let references = references();
+        let citations = Citations {
+            references: &references,
+            bibliography: String::from("../bibliography.md"),
+        };
+        let mut cited = HashSet::new();
+        let src = "As shown [@knuth84, p. 97; @lamport94], but not [@nobody], \
+                   [@handle](https://example.com) or `[@knuth84]`.";
+
+        let got = citations.cite(src, Path::new("ch.md"), &mut cited);
+
+        assert_eq!(
+            got,
+            "As shown ([Knuth 1984, p. 97](../bibliography.md#ref-knuth84); \
+             [Lamport et al. 1994](../bibliography.md#ref-lamport94)), but not [@nobody], \
+             [@handle](https://example.com) or `[@knuth84]`."
+        );
+        assert_eq!(cited.len(), 2);
+    }
+
+    #[test]
+    fn the_bibliography_lists_what_was_cited() {
+        let references = references();
+        let cited = vec![String::from("lamport94"), String::from("knuth84")]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            render_bibliography(&references, &cited),
+            "<a id=\"ref-knuth84\"></a>Knuth, Donald E. 1984. \"Literate Programming.\" \
+             *The Computer Journal* 27 (2): 97–111. <https://doi.org/10.1093/comjnl/27.2.97>\n\n\
+             <a id=\"ref-lamport94\"></a>Lamport, Leslie, Kurt Go\u{308}del, and \
+             The LaTeX3 Project. 1994. *LaTeX: A Document Preparation System*. \
+             Addison-Wesley.\n"
+        );
+    }
+}
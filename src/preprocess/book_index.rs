@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use regex::{Captures, Regex};
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem, Chapter};
+use utils::fs::{normalize_path, path_to_root};
+use utils::{lines_outside_code, normalize_id};
+
+const DEFAULT_INDEX_CHAPTER: &str = "book-index.md";
+const DEFAULT_INDEX_TITLE: &str = "Index";
+
+/// A preprocessor which generates an alphabetised index from the
+/// `{{#index term}}` markers in each chapter.
+///
+/// Each entry links back to every place its term was marked. A marker like
+/// `{{#index traits!objects}}` adds an "objects" sub-entry under "traits".
+///
+/// The index is added to the end of the chapter at `chapter`
+/// (`book-index.md` unless configured otherwise), or to a new chapter at the
+/// end of the book if there's no such chapter in `SUMMARY.md`.
+pub struct BookIndexPreprocessor;
+
+impl BookIndexPreprocessor {
+    pub(crate) const NAME: &'static str = "book-index";
+
+    /// Create a new `BookIndexPreprocessor`.
+    pub fn new() -> Self {
+        BookIndexPreprocessor
+    }
+}
+
+impl Preprocessor for BookIndexPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let table = ctx.config.get_preprocessor(Self::NAME);
+        let get = |key: &str| table.and_then(|t| t.get(key)).and_then(|v| v.as_str());
+        let index_path = PathBuf::from(get("chapter").unwrap_or(DEFAULT_INDEX_CHAPTER));
+        let title = get("title").unwrap_or(DEFAULT_INDEX_TITLE).to_string();
+
+        let mut entries = Vec::new();
+        let mut index_exists = false;
+
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                match ch.path {
+                    Some(ref path) if *path == index_path => index_exists = true,
+                    Some(ref path) => {
+                        ch.content = mark_entries(&ch.content, &ch.name, path, &mut entries)
+                    }
+                    None => {}
+                }
+            }
+        });
+
+        let index = render_index(&entries, &title, &index_path);
+
+        if index_exists {
+            book.for_each_mut(|section: &mut BookItem| {
+                if let BookItem::Chapter(ref mut ch) = *section {
+                    if ch.path.as_ref() == Some(&index_path) {
+                        if !ch.content.trim().is_empty() {
+                            ch.content.push_str("\n\n");
+                        }
+                        ch.content.push_str(&index);
+                    }
+                }
+            });
+        } else {
+            let content = format!("# {}\n\n{}", title, index);
+            book.push_item(Chapter::new(&title, content, index_path, Vec::new()));
+        }
+
+        Ok(book)
+    }
+}
+
+/// One place a term was marked.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    term: String,
+    subterm: Option<String>,
+    chapter: String,
+    /// The chapter's path followed by the marker's anchor.
+    link: String,
+}
+
+/// Replace each `{{#index term}}` marker with an anchor for the index to link
+/// to, recording an `Entry` for it.
+fn mark_entries(content: &str, chapter: &str, path: &Path, entries: &mut Vec<Entry>) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"(?x)                     # insignificant whitespace mode
+            (\\)?                      # an optional escape
+            \{\{\s*\#index\s+          # the marker's opening
+            ([^}!]+?)                  # the term
+            (?:\s*!\s*([^}]+?))?       # an optional sub-entry
+            \s*\}\}                    # the closing parens"
+        ).unwrap();
+    }
+
+    let path = normalize_path(&path.display().to_string());
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut lines = Vec::new();
+
+    for (line, outside_code) in lines_outside_code(content) {
+        if !outside_code {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let line = RE.replace_all(line, |caps: &Captures| {
+            if caps.get(1).is_some() {
+                // omit the escape char
+                return caps[0][1..].to_string();
+            }
+
+            let term = caps[2].to_string();
+            let subterm = caps.get(3).map(|s| s.as_str().to_string());
+
+            let slug = normalize_id(&match subterm {
+                Some(ref subterm) => format!("{} {}", term, subterm),
+                None => term.clone(),
+            });
+            let count = counts.entry(slug.clone()).or_insert(0);
+            *count += 1;
+            let id = format!("index-{}-{}", slug, count);
+
+            entries.push(Entry {
+                term,
+                subterm,
+                chapter: chapter.to_string(),
+                link: format!("{}#{}", path, id),
+            });
+
+            format!("<a id=\"{}\"></a>", id)
+        });
+
+        lines.push(line.into_owned());
+    }
+
+    lines.join("\n")
+}
+
+/// The entries for one term, and its sub-entries.
+#[derive(Debug, Default)]
+struct Term<'a> {
+    entries: Vec<&'a Entry>,
+    subterms: BTreeMap<String, Vec<&'a Entry>>,
+}
+
+fn render_index(entries: &[Entry], title: &str, index_path: &Path) -> String {
+    if entries.is_empty() {
+        warn!("No {{{{#index}}}} markers were found for the \"{}\" chapter", title);
+        return String::new();
+    }
+
+    // sort the terms case-insensitively
+    let mut terms: BTreeMap<(String, String), Term> = BTreeMap::new();
+    for entry in entries {
+        let key = (entry.term.to_lowercase(), entry.term.clone());
+        let term = terms.entry(key).or_default();
+
+        match entry.subterm {
+            Some(ref subterm) => term.subterms.entry(subterm.clone()).or_default().push(entry),
+            None => term.entries.push(entry),
+        }
+    }
+
+    let root = path_to_root(index_path);
+    let mut lines = Vec::new();
+    let mut current_letter = None;
+
+    for ((lowercase, name), term) in &terms {
+        let letter = match lowercase.chars().next() {
+            Some(c) if c.is_alphabetic() => c.to_uppercase().collect::<String>(),
+            _ => "Symbols".to_string(),
+        };
+
+        if current_letter.as_ref() != Some(&letter) {
+            if current_letter.is_some() {
+                lines.push(String::new());
+            }
+            lines.push(format!("## {}", letter));
+            lines.push(String::new());
+            current_letter = Some(letter);
+        }
+
+        lines.push(format!("- {}{}", name, render_links(&term.entries, &root)));
+        for (subterm, entries) in &term.subterms {
+            lines.push(format!("  - {}{}", subterm, render_links(entries, &root)));
+        }
+    }
+
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn render_links(entries: &[&Entry], root: &str) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let links: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let count = seen.entry(&entry.chapter).or_insert(0);
+            *count += 1;
+
+            // later uses in the same chapter are numbered
+            let text = if *count == 1 {
+                entry.chapter.clone()
+            } else {
+                format!("{} ({})", entry.chapter, count)
+            };
+
+            format!("[{}]({}{})", text, root, entry.link)
+        }).collect();
+
+    format!(": {}", links.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers_are_replaced_with_anchors() {
+        let src = "# Traits\n\n{{#index traits}}Traits are great. {{#index traits}}\n\
+                   ```\n{{#index not-an-entry}}\n```\n\\{{#index escaped}}";
+        let mut entries = Vec::new();
+
+        let got = mark_entries(src, "Traits", Path::new("guide/traits.md"), &mut entries);
+
+        assert_eq!(
+            got,
+            "# Traits\n\n<a id=\"index-traits-1\"></a>Traits are great. \
+             <a id=\"index-traits-2\"></a>\n```\n{{#index not-an-entry}}\n```\n{{#index escaped}}"
+        );
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].link, "guide/traits.md#index-traits-2");
+    }
+
+    #[test]
+    fn sub_entries() {
+        let mut entries = Vec::new();
+
+        mark_entries("{{#index traits ! objects}}", "Ch", Path::new("ch.md"), &mut entries);
+
+        assert_eq!(
+            entries,
+            vec![Entry {
+                term: "traits".to_string(),
+                subterm: Some("objects".to_string()),
+                chapter: "Ch".to_string(),
+                link: "ch.md#index-traits-objects-1".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn render_an_alphabetised_index() {
+        let mut entries = Vec::new();
+        mark_entries(
+            "{{#index Traits}} {{#index closures}} {{#index Traits}}",
+            "Basics",
+            Path::new("basics.md"),
+            &mut entries,
+        );
+        mark_entries(
+            "{{#index Traits!objects}} {{#index traits}} {{#index 2D}}",
+            "More",
+            Path::new("more/more.md"),
+            &mut entries,
+        );
+
+        let got = render_index(&entries, "Index", Path::new("book-index.md"));
+
+        assert_eq!(
+            got,
+            "## Symbols\n\n\
+             - 2D: [More](more/more.md#index-a2d-1)\n\n\
+             ## C\n\n\
+             - closures: [Basics](basics.md#index-closures-1)\n\n\
+             ## T\n\n\
+             - Traits: [Basics](basics.md#index-traits-1), \
+             [Basics (2)](basics.md#index-traits-2)\n  \
+             - objects: [More](more/more.md#index-traits-objects-1)\n\
+             - traits: [More](more/more.md#index-traits-1)\n"
+        );
+    }
+}
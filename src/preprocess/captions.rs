@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::{Captures, Regex};
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use diagnostics::{chapter_file, Diagnostic};
+use utils::fs::{normalize_path, path_to_root};
+use utils::{escape_html, lines_outside_code, replace_outside_code};
+
+const DEFAULT_FIGURE_LABEL: &str = "Figure";
+const DEFAULT_TABLE_LABEL: &str = "Table";
+
+/// A preprocessor which numbers the book's figures and tables, and resolves
+/// references to them.
+///
+/// A figure is an image on a line of its own with an id after it, like
+/// `![A caption](diagram.png){#fig:diagram}`, and a table is captioned by a
+/// `Table: A caption {#tbl:sizes}` line just above or below it. Figures and
+/// tables are each numbered in the order they appear in the book and get a
+/// caption saying so, while `{{#ref fig:diagram}}` anywhere in the book
+/// becomes a link to the figure reading "Figure 3".
+pub struct CaptionsPreprocessor;
+
+impl CaptionsPreprocessor {
+    pub(crate) const NAME: &'static str = "captions";
+
+    /// Create a new `CaptionsPreprocessor`.
+    pub fn new() -> Self {
+        CaptionsPreprocessor
+    }
+}
+
+impl Preprocessor for CaptionsPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let table = ctx.config.get_preprocessor(Self::NAME);
+        let label = |key: &str, default| {
+            table
+                .and_then(|t| t.get(key))
+                .and_then(|v| v.as_str())
+                .unwrap_or(default)
+        };
+
+        // every chapter has to be seen before any can be numbered
+        let mut numbering = Numbering::new(
+            label("figure-label", DEFAULT_FIGURE_LABEL),
+            label("table-label", DEFAULT_TABLE_LABEL),
+        );
+        for item in book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                if let Some(ref path) = ch.path {
+                    let chapter = normalize_path(&path.display().to_string());
+                    numbering.number(&ch.content, &chapter, &chapter_file(ch));
+                }
+            }
+        }
+
+        let html = ctx.renderer == "html" || ctx.renderer == "epub";
+        book.for_each_mut(|item: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                if let Some(path) = ch.path.clone() {
+                    let chapter = normalize_path(&path.display().to_string());
+                    let captions = Captions {
+                        targets: &numbering.targets,
+                        chapter: &chapter,
+                        root: path_to_root(&path),
+                        html,
+                    };
+                    ch.content = captions.render(&ch.content, &chapter_file(ch));
+                }
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+/// Something `{{#ref}}` can point at.
+#[derive(Debug, Clone, PartialEq)]
+struct Target {
+    /// What it's called, like "Figure 3".
+    name: String,
+    /// The chapter it's in, relative to the `src` directory.
+    chapter: String,
+    /// The line of the chapter it's on, counting from 0.
+    line: usize,
+}
+
+struct Figure<'a> {
+    caption: &'a str,
+    src: &'a str,
+    title: Option<&'a str>,
+    id: &'a str,
+}
+
+fn parse_figure<'a>(line: &'a str) -> Option<Figure<'a>> {
+    lazy_static! {
+        static ref FIGURE: Regex = Regex::new(
+            r#"^\s{0,3}!\[([^\]]*)\]\(\s*([^)\s]+)(?:\s+"([^"]*)")?\s*\)\{#([\w.:-]+)\}\s*$"#
+        ).unwrap();
+    }
+
+    FIGURE.captures(line).map(|caps| Figure {
+        caption: caps.get(1).map_or("", |m| m.as_str()),
+        src: caps.get(2).map_or("", |m| m.as_str()),
+        title: caps.get(3).map(|m| m.as_str()),
+        id: caps.get(4).map_or("", |m| m.as_str()),
+    })
+}
+
+/// A table with a caption.
+struct Table<'a> {
+    caption: &'a str,
+    id: &'a str,
+    /// The caption's line.
+    line: usize,
+    /// The table's first line, and the line after its last.
+    rows: (usize, usize),
+}
+
+/// The caption and id in a `Table: <caption> {#<id>}` line.
+fn parse_table_caption(line: &str) -> Option<(&str, &str)> {
+    lazy_static! {
+        static ref CAPTION: Regex =
+            Regex::new(r"^\s{0,3}Table:\s*(.*?)\s*\{#([\w.:-]+)\}\s*$").unwrap();
+    }
+
+    CAPTION.captures(line).map(|caps| {
+        (
+            caps.get(1).map_or("", |m| m.as_str()),
+            caps.get(2).map_or("", |m| m.as_str()),
+        )
+    })
+}
+
+/// The tables in a chapter's `lines` which have a caption right above or
+/// below them, or a blank line away.
+fn find_tables<'a>(lines: &[(&'a str, bool)]) -> Vec<Table<'a>> {
+    lazy_static! {
+        static ref DELIMITER_ROW: Regex =
+            Regex::new(r"^\s*\|?\s*:?-+:?\s*(\|\s*:?-+:?\s*)*\|?\s*$").unwrap();
+    }
+
+    // a table is a paragraph whose second line is a delimiter row
+    let mut paragraphs = Vec::new();
+    let mut start = None;
+    for (i, &(line, prose)) in lines.iter().enumerate() {
+        let inside = prose && !line.trim().is_empty() && parse_table_caption(line).is_none();
+        match start {
+            None if inside => start = Some(i),
+            Some(s) if !inside => {
+                paragraphs.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        paragraphs.push((s, lines.len()));
+    }
+    let tables: Vec<_> = paragraphs
+        .into_iter()
+        .filter(|&(start, end)| {
+            end - start >= 2 && lines[start].0.contains('|')
+                && DELIMITER_ROW.is_match(lines[start + 1].0)
+        }).collect();
+
+    let blank = |i: usize| lines[i].1 && lines[i].0.trim().is_empty();
+    let mut captioned: Vec<Table> = Vec::new();
+    for (i, &(line, prose)) in lines.iter().enumerate() {
+        let (caption, id) = match parse_table_caption(line) {
+            Some(caption) if prose => caption,
+            _ => continue,
+        };
+        let rows = tables.iter().cloned().find(|&(start, end)| {
+            let next_to = start == i + 1 || end == i;
+            let nearby = (start == i + 2 && blank(i + 1)) || (end + 1 == i && blank(end));
+            (next_to || nearby) && !captioned.iter().any(|t| t.rows == (start, end))
+        });
+
+        if let Some(rows) = rows {
+            captioned.push(Table {
+                caption,
+                id,
+                line: i,
+                rows,
+            });
+        }
+    }
+    captioned
+}
+
+/// The numbers given to the figures and tables seen so far.
+struct Numbering<'a> {
+    figure_label: &'a str,
+    table_label: &'a str,
+    figures: usize,
+    tables: usize,
+    targets: HashMap<String, Target>,
+}
+
+impl<'a> Numbering<'a> {
+    fn new(figure_label: &'a str, table_label: &'a str) -> Self {
+        Numbering {
+            figure_label,
+            table_label,
+            figures: 0,
+            tables: 0,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Give the figures and tables in a chapter the next numbers.
+    fn number(&mut self, content: &str, chapter: &str, file: &Path) {
+        let lines = lines_outside_code(content);
+
+        for (i, &(line, prose)) in lines.iter().enumerate() {
+            match parse_figure(line) {
+                Some(ref figure) if prose && self.is_new(figure.id, file, i) => {
+                    self.figures += 1;
+                    let name = format!("{} {}", self.figure_label, self.figures);
+                    self.add(figure.id, name, chapter, i);
+                }
+                _ => {}
+            }
+        }
+
+        for table in find_tables(&lines) {
+            if self.is_new(table.id, file, table.line) {
+                self.tables += 1;
+                let name = format!("{} {}", self.table_label, self.tables);
+                self.add(table.id, name, chapter, table.line);
+            }
+        }
+    }
+
+    fn is_new(&self, id: &str, file: &Path, line: usize) -> bool {
+        match self.targets.get(id) {
+            Some(target) => {
+                let message = format!("The id \"{}\" is already used by {}", id, target.name);
+                Diagnostic::warning(message).in_file(file).at_line(line + 1).emit();
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn add(&mut self, id: &str, name: String, chapter: &str, line: usize) {
+        let target = Target {
+            name,
+            chapter: chapter.to_string(),
+            line,
+        };
+        self.targets.insert(id.to_string(), target);
+    }
+}
+
+/// Everything needed to caption one chapter's figures and tables, and
+/// resolve its references.
+struct Captions<'a> {
+    targets: &'a HashMap<String, Target>,
+    /// The chapter, relative to the `src` directory.
+    chapter: &'a str,
+    /// The way from the chapter back to the `src` directory.
+    root: String,
+    /// Whether the renderer takes HTML, or has to make do with markdown.
+    html: bool,
+}
+
+impl<'a> Captions<'a> {
+    fn render(&self, content: &str, file: &Path) -> String {
+        let lines = lines_outside_code(content);
+        let mut rendered: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, &(line, prose))| match parse_figure(line) {
+                Some(ref figure) if prose => self.figure(figure, i),
+                _ => line.to_string(),
+            }).collect();
+        for table in find_tables(&lines) {
+            self.caption_table(&table, &mut rendered);
+        }
+
+        self.resolve_references(&rendered.join("\n"), file)
+    }
+
+    /// The name of the figure or table on `line` of the chapter, unless it
+    /// reuses an earlier one's id.
+    fn name(&self, id: &str, line: usize) -> Option<&str> {
+        match self.targets.get(id) {
+            Some(target) if target.chapter == self.chapter && target.line == line => {
+                Some(&target.name)
+            }
+            _ => None,
+        }
+    }
+
+    fn figure(&self, figure: &Figure, line: usize) -> String {
+        let title = figure.title.map(|t| format!(" \"{}\"", t)).unwrap_or_default();
+        let name = match self.name(figure.id, line) {
+            Some(name) => name,
+            // a duplicate, which keeps its image and title but not the id
+            None => return format!("![{}]({}{})", figure.caption, figure.src, title),
+        };
+
+        if !self.html {
+            return format!(
+                "![{}]({}{})\n\n*{}: {}*",
+                figure.caption, figure.src, title, name, figure.caption
+            );
+        }
+
+        let title = figure
+            .title
+            .map(|t| format!(" title=\"{}\"", escape_html(t)))
+            .unwrap_or_default();
+        let caption = escape_html(figure.caption);
+        format!(
+            "<figure id=\"{}\">\n<img src=\"{}\" alt=\"{}\"{}>\n\
+             <figcaption>{}: {}</figcaption>\n</figure>",
+            figure.id,
+            escape_html(figure.src),
+            caption,
+            title,
+            name,
+            caption
+        )
+    }
+
+    /// Replace a table's caption line with its numbered caption. For HTML,
+    /// the table is wrapped in a `<figure>`, leaving blank lines around it so
+    /// it's still read as markdown.
+    fn caption_table(&self, table: &Table, lines: &mut [String]) {
+        let above = table.line < table.rows.0;
+        let markdown_caption = |caption: String, lines: &mut [String]| {
+            lines[table.line] = if above {
+                format!("{}\n", caption)
+            } else {
+                format!("\n{}", caption)
+            };
+        };
+        let name = match self.name(table.id, table.line) {
+            Some(name) => name,
+            // a duplicate, which keeps its caption but not the id
+            None => return markdown_caption(format!("*{}*", table.caption), lines),
+        };
+
+        if !self.html {
+            return markdown_caption(format!("*{}: {}*", name, table.caption), lines);
+        }
+
+        let open = format!("<figure class=\"table\" id=\"{}\">", table.id);
+        let caption = format!("<figcaption>{}: {}</figcaption>", name, escape_html(table.caption));
+        let (first, last) = (table.rows.0, table.rows.1 - 1);
+        if above {
+            lines[table.line] = format!("{}\n{}\n", open, caption);
+            lines[last] = format!("{}\n\n</figure>", lines[last]);
+        } else {
+            lines[first] = format!("{}\n\n{}", open, lines[first]);
+            lines[table.line] = format!("\n{}\n</figure>", caption);
+        }
+    }
+
+    /// Turn each `{{#ref <id>}}` into a link to what it refers to.
+    fn resolve_references(&self, content: &str, file: &Path) -> String {
+        lazy_static! {
+            static ref REFERENCE: Regex = Regex::new(r"\{\{\s*#ref\s+([\w.:-]+)\s*\}\}").unwrap();
+        }
+
+        replace_outside_code(content, |text| {
+            REFERENCE
+                .replace_all(text, |caps: &Captures| match self.targets.get(&caps[1]) {
+                    Some(target) if target.chapter == self.chapter => {
+                        format!("[{}](#{})", target.name, &caps[1])
+                    }
+                    Some(target) => format!(
+                        "[{}]({}{}#{})",
+                        target.name, self.root, target.chapter, &caps[1]
+                    ),
+                    None => {
+                        Diagnostic::warning(format!("Unknown reference \"{}\"", &caps[1]))
+                            .in_file(file)
+                            .emit();
+                        caps[0].to_string()
+                    }
+                }).into_owned()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(chapters: &[(&str, &str)]) -> HashMap<String, Target> {
+        let mut numbering = Numbering::new("Figure", "Table");
+        for &(chapter, content) in chapters {
+            numbering.number(content, chapter, Path::new(chapter));
+        }
+        numbering.targets
+    }
+
+    #[test]
+    fn figures_are_numbered_through_the_book() {
+        let targets = number(&[
+            ("intro.md", "![One](a.png){#fig:a}\n\n```md\n![No](b.png){#fig:b}\n```"),
+            ("guide/usage.md", "Text ![Inline](c.png){#fig:c}\n\n![Two](d.png \"D\"){#fig:d}"),
+        ]);
+
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets["fig:a"].name, "Figure 1");
+        assert_eq!(
+            targets["fig:d"],
+            Target {
+                name: String::from("Figure 2"),
+                chapter: String::from("guide/usage.md"),
+                line: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn figures_get_captions_and_references_become_links() {
+        let targets = number(&[
+            ("intro.md", "![One](a.png){#fig:a}"),
+            ("guide/usage.md", "![Two & more](d.png \"D\"){#fig:d}"),
+        ]);
+        let captions = Captions {
+            targets: &targets,
+            chapter: "guide/usage.md",
+            root: String::from("../"),
+            html: true,
+        };
+        let src = "![Two & more](d.png \"D\"){#fig:d}\n\n\
+                   See {{#ref fig:d}}, {{#ref fig:a}} and `{{#ref fig:a}}`.";
+
+        assert_eq!(
+            captions.render(src, Path::new("guide/usage.md")),
+            "<figure id=\"fig:d\">\n<img src=\"d.png\" alt=\"Two &amp; more\" title=\"D\">\n\
+             <figcaption>Figure 2: Two &amp; more</figcaption>\n</figure>\n\n\
+             See [Figure 2](#fig:d), [Figure 1](../intro.md#fig:a) and `{{#ref fig:a}}`."
+        );
+    }
+
+    #[test]
+    fn other_renderers_get_a_markdown_caption() {
+        let targets = number(&[("intro.md", "![One](a.png){#fig:a}")]);
+        let captions = Captions {
+            targets: &targets,
+            chapter: "intro.md",
+            root: String::new(),
+            html: false,
+        };
+
+        assert_eq!(
+            captions.render("![One](a.png){#fig:a}", Path::new("intro.md")),
+            "![One](a.png)\n\n*Figure 1: One*"
+        );
+    }
+
+    #[test]
+    fn duplicates_keep_their_captions() {
+        let src = "![One](a.png){#fig:a}\n\n![Two](b.png \"B\"){#fig:a}\n\n\
+                   Table: Sizes {#fig:a}\n| A |\n|---|";
+        let targets = number(&[("intro.md", src)]);
+        let captions = Captions {
+            targets: &targets,
+            chapter: "intro.md",
+            root: String::new(),
+            html: true,
+        };
+
+        assert_eq!(
+            captions.render(src, Path::new("intro.md")),
+            "<figure id=\"fig:a\">\n<img src=\"a.png\" alt=\"One\">\n\
+             <figcaption>Figure 1: One</figcaption>\n</figure>\n\n\
+             ![Two](b.png \"B\")\n\n*Sizes*\n\n| A |\n|---|"
+        );
+    }
+
+    #[test]
+    fn tables_are_numbered_separately() {
+        let targets = number(&[
+            ("intro.md", "![One](a.png){#fig:a}"),
+            (
+                "guide/usage.md",
+                "Table: Above {#tbl:a}\n| A |\n|---|\n| 1 |\n\n\
+                 Table: Nowhere near a table {#tbl:b}\n\nSome text.\n\n\
+                 A | B\n--|--\n\nTable: Below {#tbl:c}",
+            ),
+        ]);
+
+        assert_eq!(targets.len(), 3);
+        assert_eq!(targets["tbl:a"].name, "Table 1");
+        assert_eq!(targets["tbl:c"].name, "Table 2");
+    }
+
+    #[test]
+    fn tables_are_wrapped_in_figures() {
+        let src = "Table: Sizes {#tbl:a}\n\n| A |\n|---|\n\n\
+                   A | B\n--|--\nTable: More {#tbl:b}\n\nSee {{#ref tbl:b}}.";
+        let targets = number(&[("intro.md", src)]);
+        let captions = Captions {
+            targets: &targets,
+            chapter: "intro.md",
+            root: String::new(),
+            html: true,
+        };
+
+        assert_eq!(
+            captions.render(src, Path::new("intro.md")),
+            "<figure class=\"table\" id=\"tbl:a\">\n<figcaption>Table 1: Sizes</figcaption>\n\n\n\
+             | A |\n|---|\n\n</figure>\n\n\
+             <figure class=\"table\" id=\"tbl:b\">\n\nA | B\n--|--\n\
+             \n<figcaption>Table 2: More</figcaption>\n</figure>\n\nSee [Table 2](#tbl:b)."
+        );
+    }
+}
@@ -23,7 +23,9 @@ use std::process::{Child, Command, Stdio};
 ///
 /// Exiting with a non-zero exit code while preprocessing is considered an
 /// error. `stderr` is passed directly through to the user, so it can be used
-/// for logging or emitting warnings if desired.
+/// for logging or emitting warnings if desired. mdBook's own messages about
+/// the preprocessor are logged with the `mdbook::preprocess::<name>` target,
+/// so they can be picked out with `RUST_LOG`.
 ///
 /// # Examples
 ///
@@ -61,7 +63,11 @@ impl CmdPreprocessor {
         if let Err(e) = self.write_input(stdin, &book, &ctx) {
             // Looks like the backend hung up before we could finish
             // sending it the render context. Log the error and keep going
-            warn!("Error writing the RenderContext to the backend, {}", e);
+            warn!(
+                target: &self.log_target(),
+                "Error writing the RenderContext to the backend, {}",
+                e
+            );
         }
     }
 
@@ -79,6 +85,10 @@ impl CmdPreprocessor {
         &self.cmd
     }
 
+    fn log_target(&self) -> String {
+        format!("mdbook::preprocess::{}", self.name)
+    }
+
     fn command(&self) -> Result<Command> {
         let mut words = Shlex::new(&self.cmd);
         let executable = match words.next() {
@@ -122,7 +132,7 @@ impl Preprocessor for CmdPreprocessor {
             .wait_with_output()
             .chain_err(|| "Error waiting for the preprocessor to complete")?;
 
-        trace!("{} exited with output: {:?}", self.cmd, output);
+        trace!(target: &self.log_target(), "{} exited with output: {:?}", self.cmd, output);
         ensure!(
             output.status.success(),
             "The preprocessor exited unsuccessfully"
@@ -134,6 +144,7 @@ impl Preprocessor for CmdPreprocessor {
 
     fn supports_renderer(&self, renderer: &str) -> bool {
         debug!(
+            target: &self.log_target(),
             "Checking if the \"{}\" preprocessor supports \"{}\"",
             self.name(),
             renderer
@@ -142,7 +153,12 @@ impl Preprocessor for CmdPreprocessor {
         let mut cmd = match self.command() {
             Ok(c) => c,
             Err(e) => {
-                warn!("Unable to create the command for the \"{}\" preprocessor, {}", self.name(), e);
+                warn!(
+                    target: &self.log_target(),
+                    "Unable to create the command for the \"{}\" preprocessor, {}",
+                    self.name(),
+                    e
+                );
                 return false;
             }
         };
@@ -159,10 +175,11 @@ impl Preprocessor for CmdPreprocessor {
         if let Err(ref e) = outcome {
             if e.kind() == io::ErrorKind::NotFound {
                 warn!(
+                    target: &self.log_target(),
                     "The command wasn't found, is the \"{}\" preprocessor installed?",
                     self.name
                 );
-                warn!("\tCommand: {}", self.cmd);
+                warn!(target: &self.log_target(), "\tCommand: {}", self.cmd);
             }
         }
 
@@ -0,0 +1,217 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use diagnostics::{chapter_file, Diagnostic};
+
+/// A preprocessor for only including content when certain features are
+/// enabled.
+///
+/// Anything between `{{#if feature="name"}}` and `{{#endif}}` is removed
+/// unless `name` is one of the preprocessor's `features` (which can also be
+/// enabled with `mdbook build --features name`). An optional `{{#else}}`
+/// marks content to use when the feature *isn't* enabled, and blocks may be
+/// nested.
+pub struct ConditionalPreprocessor;
+
+impl ConditionalPreprocessor {
+    pub(crate) const NAME: &'static str = "conditional";
+
+    /// Create a new `ConditionalPreprocessor`.
+    pub fn new() -> Self {
+        ConditionalPreprocessor
+    }
+}
+
+impl Preprocessor for ConditionalPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let features: Vec<String> = ctx
+            .config
+            .get_preprocessor(Self::NAME)
+            .and_then(|t| t.get("features"))
+            .and_then(|v| v.as_array())
+            .map(|features| {
+                features
+                    .iter()
+                    .filter_map(|f| f.as_str())
+                    .map(|f| f.to_string())
+                    .collect()
+            }).unwrap_or_default();
+
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                let file = chapter_file(ch);
+                ch.content = filter_content(&ch.content, &features, &file);
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+/// The state of one `{{#if}}` block.
+struct Block {
+    /// Whether the feature was enabled.
+    condition: bool,
+    /// Whether we've seen the `{{#else}}`.
+    in_else: bool,
+    /// Where the `{{#if}}` is.
+    start: usize,
+}
+
+impl Block {
+    fn is_active(&self) -> bool {
+        self.condition != self.in_else
+    }
+}
+
+fn filter_content(content: &str, features: &[String], file: &Path) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r#"(?x)                             # insignificant whitespace mode
+            (\\)?                               # an optional escape
+            \{\{\s*\#(if|else|endif)            # the directive
+            (?:\s+feature\s*=\s*"([^"]*)")?     # the feature an `if` depends on
+            \s*\}\}                             # the closing parens"#
+        ).unwrap();
+    }
+
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut filtered = String::with_capacity(content.len());
+    let mut previous_end_index = 0;
+
+    for caps in RE.captures_iter(content) {
+        let mat = caps.get(0).unwrap();
+        let is_active = blocks.iter().all(Block::is_active);
+
+        if caps.get(1).is_some() {
+            if is_active {
+                // omit the escape char
+                filtered.push_str(&content[previous_end_index..mat.start()]);
+                filtered.push_str(&mat.as_str()[1..]);
+            }
+            previous_end_index = mat.end();
+            continue;
+        }
+
+        let (start, end) = directive_span(content, mat.start(), mat.end());
+        if is_active {
+            filtered.push_str(&content[previous_end_index..start]);
+        }
+        previous_end_index = end;
+
+        let warn = |message: &str| {
+            Diagnostic::warning(message)
+                .in_file(file)
+                .at_offset(content, mat.start())
+                .emit()
+        };
+        match &caps[2] {
+            "if" => {
+                if caps.get(3).is_none() {
+                    warn("An {{#if}} is missing its feature=\"...\"");
+                }
+
+                blocks.push(Block {
+                    condition: caps.get(3).map_or(false, |feature| {
+                        features.iter().any(|f| f == feature.as_str())
+                    }),
+                    in_else: false,
+                    start: mat.start(),
+                });
+            }
+            "else" => match blocks.last_mut() {
+                Some(ref mut block) if !block.in_else => block.in_else = true,
+                _ => warn("Found an unexpected {{#else}}"),
+            },
+            _ => {
+                if blocks.pop().is_none() {
+                    warn("Found an unexpected {{#endif}}");
+                }
+            }
+        }
+    }
+
+    for block in &blocks {
+        Diagnostic::warning("An {{#if}} is missing its {{#endif}}")
+            .in_file(file)
+            .at_offset(content, block.start)
+            .emit();
+    }
+
+    if blocks.iter().all(Block::is_active) {
+        filtered.push_str(&content[previous_end_index..]);
+    }
+
+    filtered
+}
+
+/// A directive on a line of its own takes the whole line with it, so it
+/// doesn't leave a blank line behind.
+fn directive_span(content: &str, start: usize, end: usize) -> (usize, usize) {
+    let line_start = content[..start].rfind('\n').map_or(0, |ix| ix + 1);
+    let line_end = content[end..].find('\n').map_or(content.len(), |ix| end + ix + 1);
+
+    let only_whitespace_before = content[line_start..start].trim().is_empty();
+    let only_whitespace_after = content[end..line_end].trim().is_empty();
+
+    if only_whitespace_before && only_whitespace_after {
+        (line_start, line_end)
+    } else {
+        (start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(content: &str) -> String {
+        filter_content(content, &["enterprise".to_string()], Path::new("chapter.md"))
+    }
+
+    #[test]
+    fn content_for_enabled_features_is_kept() {
+        let src = "Intro\n{{#if feature=\"enterprise\"}}\nEnterprise\n{{#endif}}\nOutro";
+
+        assert_eq!(filter(src), "Intro\nEnterprise\nOutro");
+    }
+
+    #[test]
+    fn content_for_disabled_features_is_removed() {
+        let src = "Intro\n  {{#if feature=\"community\"}}\nCommunity\n{{#endif}}\nOutro";
+
+        assert_eq!(filter(src), "Intro\nOutro");
+    }
+
+    #[test]
+    fn else_blocks_and_nesting() {
+        let src = "{{#if feature=\"community\"}}\nA\n{{#else}}\nB\n\
+                   {{#if feature=\"enterprise\"}}\nC\n{{#endif}}\n{{#endif}}\n";
+
+        assert_eq!(filter(src), "B\nC\n");
+    }
+
+    #[test]
+    fn inline_directives() {
+        let src = "Call us{{#if feature=\"enterprise\"}} any time{{#endif}}. Bye";
+
+        assert_eq!(filter(src), "Call us any time. Bye");
+        assert_eq!(filter_content(src, &[], Path::new("chapter.md")), "Call us. Bye");
+    }
+
+    #[test]
+    fn escaped_directives_are_left_alone() {
+        let src = "Write \\{{#if feature=\"x\"}} to start a block";
+
+        assert_eq!(filter(src), "Write {{#if feature=\"x\"}} to start a block");
+    }
+}
@@ -0,0 +1,211 @@
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::Book;
+use utils::{escape_html, run_with_input, split_code_blocks, Block};
+
+const DEFAULT_MERMAID_URL: &str = "https://unpkg.com/mermaid@8/dist/mermaid.min.js";
+
+/// A preprocessor for turning `mermaid` and `plantuml` code blocks into
+/// diagrams.
+///
+/// If a `mermaid-command` or `plantuml-command` is configured, each diagram's
+/// source is piped to that command and the SVG it prints is embedded in the
+/// chapter. Otherwise `mermaid` diagrams are left for the mermaid script to
+/// draw in the browser, while `plantuml` code blocks are left alone.
+pub struct DiagramsPreprocessor;
+
+impl DiagramsPreprocessor {
+    pub(crate) const NAME: &'static str = "diagrams";
+
+    /// Create a new `DiagramsPreprocessor`.
+    pub fn new() -> Self {
+        DiagramsPreprocessor
+    }
+}
+
+impl Preprocessor for DiagramsPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let cfg = DiagramsConfig::from_context(ctx);
+
+        book.par_for_each_chapter_mut(|ch| {
+            ch.content = render_diagrams(&ch.content, &cfg)
+                .chain_err(|| format!("Unable to render the diagrams in \"{}\"", ch.name))?;
+            Ok(())
+        })?;
+
+        Ok(book)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DiagramsConfig {
+    mermaid_command: Option<String>,
+    plantuml_command: Option<String>,
+    mermaid_url: String,
+}
+
+impl DiagramsConfig {
+    fn from_context(ctx: &PreprocessorContext) -> DiagramsConfig {
+        let table = ctx.config.get_preprocessor(DiagramsPreprocessor::NAME);
+        let get = |key: &str| {
+            table
+                .and_then(|t| t.get(key))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+
+        DiagramsConfig {
+            mermaid_command: get("mermaid-command"),
+            plantuml_command: get("plantuml-command"),
+            mermaid_url: get("mermaid-url").unwrap_or_else(|| DEFAULT_MERMAID_URL.to_string()),
+        }
+    }
+
+    /// The command used to render a language's diagrams to SVG, if any.
+    fn command(&self, language: &str) -> Option<&str> {
+        match language {
+            "mermaid" => self.mermaid_command.as_ref().map(|s| s.as_str()),
+            "plantuml" => self.plantuml_command.as_ref().map(|s| s.as_str()),
+            _ => None,
+        }
+    }
+}
+
+fn render_diagrams(content: &str, cfg: &DiagramsConfig) -> Result<String> {
+    let mut needs_mermaid_script = false;
+    let mut lines = Vec::new();
+
+    for block in split_code_blocks(content) {
+        let diagram = match block {
+            // an unclosed code block is left as it is
+            Block::Code(ref diagram) if diagram.closing_fence.is_some() => diagram,
+            _ => {
+                lines.push(block.to_markdown());
+                continue;
+            }
+        };
+
+        let language = diagram.language().unwrap_or("");
+        if let Some(command) = cfg.command(language) {
+            lines.push(embed_svg(&run_with_input(command, &diagram.code())?));
+        } else if language == "mermaid" {
+            let source = without_blank_lines(&escape_html(&diagram.code()));
+            lines.push(format!("<div class=\"mermaid\">\n{}\n</div>", source));
+            needs_mermaid_script = true;
+        } else {
+            lines.push(diagram.to_markdown());
+        }
+    }
+
+    if needs_mermaid_script {
+        if lines.last().map_or(false, |line| !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push(mermaid_script(&cfg.mermaid_url));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Loads mermaid, then draws the diagrams. Every chapter with a diagram has
+/// this, so the print page only loads mermaid for the first of them.
+const MERMAID_SCRIPT: &str = r#"<script>
+if (!window.mermaidLoaded) {
+    window.mermaidLoaded = true;
+    var script = document.createElement('script');
+    script.src = '{url}';
+    script.onload = function () {
+        mermaid.initialize({ startOnLoad: false });
+        if (document.readyState === 'loading') {
+            document.addEventListener('DOMContentLoaded', function () { mermaid.init(); });
+        } else {
+            mermaid.init();
+        }
+    };
+    document.head.appendChild(script);
+}
+</script>"#;
+
+fn mermaid_script(url: &str) -> String {
+    let url = url.replace('\\', "\\\\").replace('\'', "\\'");
+    MERMAID_SCRIPT.replace("{url}", &url)
+}
+
+/// Wrap the SVG printed by a diagram command so it can be embedded in
+/// markdown, dropping any XML declaration or doctype in front of it.
+fn embed_svg(svg: &str) -> String {
+    let svg = match svg.find("<svg") {
+        Some(start) => &svg[start..],
+        None => svg,
+    };
+
+    format!("<div class=\"diagram\">\n{}\n</div>", without_blank_lines(svg.trim()))
+}
+
+/// A blank line would end the HTML block early, letting markdown mangle the
+/// rest of the diagram.
+fn without_blank_lines(s: &str) -> String {
+    s.lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> DiagramsConfig {
+        DiagramsConfig {
+            mermaid_command: None,
+            plantuml_command: None,
+            mermaid_url: "mermaid.js".to_string(),
+        }
+    }
+
+    #[test]
+    fn mermaid_diagrams_are_drawn_in_the_browser_by_default() {
+        let src = "Text\n\n```mermaid\ngraph TD;\n\n    A-->B;\n```\n";
+        let should_be = "Text\n\n<div class=\"mermaid\">\ngraph TD;\n    A--&gt;B;\n</div>\n\n";
+
+        let got = render_diagrams(src, &config()).unwrap();
+
+        assert_eq!(got, format!("{}{}", should_be, mermaid_script("mermaid.js")));
+        assert!(got.contains("script.src = 'mermaid.js';"));
+    }
+
+    #[test]
+    fn plantuml_is_left_alone_without_a_command() {
+        let src = "```plantuml\n@startuml\nA -> B\n@enduml\n```\n\n```rust\nfn main() {}\n```";
+
+        let got = render_diagrams(src, &config()).unwrap();
+
+        assert_eq!(got, src);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn diagrams_can_be_rendered_by_a_command() {
+        let mut cfg = config();
+        cfg.plantuml_command = Some("cat".to_string());
+        let src = "```plantuml\n<?xml version=\"1.0\"?>\n<svg>\n\n</svg>\n```";
+
+        let got = render_diagrams(src, &cfg).unwrap();
+
+        assert_eq!(got, "<div class=\"diagram\">\n<svg>\n</svg>\n</div>");
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn failing_diagram_commands_are_an_error() {
+        let mut cfg = config();
+        cfg.mermaid_command = Some("false".to_string());
+
+        assert!(render_diagrams("```mermaid\ngraph TD;\n```", &cfg).is_err());
+    }
+}
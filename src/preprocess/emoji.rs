@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::{Captures, Regex};
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use utils::fs::path_to_root;
+use utils::replace_outside_code;
+
+/// The shortcodes which are always available, using the same names as GitHub.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("alarm_clock", "⏰"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("art", "🎨"),
+    ("balloon", "🎈"),
+    ("bangbang", "‼️"),
+    ("bell", "🔔"),
+    ("bike", "🚲"),
+    ("blush", "😊"),
+    ("bomb", "💣"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("brain", "🧠"),
+    ("broken_heart", "💔"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("calendar", "📆"),
+    ("camera", "📷"),
+    ("car", "🚗"),
+    ("cat", "🐱"),
+    ("chart_with_upwards_trend", "📈"),
+    ("check", "✔️"),
+    ("checkered_flag", "🏁"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("cloud", "☁️"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cool", "🆒"),
+    ("crab", "🦀"),
+    ("cry", "😢"),
+    ("crystal_ball", "🔮"),
+    ("dart", "🎯"),
+    ("dog", "🐶"),
+    ("dragon", "🐉"),
+    ("earth_americas", "🌎"),
+    ("envelope", "✉️"),
+    ("exclamation", "❗"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("flashlight", "🔦"),
+    ("floppy_disk", "💾"),
+    ("gear", "⚙️"),
+    ("gem", "💎"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("hammer_and_wrench", "🛠️"),
+    ("hand", "✋"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("heavy_check_mark", "✔️"),
+    ("heavy_minus_sign", "➖"),
+    ("heavy_plus_sign", "➕"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("hugs", "🤗"),
+    ("information_source", "ℹ️"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("keyboard", "⌨️"),
+    ("label", "🏷️"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("loudspeaker", "📢"),
+    ("mag", "🔍"),
+    ("mailbox", "📫"),
+    ("memo", "📝"),
+    ("microscope", "🔬"),
+    ("moneybag", "💰"),
+    ("muscle", "💪"),
+    ("new", "🆕"),
+    ("no_entry", "⛔"),
+    ("no_entry_sign", "🚫"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("page_facing_up", "📄"),
+    ("paperclip", "📎"),
+    ("pencil", "📝"),
+    ("pencil2", "✏️"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("pray", "🙏"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("rainbow", "🌈"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("red_circle", "🔴"),
+    ("rocket", "🚀"),
+    ("rotating_light", "🚨"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("shield", "🛡️"),
+    ("shrug", "🤷"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("snail", "🐌"),
+    ("snake", "🐍"),
+    ("snowflake", "❄️"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("speech_balloon", "💬"),
+    ("star", "⭐"),
+    ("star2", "🌟"),
+    ("stop_sign", "🛑"),
+    ("stopwatch", "⏱️"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("trophy", "🏆"),
+    ("turtle", "🐢"),
+    ("umbrella", "☔"),
+    ("unlock", "🔓"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+/// A preprocessor for replacing emoji shortcodes like `:rocket:` with the
+/// emoji they stand for.
+///
+/// Extra shortcodes can be added in the `[preprocessor.emoji.custom]` table.
+/// If `image-url` is set, emoji are replaced with images instead, for
+/// renderers with poor emoji support. `{codepoints}` in the URL is replaced
+/// with the emoji's codepoints in hex, separated by `-`, which is how
+/// Twemoji's SVGs are named. A relative URL is relative to the root of the
+/// book. Setting `image-renderers` limits the images to those renderers.
+pub struct EmojiPreprocessor;
+
+impl EmojiPreprocessor {
+    pub(crate) const NAME: &'static str = "emoji";
+
+    /// Create a new `EmojiPreprocessor`.
+    pub fn new() -> Self {
+        EmojiPreprocessor
+    }
+}
+
+impl Preprocessor for EmojiPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let table = ctx.config.get_preprocessor(Self::NAME);
+        let mut emoji: HashMap<String, String> = SHORTCODES
+            .iter()
+            .map(|&(code, emoji)| (code.to_string(), emoji.to_string()))
+            .collect();
+
+        if let Some(custom) = table.and_then(|t| t.get("custom")).and_then(|v| v.as_table()) {
+            for (code, value) in custom {
+                match value.as_str() {
+                    Some(value) => emoji.insert(code.clone(), value.to_string()),
+                    None => bail!("The custom emoji \":{}:\" should be a string", code),
+                };
+            }
+        }
+
+        let image_url = table
+            .and_then(|t| t.get("image-url"))
+            .and_then(|v| v.as_str())
+            .filter(|_| {
+                match table.and_then(|t| t.get("image-renderers")).and_then(|v| v.as_array()) {
+                    Some(renderers) => renderers.iter().any(|r| r.as_str() == Some(&ctx.renderer)),
+                    None => true,
+                }
+            });
+
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                let image_url = image_url.map(|url| chapter_image_url(url, ch.path.as_deref()));
+                ch.content = replace_outside_code(&ch.content, |text| {
+                    replace_shortcodes(text, &emoji, image_url.as_deref())
+                });
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+/// The `image-url` for the chapter at `path`, since a relative one is
+/// relative to the root of the book rather than the chapter.
+fn chapter_image_url(url: &str, path: Option<&Path>) -> String {
+    lazy_static! {
+        static ref SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+    }
+
+    match path {
+        Some(path) if !SCHEME.is_match(url) && !url.starts_with('/') => {
+            format!("{}{}", path_to_root(path), url)
+        }
+        _ => url.to_string(),
+    }
+}
+
+fn replace_shortcodes(
+    text: &str,
+    emoji: &HashMap<String, String>,
+    image_url: Option<&str>,
+) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r":([a-zA-Z0-9_+\-]+):").unwrap();
+    }
+
+    RE.replace_all(text, |caps: &Captures| match emoji.get(&caps[1]) {
+        Some(emoji) => match image_url {
+            Some(url) => format!(
+                "<img class=\"emoji\" alt=\"{}\" src=\"{}\">",
+                emoji,
+                url.replace("{codepoints}", &codepoints(emoji))
+            ),
+            None => emoji.clone(),
+        },
+        // e.g. the colons in a time like 10:30:00
+        None => caps[0].to_string(),
+    }).into_owned()
+}
+
+/// The emoji's codepoints, leaving out variation selectors.
+fn codepoints(emoji: &str) -> String {
+    emoji
+        .chars()
+        .filter(|&c| c != '\u{fe0f}')
+        .map(|c| format!("{:x}", c as u32))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::Chapter;
+    use config::Config;
+    use std::path::PathBuf;
+
+    fn builtin() -> HashMap<String, String> {
+        SHORTCODES
+            .iter()
+            .map(|&(code, emoji)| (code.to_string(), emoji.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn shortcodes_are_replaced() {
+        let src = "Launch :rocket: at 10:30:00 :not_an_emoji:";
+
+        let got = replace_shortcodes(src, &builtin(), None);
+
+        assert_eq!(got, "Launch 🚀 at 10:30:00 :not_an_emoji:");
+    }
+
+    #[test]
+    fn shortcodes_can_be_images() {
+        let got = replace_shortcodes(":warning:", &builtin(), Some("svg/{codepoints}.svg"));
+
+        assert_eq!(got, "<img class=\"emoji\" alt=\"⚠️\" src=\"svg/26a0.svg\">");
+    }
+
+    #[test]
+    fn relative_image_urls_are_relative_to_the_root() {
+        let mut config = Config::default();
+        config.set("preprocessor.emoji.image-url", "emoji/{codepoints}.svg").unwrap();
+        let ctx = PreprocessorContext::new(PathBuf::from("/book"), config, "html".into());
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Top", ":tada:".into(), "top.md", Vec::new()))
+            .push_item(Chapter::new("Nested", ":tada:".into(), "a/b/nested.md", Vec::new()));
+
+        let book = EmojiPreprocessor::new().run(&ctx, book).unwrap();
+        let contents: Vec<_> = book
+            .iter()
+            .filter_map(|item| match *item {
+                BookItem::Chapter(ref ch) => Some(ch.content.clone()),
+                _ => None,
+            }).collect();
+
+        assert_eq!(
+            contents,
+            vec![
+                "<img class=\"emoji\" alt=\"🎉\" src=\"emoji/1f389.svg\">",
+                "<img class=\"emoji\" alt=\"🎉\" src=\"../../emoji/1f389.svg\">",
+            ]
+        );
+        assert_eq!(
+            chapter_image_url("https://cdn.example/{codepoints}.svg", Some(Path::new("a/b.md"))),
+            "https://cdn.example/{codepoints}.svg"
+        );
+    }
+
+    #[test]
+    fn shortcodes_in_code_are_left_alone() {
+        let src = "`:tada:` :tada:\n```\n:tada:\n```";
+
+        let got = replace_outside_code(src, |text| replace_shortcodes(text, &builtin(), None));
+
+        assert_eq!(got, "`:tada:` 🎉\n```\n:tada:\n```");
+    }
+
+    #[test]
+    fn shortcodes_are_sorted_and_unique() {
+        for pair in SHORTCODES.windows(2) {
+            assert!(pair[0].0 < pair[1].0, "{} should come after {}", pair[0].0, pair[1].0);
+        }
+    }
+}
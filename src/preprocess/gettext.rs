@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use errors::*;
+use utils;
+use utils::lines_outside_code;
+
+/// A preprocessor which translates a book with the `.po` catalog for its
+/// `book.language`, like `po/ja.po`.
+///
+/// Each paragraph, header, list and other block of text is looked up in the
+/// catalog on its own, along with the chapters' names. Anything without a
+/// translation (or with a fuzzy one) is left as it is, and code blocks are
+/// never touched. `GettextPreprocessor::extract()` makes the template the
+/// catalogs start from.
+pub struct GettextPreprocessor;
+
+impl GettextPreprocessor {
+    pub(crate) const NAME: &'static str = "gettext";
+
+    /// Create a new `GettextPreprocessor`.
+    pub fn new() -> Self {
+        GettextPreprocessor
+    }
+
+    /// The `.pot` template with every translatable message in `book`, which
+    /// says where each of them came from.
+    pub fn extract(book: &Book) -> String {
+        let mut order = Vec::new();
+        let mut references: HashMap<String, Vec<String>> = HashMap::new();
+        let mut add = |message: &str, reference: String| {
+            if !references.contains_key(message) {
+                order.push(message.to_string());
+            }
+            references
+                .entry(message.to_string())
+                .or_default()
+                .push(reference);
+        };
+
+        for item in book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                add(&ch.name, String::from("SUMMARY.md"));
+
+                let file = match ch.source_path.as_ref().or(ch.path.as_ref()) {
+                    Some(path) => utils::fs::normalize_path(&path.display().to_string()),
+                    None => continue,
+                };
+                for (range, line) in messages(&ch.content) {
+                    add(&ch.content[range], format!("{}:{}", file, line));
+                }
+            }
+        }
+
+        let mut pot = String::from(
+            "msgid \"\"\n\
+             msgstr \"\"\n\
+             \"MIME-Version: 1.0\\n\"\n\
+             \"Content-Type: text/plain; charset=UTF-8\\n\"\n\
+             \"Content-Transfer-Encoding: 8bit\\n\"\n",
+        );
+        for message in &order {
+            pot.push('\n');
+            pot.push_str(&format!("#: {}\n", references[message].join(" ")));
+            pot.push_str(&po_string("msgid", message));
+            pot.push_str("msgstr \"\"\n");
+        }
+        pot
+    }
+}
+
+impl Preprocessor for GettextPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let language = match ctx.config.book.language {
+            Some(ref language) => language,
+            None => {
+                debug!("The book doesn't have a language, so it isn't translated");
+                return Ok(book);
+            }
+        };
+
+        let po_dir = ctx
+            .config
+            .get("preprocessor.gettext.po-dir")
+            .and_then(|dir| dir.as_str())
+            .unwrap_or("po");
+        let catalog = ctx.root.join(po_dir).join(format!("{}.po", language));
+        if !catalog.exists() {
+            debug!("There's no {}, so the book isn't translated", catalog.display());
+            return Ok(book);
+        }
+
+        let content = utils::fs::file_to_string(&catalog)?;
+        let translations =
+            parse_po(&content).chain_err(|| format!("Unable to read {}", catalog.display()))?;
+        let translate = |message: &str| match translations.get(message) {
+            Some(translation) => translation.clone(),
+            None => message.to_string(),
+        };
+
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(ref mut ch) = *item {
+                ch.name = translate(&ch.name);
+                ch.parent_names = ch.parent_names.iter().map(|name| translate(name)).collect();
+
+                let mut content = ch.content.clone();
+                for (range, _) in messages(&ch.content).into_iter().rev() {
+                    let translation = translate(&ch.content[range.clone()]);
+                    content.replace_range(range, &translation);
+                }
+                ch.content = content;
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+/// The blocks of text in a chapter which can be translated, as their byte
+/// ranges and the lines they start on. Blank lines separate them, and headers
+/// are on their own. Code blocks aren't translated, so they're skipped, along
+/// with lines which are only an `{{#include}}` (or similar).
+fn messages(content: &str) -> Vec<(Range<usize>, usize)> {
+    let mut messages = Vec::new();
+    let mut block: Option<(usize, usize)> = None;
+    let mut end = 0;
+    let mut offset = 0;
+
+    {
+        let mut finish = |block: &mut Option<(usize, usize)>, end: usize| {
+            if let Some((start, line)) = block.take() {
+                let text = &content[start..end];
+                let indented_code = text
+                    .lines()
+                    .all(|l| l.starts_with("    ") || l.starts_with('\t'));
+                let trimmed = text.trim();
+                let placeholder = !trimmed.contains('\n')
+                    && trimmed.starts_with("{{#")
+                    && trimmed.ends_with("}}");
+                if !indented_code && !placeholder {
+                    messages.push((start..end, line));
+                }
+            }
+        };
+
+        for (i, (line, outside_code)) in lines_outside_code(content).into_iter().enumerate() {
+            let start = offset;
+            offset += line.len() + 1;
+            let line_end = start + line.trim_end_matches('\r').len();
+
+            if !outside_code || line.trim().is_empty() {
+                finish(&mut block, end);
+            } else if is_header(line) {
+                finish(&mut block, end);
+                block = Some((start, i + 1));
+                finish(&mut block, line_end);
+            } else {
+                if block.is_none() {
+                    block = Some((start, i + 1));
+                }
+                end = line_end;
+            }
+        }
+        finish(&mut block, end);
+    }
+
+    messages
+}
+
+fn is_header(line: &str) -> bool {
+    let line = line.trim_start();
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[hashes..];
+    (1..=6).contains(&hashes) && (rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Write a `.po` keyword and its string, splitting the string after each
+/// newline like `xgettext` does.
+fn po_string(keyword: &str, s: &str) -> String {
+    if !s.contains('\n') {
+        return format!("{} \"{}\"\n", keyword, escape_po(s));
+    }
+
+    let mut out = format!("{} \"\"\n", keyword);
+    for line in s.split_inclusive('\n') {
+        out.push_str(&format!("\"{}\"\n", escape_po(line)));
+    }
+    out
+}
+
+fn escape_po(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+/// The translations in a `.po` catalog, leaving out any which are empty or
+/// marked as fuzzy.
+fn parse_po(content: &str) -> Result<HashMap<String, String>> {
+    #[derive(PartialEq)]
+    enum Field {
+        None,
+        Id,
+        Str,
+        Other,
+    }
+
+    let mut translations = HashMap::new();
+    let mut id = String::new();
+    let mut translation = String::new();
+    let mut fuzzy = false;
+    // whether the current entry has got as far as its `msgstr`
+    let mut translated = false;
+    let mut field = Field::None;
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        let unquoted = |s: &str| {
+            unquote(s.trim()).chain_err(|| format!("Line {} has a broken string", i + 1))
+        };
+
+        let starts_entry = line.starts_with('#')
+            || line.starts_with("msgctxt")
+            || (line.starts_with("msgid") && !line.starts_with("msgid_plural"));
+        if starts_entry && translated {
+            if !id.is_empty() && !translation.is_empty() && !fuzzy {
+                translations.insert(id.clone(), translation.clone());
+            }
+            id.clear();
+            translation.clear();
+            fuzzy = false;
+            translated = false;
+        }
+
+        if line.is_empty() {
+            continue;
+        } else if line.starts_with("#,") {
+            fuzzy = fuzzy || line.contains("fuzzy");
+        } else if line.starts_with('#') {
+            continue;
+        } else if line.starts_with("msgctxt") || line.starts_with("msgid_plural") {
+            field = Field::Other;
+        } else if line.starts_with("msgstr[") {
+            // plural translations aren't used
+            field = Field::Other;
+            translated = true;
+        } else if let Some(rest) = line.strip_prefix("msgid") {
+            id = unquoted(rest)?;
+            field = Field::Id;
+        } else if let Some(rest) = line.strip_prefix("msgstr") {
+            translation = unquoted(rest)?;
+            field = Field::Str;
+            translated = true;
+        } else if line.starts_with('"') {
+            let s = unquoted(line)?;
+            match field {
+                Field::Id => id.push_str(&s),
+                Field::Str => translation.push_str(&s),
+                Field::Other => {}
+                Field::None => bail!("Line {} continues a string which hasn't started", i + 1),
+            }
+        } else {
+            bail!("Line {} isn't part of a .po file: {}", i + 1, line);
+        }
+    }
+    if !id.is_empty() && !translation.is_empty() && !fuzzy {
+        translations.insert(id, translation);
+    }
+
+    Ok(translations)
+}
+
+fn unquote(s: &str) -> Result<String> {
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        bail!("{} isn't a quoted string", s);
+    }
+
+    let mut unquoted = String::new();
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unquoted.push('\n'),
+            Some('t') => unquoted.push('\t'),
+            Some('r') => unquoted.push('\r'),
+            Some(c @ '"') | Some(c @ '\\') => unquoted.push(c),
+            Some(c) => bail!("\\{} isn't an escape sequence .po files have", c),
+            None => bail!("{} ends in the middle of an escape sequence", s),
+        }
+    }
+    Ok(unquoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::Chapter;
+
+    const CHAPTER: &str = "# Usage\n\
+                           \n\
+                           Run it like this,\n\
+                           with a file:\n\
+                           \n\
+                           ```sh\n\
+                           \n\
+                           mdbook build\n\
+                           ```\n\
+                           \n\
+                           {{#include example.md}}\n\
+                           \n\
+                           - One\n\
+                           - Two\n";
+
+    fn texts(content: &str) -> Vec<(&str, usize)> {
+        messages(content)
+            .into_iter()
+            .map(|(range, line)| (&content[range], line))
+            .collect()
+    }
+
+    #[test]
+    fn code_blocks_are_left_out_of_the_messages() {
+        assert_eq!(
+            texts(CHAPTER),
+            vec![
+                ("# Usage", 1),
+                ("Run it like this,\nwith a file:", 3),
+                ("- One\n- Two", 13),
+            ]
+        );
+        assert_eq!(
+            texts("Some text\n## Header\nMore\n\n    let x = 1;\n"),
+            vec![("Some text", 1), ("## Header", 2), ("More", 3)]
+        );
+    }
+
+    #[test]
+    fn the_template_lists_every_message_once() {
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Usage", CHAPTER.to_string(), "usage.md", Vec::new()));
+        book.push_item(Chapter::new(
+            "Again",
+            String::from("- One\n- Two\n"),
+            "again.md",
+            Vec::new(),
+        ));
+
+        let pot = GettextPreprocessor::extract(&book);
+        assert!(pot.contains("#: SUMMARY.md\nmsgid \"Usage\"\nmsgstr \"\"\n"));
+        assert!(pot.contains("#: usage.md:1\nmsgid \"# Usage\"\nmsgstr \"\"\n"));
+        assert!(pot.contains(
+            "#: usage.md:3\nmsgid \"\"\n\"Run it like this,\\n\"\n\"with a file:\"\nmsgstr \"\"\n"
+        ));
+        assert!(pot.contains("#: usage.md:13 again.md:1\nmsgid \"\"\n\"- One\\n\"\n\"- Two\"\n"));
+        assert!(!pot.contains("mdbook build"));
+    }
+
+    #[test]
+    fn translations_are_read_from_po_files() {
+        let po = "msgid \"\"\n\
+                  msgstr \"\"\n\
+                  \"Content-Type: text/plain; charset=UTF-8\\n\"\n\
+                  \n\
+                  #: usage.md:1\n\
+                  msgid \"# Usage\"\n\
+                  msgstr \"# 使い方\"\n\
+                  \n\
+                  msgid \"\"\n\
+                  \"Run it like this,\\n\"\n\
+                  \"with a file:\"\n\
+                  msgstr \"こう \\\"実行\\\" します：\"\n\
+                  \n\
+                  #, fuzzy\n\
+                  msgid \"- One\\n- Two\"\n\
+                  msgstr \"- 一\\n- 二\"\n\
+                  \n\
+                  msgid \"Usage\"\n\
+                  msgstr \"\"\n";
+
+        let translations = parse_po(po).unwrap();
+        assert_eq!(translations.len(), 2);
+        assert_eq!(translations["# Usage"], "# 使い方");
+        assert_eq!(translations["Run it like this,\nwith a file:"], "こう \"実行\" します：");
+
+        assert!(parse_po("msgid \"Oops\nmsgstr \"\"").is_err());
+        assert!(parse_po("Hello").is_err());
+    }
+}
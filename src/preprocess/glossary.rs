@@ -0,0 +1,277 @@
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use utils::fs::{normalize_path, path_to_root};
+use utils::{escape_html, id_from_content, lines_outside_code};
+
+const DEFAULT_GLOSSARY_CHAPTER: &str = "glossary.md";
+
+/// A preprocessor which marks up the first use of each glossary term in a
+/// chapter with an `<abbr>` tag containing its definition.
+///
+/// Terms come from the `[preprocessor.glossary.terms]` table as well as the
+/// glossary chapter (`glossary.md` unless `chapter` says otherwise), where
+/// each second-level (or deeper) heading is a term and the paragraph after it
+/// is its definition. Terms defined in the glossary chapter also link back to
+/// their heading.
+pub struct GlossaryPreprocessor;
+
+impl GlossaryPreprocessor {
+    pub(crate) const NAME: &'static str = "glossary";
+
+    /// Create a new `GlossaryPreprocessor`.
+    pub fn new() -> Self {
+        GlossaryPreprocessor
+    }
+}
+
+impl Preprocessor for GlossaryPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let table = ctx.config.get_preprocessor(Self::NAME);
+        let chapter = table
+            .and_then(|t| t.get("chapter"))
+            .and_then(|v| v.as_str())
+            .unwrap_or(DEFAULT_GLOSSARY_CHAPTER);
+        let glossary_path = PathBuf::from(chapter);
+
+        let mut terms = Vec::new();
+
+        if let Some(configured) = table.and_then(|t| t.get("terms")).and_then(|v| v.as_table()) {
+            for (name, definition) in configured {
+                match definition.as_str() {
+                    Some(definition) => terms.push(Term::new(name, definition, None)),
+                    None => bail!("The definition of the glossary term \"{}\" should be a string", name),
+                }
+            }
+        }
+
+        for item in book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                if let Some(ref path) = ch.path {
+                    if *path == glossary_path {
+                        terms.extend(parse_glossary(&ch.content, path));
+                    }
+                }
+            }
+        }
+
+        // prefer the longest match when one term contains another
+        terms.sort_by_key(|term| Reverse(term.name.len()));
+
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                let root = match ch.path {
+                    Some(ref path) if *path != glossary_path => path_to_root(path),
+                    _ => return,
+                };
+
+                for term in &terms {
+                    ch.content = mark_first_use(&ch.content, term, &root);
+                }
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    name: String,
+    definition: String,
+    /// The term's heading in the glossary chapter, relative to the book's
+    /// `src` directory.
+    link: Option<String>,
+}
+
+impl Term {
+    fn new(name: &str, definition: &str, link: Option<String>) -> Term {
+        Term {
+            name: name.to_string(),
+            definition: definition.to_string(),
+            link,
+        }
+    }
+
+    fn render(&self, text: &str, root: &str) -> String {
+        let text = match self.link {
+            Some(ref link) => format!("[{}]({}{})", text, root, link),
+            None => text.to_string(),
+        };
+
+        format!("<abbr title=\"{}\">{}</abbr>", escape_html(&self.definition), text)
+    }
+}
+
+/// Read the terms out of the glossary chapter's headings.
+fn parse_glossary(content: &str, path: &Path) -> Vec<Term> {
+    let path = normalize_path(&path.display().to_string());
+    let mut terms = Vec::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+
+    for (line, outside_code) in lines_outside_code(content) {
+        if !outside_code {
+            continue;
+        }
+
+        let trimmed = line.trim();
+
+        if trimmed.starts_with("##") {
+            terms.extend(current.take());
+            let name = trimmed.trim_start_matches('#').trim();
+            current = Some((name.to_string(), Vec::new()));
+        } else if trimmed.starts_with('#') {
+            terms.extend(current.take());
+        } else if let Some((_, ref mut definition)) = current {
+            if !trimmed.is_empty() {
+                definition.push(trimmed);
+            } else if !definition.is_empty() {
+                // only the first paragraph is used as the definition
+                terms.extend(current.take());
+            }
+        }
+    }
+    terms.extend(current);
+
+    terms
+        .into_iter()
+        .filter(|(name, _)| !name.is_empty())
+        .map(|(name, definition)| {
+            let link = format!("{}#{}", path, id_from_content(&name));
+            Term::new(&name, &definition.join(" "), Some(link))
+        }).collect()
+}
+
+/// Mark up the first use of a term, skipping code, headings, links and HTML.
+fn mark_first_use(content: &str, term: &Term, root: &str) -> String {
+    let mut offset = 0;
+
+    for (line, outside_code) in lines_outside_code(content) {
+        let line_start = offset;
+        offset += line.len() + 1;
+
+        if !outside_code || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if let Some(ix) = find_term(line, &term.name) {
+            let start = line_start + ix;
+            let end = start + term.name.len();
+
+            return format!(
+                "{}{}{}",
+                &content[..start],
+                term.render(&content[start..end], root),
+                &content[end..]
+            );
+        }
+    }
+
+    content.to_string()
+}
+
+fn find_term(line: &str, term: &str) -> Option<usize> {
+    lazy_static! {
+        static ref PROTECTED: Regex = Regex::new(
+            r"(?x)
+            <abbr[^>]*>.*?</abbr>       # a term we've already marked up
+            | `+[^`]*`+                 # inline code
+            | <[^>]*>                   # HTML tags
+            | \[[^\]]*\]\([^)]*\)       # inline links
+            | \[[^\]]*\](\[[^\]]*\])?   # reference links and footnotes"
+        ).unwrap();
+    }
+
+    if term.is_empty() {
+        return None;
+    }
+
+    let protected: Vec<_> = PROTECTED
+        .find_iter(line)
+        .map(|m| (m.start(), m.end()))
+        .collect();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    line.match_indices(term).map(|(ix, _)| ix).find(|&ix| {
+        let end = ix + term.len();
+        let starts_word = !line[..ix].chars().next_back().map_or(false, &is_word);
+        let ends_word = !line[end..].chars().next().map_or(false, &is_word);
+        let is_protected = protected.iter().any(|&(start, stop)| ix < stop && end > start);
+
+        starts_word && ends_word && !is_protected
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api() -> Term {
+        Term::new("API", "Application \"Programming\" Interface", None)
+    }
+
+    #[test]
+    fn only_the_first_use_is_marked_up() {
+        let src = "# APIs\n\nAn API, or two APIs. Another API.";
+        let should_be = "# APIs\n\nAn <abbr title=\"Application &quot;Programming&quot; Interface\">API</abbr>, \
+                         or two APIs. Another API.";
+
+        assert_eq!(mark_first_use(src, &api(), ""), should_be);
+    }
+
+    #[test]
+    fn code_and_links_are_skipped() {
+        let src = "```\nAPI\n```\n`API` [API](api.md) <a title=\"API\">link</a> API";
+        let should_be = "```\nAPI\n```\n`API` [API](api.md) <a title=\"API\">link</a> \
+                         <abbr title=\"Application &quot;Programming&quot; Interface\">API</abbr>";
+
+        assert_eq!(mark_first_use(src, &api(), ""), should_be);
+    }
+
+    #[test]
+    fn glossary_terms_link_to_their_heading() {
+        let term = Term::new("Crate", "A package", Some("glossary.md#crate".to_string()));
+        let src = "Every Crate";
+        let should_be = "Every <abbr title=\"A package\">[Crate](../glossary.md#crate)</abbr>";
+
+        assert_eq!(mark_first_use(src, &term, "../"), should_be);
+    }
+
+    #[test]
+    fn longer_terms_arent_marked_up_twice() {
+        let long = Term::new("API key", "A secret", None);
+        let src = "Your API key";
+
+        let got = mark_first_use(&mark_first_use(src, &long, ""), &api(), "");
+
+        assert_eq!(got, "Your <abbr title=\"A secret\">API key</abbr>");
+    }
+
+    #[test]
+    fn parse_the_glossary_chapter() {
+        let src = "# Glossary\n\nSome intro.\n\n## Crate\n\nA package\nof code.\n\nMore text.\n\n\
+                   ```\n## not a term\n```\n\n### Trait Object\nDynamic dispatch.\n";
+        let should_be = vec![
+            Term::new("Crate", "A package of code.", Some("glossary.md#crate".to_string())),
+            Term::new(
+                "Trait Object",
+                "Dynamic dispatch.",
+                Some("glossary.md#trait-object".to_string()),
+            ),
+        ];
+
+        let got = parse_glossary(src, Path::new("glossary.md"));
+
+        assert_eq!(got, should_be);
+    }
+}
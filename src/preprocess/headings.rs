@@ -0,0 +1,217 @@
+use regex::Regex;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::Book;
+use diagnostics::{chapter_file, Diagnostic};
+use utils::{lines_outside_code, parse_heading};
+
+/// A preprocessor which checks the levels of each chapter's headings, and
+/// can shift them.
+///
+/// A chapter should start with an h1 and never skip a level (like an h4
+/// straight after an h2), or its table of contents and the EPUB's structure
+/// come out wrong, so either is warned about. With `normalize`, a chapter's
+/// headings are shifted so the highest of them is an h1, and `offset` moves
+/// every heading that many levels down (or up, if it's negative), after
+/// which chapters are expected to start at h1 plus the offset.
+pub struct HeadingsPreprocessor;
+
+impl HeadingsPreprocessor {
+    pub(crate) const NAME: &'static str = "headings";
+
+    /// Create a new `HeadingsPreprocessor`.
+    pub fn new() -> Self {
+        HeadingsPreprocessor
+    }
+}
+
+impl Preprocessor for HeadingsPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let table = ctx.config.get_preprocessor(Self::NAME);
+        let offset = table
+            .and_then(|t| t.get("offset"))
+            .and_then(|v| v.as_integer())
+            .unwrap_or(0);
+        let normalize = table
+            .and_then(|t| t.get("normalize"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        book.par_for_each_chapter_mut(|ch| {
+            let (content, warnings) = shift_headings(&ch.content, offset, normalize);
+            let file = chapter_file(ch);
+            for (line, message) in warnings {
+                Diagnostic::warning(message).in_file(&file).at_line(line).emit();
+            }
+
+            ch.content = content;
+            Ok(())
+        })?;
+
+        Ok(book)
+    }
+}
+
+/// A heading, found on `line` of a chapter (counting from 0).
+struct Heading {
+    line: usize,
+    level: usize,
+    /// Whether it's a setext heading, underlined on the next line.
+    setext: bool,
+}
+
+/// Shift a chapter's headings by `offset` levels, first making the highest
+/// an h1 if `normalize`. Setext headings which are shifted become ATX ones.
+/// Along with the chapter comes what's wrong with its heading levels, as
+/// line numbers (counting from 1) and warnings.
+fn shift_headings(content: &str, offset: i64, normalize: bool) -> (String, Vec<(usize, String)>) {
+    let mut lines: Vec<_> = content.split('\n').map(|line| Some(line.to_string())).collect();
+    let headings = find_headings(content);
+
+    let highest = headings.iter().map(|h| h.level).min().unwrap_or(1);
+    let shift = offset + if normalize { 1 - highest as i64 } else { 0 };
+    let shifted = |level: usize| (level as i64 + shift).clamp(1, 6) as usize;
+
+    let mut warnings = Vec::new();
+    let expected = (1 + offset).clamp(1, 6) as usize;
+    let mut previous = None;
+
+    for heading in &headings {
+        let level = shifted(heading.level);
+        match previous {
+            None if level != expected => {
+                let message = format!(
+                    "The chapter starts with an h{} heading rather than an h{}",
+                    level, expected
+                );
+                warnings.push((heading.line + 1, message));
+            }
+            Some(previous) if level > previous + 1 => {
+                let message =
+                    format!("An h{} heading follows an h{}, skipping a level", level, previous);
+                warnings.push((heading.line + 1, message));
+            }
+            _ => {}
+        }
+        previous = Some(level);
+
+        if shift == 0 {
+            continue;
+        }
+        let line = lines[heading.line].take().unwrap_or_default();
+        lines[heading.line] = Some(if heading.setext {
+            lines[heading.line + 1] = None;
+            format!("{} {}", "#".repeat(level), line.trim())
+        } else {
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            let rest = &line[indent + heading.level..];
+            format!("{}{}{}", &line[..indent], "#".repeat(level), rest)
+        });
+    }
+
+    let lines: Vec<_> = lines.into_iter().flatten().collect();
+    (lines.join("\n"), warnings)
+}
+
+/// The ATX and setext headings in a chapter, leaving out its code blocks.
+fn find_headings(content: &str) -> Vec<Heading> {
+    lazy_static! {
+        static ref UNDERLINE: Regex = Regex::new(r"^ {0,3}(=+|-+)\s*$").unwrap();
+        static ref NOT_A_PARAGRAPH: Regex = Regex::new(r"^\s*([>*+|<-]|\d+[.)])").unwrap();
+    }
+
+    let lines: Vec<_> = content.split('\n').collect();
+    let outside_code = lines_outside_code(content);
+    let mut headings = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        if !outside_code[i].1 {
+            continue;
+        }
+
+        if let Some((level, _)) = parse_heading(line) {
+            headings.push(Heading {
+                line: i,
+                level,
+                setext: false,
+            });
+            continue;
+        }
+
+        // only a one-line paragraph is turned into a setext heading, since
+        // the rest of a longer one would be left behind if it were shifted
+        let underline = match UNDERLINE.captures(line) {
+            Some(ref caps) if i > 0 => caps[1].starts_with('='),
+            _ => continue,
+        };
+        let text = lines[i - 1];
+        let on_its_own = i == 1 || lines[i - 2].trim().is_empty()
+            || matches!(headings.last(), Some(h) if h.line == i - 2);
+        if !text.trim().is_empty() && !NOT_A_PARAGRAPH.is_match(text) && on_its_own
+            && parse_heading(text).is_none()
+        {
+            headings.push(Heading {
+                line: i - 1,
+                level: if underline { 1 } else { 2 },
+                setext: true,
+            });
+        }
+    }
+
+    headings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn good_headings_are_left_alone() {
+        let src = "# Chapter\n\nText\n\n## Section\n\n```md\n#### Not a heading\n```\n\
+                   Subsection\n----------\n\n- item\n---\n\n# Another";
+
+        assert_eq!(shift_headings(src, 0, false), (String::from(src), vec![]));
+    }
+
+    #[test]
+    fn skipped_levels_are_warned_about() {
+        let src = "Intro\n\n## Chapter\n\n#### Too deep\n\n### Fine";
+
+        let (content, warnings) = shift_headings(src, 0, false);
+
+        assert_eq!(content, src);
+        assert_eq!(
+            warnings,
+            vec![
+                (3, String::from("The chapter starts with an h2 heading rather than an h1")),
+                (5, String::from("An h4 heading follows an h2, skipping a level")),
+            ]
+        );
+    }
+
+    #[test]
+    fn headings_are_normalized() {
+        let src = "### Chapter\n\n  #### Section ####\n\n####### Not a heading";
+
+        assert_eq!(
+            shift_headings(src, 0, true),
+            (String::from("# Chapter\n\n  ## Section ####\n\n####### Not a heading"), vec![])
+        );
+    }
+
+    #[test]
+    fn shifted_setext_headings_become_atx_ones() {
+        let src = "Chapter\n=======\n\nText\n\nSection\n-------";
+
+        assert_eq!(
+            shift_headings(src, 1, false),
+            (String::from("## Chapter\n\nText\n\n### Section"), vec![])
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use utils::{split_code_blocks, Block};
+
+/// A preprocessor for removing "hidden" lines from code blocks in languages
+/// other than Rust.
+///
+/// Each language gets its own prefix in the `[preprocessor.hide-lines]`
+/// table, for example `scala = "// hide"`. Any line inside a fenced code
+/// block of that language which starts with the prefix (ignoring leading
+/// whitespace) is removed from the chapter.
+///
+/// `mdbook test` only runs the preprocessors in `test.preprocessors`, so
+/// hidden lines are still there when testing unless this is one of them.
+pub struct HideLinesPreprocessor;
+
+impl HideLinesPreprocessor {
+    pub(crate) const NAME: &'static str = "hide-lines";
+
+    /// Create a new `HideLinesPreprocessor`.
+    pub fn new() -> Self {
+        HideLinesPreprocessor
+    }
+}
+
+impl Preprocessor for HideLinesPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let prefixes = hidden_prefixes(ctx);
+        if prefixes.is_empty() {
+            return Ok(book);
+        }
+
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                ch.content = hide_lines(&ch.content, &prefixes);
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+/// Read the language to prefix mapping out of the preprocessor's table,
+/// ignoring anything which isn't a string (e.g. `renderers`).
+fn hidden_prefixes(ctx: &PreprocessorContext) -> HashMap<String, String> {
+    let mut prefixes = HashMap::new();
+
+    if let Some(table) = ctx.config.get_preprocessor(HideLinesPreprocessor::NAME) {
+        for (language, prefix) in table {
+            if language == "command" {
+                continue;
+            }
+
+            if let Some(prefix) = prefix.as_str() {
+                prefixes.insert(language.clone(), prefix.to_string());
+            }
+        }
+    }
+
+    prefixes
+}
+
+fn hide_lines(content: &str, prefixes: &HashMap<String, String>) -> String {
+    let mut blocks = Vec::new();
+
+    for mut block in split_code_blocks(content) {
+        if let Block::Code(ref mut code) = block {
+            if let Some(prefix) = code.language().and_then(|language| prefixes.get(language)) {
+                code.lines.retain(|line| !line.trim_start().starts_with(&prefix[..]));
+            }
+        }
+        blocks.push(block.to_markdown());
+    }
+
+    blocks.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefixes() -> HashMap<String, String> {
+        let mut prefixes = HashMap::new();
+        prefixes.insert("scala".to_string(), "// hide".to_string());
+        prefixes.insert("python".to_string(), "# hide".to_string());
+        prefixes
+    }
+
+    #[test]
+    fn hidden_lines_are_removed_from_matching_blocks() {
+        let src = "Text\n\n```scala\n// hide import foo._\nval x = 1\n  // hide val y = 2\n```\n";
+        let should_be = "Text\n\n```scala\nval x = 1\n```\n";
+
+        assert_eq!(hide_lines(src, &prefixes()), should_be);
+    }
+
+    #[test]
+    fn other_languages_are_left_alone() {
+        let src = "```rust\n// hide this is a normal comment\n```\n```\n# hide\n```";
+
+        assert_eq!(hide_lines(src, &prefixes()), src);
+    }
+
+    #[test]
+    fn text_outside_code_blocks_is_left_alone() {
+        let src = "# hide is a heading\n\n```python\n# hide\nprint(1)\n```\n# hide";
+        let should_be = "# hide is a heading\n\n```python\nprint(1)\n```\n# hide";
+
+        assert_eq!(hide_lines(src, &prefixes()), should_be);
+    }
+
+    #[test]
+    fn fence_attributes_dont_affect_the_language() {
+        let src = "~~~~scala,editable\n// hide\n```\n// hide\n~~~~";
+        let should_be = "~~~~scala,editable\n```\n~~~~";
+
+        assert_eq!(hide_lines(src, &prefixes()), should_be);
+    }
+}
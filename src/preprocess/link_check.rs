@@ -0,0 +1,350 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use pulldown_cmark::{OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use regex::Regex;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use diagnostics::{line_at, Diagnostic};
+use utils;
+use utils::fs::{normalize_path, resolve_relative_link};
+
+/// A preprocessor which checks that every relative link in the book points
+/// at something, and that links to a header of a chapter point at one which
+/// exists. Headers which get the same ID as an earlier one in their chapter
+/// are warned about too, since nothing can link to them by that ID.
+///
+/// Broken links fail the build, unless `warn-only` is set in the
+/// `[preprocessor.link-check]` table. The book itself is left as it is.
+pub struct LinkCheckPreprocessor;
+
+impl LinkCheckPreprocessor {
+    pub(crate) const NAME: &'static str = "link-check";
+
+    /// Create a new `LinkCheckPreprocessor`.
+    pub fn new() -> Self {
+        LinkCheckPreprocessor
+    }
+}
+
+impl Preprocessor for LinkCheckPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, book: Book) -> Result<Book> {
+        let warn_only = ctx
+            .config
+            .get_preprocessor(Self::NAME)
+            .and_then(|t| t.get("warn-only"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let src_dir = ctx.root.join(&ctx.config.book.src);
+
+        let chapters = chapter_ids(&book);
+        let mut broken = Vec::new();
+        for item in book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                // Links are relative to the file the chapter came from, which
+                // is also the name it should be reported under
+                if let Some(path) = ch.source_path.as_ref().or(ch.path.as_ref()) {
+                    let path = normalize_path(&path.display().to_string());
+                    for (link, problem, line) in
+                        check_links(&path, &ch.content, &chapters, &ctx.root, &src_dir)
+                    {
+                        let message = format!("Broken link \"{}\" {}", link, problem);
+                        let diagnostic = if warn_only {
+                            Diagnostic::warning(message)
+                        } else {
+                            Diagnostic::error(message)
+                        };
+                        broken.push(diagnostic.in_file(path.clone()).at_line(line));
+                    }
+                    for (id, line) in duplicate_headers(&ch.content) {
+                        let message = format!(
+                            "Another header already has the ID \"{}\", so links to it go there",
+                            id
+                        );
+                        Diagnostic::warning(message).in_file(path.clone()).at_line(line).emit();
+                    }
+                }
+            }
+        }
+
+        let count = broken.len();
+        for diagnostic in broken {
+            diagnostic.emit();
+        }
+        if count > 0 && !warn_only {
+            // each link has already been logged, along with where it is
+            bail!("Found {} broken link(s)", count);
+        }
+
+        Ok(book)
+    }
+}
+
+/// The IDs which can be linked to in each chapter, keyed by both the
+/// chapter's path and the file it came from (which differ for a `README.md`
+/// turned into `index.md`).
+fn chapter_ids(book: &Book) -> HashMap<String, HashSet<String>> {
+    let mut chapters = HashMap::new();
+
+    for item in book.iter() {
+        if let BookItem::Chapter(ref ch) = *item {
+            let ids = link_targets(&ch.content);
+            for path in ch.path.iter().chain(ch.source_path.iter()) {
+                chapters.insert(normalize_path(&path.display().to_string()), ids.clone());
+            }
+        }
+    }
+
+    chapters
+}
+
+/// Every ID in a chapter's HTML, including the ones its headers will get.
+fn link_targets(content: &str) -> HashSet<String> {
+    lazy_static! {
+        static ref ID: Regex = Regex::new(r#"\sid="([^"]+)""#).unwrap();
+    }
+
+    let html = utils::render_markdown(content, false);
+    let mut ids: HashSet<String> = ID
+        .captures_iter(&html)
+        .map(|caps| caps[1].to_string())
+        .collect();
+
+    // Repeated header IDs get a number on the end, in the same way they do
+    // when the HTML renderer adds them
+    let mut counter = HashMap::new();
+    for raw_id in header_ids(&html) {
+        let count = counter.entry(raw_id.clone()).or_insert(0);
+        ids.insert(match *count {
+            0 => raw_id,
+            other => format!("{}-{}", raw_id, other),
+        });
+        *count += 1;
+    }
+
+    ids
+}
+
+/// The ID each header in a chapter's HTML asks for, in order, before repeated
+/// ones are told apart.
+fn header_ids(html: &str) -> Vec<String> {
+    lazy_static! {
+        static ref HEADER: Regex = Regex::new(r#"<h\d(?: id="([^"]+)")?>(.*?)</h\d>"#).unwrap();
+    }
+
+    HEADER
+        .captures_iter(html)
+        .map(|caps| match caps.get(1) {
+            Some(id) => id.as_str().to_string(),
+            None => utils::id_from_content(&caps[2]),
+        }).collect()
+}
+
+/// The headers in a chapter with the same ID as an earlier one, and the line
+/// each of them is on.
+fn duplicate_headers(content: &str) -> Vec<(String, usize)> {
+    let mut lines = Vec::new();
+    let mut parser = Parser::new_ext(content, options());
+    while let Some(event) = parser.next() {
+        if let Event::Start(Tag::Header(_)) = event {
+            lines.push(line_at(content, parser.get_offset()));
+        }
+    }
+
+    let html = utils::render_markdown(content, false);
+    let mut seen = HashSet::new();
+    header_ids(&html)
+        .into_iter()
+        .zip(lines)
+        .filter(|header| !seen.insert(header.0.clone()))
+        .collect()
+}
+
+fn options() -> Options {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+    opts
+}
+
+/// Find the relative links in a chapter which don't point at anything,
+/// alongside what's wrong with them and the line they're on.
+fn check_links(
+    path: &str,
+    content: &str,
+    chapters: &HashMap<String, HashSet<String>>,
+    root: &Path,
+    src_dir: &Path,
+) -> Vec<(String, String, usize)> {
+    lazy_static! {
+        static ref SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+    }
+
+    let mut broken = Vec::new();
+    let mut parser = Parser::new_ext(content, options());
+    while let Some(event) = parser.next() {
+        let link = match event {
+            Event::Start(Tag::Link(dest, _)) | Event::Start(Tag::Image(dest, _)) => dest,
+            _ => continue,
+        };
+        if link.is_empty() || SCHEME.is_match(&link) || link.starts_with('/') {
+            continue;
+        }
+
+        // it's somewhere outside the book, like another book in the workspace
+        if leaves_book(path, &link) {
+            continue;
+        }
+
+        let (target, fragment) = match link.find('#') {
+            Some(i) => (&link[..i], Some(&link[i + 1..])),
+            None => (&link[..], None),
+        };
+        let target = if target.is_empty() {
+            path.to_string()
+        } else {
+            resolve_relative_link(path, &target.replace("%20", " "))
+        };
+        let chapter_md = if target.ends_with(".html") {
+            format!("{}.md", &target[..target.len() - ".html".len()])
+        } else {
+            target.clone()
+        };
+
+        let problem = match chapters.get(&chapter_md) {
+            Some(ids) => match fragment {
+                Some(fragment) if !ids.contains(fragment) => {
+                    format!("points at a header which isn't in {}", chapter_md)
+                }
+                _ => continue,
+            },
+            None if target.ends_with(".md") => {
+                format!("points at {}, which isn't a chapter", target)
+            }
+            None if src_dir.join(&target).exists() || root.join(&target).exists() => continue,
+            None => format!("points at {}, which doesn't exist", target),
+        };
+        let line = line_at(content, parser.get_offset());
+        broken.push((link.to_string(), problem, line));
+    }
+
+    broken
+}
+
+/// Whether a relative `link` in the chapter at `path` climbs out of the
+/// book's source directory.
+fn leaves_book(path: &str, link: &str) -> bool {
+    let mut depth = path.split('/').count() - 1;
+    for part in link.split('#').next().unwrap_or_default().split('/') {
+        match part {
+            ".." if depth == 0 => return true,
+            ".." => depth -= 1,
+            "" | "." => {}
+            _ => depth += 1,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::Chapter;
+    use config::Config;
+    use tempfile::Builder as TempFileBuilder;
+
+    fn book() -> Book {
+        let mut book = Book::new();
+        let mut readme = Chapter::new(
+            "Intro",
+            String::from("# Intro\n\n## Setup {#install}"),
+            "README.md",
+            Vec::new(),
+        );
+        readme.path = Some("index.md".into());
+        book.push_item(readme);
+        book.push_item(Chapter::new(
+            "Usage",
+            String::from(
+                "# Usage\n\n## Usage\n\n[a](../README.md#install) [b](#usage-1) [c](../index.html) \
+                 [d](https://example.com/) [e](img/a.png) [j](../../http/intro.md)\n\n\
+                 [f](../README.md#setup) [g](missing.md) [h](#nope) [i](img/b.png)",
+            ),
+            "guide/usage.md",
+            Vec::new(),
+        ));
+        book
+    }
+
+    #[test]
+    fn only_broken_links_are_reported() {
+        let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+        utils::fs::write_file(temp.path(), "src/guide/img/a.png", b"").unwrap();
+        let book = book();
+        let chapters = chapter_ids(&book);
+
+        let got: Vec<_> = check_links(
+            "guide/usage.md",
+            &book
+                .iter()
+                .nth(1)
+                .and_then(|item| match *item {
+                    BookItem::Chapter(ref ch) => Some(ch.content.clone()),
+                    _ => None,
+                })
+                .unwrap(),
+            &chapters,
+            temp.path(),
+            &temp.path().join("src"),
+        )
+        .into_iter()
+        .map(|(link, _, line)| (link, line))
+        .collect();
+
+        assert_eq!(
+            got,
+            vec![
+                (String::from("../README.md#setup"), 7),
+                (String::from("missing.md"), 7),
+                (String::from("#nope"), 7),
+                (String::from("img/b.png"), 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn headers_with_the_same_id_are_found() {
+        let content = "# Usage\n\n## Usage\n\nText\n\n## Setup {#install}\n\n## Usage\n";
+        assert_eq!(
+            duplicate_headers(content),
+            vec![(String::from("usage"), 3), (String::from("usage"), 9)]
+        );
+        assert!(duplicate_headers("# Intro\n\n## Setup {#install}").is_empty());
+    }
+
+    #[test]
+    fn broken_links_fail_the_build_unless_told_otherwise() {
+        let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+        let mut config = Config::default();
+        let ctx =
+            PreprocessorContext::new(temp.path().to_path_buf(), config.clone(), "html".into());
+        assert!(LinkCheckPreprocessor::new().run(&ctx, book()).is_err());
+
+        config
+            .set("preprocessor.link-check.warn-only", true)
+            .unwrap();
+        let ctx = PreprocessorContext::new(temp.path().to_path_buf(), config, "html".into());
+        assert_eq!(
+            LinkCheckPreprocessor::new().run(&ctx, book()).unwrap(),
+            book()
+        );
+    }
+}
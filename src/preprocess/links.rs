@@ -3,10 +3,14 @@ use regex::{CaptureMatches, Captures, Regex};
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
 use std::path::{Path, PathBuf};
 use utils::fs::file_to_string;
-use utils::take_lines;
+use utils::{
+    id_from_content, lines_outside_code, parse_heading, replace_outside_code, take_anchored_lines,
+    take_lines,
+};
 
 use super::{Preprocessor, PreprocessorContext};
 use book::{Book, BookItem};
+use diagnostics::{chapter_file, Diagnostic};
 
 const ESCAPE_CHAR: char = '\\';
 const MAX_LINK_NESTED_DEPTH: usize = 10;
@@ -34,14 +38,18 @@ impl Preprocessor for LinkPreprocessor {
 
         book.for_each_mut(|section: &mut BookItem| {
             if let BookItem::Chapter(ref mut ch) = *section {
-                let base = ch
-                    .path
-                    .parent()
-                    .map(|dir| src_dir.join(dir))
-                    .expect("All book items have a parent");
-
-                let content = replace_all(&ch.content, base, &ch.path, 0);
-                ch.content = content;
+                if let Some(ref chapter_path) = ch.path {
+                    // includes are relative to where the chapter was loaded
+                    // from, which might not be its path in a translation
+                    let source_path = ch.source_path.as_ref().unwrap_or(chapter_path);
+                    let base = source_path
+                        .parent()
+                        .map(|dir| src_dir.join(dir))
+                        .expect("All book items have a parent");
+
+                    let content = replace_all(&ch.content, base, chapter_file(ch), 0);
+                    ch.content = content;
+                }
             }
         });
 
@@ -68,29 +76,39 @@ where
         match playpen.render_with_path(&path) {
             Ok(new_content) => {
                 if depth < MAX_LINK_NESTED_DEPTH {
-                    if let Some(rel_path) = playpen.link.relative_path(path) {
-                        replaced.push_str(&replace_all(
-                            &new_content,
-                            rel_path,
-                            source,
-                            depth + 1,
-                        ));
-                    } else {
-                        replaced.push_str(&new_content);
+                    let section_file = match playpen.link {
+                        LinkType::IncludeSection(ref file, _) => Some(file.clone()),
+                        _ => None,
+                    };
+                    let mut new_content = match playpen.link.relative_path(path) {
+                        Some(rel_path) => replace_all(&new_content, rel_path, source, depth + 1),
+                        None => new_content,
+                    };
+                    // a section of another chapter links to things from there
+                    if let Some(file) = section_file {
+                        new_content = rewrite_relative_links(&new_content, &file);
                     }
+                    replaced.push_str(&new_content);
                 } else {
-                    error!(
-                        "Stack depth exceeded in {}. Check for cyclic includes",
-                        source.display()
-                    );
+                    Diagnostic::error("Stack depth exceeded. Check for cyclic includes")
+                        .in_file(source)
+                        .emit();
                 }
                 previous_end_index = playpen.end_index;
             }
             Err(e) => {
-                error!("Error updating \"{}\", {}", playpen.link_text, e);
-                for cause in e.iter().skip(1) {
-                    warn!("Caused By: {}", cause);
+                let causes: Vec<_> = e.iter().map(|cause| cause.to_string()).collect();
+                let message = format!(
+                    "Error updating \"{}\", {}",
+                    playpen.link_text,
+                    causes.join(": ")
+                );
+                let mut diagnostic = Diagnostic::error(message).in_file(source);
+                // the offsets of nested includes are in another file
+                if depth == 0 {
+                    diagnostic = diagnostic.at_offset(s, playpen.start_index);
                 }
+                diagnostic.emit();
 
                 // This should make sure we include the raw `{{# ... }}` snippet
                 // in the page content if there are any errors.
@@ -103,6 +121,34 @@ where
     replaced
 }
 
+/// Every file pulled into a chapter by its `{{#include}}` and `{{#playpen}}`
+/// links, including ones nested inside other included files, where `base` is
+/// the directory of the chapter's source file.
+pub(crate) fn included_files(content: &str, base: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    add_included_files(content, base, 0, &mut files);
+    files
+}
+
+fn add_included_files(content: &str, base: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    for link in find_links(content) {
+        let target = match link.link.path() {
+            Some(path) => base.join(path),
+            None => continue,
+        };
+        if files.contains(&target) {
+            continue;
+        }
+        files.push(target.clone());
+
+        if depth < MAX_LINK_NESTED_DEPTH {
+            if let (Ok(nested), Some(nested_base)) = (file_to_string(&target), target.parent()) {
+                add_included_files(&nested, nested_base, depth + 1, files);
+            }
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 enum LinkType<'a> {
     Escaped,
@@ -110,10 +156,26 @@ enum LinkType<'a> {
     IncludeRangeFrom(PathBuf, RangeFrom<usize>),
     IncludeRangeTo(PathBuf, RangeTo<usize>),
     IncludeRangeFull(PathBuf, RangeFull),
+    IncludeAnchor(PathBuf, String),
+    IncludeSection(PathBuf, String),
     Playpen(PathBuf, Vec<&'a str>),
 }
 
 impl<'a> LinkType<'a> {
+    /// The file the link pulls in, relative to the file it's in.
+    fn path(&self) -> Option<&Path> {
+        match *self {
+            LinkType::Escaped => None,
+            LinkType::IncludeRange(ref p, _)
+            | LinkType::IncludeRangeFrom(ref p, _)
+            | LinkType::IncludeRangeTo(ref p, _)
+            | LinkType::IncludeRangeFull(ref p, _)
+            | LinkType::IncludeAnchor(ref p, _)
+            | LinkType::IncludeSection(ref p, _)
+            | LinkType::Playpen(ref p, _) => Some(p),
+        }
+    }
+
     fn relative_path<P: AsRef<Path>>(self, base: P) -> Option<PathBuf> {
         let base = base.as_ref();
         match self {
@@ -130,6 +192,12 @@ impl<'a> LinkType<'a> {
             LinkType::IncludeRangeFull(p, _) => {
                 Some(return_relative_path(base, &p))
             }
+            LinkType::IncludeAnchor(p, _) => {
+                Some(return_relative_path(base, &p))
+            }
+            LinkType::IncludeSection(p, _) => {
+                Some(return_relative_path(base, &p))
+            }
             LinkType::Playpen(p, _) => Some(return_relative_path(base, &p)),
         }
     }
@@ -143,14 +211,29 @@ fn return_relative_path<P: AsRef<Path>>(base: P, relative: P) -> PathBuf {
 }
 
 fn parse_include_path(path: &str) -> LinkType<'static> {
+    // `chapter.md#heading-id` is the section of a chapter under that heading
+    if let Some(hash) = path.find('#') {
+        if hash + 1 < path.len() {
+            return LinkType::IncludeSection(path[..hash].into(), path[hash + 1..].to_string());
+        }
+    }
+
     let mut parts = path.split(':');
     let path = parts.next().unwrap().into();
+    let start = parts.next();
+    let end = parts.next();
+
+    // anything other than a line number is the name of an anchor
+    if let Some(anchor) = start {
+        if end.is_none() && !anchor.is_empty() && anchor.parse::<usize>().is_err() {
+            return LinkType::IncludeAnchor(path, anchor.to_string());
+        }
+    }
+
     // subtract 1 since line numbers usually begin with 1
-    let start = parts
-        .next()
+    let start = start
         .and_then(|s| s.parse::<usize>().ok())
         .map(|val| val.saturating_sub(1));
-    let end = parts.next();
     let has_end = end.is_some();
     let end = end.and_then(|s| s.parse::<usize>().ok());
     match start {
@@ -267,6 +350,37 @@ impl<'a> Link<'a> {
                         )
                     })
             }
+            LinkType::IncludeAnchor(ref pat, ref anchor) => {
+                let target = base.join(pat);
+
+                file_to_string(&target)
+                    .map(|s| take_anchored_lines(&s, anchor))
+                    .chain_err(|| {
+                        format!(
+                            "Could not read file for link {} ({})",
+                            self.link_text,
+                            target.display(),
+                        )
+                    })
+            }
+            LinkType::IncludeSection(ref pat, ref id) => {
+                let target = base.join(pat);
+
+                let contents = file_to_string(&target).chain_err(|| {
+                    format!(
+                        "Could not read file for link {} ({})",
+                        self.link_text,
+                        target.display()
+                    )
+                })?;
+                take_section(&contents, id).ok_or_else(|| {
+                    Error::from(format!(
+                        "{} has no heading with the id \"{}\"",
+                        target.display(),
+                        id
+                    ))
+                })
+            }
             LinkType::IncludeRangeFull(ref pat, _) => {
                 let target = base.join(pat);
 
@@ -299,6 +413,93 @@ impl<'a> Link<'a> {
     }
 }
 
+/// The section of a chapter under the heading with the anchor `id`, up to the
+/// next heading at the same level or above.
+fn take_section(content: &str, id: &str) -> Option<String> {
+    let mut section: Option<(usize, Vec<&str>)> = None;
+
+    for (line, outside_code) in lines_outside_code(content) {
+        let heading = if outside_code { parse_heading(line) } else { None };
+        if let Some((level, text)) = heading {
+            match section {
+                Some((section_level, _)) if level <= section_level => break,
+                None if heading_id(text) == id => section = Some((level, Vec::new())),
+                _ => {}
+            }
+        }
+
+        if let Some((_, ref mut lines)) = section {
+            lines.push(line);
+        }
+    }
+
+    section.map(|(_, lines)| lines.join("\n"))
+}
+
+/// The anchor the HTML renderer gives a heading, which is either written out
+/// (`## Heading {#anchor}`) or made from its text.
+fn heading_id(text: &str) -> String {
+    lazy_static! {
+        static ref HEADER_ID: Regex = Regex::new(r"\{#([\w.:-]+)\}\s*$").unwrap();
+    }
+
+    match HEADER_ID.captures(text) {
+        Some(caps) => caps[1].to_string(),
+        None => id_from_content(text),
+    }
+}
+
+/// Make the relative links in a section of `file` (relative to the chapter
+/// including it) relative to the chapter instead, with links to anchors
+/// pointing at `file`.
+fn rewrite_relative_links(content: &str, file: &Path) -> String {
+    lazy_static! {
+        static ref LINK: Regex =
+            Regex::new(r#"(?m)(\]\(\s*|^ {0,3}\[[^\]^][^\]]*\]:\s*|(?:src|href)=")([^)\s"]+)"#)
+                .unwrap();
+    }
+
+    let file = file.to_string_lossy().replace('\\', "/");
+    replace_outside_code(content, |text| {
+        LINK.replace_all(text, |caps: &Captures| {
+            format!("{}{}", &caps[1], relative_link(&caps[2], &file))
+        }).into_owned()
+    })
+}
+
+/// Where `link`, from a file at the relative path `file`, points to from the
+/// file's includer.
+fn relative_link(link: &str, file: &str) -> String {
+    lazy_static! {
+        static ref SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+    }
+
+    if link.starts_with('#') {
+        return format!("{}{}", file, link);
+    }
+    if link.starts_with('/') || SCHEME.is_match(link) {
+        return link.to_string();
+    }
+
+    let directory = file.rsplit_once('/').map_or("", |(directory, _)| directory);
+    let mut parts: Vec<&str> = Vec::new();
+    for part in directory.split('/').chain(link.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." if matches!(parts.last(), Some(&last) if last != "..") => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+
+    match parts.join("/") {
+        ref joined if joined.is_empty() => String::from("./"),
+        joined if link.ends_with('/') => joined + "/",
+        joined => joined,
+    }
+}
+
 struct LinkIter<'a>(CaptureMatches<'a, 'a>);
 
 impl<'a> Iterator for LinkIter<'a> {
@@ -324,7 +525,7 @@ fn find_links(contents: &str) -> LinkIter {
             \{\{\s*                    # link opening parens and whitespace
             \#([a-zA-Z0-9]+)           # link type
             \s+                        # separating whitespace
-            ([a-zA-Z0-9\s_.\-:/\\]+)   # link target path and space separated properties
+            ([a-zA-Z0-9\s_.\-:/\\\#]+) # link target path and space separated properties
             \s*\}\}                    # whitespace and link closing parens"
         ).unwrap();
     }
@@ -500,6 +701,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_links_with_anchor() {
+        let s = "Some random text with {{#include file.rs:anchor}}...";
+        let res = find_links(s).collect::<Vec<_>>();
+        println!("\nOUTPUT: {:?}\n", res);
+        assert_eq!(
+            res,
+            vec![Link {
+                start_index: 22,
+                end_index: 49,
+                link: LinkType::IncludeAnchor(
+                    PathBuf::from("file.rs"),
+                    String::from("anchor")
+                ),
+                link_text: "{{#include file.rs:anchor}}",
+            }]
+        );
+    }
+
     #[test]
     fn test_find_links_escaped_link() {
         let s = "Some random text with escaped playpen \\{{#playpen file.rs editable}} ...";
@@ -592,4 +812,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn included_files_follow_nested_includes() {
+        use tempfile::TempDir;
+        use utils::fs::write_file;
+
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        write_file(dir, "outer.md", b"{{#include nested/inner.md}}").unwrap();
+        write_file(dir, "nested/inner.md", b"{{#playpen code.rs}}").unwrap();
+
+        let got = included_files("{{#include outer.md}} \\{{#include skipped.md}}", dir);
+        assert_eq!(
+            got,
+            vec![
+                dir.join("outer.md"),
+                dir.join("nested/inner.md"),
+                dir.join("nested/code.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_links_with_section() {
+        let s = "{{#include ../install.md#on-linux}}";
+        let res = find_links(s).collect::<Vec<_>>();
+        assert_eq!(
+            res[0].link,
+            LinkType::IncludeSection(PathBuf::from("../install.md"), String::from("on-linux"))
+        );
+    }
+
+    #[test]
+    fn sections_end_at_the_next_heading_at_their_level() {
+        let chapter = "# Installing\n\
+                       ## On Linux\n\
+                       Use a package manager.\n\
+                       ```sh\n\
+                       # not a heading\n\
+                       ```\n\
+                       ### Debian\n\
+                       `apt install`\n\
+                       ## On *Windows* {#windows}\n\
+                       Use the installer.";
+
+        let linux = take_section(chapter, "on-linux").unwrap();
+        assert!(linux.starts_with("## On Linux\n"));
+        assert!(linux.contains("# not a heading"));
+        assert!(linux.ends_with("`apt install`"));
+        assert_eq!(
+            take_section(chapter, "windows").unwrap(),
+            "## On *Windows* {#windows}\nUse the installer."
+        );
+        assert_eq!(take_section(chapter, "on-windows"), None);
+    }
+
+    #[test]
+    fn links_in_a_section_point_where_they_did() {
+        let section = "See [the FAQ](faq.md#why), [below](#later), [docs](https://docs.rs),\n\
+                       ![logo](../images/logo.png) and [this][code], but not `[x](y)`.\n\
+                       \n\
+                       [code]: ./src/main.rs";
+        let got = rewrite_relative_links(section, Path::new("../guide/install.md"));
+
+        assert_eq!(
+            got,
+            "See [the FAQ](../guide/faq.md#why), [below](../guide/install.md#later), \
+             [docs](https://docs.rs),\n\
+             ![logo](../images/logo.png) and [this][code], but not `[x](y)`.\n\
+             \n\
+             [code]: ../guide/src/main.rs"
+        );
+    }
+
+    #[test]
+    fn sections_of_other_chapters_can_be_included() {
+        use tempfile::TempDir;
+        use utils::fs::write_file;
+
+        let temp = TempDir::new().unwrap();
+        let dir = temp.path();
+        let install = "# Installing\n\n## From source\n\nSee [building](building.md).\n\n## Next";
+        write_file(dir, "shared/install.md", install.as_bytes()).unwrap();
+
+        let got = replace_all("{{#include shared/install.md#from-source}}", dir, "guide.md", 0);
+        assert_eq!(got, "## From source\n\nSee [building](shared/building.md).\n");
+    }
 }
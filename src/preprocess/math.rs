@@ -0,0 +1,300 @@
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::Book;
+use utils::{find_closing_backticks, run_with_input, split_code_blocks, Block};
+
+const DEFAULT_KATEX_COMMAND: &str = "katex";
+
+/// A preprocessor for converting `$...$` (inline) and `$$...$$` (display)
+/// maths into something a browser can show.
+///
+/// By default the maths is turned into MathJax-compatible spans, so it still
+/// needs the `mathjax-support` option to be rendered. Setting `mode =
+/// "katex"` will instead pre-render it to HTML at build time by piping each
+/// formula to the `katex` command.
+pub struct MathPreprocessor;
+
+impl MathPreprocessor {
+    pub(crate) const NAME: &'static str = "math";
+
+    /// Create a new `MathPreprocessor`.
+    pub fn new() -> Self {
+        MathPreprocessor
+    }
+}
+
+impl Preprocessor for MathPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let mode = Mode::from_context(ctx)?;
+
+        book.par_for_each_chapter_mut(|ch| {
+            ch.content = replace_math(&ch.content, &mode)
+                .chain_err(|| format!("Unable to render the maths in \"{}\"", ch.name))?;
+            Ok(())
+        })?;
+
+        Ok(book)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    MathJax,
+    Katex { command: String },
+}
+
+impl Mode {
+    fn from_context(ctx: &PreprocessorContext) -> Result<Mode> {
+        let table = ctx.config.get_preprocessor(MathPreprocessor::NAME);
+        let get = |key: &str| table.and_then(|t| t.get(key)).and_then(|v| v.as_str());
+
+        match get("mode") {
+            None | Some("mathjax") => Ok(Mode::MathJax),
+            Some("katex") => Ok(Mode::Katex {
+                command: get("katex-command")
+                    .unwrap_or(DEFAULT_KATEX_COMMAND)
+                    .to_string(),
+            }),
+            Some(other) => bail!(
+                "Unknown maths mode \"{}\", expected \"mathjax\" or \"katex\"",
+                other
+            ),
+        }
+    }
+
+    fn render(&self, math: &str, display: bool) -> Result<String> {
+        match *self {
+            Mode::MathJax => {
+                let (class, open, close) = if display {
+                    ("display", "\\[", "\\]")
+                } else {
+                    ("inline", "\\(", "\\)")
+                };
+
+                Ok(format!(
+                    "<span class=\"math {}\">{}{}{}</span>",
+                    class,
+                    escape_markdown(open),
+                    escape_markdown(math),
+                    escape_markdown(close)
+                ))
+            }
+            Mode::Katex { ref command } => {
+                let command = if display {
+                    format!("{} --display-mode", command)
+                } else {
+                    command.clone()
+                };
+
+                let html = run_with_input(&command, math)?;
+                Ok(escape_markdown_in_html(html.trim()))
+            }
+        }
+    }
+}
+
+/// Replace the maths in a chapter, leaving fenced code blocks untouched.
+fn replace_math(content: &str, mode: &Mode) -> Result<String> {
+    let mut blocks = Vec::new();
+    for block in split_code_blocks(content) {
+        blocks.push(match block {
+            Block::Text { ref lines, .. } => replace_math_in_prose(&lines.join("\n"), mode)?,
+            Block::Code(_) => block.to_markdown(),
+        });
+    }
+
+    Ok(blocks.join("\n"))
+}
+
+fn replace_math_in_prose(text: &str, mode: &Mode) -> Result<String> {
+    let bytes = text.as_bytes();
+    let mut replaced = String::with_capacity(text.len());
+    let mut previous_end_index = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            // skip over whatever is being escaped
+            b'\\' => i += 2,
+            b'`' => {
+                let ticks = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+                i += ticks;
+                if let Some(end) = find_closing_backticks(&text[i..], ticks) {
+                    i += end + ticks;
+                }
+            }
+            b'$' if text[i..].starts_with("$$") => match text[i + 2..].find("$$") {
+                Some(len) => {
+                    let math = &text[i + 2..i + 2 + len];
+                    replaced.push_str(&text[previous_end_index..i]);
+                    replaced.push_str(&mode.render(math.trim(), true)?);
+                    i += len + 4;
+                    previous_end_index = i;
+                }
+                None => i += 2,
+            },
+            b'$' => match find_inline_math_end(&text[i + 1..]) {
+                Some(len) => {
+                    let math = &text[i + 1..i + 1 + len];
+                    replaced.push_str(&text[previous_end_index..i]);
+                    replaced.push_str(&mode.render(math, false)?);
+                    i += len + 2;
+                    previous_end_index = i;
+                }
+                None => i += 1,
+            },
+            _ => i += 1,
+        }
+    }
+
+    replaced.push_str(&text[previous_end_index..]);
+    Ok(replaced)
+}
+
+/// Find the `$` closing some inline maths.
+///
+/// To avoid mistaking prices like "$5 and $10" for maths, the maths can't
+/// start or end with whitespace, the closing `$` can't be followed by a digit
+/// and a formula can't span paragraphs.
+fn find_inline_math_end(text: &str) -> Option<usize> {
+    if text.is_empty() || text.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let bytes = text.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' if text[i + 1..].trim_start_matches(&[' ', '\t'][..]).starts_with('\n') => {
+                return None
+            }
+            b'$' => {
+                let followed_by_digit = bytes.get(i + 1).map_or(false, |b| b.is_ascii_digit());
+                if i == 0 || text[..i].ends_with(char::is_whitespace) || followed_by_digit {
+                    return None;
+                }
+                return Some(i);
+            }
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+/// Escape anything markdown would otherwise treat as formatting.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        if needs_escaping(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+
+    escaped
+}
+
+/// Escape the text in a snippet of HTML without touching its tags.
+fn escape_markdown_in_html(html: &str) -> String {
+    let mut escaped = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for ch in html.chars() {
+        match ch {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag && needs_escaping(ch) => escaped.push('\\'),
+            _ => {}
+        }
+        escaped.push(ch);
+    }
+
+    escaped
+}
+
+fn needs_escaping(ch: char) -> bool {
+    "\\*_`[]<>".contains(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_and_display_maths_become_mathjax_spans() {
+        let src = "Euler: $e^{i\\pi} + 1 = 0$\n\n$$\nx_1 < x_2\n$$";
+        let should_be = "Euler: <span class=\"math inline\">\\\\(e^{i\\\\pi} + 1 = 0\\\\)</span>\n\n\
+                         <span class=\"math display\">\\\\\\[x\\_1 \\< x\\_2\\\\\\]</span>";
+
+        let got = replace_math(src, &Mode::MathJax).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn mathjax_delimiters_survive_rendering_markdown() {
+        let src = "$a_1 * b_1$ and $$[x]$$";
+        let should_be = "<p><span class=\"math inline\">\\(a_1 * b_1\\)</span> and \
+                         <span class=\"math display\">\\[[x]\\]</span></p>\n";
+
+        let got = replace_math(src, &Mode::MathJax).unwrap();
+
+        assert_eq!(::utils::render_markdown(&got, false), should_be);
+    }
+
+    #[test]
+    fn dollar_signs_which_arent_maths_are_left_alone() {
+        let inputs = vec![
+            "It costs $5 and $10",
+            "Padded $ x $ maths",
+            "Escaped \\$x\\$",
+            "A lone $",
+            "Across $paragraphs\n\nlike$ this",
+            "In `code $x$` spans",
+            "In ``code `$x$` `` spans",
+        ];
+
+        for src in inputs {
+            assert_eq!(replace_math(src, &Mode::MathJax).unwrap(), src);
+        }
+    }
+
+    #[test]
+    fn code_blocks_are_left_alone() {
+        let src = "```sh\necho $HOME $PATH\n```\n$x$\n~~~\n$$\n~~~";
+        let should_be = "```sh\necho $HOME $PATH\n```\n\
+                         <span class=\"math inline\">\\\\(x\\\\)</span>\n~~~\n$$\n~~~";
+
+        let got = replace_math(src, &Mode::MathJax).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn katex_output_is_embedded() {
+        let mode = Mode::Katex {
+            command: "cat".to_string(),
+        };
+
+        let got = replace_math("Inline $x_1$ maths", &mode).unwrap();
+
+        assert_eq!(got, "Inline x\\_1 maths");
+    }
+
+    #[test]
+    fn html_tags_arent_escaped() {
+        let got = escape_markdown_in_html("<span class=\"a_b\">x_1</span>");
+
+        assert_eq!(got, "<span class=\"a_b\">x\\_1</span>");
+    }
+}
@@ -2,11 +2,49 @@
 
 pub use self::index::IndexPreprocessor;
 pub use self::links::LinkPreprocessor;
+pub use self::admonitions::AdmonitionsPreprocessor;
+pub use self::bibliography::BibliographyPreprocessor;
+pub use self::book_index::BookIndexPreprocessor;
+pub use self::captions::CaptionsPreprocessor;
 pub use self::cmd::CmdPreprocessor;
+pub use self::conditional::ConditionalPreprocessor;
+pub use self::diagrams::DiagramsPreprocessor;
+pub use self::emoji::EmojiPreprocessor;
+pub use self::gettext::GettextPreprocessor;
+pub use self::glossary::GlossaryPreprocessor;
+pub use self::headings::HeadingsPreprocessor;
+pub use self::hide_lines::HideLinesPreprocessor;
+pub use self::link_check::LinkCheckPreprocessor;
+pub use self::math::MathPreprocessor;
+pub use self::scala_eval::ScalaEvalPreprocessor;
+pub use self::scastie::ScastiePreprocessor;
+pub use self::smart_punctuation::SmartPunctuationPreprocessor;
+pub use self::tabs::TabsPreprocessor;
+pub use self::variables::VariablesPreprocessor;
+
+pub(crate) use self::links::included_files;
 
 mod index;
 mod links;
+mod admonitions;
+mod bibliography;
+mod book_index;
+mod captions;
 mod cmd;
+mod conditional;
+mod diagrams;
+mod emoji;
+mod gettext;
+mod glossary;
+mod headings;
+mod hide_lines;
+mod link_check;
+mod math;
+mod scala_eval;
+mod scastie;
+mod smart_punctuation;
+mod tabs;
+mod variables;
 
 use book::Book;
 use config::Config;
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tempfile::NamedTempFile;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::Book;
+use utils::fs::file_to_string;
+use utils::{code_block_attributes, run_with_input, split_code_blocks, stable_hash, Block};
+
+const DEFAULT_COMMAND: &str = "scala-cli run -";
+const DEFAULT_CACHE_DIR: &str = ".scala-eval-cache";
+
+/// A preprocessor which runs `scala` code blocks tagged with `run` and
+/// appends whatever they print to `stdout` as a separate code block.
+///
+/// Each snippet is piped to the configured `command` via `stdin`. Output is
+/// cached in the `cache-dir` directory, keyed on a hash of the command and
+/// the snippet, so unchanged snippets aren't run again when the book is
+/// rebuilt. Entries no snippet needed any more are removed after each run.
+pub struct ScalaEvalPreprocessor;
+
+impl ScalaEvalPreprocessor {
+    pub(crate) const NAME: &'static str = "scala-eval";
+
+    /// Create a new `ScalaEvalPreprocessor`.
+    pub fn new() -> Self {
+        ScalaEvalPreprocessor
+    }
+}
+
+impl Preprocessor for ScalaEvalPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let evaluator = Evaluator::from_context(ctx);
+
+        book.par_for_each_chapter_mut(|ch| {
+            let msg = format!("Unable to evaluate the Scala snippets in \"{}\"", ch.name);
+            ch.content = evaluate_snippets(&ch.content, &evaluator).chain_err(|| msg)?;
+            Ok(())
+        })?;
+        evaluator
+            .prune()
+            .chain_err(|| "Unable to clean up the Scala output cache")?;
+
+        Ok(book)
+    }
+}
+
+struct Evaluator {
+    command: String,
+    cache_dir: PathBuf,
+    /// The cache entries this run has looked for, so the rest can be removed.
+    used: Mutex<HashSet<String>>,
+}
+
+impl Evaluator {
+    fn from_context(ctx: &PreprocessorContext) -> Evaluator {
+        let table = ctx.config.get_preprocessor(ScalaEvalPreprocessor::NAME);
+        let get = |key: &str| table.and_then(|t| t.get(key)).and_then(|v| v.as_str());
+
+        Evaluator {
+            command: get("command").unwrap_or(DEFAULT_COMMAND).to_string(),
+            cache_dir: ctx.root.join(get("cache-dir").unwrap_or(DEFAULT_CACHE_DIR)),
+            used: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Get the output of a snippet, only running the command if it isn't
+    /// already cached.
+    fn evaluate(&self, snippet: &str) -> Result<String> {
+        let key = stable_hash(format!("{}\0{}", self.command, snippet).as_bytes());
+        let cache_file = format!("{}.txt", key);
+        let cached = self.cache_dir.join(&cache_file);
+        self.used.lock().unwrap().insert(key);
+
+        if cached.exists() {
+            debug!("Using the cached output for snippet {}", cache_file);
+            return file_to_string(&cached);
+        }
+
+        let output = run_with_input(&self.command, snippet)?;
+        self.cache(&cached, &output)
+            .chain_err(|| "Unable to cache the snippet's output")?;
+
+        Ok(output)
+    }
+
+    /// Save a snippet's output. Chapters are evaluated in parallel, so it's
+    /// written to a temporary file first, and then moved into place so no one
+    /// reads it half-written.
+    fn cache(&self, cached: &Path, output: &str) -> Result<()> {
+        fs::create_dir_all(&self.cache_dir)?;
+        let mut temp = NamedTempFile::new_in(&self.cache_dir)?;
+        temp.write_all(output.as_bytes())?;
+        temp.persist(cached).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    /// Remove the cached output of snippets which this run didn't evaluate,
+    /// like ones which have since changed or been deleted.
+    fn prune(&self) -> Result<()> {
+        if !self.cache_dir.exists() {
+            return Ok(());
+        }
+
+        let used = self.used.lock().unwrap();
+        for entry in fs::read_dir(&self.cache_dir)? {
+            let path = entry?.path();
+            if !is_stale(&path, &used) {
+                continue;
+            }
+            debug!("Removing {} from the Scala output cache", path.display());
+            fs::remove_file(&path)
+                .chain_err(|| format!("Unable to remove {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Is this a snippet's cached output which wasn't used? Anything else in the
+/// directory is left alone.
+fn is_stale(path: &Path, used: &HashSet<String>) -> bool {
+    if path.extension().and_then(|ext| ext.to_str()) != Some("txt") {
+        return false;
+    }
+    match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(key) => key.chars().all(|c| c.is_ascii_hexdigit()) && !used.contains(key),
+        None => false,
+    }
+}
+
+fn evaluate_snippets(content: &str, evaluator: &Evaluator) -> Result<String> {
+    let mut evaluated = Vec::new();
+
+    for block in split_code_blocks(content) {
+        evaluated.push(block.to_markdown());
+        if let Block::Code(ref snippet) = block {
+            // the output of an unclosed snippet would end up inside it
+            if snippet.closing_fence.is_some() && is_runnable(snippet.info) {
+                let output = evaluator.evaluate(&snippet.code())?;
+                evaluated.push(String::new());
+                evaluated.push(output_block(&output));
+            }
+        }
+    }
+
+    Ok(evaluated.join("\n"))
+}
+
+/// A `text` code block holding a snippet's output, fenced with more backticks
+/// than any run of them in the output so nothing in it can close the block.
+fn output_block(output: &str) -> String {
+    let longest_run = output
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat((longest_run + 1).max(3));
+
+    format!("{}text\n{}\n{}", fence, output.trim_end_matches('\n'), fence)
+}
+
+fn is_runnable(info: &str) -> bool {
+    let mut attributes = code_block_attributes(info);
+    attributes.next() == Some("scala") && attributes.any(|attr| attr == "run")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(windows))]
+    use tempfile::TempDir;
+    #[cfg(not(windows))]
+    use utils::fs::write_file;
+
+    // `cat` and `false` stand in for scala-cli, so tests using them are only
+    // run where they exist
+    #[cfg(not(windows))]
+    fn evaluator(command: &str, cache_dir: &TempDir) -> Evaluator {
+        Evaluator {
+            command: command.to_string(),
+            cache_dir: cache_dir.path().to_path_buf(),
+            used: Mutex::new(HashSet::new()),
+        }
+    }
+
+    #[test]
+    fn only_scala_blocks_tagged_with_run_are_runnable() {
+        assert!(is_runnable("scala,run"));
+        assert!(is_runnable("scala,editable,run"));
+        assert!(!is_runnable("scala"));
+        assert!(!is_runnable("rust,run"));
+        assert!(!is_runnable(""));
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn output_is_appended_after_the_snippet() {
+        let temp = TempDir::new().unwrap();
+        let src = "Text\n\n```scala,run\nprintln(1)\n```\n\n```scala\nval x = 1\n```\n";
+        let should_be = "Text\n\n```scala,run\nprintln(1)\n```\n\n```text\nprintln(1)\n```\n\n```scala\nval x = 1\n```\n";
+
+        let got = evaluate_snippets(src, &evaluator("cat", &temp)).unwrap();
+
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn output_containing_a_fence_stays_in_its_block() {
+        assert_eq!(output_block("1\n"), "```text\n1\n```");
+        assert_eq!(
+            output_block("```\ncode\n`````\n"),
+            "``````text\n```\ncode\n`````\n``````"
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn output_is_cached() {
+        let temp = TempDir::new().unwrap();
+        let evaluator = evaluator("cat", &temp);
+
+        assert_eq!(evaluator.evaluate("println(1)").unwrap(), "println(1)");
+
+        let entries: Vec<_> = temp.path().read_dir().unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        // the command shouldn't be run again while the cache entry exists
+        let cached = entries[0].as_ref().unwrap().path();
+        write_file(temp.path(), &cached, b"1").unwrap();
+        assert_eq!(evaluator.evaluate("println(1)").unwrap(), "1");
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn unused_output_is_removed_from_the_cache() {
+        let temp = TempDir::new().unwrap();
+        write_file(temp.path(), "0123456789abcdef.txt", b"old").unwrap();
+        write_file(temp.path(), "notes.txt", b"not an entry").unwrap();
+        let evaluator = evaluator("cat", &temp);
+
+        evaluator.evaluate("println(1)").unwrap();
+        evaluator.prune().unwrap();
+
+        let mut left: Vec<_> = fs::read_dir(temp.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        let key = stable_hash(b"cat\0println(1)");
+        assert_eq!(left, vec![format!("{}.txt", key), String::from("notes.txt")]);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn failing_snippets_are_an_error() {
+        let temp = TempDir::new().unwrap();
+        let src = "```scala,run\nthrow new Exception()\n```";
+
+        assert!(evaluate_snippets(src, &evaluator("false", &temp)).is_err());
+        assert_eq!(temp.path().read_dir().unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,181 @@
+use regex::{Captures, Regex};
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::Book;
+use utils::{
+    code_block_attributes, escape_html, parse_opening_fence, replace_outside_code,
+    split_code_blocks, Block, CodeBlock,
+};
+
+const DEFAULT_URL: &str = "https://scastie.scala-lang.org";
+
+/// A preprocessor for embedding [Scastie](https://scastie.scala-lang.org), so
+/// readers can edit and run Scala code without leaving the page.
+///
+/// `scala` code blocks tagged with `scastie` are turned into Scastie editors
+/// with the snippet already loaded, and `{{#scastie <id>}}` embeds a snippet
+/// saved on Scastie. Renderers other than HTML get the code block as an
+/// ordinary `scala` one, and a link to a saved snippet.
+pub struct ScastiePreprocessor;
+
+impl ScastiePreprocessor {
+    pub(crate) const NAME: &'static str = "scastie";
+
+    /// Create a new `ScastiePreprocessor`.
+    pub fn new() -> Self {
+        ScastiePreprocessor
+    }
+}
+
+impl Preprocessor for ScastiePreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let table = ctx.config.get_preprocessor(Self::NAME);
+        let url = table
+            .and_then(|t| t.get("url"))
+            .and_then(|v| v.as_str())
+            .unwrap_or(DEFAULT_URL)
+            .trim_end_matches('/')
+            .to_string();
+        let embed = ctx.renderer == "html";
+
+        book.par_for_each_chapter_mut(|ch| {
+            ch.content = embed_scastie(&ch.content, &url, embed);
+            Ok(())
+        })?;
+
+        Ok(book)
+    }
+}
+
+/// Replace a chapter's Scastie snippets with embedded editors (if `embed`)
+/// or plain code blocks and links.
+fn embed_scastie(content: &str, url: &str, embed: bool) -> String {
+    let mut needs_script = false;
+    let mut lines = Vec::new();
+
+    for block in split_code_blocks(content) {
+        match block {
+            Block::Text { lines: ref prose, .. } => {
+                lines.push(replace_directives(&prose.join("\n"), url, embed));
+            }
+            Block::Code(ref snippet)
+                if snippet.language() == Some("scala") && snippet.has("scastie") =>
+            {
+                if embed {
+                    lines.push(editor(&snippet.lines));
+                    needs_script = true;
+                } else {
+                    let opening_fence = without_scastie(snippet.opening_fence);
+                    let plain = CodeBlock {
+                        opening_fence: &opening_fence,
+                        ..snippet.clone()
+                    };
+                    lines.push(plain.to_markdown());
+                }
+            }
+            Block::Code(_) => lines.push(block.to_markdown()),
+        }
+    }
+
+    if needs_script {
+        if matches!(lines.last(), Some(line) if !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push(format!("<script src=\"{}/embedded.js\"></script>", url));
+        lines.push(
+            "<script>window.addEventListener('load', function () { \
+             scastie.Embedded('.scastie-editor'); });</script>"
+                .to_string(),
+        );
+    }
+
+    lines.join("\n")
+}
+
+/// The element Scastie turns into an editor for a snippet. Its blank lines
+/// are written as `&#10;`, since a blank line would end the HTML early.
+fn editor(snippet: &[&str]) -> String {
+    let mut code = String::new();
+    for line in snippet {
+        if line.trim().is_empty() {
+            code.push_str("&#10;");
+        } else {
+            code.push_str(&escape_html(line));
+            code.push('\n');
+        }
+    }
+
+    format!("<pre class=\"scastie-editor\">{}</pre>", code.trim_end_matches('\n'))
+}
+
+/// Replace the `{{#scastie <id>}}` directives in some prose.
+fn replace_directives(prose: &str, url: &str, embed: bool) -> String {
+    lazy_static! {
+        static ref DIRECTIVE: Regex =
+            Regex::new(r"\{\{\s*#scastie\s+([a-zA-Z0-9_\-/]+)\s*\}\}").unwrap();
+    }
+
+    replace_outside_code(prose, |text| {
+        DIRECTIVE
+            .replace_all(text, |caps: &Captures| {
+                let id = caps[1].trim_matches('/');
+                if embed {
+                    format!(
+                        "<div class=\"scastie\"><script src=\"{}/{}.js\"></script></div>",
+                        url, id
+                    )
+                } else {
+                    format!("[Open the example on Scastie]({}/{})", url, id)
+                }
+            }).into_owned()
+    })
+}
+
+/// The opening fence of a code block, without its `scastie` attribute.
+fn without_scastie(fence: &str) -> String {
+    let start = fence.len() - fence.trim_start().len();
+    let (_, len, info) = parse_opening_fence(fence).expect("It's an opening fence");
+    let attributes: Vec<_> = code_block_attributes(info)
+        .filter(|attribute| *attribute != "scastie")
+        .collect();
+
+    format!("{}{}", &fence[..start + len], attributes.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippets_are_embedded_in_html() {
+        let src = "Try it:\n\n```scala,scastie\nval x = 1\n\nList(x) <+> List(3)\n```\n\n\
+                   ```scala\nval x = 1\n```\n\nOr {{#scastie user/AbC123}}.";
+        let should_be = "Try it:\n\n\
+                         <pre class=\"scastie-editor\">val x = 1\n\
+                         &#10;List(x) &lt;+&gt; List(3)</pre>\n\n\
+                         ```scala\nval x = 1\n```\n\nOr <div class=\"scastie\">\
+                         <script src=\"https://s.example/user/AbC123.js\"></script></div>.\n\n\
+                         <script src=\"https://s.example/embedded.js\"></script>\n\
+                         <script>window.addEventListener('load', function () { \
+                         scastie.Embedded('.scastie-editor'); });</script>";
+
+        assert_eq!(embed_scastie(src, "https://s.example", true), should_be);
+    }
+
+    #[test]
+    fn other_renderers_get_code_blocks_and_links() {
+        let src = "```scala,scastie,editable\nval x = 1\n```\n\n\
+                   {{#scastie AbC123}} `{{#scastie x}}`";
+        let should_be = "```scala,editable\nval x = 1\n```\n\n\
+                         [Open the example on Scastie](https://s.example/AbC123) \
+                         `{{#scastie x}}`";
+
+        assert_eq!(embed_scastie(src, "https://s.example", false), should_be);
+    }
+}
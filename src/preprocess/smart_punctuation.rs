@@ -0,0 +1,172 @@
+use regex::Regex;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use utils::replace_outside_code;
+
+/// A preprocessor for typographic punctuation.
+///
+/// Straight quotes become curly quotes, `--` and `---` become en and em
+/// dashes, and `...` becomes an ellipsis. Code, HTML tags, link destinations
+/// and markdown's own uses of dashes (horizontal rules, table headers, etc.)
+/// are left alone, as is anything escaped with a `\`.
+pub struct SmartPunctuationPreprocessor;
+
+impl SmartPunctuationPreprocessor {
+    pub(crate) const NAME: &'static str = "smart-punctuation";
+
+    /// Create a new `SmartPunctuationPreprocessor`.
+    pub fn new() -> Self {
+        SmartPunctuationPreprocessor
+    }
+}
+
+impl Preprocessor for SmartPunctuationPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, _ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                ch.content = replace_outside_code(&ch.content, smarten);
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+fn smarten(text: &str) -> String {
+    lazy_static! {
+        // horizontal rules, setext heading underlines and table delimiter rows
+        static ref MARKDOWN_RULE: Regex = Regex::new(r"^[\s\-=*_|:+]*$").unwrap();
+        static ref PROTECTED: Regex = Regex::new(
+            r"(?x)
+            <[^>]*>                 # HTML tags and autolinks
+            | \]\([^)]*\)           # link destinations
+            | \{\{[^}]*\}\}         # mdbook's {{#...}} directives"
+        ).unwrap();
+    }
+
+    let lines: Vec<_> = text
+        .split('\n')
+        .map(|line| {
+            if MARKDOWN_RULE.is_match(line) {
+                return line.to_string();
+            }
+
+            let mut smartened = String::with_capacity(line.len());
+            let mut previous = None;
+            let mut previous_end_index = 0;
+
+            for protected in PROTECTED.find_iter(line) {
+                smarten_span(
+                    &line[previous_end_index..protected.start()],
+                    &mut previous,
+                    &mut smartened,
+                );
+                smartened.push_str(protected.as_str());
+                previous = protected.as_str().chars().next_back();
+                previous_end_index = protected.end();
+            }
+            smarten_span(&line[previous_end_index..], &mut previous, &mut smartened);
+
+            smartened
+        }).collect();
+
+    lines.join("\n")
+}
+
+/// Smarten a span of text, where `previous` is the character before it (if
+/// any) and is updated as we go.
+fn smarten_span(text: &str, previous: &mut Option<char>, out: &mut String) {
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        let replacement = match ch {
+            '\\' => {
+                out.push(ch);
+                if let Some(escaped) = chars.next() {
+                    out.push(escaped);
+                }
+                *previous = Some('\\');
+                continue;
+            }
+            '"' | '\'' => {
+                let opening =
+                    previous.map_or(true, |p| p.is_whitespace() || "([{‘“".contains(p));
+                match (ch, opening) {
+                    ('"', true) => '“',
+                    ('"', false) => '”',
+                    (_, true) => '‘',
+                    (_, false) => '’',
+                }
+            }
+            '-' | '.' => {
+                let mut run = 1;
+                while chars.peek() == Some(&ch) {
+                    chars.next();
+                    run += 1;
+                }
+
+                let replacement = match (ch, run) {
+                    ('-', 2) => Some('–'),
+                    ('-', 3) => Some('—'),
+                    ('.', 3) => Some('…'),
+                    _ => None,
+                };
+
+                match replacement {
+                    Some(replacement) => out.push(replacement),
+                    None => out.extend((0..run).map(|_| ch)),
+                }
+                *previous = Some(replacement.unwrap_or(ch));
+                continue;
+            }
+            _ => ch,
+        };
+
+        out.push(replacement);
+        *previous = Some(replacement);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_dashes_and_ellipses() {
+        let src = "\"Don't,\" she said -- 'twice' --- and (\"'quoted'\")...";
+        let should_be = "“Don’t,” she said – ‘twice’ — and (“‘quoted’”)…";
+
+        assert_eq!(smarten(src), should_be);
+    }
+
+    #[test]
+    fn markdown_syntax_is_left_alone() {
+        let src = "Heading\n---\n\n| a | b |\n|---|:-:|\n\n***\n\n\
+                   <a href=\"x--y\">it's</a> [link](a--b.md \"title\")'s \\\"escaped\\\" {{#include \"a\"}}";
+        let should_be = "Heading\n---\n\n| a | b |\n|---|:-:|\n\n***\n\n\
+                         <a href=\"x--y\">it’s</a> [link](a--b.md \"title\")’s \\\"escaped\\\" {{#include \"a\"}}";
+
+        assert_eq!(smarten(src), should_be);
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        let src = "`\"--\"` \"--\"\n```\n\"...\"\n```";
+
+        let got = replace_outside_code(src, smarten);
+
+        assert_eq!(got, "`\"--\"` “–”\n```\n\"...\"\n```");
+    }
+
+    #[test]
+    fn other_runs_are_left_alone() {
+        assert_eq!(smarten("a ---- b .. c ....."), "a ---- b .. c .....");
+    }
+}
@@ -0,0 +1,249 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use diagnostics::{chapter_file, Diagnostic};
+use utils::{escape_html, split_code_blocks, Block};
+
+/// A preprocessor for grouping code blocks (or any other content) into a
+/// tabbed widget.
+///
+/// Everything between `{{#tabs}}` and `{{#endtabs}}` becomes one group of
+/// tabs. Each code block in the group gets a tab of its own, labelled with
+/// the code block's language, unless the group uses `{{#tab Label}}` lines to
+/// say where each tab starts and what it's called.
+pub struct TabsPreprocessor;
+
+impl TabsPreprocessor {
+    pub(crate) const NAME: &'static str = "tabs";
+
+    /// Create a new `TabsPreprocessor`.
+    pub fn new() -> Self {
+        TabsPreprocessor
+    }
+}
+
+impl Preprocessor for TabsPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, _ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                let file = chapter_file(ch);
+                ch.content = render_tabs(&ch.content, &file);
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Directive<'a> {
+    Tabs,
+    Tab(&'a str),
+    EndTabs,
+    /// A directive with a `\` in front of it, which should be left in the
+    /// chapter without the `\`.
+    Escaped(&'a str),
+}
+
+fn parse_directive<'a>(line: &'a str) -> Option<Directive<'a>> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"(?x)                                # insignificant whitespace mode
+            ^\s*(\\)?                             # an optional escape
+            (\{\{\s*\#(tabs|endtabs|tab)\b        # the directive
+            \s*([^}]*?)                           # a tab's label
+            \s*\}\})\s*$                          # the closing parens"
+        ).unwrap();
+    }
+
+    let caps = RE.captures(line)?;
+
+    if caps.get(1).is_some() {
+        return Some(Directive::Escaped(caps.get(2).unwrap().as_str()));
+    }
+
+    match &caps[3] {
+        "tabs" => Some(Directive::Tabs),
+        "endtabs" => Some(Directive::EndTabs),
+        _ => Some(Directive::Tab(caps.get(4).unwrap().as_str())),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Tab<'a> {
+    label: String,
+    lines: Vec<&'a str>,
+}
+
+#[derive(Debug, Default)]
+struct Group<'a> {
+    tabs: Vec<Tab<'a>>,
+    /// Whether the group's tabs are marked with `{{#tab}}` instead of having
+    /// one tab per code block.
+    explicit: bool,
+    /// The line the `{{#tabs}}` is on.
+    start: usize,
+}
+
+fn render_tabs(content: &str, file: &Path) -> String {
+    let warn = |message: &str, line: usize| {
+        Diagnostic::warning(message)
+            .in_file(file)
+            .at_line(line)
+            .emit()
+    };
+
+    let mut group: Option<Group> = None;
+    let mut lines = Vec::new();
+
+    for block in split_code_blocks(content) {
+        let code = match block {
+            Block::Code(ref code) => Some(code),
+            Block::Text { .. } => None,
+        };
+        if let (Some(code), Some(group)) = (code, group.as_mut()) {
+            if !group.explicit {
+                group.tabs.push(Tab {
+                    label: code.language().unwrap_or("Code").to_string(),
+                    lines: Vec::new(),
+                });
+            }
+        }
+
+        for (i, line) in (block.start()..).zip(block.lines()) {
+            let directive = if code.is_none() { parse_directive(line) } else { None };
+            if let Some(directive) = directive {
+                match directive {
+                    Directive::Tabs => {
+                        if group.is_some() {
+                            warn("Tabs can't be nested", i + 1);
+                        } else {
+                            group = Some(Group {
+                                start: i + 1,
+                                ..Group::default()
+                            });
+                        }
+                    }
+                    Directive::Tab(label) => match group {
+                        Some(ref mut group) => {
+                            group.explicit = true;
+                            group.tabs.push(Tab {
+                                label: label.to_string(),
+                                lines: Vec::new(),
+                            });
+                        }
+                        None => warn("Found a {{#tab}} outside of any tabs", i + 1),
+                    },
+                    Directive::EndTabs => match group.take() {
+                        Some(group) => lines.push(render_group(&group.tabs)),
+                        None => warn("Found an unexpected {{#endtabs}}", i + 1),
+                    },
+                    Directive::Escaped(directive) => lines.push(directive.to_string()),
+                }
+                continue;
+            }
+
+            // anything before a group's first tab stays outside of the tabs
+            match group.as_mut().and_then(|group| group.tabs.last_mut()) {
+                Some(tab) => tab.lines.push(line),
+                None => lines.push(line.to_string()),
+            }
+        }
+    }
+
+    if let Some(group) = group {
+        warn("A {{#tabs}} is missing its {{#endtabs}}", group.start);
+        lines.push(render_group(&group.tabs));
+    }
+
+    lines.join("\n")
+}
+
+fn render_group(tabs: &[Tab]) -> String {
+    let mut html = String::from("<div class=\"tabs\">\n<div class=\"tab-labels\">\n");
+
+    for (i, tab) in tabs.iter().enumerate() {
+        html.push_str(&format!(
+            "<button class=\"tab-label{}\">{}</button>\n",
+            if i == 0 { " active" } else { "" },
+            escape_html(&tab.label)
+        ));
+    }
+    html.push_str("</div>\n");
+
+    for (i, tab) in tabs.iter().enumerate() {
+        let start = tab.lines.iter().position(|l| !l.trim().is_empty());
+        let end = tab.lines.iter().rposition(|l| !l.trim().is_empty());
+
+        html.push_str(&format!(
+            "<div class=\"tab-panel{}\">\n",
+            if i == 0 { " active" } else { "" }
+        ));
+
+        // the blank lines let the panel's contents be parsed as markdown
+        if let (Some(start), Some(end)) = (start, end) {
+            html.push('\n');
+            html.push_str(&tab.lines[start..end + 1].join("\n"));
+            html.push_str("\n\n");
+        }
+        html.push_str("</div>\n");
+    }
+
+    html.push_str("</div>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_code_block_gets_a_tab() {
+        let src = "Intro\n{{#tabs}}\n```scala\nval x = 1\n```\n\n```java\nint x = 1;\n```\n{{#endtabs}}\nOutro";
+        let should_be = "Intro\n<div class=\"tabs\">\n<div class=\"tab-labels\">\n\
+                         <button class=\"tab-label active\">scala</button>\n\
+                         <button class=\"tab-label\">java</button>\n</div>\n\
+                         <div class=\"tab-panel active\">\n\n```scala\nval x = 1\n```\n\n</div>\n\
+                         <div class=\"tab-panel\">\n\n```java\nint x = 1;\n```\n\n</div>\n\
+                         </div>\nOutro";
+
+        assert_eq!(render_tabs(src, Path::new("chapter.md")), should_be);
+    }
+
+    #[test]
+    fn tabs_can_be_labelled() {
+        let src = "{{#tabs}}\n{{#tab Scala 2}}\nImplicits:\n```scala\nimplicit val x = 1\n```\n\
+                   {{#tab Scala 3}}\n```scala\ngiven Int = 1\n```\n{{#endtabs}}";
+
+        let got = render_tabs(src, Path::new("chapter.md"));
+
+        assert!(got.contains("<button class=\"tab-label active\">Scala 2</button>"));
+        assert!(got.contains("<button class=\"tab-label\">Scala 3</button>"));
+        assert!(got.contains("\nImplicits:\n```scala\nimplicit val x = 1\n```\n\n</div>"));
+    }
+
+    #[test]
+    fn directives_in_code_blocks_and_escaped_directives_are_left_alone() {
+        let src = "```\n{{#tabs}}\n```\n\\{{#endtabs}}";
+
+        assert_eq!(render_tabs(src, Path::new("chapter.md")), "```\n{{#tabs}}\n```\n{{#endtabs}}");
+    }
+
+    #[test]
+    fn parse_directives() {
+        assert_eq!(parse_directive("{{#tabs}}"), Some(Directive::Tabs));
+        assert_eq!(parse_directive("  {{ #endtabs }} "), Some(Directive::EndTabs));
+        assert_eq!(parse_directive("{{#tab Java <8> }}"), Some(Directive::Tab("Java <8>")));
+        assert_eq!(parse_directive("Some {{#tabs}}"), None);
+        assert_eq!(parse_directive("{{#tabsies}}"), None);
+    }
+}
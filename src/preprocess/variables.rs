@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+use regex::{Captures, Regex};
+use toml::Value;
+
+use errors::*;
+
+use super::{Preprocessor, PreprocessorContext};
+use book::{Book, BookItem};
+use diagnostics::{chapter_file, Diagnostic};
+
+const ENV_PREFIX: &str = "MDBOOK_VAR_";
+
+/// A preprocessor for replacing `{{#var name}}` placeholders with the values
+/// in the `[preprocessor.variables]` table.
+///
+/// When `use-env = true`, a variable can also be set (or overridden) with an
+/// environment variable named `MDBOOK_VAR_` followed by the variable's name
+/// in upper case, so `{{#var product-version}}` would be read from
+/// `MDBOOK_VAR_PRODUCT_VERSION`.
+pub struct VariablesPreprocessor;
+
+impl VariablesPreprocessor {
+    pub(crate) const NAME: &'static str = "variables";
+
+    /// Create a new `VariablesPreprocessor`.
+    pub fn new() -> Self {
+        VariablesPreprocessor
+    }
+}
+
+impl Preprocessor for VariablesPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let mut variables = HashMap::new();
+        let mut use_env = false;
+
+        if let Some(table) = ctx.config.get_preprocessor(Self::NAME) {
+            use_env = table.get("use-env").and_then(|v| v.as_bool()).unwrap_or(false);
+
+            for (name, value) in table {
+                if name == "use-env" || name == "command" {
+                    continue;
+                }
+
+                let value = match *value {
+                    Value::String(ref s) => s.clone(),
+                    Value::Integer(i) => i.to_string(),
+                    Value::Float(f) => f.to_string(),
+                    Value::Boolean(b) => b.to_string(),
+                    Value::Datetime(ref d) => d.to_string(),
+                    // e.g. the list of `renderers`
+                    Value::Array(_) | Value::Table(_) => continue,
+                };
+                variables.insert(name.clone(), value);
+            }
+        }
+
+        book.for_each_mut(|section: &mut BookItem| {
+            if let BookItem::Chapter(ref mut ch) = *section {
+                let file = chapter_file(ch);
+                ch.content = replace_variables(&ch.content, &file, |name| {
+                    if use_env {
+                        if let Ok(value) = env::var(env_var_name(name)) {
+                            return Some(value);
+                        }
+                    }
+
+                    variables.get(name).cloned()
+                });
+            }
+        });
+
+        Ok(book)
+    }
+}
+
+fn env_var_name(variable: &str) -> String {
+    let name: String = variable
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+
+    format!("{}{}", ENV_PREFIX, name)
+}
+
+fn replace_variables<F>(content: &str, file: &Path, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"(?x)                     # insignificant whitespace mode
+            (\\)?                      # an optional escape
+            \{\{\s*\#var\s+            # the placeholder's opening
+            ([a-zA-Z0-9_.\-]+)         # the variable's name
+            \s*\}\}                    # the closing parens"
+        ).unwrap();
+    }
+
+    RE.replace_all(content, |caps: &Captures| {
+        if caps.get(1).is_some() {
+            // omit the escape char
+            return caps[0][1..].to_string();
+        }
+
+        match lookup(&caps[2]) {
+            Some(value) => value,
+            None => {
+                Diagnostic::warning(format!("The variable \"{}\" isn't defined", &caps[2]))
+                    .in_file(file)
+                    .at_offset(content, caps.get(0).unwrap().start())
+                    .emit();
+                caps[0].to_string()
+            }
+        }
+    }).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(content: &str) -> String {
+        replace_variables(content, Path::new("chapter.md"), |name| match name {
+            "product" => Some("Widget".to_string()),
+            "product-version" => Some("1.2.3".to_string()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn variables_are_replaced() {
+        let src = "{{#var product}} v{{ #var product-version }} is out!";
+
+        assert_eq!(replace(src), "Widget v1.2.3 is out!");
+    }
+
+    #[test]
+    fn undefined_and_escaped_variables_are_left_alone() {
+        let src = "{{#var unknown}} and \\{{#var product}}";
+
+        assert_eq!(replace(src), "{{#var unknown}} and {{#var product}}");
+    }
+
+    #[test]
+    fn environment_variable_names() {
+        assert_eq!(env_var_name("product-version"), "MDBOOK_VAR_PRODUCT_VERSION");
+        assert_eq!(env_var_name("name.first"), "MDBOOK_VAR_NAME_FIRST");
+    }
+}
@@ -0,0 +1,530 @@
+//! A renderer which packages the book up as an EPUB 3 e-book.
+
+mod zip;
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use handlebars::html_escape;
+use pulldown_cmark::{Event, Parser};
+use regex::{Captures, Regex};
+
+use self::zip::ZipWriter;
+use book::{Book, BookItem};
+use errors::*;
+use renderer::{RenderContext, Renderer};
+use theme::{self, Theme};
+use utils;
+
+const STYLESHEET: &str = "epub.css";
+
+/// A renderer which creates an EPUB from the book.
+///
+/// Chapters are rendered the same way as for the HTML renderer, then
+/// packaged up with a navigation document, the theme's `epub.css`, the
+/// FontAwesome fonts and any images (or other resources) found in the `src`
+/// directory.
+#[derive(Default)]
+pub struct EpubRenderer;
+
+impl EpubRenderer {
+    /// Create a new `EpubRenderer` instance.
+    pub fn new() -> Self {
+        EpubRenderer
+    }
+}
+
+/// The `[output.epub]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct EpubConfig {
+    /// A unique identifier for the book, such as its ISBN.
+    identifier: Option<String>,
+    /// Extra stylesheets to include in every chapter.
+    additional_css: Vec<PathBuf>,
+}
+
+/// A file going into the EPUB, relative to the `OEBPS` directory.
+struct Resource {
+    href: String,
+    media_type: &'static str,
+    contents: Vec<u8>,
+}
+
+impl Renderer for EpubRenderer {
+    fn name(&self) -> &str {
+        "epub"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let epub_config: EpubConfig = ctx
+            .config
+            .get_deserialized_opt("output.epub")?
+            .unwrap_or_default();
+        let html_config = ctx.config.html_config().unwrap_or_default();
+        let title = ctx
+            .config
+            .book
+            .title
+            .clone()
+            .unwrap_or_else(|| String::from("Untitled"));
+        let language = ctx.config.book.language();
+
+        let theme_dir = match html_config.theme {
+            Some(ref theme) => ctx.root.join(theme),
+            None => ctx.root.join("theme"),
+        };
+        let theme = Theme::new(&theme_dir);
+
+        let mut stylesheets = vec![String::from(STYLESHEET)];
+        let mut resources = vec![
+            Resource {
+                href: String::from(STYLESHEET),
+                media_type: "text/css",
+                contents: theme.epub_css.clone(),
+            },
+            Resource {
+                href: String::from("fonts/fontawesome-webfont.woff"),
+                media_type: "application/font-woff",
+                contents: theme::FONT_AWESOME_WOFF.to_vec(),
+            },
+            Resource {
+                href: String::from("fonts/fontawesome-webfont.ttf"),
+                media_type: "application/vnd.ms-opentype",
+                contents: theme::FONT_AWESOME_TTF.to_vec(),
+            },
+        ];
+
+        for css in &epub_config.additional_css {
+            let href = relative_href(css);
+            let contents = fs::read(ctx.root.join(css))
+                .chain_err(|| format!("Unable to read the stylesheet {}", css.display()))?;
+            resources.push(Resource {
+                href: href.clone(),
+                media_type: "text/css",
+                contents: contents,
+            });
+            stylesheets.push(href);
+        }
+
+        let src_dir = ctx.source_dir();
+        if src_dir.exists() {
+            collect_resources(&src_dir, &src_dir, &theme_dir, &mut resources)?;
+        }
+
+        let mut chapters = Vec::new();
+        for item in ctx.book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                let path = match ch.path {
+                    Some(ref path) => path,
+                    None => continue,
+                };
+
+                let root = utils::fs::path_to_root(path);
+                let links: Vec<_> = stylesheets.iter().map(|s| format!("{}{}", root, s)).collect();
+                let content = utils::render_markdown(&ch.content, html_config.curly_quotes);
+                let content = to_xhtml(&content);
+
+                chapters.push(Resource {
+                    href: chapter_href(path),
+                    media_type: "application/xhtml+xml",
+                    contents: xhtml_page(&ch.name, language, &links, &content).into_bytes(),
+                });
+            }
+        }
+
+        let nav = xhtml_page(
+            &title,
+            language,
+            &stylesheets,
+            &format!(
+                "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n{}</ol>\n</nav>",
+                html_escape(&title),
+                render_nav(&ctx.book)
+            ),
+        );
+        let modified = utils::build_time()?;
+        let package =
+            render_package(ctx, &epub_config, &title, &chapters, &resources, &modified);
+
+        fs::create_dir_all(&ctx.destination)
+            .chain_err(|| "Unexpected error when constructing destination path")?;
+        let filename = ctx.destination.join(epub_filename(&title));
+        debug!("Creating {}", filename.display());
+        let file = File::create(&filename)
+            .chain_err(|| format!("Unable to create {}", filename.display()))?;
+
+        let mut epub = ZipWriter::new(file);
+        epub.add_file("mimetype", b"application/epub+zip")?;
+        epub.add_file("META-INF/container.xml", CONTAINER_XML.as_bytes())?;
+        epub.add_file("OEBPS/content.opf", package.as_bytes())?;
+        epub.add_file("OEBPS/nav.xhtml", nav.as_bytes())?;
+        for resource in chapters.iter().chain(&resources) {
+            epub.add_file(&format!("OEBPS/{}", resource.href), &resource.contents)?;
+        }
+        epub.finish()?;
+
+        Ok(())
+    }
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn xhtml_page(title: &str, language: &str, stylesheets: &[String], body: &str) -> String {
+    let links: String = stylesheets
+        .iter()
+        .map(|href| {
+            format!(
+                "<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\"/>\n",
+                html_escape(href)
+            )
+        }).collect();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" \
+         xml:lang=\"{lang}\" lang=\"{lang}\">\n<head>\n<meta charset=\"UTF-8\"/>\n\
+         <title>{title}</title>\n{links}</head>\n<body>\n{body}\n</body>\n</html>\n",
+        lang = html_escape(language),
+        title = html_escape(title),
+        links = links,
+        body = body
+    )
+}
+
+/// Make a chapter's HTML well-formed XHTML. Raw HTML in the chapter comes
+/// through as it was written, so its void elements (like `<br>`) are closed,
+/// and any named character references other than the five XML has are
+/// written as numeric ones.
+fn to_xhtml(html: &str) -> String {
+    lazy_static! {
+        static ref VOID_ELEMENT: Regex = Regex::new(
+            r"(?xi)
+            <(area|base|br|col|embed|hr|img|input|link|meta|param|source|track|wbr)\b
+            ([^>]*?)\s*/?>"
+        ).unwrap();
+        static ref REFERENCE: Regex = Regex::new(r"&([a-zA-Z][a-zA-Z0-9]*);").unwrap();
+    }
+
+    let html = VOID_ELEMENT.replace_all(html, "<${1}${2} />");
+    REFERENCE
+        .replace_all(&html, |caps: &Captures| match &caps[1] {
+            "amp" | "lt" | "gt" | "quot" | "apos" => caps[0].to_string(),
+            name => numeric_reference(name),
+        }).into_owned()
+}
+
+/// The numeric character references for a named one, which pulldown-cmark
+/// knows the characters of. An unknown reference has its `&` escaped.
+fn numeric_reference(name: &str) -> String {
+    let reference = format!("&{};", name);
+    let decoded: String = Parser::new(&reference)
+        .filter_map(|event| match event {
+            Event::Text(text) => Some(text.into_owned()),
+            _ => None,
+        }).collect();
+
+    if decoded == reference {
+        format!("&amp;{};", name)
+    } else {
+        decoded.chars().map(|c| format!("&#x{:X};", c as u32)).collect()
+    }
+}
+
+/// Render the items of the navigation document's table of contents.
+///
+/// Part titles become headings with their chapters nested under them, and
+/// separators are left out.
+fn render_nav(book: &Book) -> String {
+    let mut parts: Vec<(Option<&str>, Vec<&BookItem>)> = vec![(None, Vec::new())];
+
+    for item in &book.sections {
+        match *item {
+            BookItem::PartTitle(ref title) => parts.push((Some(title), Vec::new())),
+            // suffix chapters aren't part of the last part
+            BookItem::Chapter(ref ch) if ch.number.is_none() && parts[parts.len() - 1].0.is_some() => {
+                parts.push((None, vec![item]))
+            }
+            _ => {
+                let last = parts.len() - 1;
+                parts[last].1.push(item);
+            }
+        }
+    }
+
+    let mut nav = String::new();
+    for (title, items) in parts {
+        let entries = render_nav_entries(items);
+        match title {
+            Some(title) if !entries.is_empty() => nav.push_str(&format!(
+                "<li><span>{}</span>\n<ol>\n{}</ol>\n</li>\n",
+                html_escape(title),
+                entries
+            )),
+            Some(_) => {}
+            None => nav.push_str(&entries),
+        }
+    }
+
+    nav
+}
+
+fn render_nav_entries<'a, I>(items: I) -> String
+where
+    I: IntoIterator<Item = &'a BookItem>,
+{
+    let mut entries = String::new();
+
+    for item in items {
+        if let BookItem::Chapter(ref ch) = *item {
+            let children = render_nav_entries(&ch.sub_items);
+            let name = html_escape(&ch.name);
+
+            let label = match ch.path {
+                Some(ref path) => format!("<a href=\"{}\">{}</a>", chapter_href(path), name),
+                // draft chapters can only be listed if they have children
+                None if children.is_empty() => continue,
+                None => format!("<span>{}</span>", name),
+            };
+
+            entries.push_str("<li>");
+            entries.push_str(&label);
+            if !children.is_empty() {
+                entries.push_str(&format!("\n<ol>\n{}</ol>\n", children));
+            }
+            entries.push_str("</li>\n");
+        }
+    }
+
+    entries
+}
+
+/// Render the package document, which has the book's metadata and lists
+/// every file in the EPUB, saying it was last changed at `modified`.
+fn render_package(
+    ctx: &RenderContext,
+    epub_config: &EpubConfig,
+    title: &str,
+    chapters: &[Resource],
+    resources: &[Resource],
+    modified: &DateTime<Utc>,
+) -> String {
+    let identifier = epub_config
+        .identifier
+        .clone()
+        .unwrap_or_else(|| format!("urn:mdbook:{}", utils::normalize_id(title)));
+    let language = ctx.config.book.language();
+
+    let mut metadata = vec![
+        format!("<dc:identifier id=\"book-id\">{}</dc:identifier>", html_escape(&identifier)),
+        format!("<dc:title>{}</dc:title>", html_escape(title)),
+        format!("<dc:language>{}</dc:language>", html_escape(language)),
+    ];
+    for author in &ctx.config.book.authors {
+        metadata.push(format!("<dc:creator>{}</dc:creator>", html_escape(author)));
+    }
+    if let Some(ref description) = ctx.config.book.description {
+        metadata.push(format!(
+            "<dc:description>{}</dc:description>",
+            html_escape(description)
+        ));
+    }
+    metadata.push(format!(
+        "<meta property=\"dcterms:modified\">{}</meta>",
+        modified.format("%Y-%m-%dT%H:%M:%SZ")
+    ));
+
+    let mut manifest = vec![String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" \
+         properties=\"nav\"/>",
+    )];
+    let mut spine = Vec::new();
+    for (i, resource) in chapters.iter().chain(resources).enumerate() {
+        let id = format!("item-{}", i + 1);
+        manifest.push(format!(
+            "<item id=\"{}\" href=\"{}\" media-type=\"{}\"/>",
+            id,
+            html_escape(&resource.href),
+            resource.media_type
+        ));
+        if i < chapters.len() {
+            spine.push(format!("<itemref idref=\"{}\"/>", id));
+        }
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" \
+         unique-identifier=\"book-id\" xml:lang=\"{}\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}\n</metadata>\n\
+         <manifest>\n{}\n</manifest>\n<spine>\n{}\n</spine>\n</package>\n",
+        html_escape(language),
+        metadata.join("\n"),
+        manifest.join("\n"),
+        spine.join("\n")
+    )
+}
+
+/// Add every file in the `src` directory which an e-reader would understand
+/// (images, fonts, etc.) to the resources, skipping the theme if it lives in
+/// there.
+fn collect_resources(
+    dir: &Path,
+    src_dir: &Path,
+    theme_dir: &Path,
+    resources: &mut Vec<Resource>,
+) -> Result<()> {
+    for entry in utils::fs::read_dir_sorted(dir)? {
+        let path = entry.path();
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.starts_with('.'));
+
+        if hidden || path == theme_dir {
+            continue;
+        } else if path.is_dir() {
+            collect_resources(&path, src_dir, theme_dir, resources)?;
+        } else if let Some(media_type) = media_type(&path) {
+            let relative = path.strip_prefix(src_dir).expect("Always inside the src dir");
+            resources.push(Resource {
+                href: relative_href(relative),
+                media_type: media_type,
+                contents: fs::read(&path)?,
+            });
+        } else {
+            debug!("Leaving {} out of the EPUB", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+
+    let media_type = match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "ttf" | "otf" => "application/vnd.ms-opentype",
+        "woff" => "application/font-woff",
+        "woff2" => "font/woff2",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => return None,
+    };
+
+    Some(media_type)
+}
+
+fn chapter_href(path: &Path) -> String {
+    relative_href(&path.with_extension("html"))
+}
+
+fn relative_href(path: &Path) -> String {
+    let href = utils::fs::normalize_path(&path.display().to_string());
+    href.trim_start_matches("./").to_string()
+}
+
+fn epub_filename(title: &str) -> String {
+    let stem = utils::normalize_id(title);
+    if stem.is_empty() {
+        String::from("book.epub")
+    } else {
+        format!("{}.epub", stem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::{Chapter, SectionNumber};
+    use chrono::TimeZone;
+    use config::Config;
+
+    #[test]
+    fn the_table_of_contents_follows_the_book() {
+        let mut nested = Chapter::new("Nested", String::new(), "first/nested.md", Vec::new());
+        nested.number = Some(SectionNumber(vec![1, 1]));
+        let mut first = Chapter::new("First & Foremost", String::new(), "first.md", Vec::new());
+        first.number = Some(SectionNumber(vec![1]));
+        first.sub_items.push(BookItem::Chapter(nested));
+        let mut draft = Chapter::new_draft("Draft", Vec::new());
+        draft.number = Some(SectionNumber(vec![2]));
+
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Intro", String::new(), "intro.md", Vec::new()))
+            .push_item(BookItem::PartTitle(String::from("Part I")))
+            .push_item(first)
+            .push_item(BookItem::Separator)
+            .push_item(draft)
+            .push_item(Chapter::new("Outro", String::new(), "outro.md", Vec::new()));
+
+        let got = render_nav(&book);
+
+        assert_eq!(
+            got,
+            "<li><a href=\"intro.html\">Intro</a></li>\n\
+             <li><span>Part I</span>\n<ol>\n\
+             <li><a href=\"first.html\">First &amp; Foremost</a>\n<ol>\n\
+             <li><a href=\"first/nested.html\">Nested</a></li>\n</ol>\n</li>\n\
+             </ol>\n</li>\n\
+             <li><a href=\"outro.html\">Outro</a></li>\n"
+        );
+    }
+
+    #[test]
+    fn the_package_has_the_books_metadata() {
+        let mut config = Config::default();
+        config.book.authors = vec![String::from("Jane Doe")];
+        config.book.description = Some(String::from("All about <tags>"));
+        config.book.language = Some(String::from("pt-BR"));
+        let ctx = RenderContext::new("/tmp/book", Book::new(), config, "/tmp/book/epub");
+
+        let modified = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+
+        let got = render_package(&ctx, &EpubConfig::default(), "Livro", &[], &[], &modified);
+        assert!(got.contains("unique-identifier=\"book-id\" xml:lang=\"pt-BR\">"));
+        assert!(got.contains("<meta property=\"dcterms:modified\">2023-11-14T22:13:20Z</meta>"));
+        assert!(got.contains("<dc:language>pt-BR</dc:language>"));
+        assert!(got.contains("<dc:creator>Jane Doe</dc:creator>"));
+        assert!(got.contains("<dc:description>All about &lt;tags&gt;</dc:description>"));
+    }
+
+    #[test]
+    fn raw_html_is_made_well_formed() {
+        let markdown = "One&nbsp;two<br>three\n\n<div>\nA&nbsp;B &copy; &bogus; &amp;\n</div>";
+        let html = utils::render_markdown(markdown, false);
+
+        assert_eq!(
+            to_xhtml(&html),
+            "<p>One\u{a0}two<br />three</p>\n<div>\nA&#xA0;B &#xA9; &amp;bogus; &amp;\n</div>"
+        );
+        assert_eq!(to_xhtml("<img src=\"a.png\" alt=\"A\">"), "<img src=\"a.png\" alt=\"A\" />");
+        assert_eq!(to_xhtml("<hr/><brand>"), "<hr /><brand>");
+    }
+
+    #[test]
+    fn only_known_resources_are_included() {
+        assert_eq!(media_type(Path::new("images/logo.PNG")), Some("image/png"));
+        assert_eq!(media_type(Path::new("notes.txt")), None);
+        assert_eq!(media_type(Path::new("Makefile")), None);
+    }
+
+    #[test]
+    fn epub_file_names() {
+        assert_eq!(epub_filename("My Book"), "my-book.epub");
+        assert_eq!(epub_filename("!!!"), "book.epub");
+    }
+}
@@ -0,0 +1,177 @@
+//! Just enough of the ZIP format to write an EPUB.
+//!
+//! Files are stored without compression, which keeps things simple and is
+//! required for the `mimetype` file anyway.
+
+use std::io::Write;
+
+use errors::*;
+
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+/// ZIP 2.0, the oldest version which all readers understand.
+const VERSION: u16 = 20;
+/// 1980-01-01, the earliest date a ZIP file can have.
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// An entry which has already been written, for the central directory.
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes files into a ZIP archive.
+pub(crate) struct ZipWriter<W: Write> {
+    writer: W,
+    entries: Vec<Entry>,
+    offset: u32,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub(crate) fn new(writer: W) -> ZipWriter<W> {
+        ZipWriter {
+            writer: writer,
+            entries: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Add a file to the archive.
+    pub(crate) fn add_file(&mut self, name: &str, contents: &[u8]) -> Result<()> {
+        let entry = Entry {
+            name: name.to_string(),
+            crc: crc32(contents),
+            size: contents.len() as u32,
+            offset: self.offset,
+        };
+
+        let mut header = Vec::new();
+        push_u32(&mut header, LOCAL_FILE_HEADER);
+        push_u16(&mut header, VERSION);
+        push_entry_details(&mut header, &entry);
+        push_u16(&mut header, 0); // extra field length
+        header.extend_from_slice(name.as_bytes());
+
+        self.write(&header)?;
+        self.write(contents)?;
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Write the central directory, finishing the archive.
+    pub(crate) fn finish(mut self) -> Result<W> {
+        let directory_offset = self.offset;
+        let mut directory = Vec::new();
+
+        for entry in &self.entries {
+            push_u32(&mut directory, CENTRAL_DIRECTORY_HEADER);
+            push_u16(&mut directory, VERSION); // version made by
+            push_u16(&mut directory, VERSION); // version needed to extract
+            push_entry_details(&mut directory, entry);
+            push_u16(&mut directory, 0); // extra field length
+            push_u16(&mut directory, 0); // comment length
+            push_u16(&mut directory, 0); // disk number
+            push_u16(&mut directory, 0); // internal attributes
+            push_u32(&mut directory, 0); // external attributes
+            push_u32(&mut directory, entry.offset);
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let directory_size = directory.len() as u32;
+        let num_entries = self.entries.len() as u16;
+        push_u32(&mut directory, END_OF_CENTRAL_DIRECTORY);
+        push_u16(&mut directory, 0); // this disk
+        push_u16(&mut directory, 0); // the disk the directory starts on
+        push_u16(&mut directory, num_entries);
+        push_u16(&mut directory, num_entries);
+        push_u32(&mut directory, directory_size);
+        push_u32(&mut directory, directory_offset);
+        push_u16(&mut directory, 0); // comment length
+
+        self.write(&directory)?;
+        Ok(self.writer)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.writer
+            .write_all(data)
+            .chain_err(|| "Unable to write to the archive")?;
+        self.offset += data.len() as u32;
+        Ok(())
+    }
+}
+
+/// The fields which are the same in a file's local header and its central
+/// directory header, up to the file name's length.
+fn push_entry_details(buffer: &mut Vec<u8>, entry: &Entry) {
+    push_u16(buffer, 0); // flags
+    push_u16(buffer, 0); // compression method (stored)
+    push_u16(buffer, 0); // modification time
+    push_u16(buffer, DOS_DATE);
+    push_u32(buffer, entry.crc);
+    push_u32(buffer, entry.size); // compressed size
+    push_u32(buffer, entry.size);
+    push_u16(buffer, entry.name.len() as u16);
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.push(value as u8);
+    buffer.push((value >> 8) as u8);
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    push_u16(buffer, value as u16);
+    push_u16(buffer, (value >> 16) as u16);
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    lazy_static! {
+        static ref TABLE: Vec<u32> = (0..256)
+            .map(|n| (0..8).fold(n as u32, |c, _| if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            })).collect();
+    }
+
+    !data.iter().fold(!0, |crc, &byte| {
+        TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_of_a_known_string() {
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414f_a339);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn write_an_archive() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file("mimetype", b"application/epub+zip").unwrap();
+        zip.add_file("a/b.txt", b"Hello").unwrap();
+
+        let got = zip.finish().unwrap();
+
+        // the mimetype has to be the first thing in an EPUB, uncompressed
+        assert_eq!(&got[..4], b"PK\x03\x04");
+        assert_eq!(&got[30..38], b"mimetype");
+        assert_eq!(&got[38..58], b"application/epub+zip");
+
+        let end = &got[got.len() - 22..];
+        assert_eq!(&end[..4], b"PK\x05\x06");
+        assert_eq!(end[10], 2, "there should be two entries");
+        let directory_size = end[12] as usize;
+        let directory_offset = end[16] as usize;
+        assert_eq!(directory_offset + directory_size, got.len() - 22);
+        assert_eq!(&got[directory_offset..directory_offset + 4], b"PK\x01\x02");
+    }
+}
@@ -1,81 +1,167 @@
-use book::{Book, BookItem};
-use config::{Config, HtmlConfig, Playpen};
+use book::{Book, BookItem, Chapter};
+use config::{Analytics, Config, HtmlConfig, Playground, Playpen};
 use errors::*;
 use renderer::html_handlebars::helpers;
+use renderer::html_handlebars::images::add_image_variants;
+use renderer::html_handlebars::page_cache::{CachedPage, PageCache};
+use renderer::html_handlebars::resources::Resources;
+use renderer::html_handlebars::ui_strings::ui_strings;
 use renderer::{RenderContext, Renderer};
 use theme::{self, playpen_editor, Theme};
 use utils;
 
-use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
-
-use handlebars::Handlebars;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use handlebars::{
+    self, Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderError, ScopedJson,
+};
+use pulldown_cmark::{Event, Parser, Tag};
 use regex::{Captures, Regex};
+use serde::Serialize;
 use serde_json;
 
+/// What `404.html` says when the book doesn't have a `404.md`.
+const DEFAULT_404: &str = "# Document not found (404)\n\n\
+                           This URL is invalid, sorry. Please use the navigation bar or search \
+                           to continue.";
+
+/// Where Plausible's script is loaded from, unless the book has its own instance.
+const PLAUSIBLE_SCRIPT_URL: &str = "https://plausible.io/js/plausible.js";
+
 #[derive(Default)]
-pub struct HtmlHandlebars;
+pub struct HtmlHandlebars {
+    helpers: Vec<(String, Arc<HelperDef>)>,
+}
 
 impl HtmlHandlebars {
     pub fn new() -> Self {
-        HtmlHandlebars
+        HtmlHandlebars::default()
+    }
+
+    /// Register an extra Handlebars helper, like one for formatting dates,
+    /// which custom templates can use alongside the built-in ones. A helper
+    /// with the same name as a built-in one replaces it.
+    pub fn with_helper<H: HelperDef + 'static>(&mut self, name: &str, helper: H) -> &mut Self {
+        self.helpers.push((name.to_string(), Arc::new(helper)));
+        self
+    }
+
+    /// The data the page for a chapter is rendered with, which is everything
+    /// its template can use. The chapter is found by its source file, like
+    /// `guide/usage.md`, or its page, like `guide/usage.html`.
+    pub fn template_context(
+        &self,
+        ctx: &RenderContext,
+        chapter: &Path,
+    ) -> Result<serde_json::Value> {
+        let html_config = ctx.config.html_config().unwrap_or_default();
+        let src_dir = ctx.root.join(&ctx.config.book.src);
+        let theme_dir = theme_dir(&ctx.root, &html_config);
+        let theme = theme::Theme::new(&theme_dir);
+        let languages = additional_languages(&html_config, &theme_dir, &theme)?;
+        let mut data = book_data(ctx, &html_config, &languages)?;
+
+        let found = ctx.book.iter().filter_map(|item| match *item {
+            BookItem::Chapter(ref ch) => ch.path.as_ref().map(|path| (ch, path)),
+            _ => None,
+        });
+        for (ch, ch_path) in found {
+            let source_path = ch.source_path.as_ref().unwrap_or(ch_path);
+            if chapter != source_path
+                && chapter != ch_path
+                && chapter != ch_path.with_extension("html")
+            {
+                continue;
+            }
+
+            let content = utils::render_markdown(&ch.content, html_config.curly_quotes);
+            insert_chapter_data(ch, ch_path, &mut data, &html_config, &src_dir)?;
+            data.insert("content".to_owned(), json!(content));
+            return Ok(serde_json::Value::Object(data));
+        }
+
+        bail!("The book has no chapter at {}", chapter.display())
     }
 
     fn render_item(
         &self,
         item: &BookItem,
         mut ctx: RenderItemContext,
-        print_content: &mut String,
+        print_chapters: &mut Vec<(String, String)>,
     ) -> Result<()> {
         // FIXME: This should be made DRY-er and rely less on mutable state
         match *item {
             BookItem::Chapter(ref ch) => {
-                let content = ch.content.clone();
-                let content = utils::render_markdown(&content, ctx.html_config.curly_quotes);
-                print_content.push_str(&content);
+                // Draft chapters only appear in the table of contents
+                let ch_path = match ch.path {
+                    Some(ref path) => path,
+                    None => return Ok(()),
+                };
 
                 // Update the context with data for this file
-                let path = ch
-                    .path
+                let path = ch_path
                     .to_str()
                     .chain_err(|| "Could not convert path to str")?;
-                let filepath = Path::new(&ch.path).with_extension("html");
+                let filepath = ch_path.with_extension("html");
 
                 // "print.html" is used for the print page.
-                if ch.path == Path::new("print.md") {
-                    bail!(ErrorKind::ReservedFilenameError(ch.path.clone()));
+                if ch_path == Path::new("print.md") {
+                    bail!(ErrorKind::ReservedFilenameError(ch_path.clone()));
                 };
 
-                // Non-lexical lifetimes needed :'(
-                let title: String;
-                {
-                    let book_title = ctx
-                        .data
-                        .get("book_title")
-                        .and_then(serde_json::Value::as_str)
-                        .unwrap_or("");
-                    title = ch.name.clone() + " - " + book_title;
-                }
-
-                ctx.data.insert("path".to_owned(), json!(path));
-                ctx.data.insert("content".to_owned(), json!(content));
-                ctx.data.insert("chapter_title".to_owned(), json!(ch.name));
-                ctx.data.insert("title".to_owned(), json!(title));
-                ctx.data.insert(
-                    "path_to_root".to_owned(),
-                    json!(utils::fs::path_to_root(&ch.path)),
-                );
+                insert_chapter_data(ch, ch_path, &mut ctx.data, &ctx.html_config, &ctx.src_dir)?;
 
-                // Render the handlebars template with the data
-                debug!("Render template");
-                let rendered = ctx.handlebars.render("index", &ctx.data)?;
+                let key = ctx.cache.map(|cache| cache.key(&ch.content, &ctx.data));
+                let cached = match (ctx.cache, &key) {
+                    (Some(cache), Some(key)) => cache.get(key),
+                    _ => None,
+                };
+                let (content, rendered) = match cached {
+                    Some(cached) => {
+                        debug!("Using the cached page for {}", path);
+                        ctx.data.insert("content".to_owned(), json!(cached.content));
+                        (cached.content, cached.page)
+                    }
+                    None => {
+                        let content =
+                            utils::render_markdown(&ch.content, ctx.html_config.curly_quotes);
+                        ctx.data.insert("content".to_owned(), json!(content));
+
+                        // Render the handlebars template with the data
+                        debug!("Render template");
+                        let rendered = ctx.handlebars.render("index", &ctx.data)?;
+                        let rendered = self.post_process(rendered, &ctx.html_config);
+
+                        let page = CachedPage { content, page: rendered };
+                        if let (Some(cache), Some(key)) = (ctx.cache, &key) {
+                            if let Err(e) = cache.put(key, &page) {
+                                warn!("Unable to cache the page for {}: {}", path, e);
+                            }
+                        }
+                        (page.content, page.page)
+                    }
+                };
 
-                let rendered = self.post_process(rendered, &ctx.html_config.playpen);
+                print_chapters.push((
+                    utils::fs::normalize_path(&filepath.display().to_string()),
+                    content,
+                ));
 
                 // Write to file
                 debug!("Creating {}", filepath.display());
+                let rendered = with_image_variants(
+                    rendered,
+                    &filepath,
+                    &ctx.src_dir,
+                    &ctx.destination,
+                    ctx.previous_destination.as_deref(),
+                    &ctx.html_config,
+                )?;
                 utils::fs::write_file(&ctx.destination, &filepath, rendered.as_bytes())?;
 
                 if ctx.is_index {
@@ -83,7 +169,15 @@ impl HtmlHandlebars {
                     ctx.data.insert("path_to_root".to_owned(), json!(""));
                     let rendered_index = ctx.handlebars.render("index", &ctx.data)?;
                     let rendered_index =
-                        self.post_process(rendered_index, &ctx.html_config.playpen);
+                        self.post_process(rendered_index, &ctx.html_config);
+                    let rendered_index = with_image_variants(
+                        rendered_index,
+                        Path::new("index.html"),
+                        &ctx.src_dir,
+                        &ctx.destination,
+                        ctx.previous_destination.as_deref(),
+                        &ctx.html_config,
+                    )?;
                     debug!("Creating index.html from {}", path);
                     utils::fs::write_file(
                         &ctx.destination,
@@ -98,11 +192,54 @@ impl HtmlHandlebars {
         Ok(())
     }
 
+    /// Render `404.html` from `404.md` in the `src` directory, or a short
+    /// message if there isn't one.
+    fn render_404(
+        &self,
+        handlebars: &Handlebars,
+        src_dir: &Path,
+        destination: &Path,
+        mut data: serde_json::Map<String, serde_json::Value>,
+        html_config: &HtmlConfig,
+    ) -> Result<()> {
+        let input = src_dir.join("404.md");
+        let content = if input.exists() {
+            utils::fs::file_to_string(&input).chain_err(|| "Unable to read 404.md")?
+        } else {
+            String::from(DEFAULT_404)
+        };
+        let content = utils::render_markdown(&content, html_config.curly_quotes);
+
+        // The page can be shown at any URL, so links are made relative to
+        // the root of the site instead of the page
+        let mut base_url = html_config.site_url.clone().unwrap_or_else(|| String::from("/"));
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+
+        let title = match data.get("book_title").and_then(serde_json::Value::as_str) {
+            Some(book_title) => format!("Page not found - {}", book_title),
+            None => String::from("Page not found"),
+        };
+        data.insert("path".to_owned(), json!("404.md"));
+        data.insert("path_to_root".to_owned(), json!(""));
+        data.insert("base_url".to_owned(), json!(base_url));
+        data.insert("content".to_owned(), json!(content));
+        data.insert("title".to_owned(), json!(title));
+
+        let rendered = handlebars.render("index", &data)?;
+        let rendered = self.post_process(rendered, html_config);
+        utils::fs::write_file(destination, "404.html", rendered.as_bytes())?;
+        debug!("Creating 404.html ✓");
+
+        Ok(())
+    }
+
     #[cfg_attr(feature = "cargo-clippy", allow(let_and_return))]
-    fn post_process(&self, rendered: String, playpen_config: &Playpen) -> String {
+    fn post_process(&self, rendered: String, html_config: &HtmlConfig) -> String {
         let rendered = build_header_links(&rendered);
         let rendered = fix_code_blocks(&rendered);
-        let rendered = add_playpen_pre(&rendered, playpen_config);
+        let rendered = add_playpen_pre(&rendered, &html_config.playpen, &html_config.playground);
 
         rendered
     }
@@ -121,22 +258,10 @@ impl HtmlHandlebars {
             b"This file makes sure that Github Pages doesn't process mdBook's output.",
         )?;
 
-        write_file(destination, "book.js", &theme.js)?;
-        write_file(destination, "css/general.css", &theme.general_css)?;
-        write_file(destination, "css/chrome.css", &theme.chrome_css)?;
-        write_file(destination, "css/print.css", &theme.print_css)?;
-        write_file(destination, "css/variables.css", &theme.variables_css)?;
+        for (file, contents) in theme_resources(theme, html_config) {
+            write_file(destination, file, contents)?;
+        }
         write_file(destination, "favicon.png", &theme.favicon)?;
-        write_file(destination, "highlight.css", &theme.highlight_css)?;
-        write_file(destination, "tomorrow-night.css", &theme.tomorrow_night_css)?;
-        write_file(destination, "ayu-highlight.css", &theme.ayu_highlight_css)?;
-        write_file(destination, "highlight.js", &theme.highlight_js)?;
-        write_file(destination, "clipboard.min.js", &theme.clipboard_js)?;
-        write_file(
-            destination,
-            "FontAwesome/css/font-awesome.css",
-            theme::FONT_AWESOME,
-        )?;
         write_file(
             destination,
             "FontAwesome/fonts/fontawesome-webfont.eot",
@@ -168,22 +293,6 @@ impl HtmlHandlebars {
             theme::FONT_AWESOME_TTF,
         )?;
 
-        let playpen_config = &html_config.playpen;
-
-        // Ace is a very large dependency, so only load it when requested
-        if playpen_config.editable && playpen_config.copy_js {
-            // Load the editor
-            write_file(destination, "editor.js", playpen_editor::JS)?;
-            write_file(destination, "ace.js", playpen_editor::ACE_JS)?;
-            write_file(destination, "mode-rust.js", playpen_editor::MODE_RUST_JS)?;
-            write_file(destination, "theme-dawn.js", playpen_editor::THEME_DAWN_JS)?;
-            write_file(
-                destination,
-                "theme-tomorrow_night.js",
-                playpen_editor::THEME_TOMORROW_NIGHT_JS,
-            )?;
-        }
-
         Ok(())
     }
 
@@ -205,28 +314,96 @@ impl HtmlHandlebars {
         );
     }
 
-    fn register_hbs_helpers(&self, handlebars: &mut Handlebars, html_config: &HtmlConfig) {
+    /// A `Handlebars` registry with the theme's templates and every helper.
+    fn handlebars(
+        &self,
+        theme: &Theme,
+        html_config: &HtmlConfig,
+        resources: &Resources,
+    ) -> Result<Handlebars> {
+        let mut handlebars = Handlebars::new();
+
+        debug!("Register the index handlebars template");
+        handlebars.register_template_string("index", String::from_utf8(theme.index.clone())?)?;
+
+        debug!("Register the header handlebars template");
+        handlebars.register_partial("header", String::from_utf8(theme.header.clone())?)?;
+
+        debug!("Register handlebars helpers");
+        self.register_hbs_helpers(&mut handlebars, html_config, resources);
+
+        Ok(handlebars)
+    }
+
+    /// The cache of pages from earlier builds, if the book has one. Custom
+    /// helpers could render anything, so pages aren't cached when there are
+    /// any.
+    fn page_cache(
+        &self,
+        ctx: &RenderContext,
+        theme: &Theme,
+        html_config: &HtmlConfig,
+        resources: &Resources,
+    ) -> Option<PageCache> {
+        let dir = match html_config.cache_dir {
+            Some(ref dir) => ctx.root.join(dir),
+            None => return None,
+        };
+        if !self.helpers.is_empty() {
+            debug!("Not using the page cache because custom helpers are registered");
+            return None;
+        }
+        Some(PageCache::new(dir, theme, html_config, resources))
+    }
+
+    fn register_hbs_helpers(
+        &self,
+        handlebars: &mut Handlebars,
+        html_config: &HtmlConfig,
+        resources: &Resources,
+    ) {
         handlebars.register_helper(
             "toc",
             Box::new(helpers::toc::RenderToc {
                 no_section_label: html_config.no_section_label,
+                section_numbering_depth: html_config.section_numbering_depth,
+                fold_level: if html_config.fold.enable {
+                    Some(html_config.fold.level)
+                } else {
+                    None
+                },
             }),
         );
         handlebars.register_helper("previous", Box::new(helpers::navigation::previous));
         handlebars.register_helper("next", Box::new(helpers::navigation::next));
+        handlebars.register_helper(
+            "resource",
+            Box::new(helpers::resource::RenderResource {
+                resources: resources.clone(),
+            }),
+        );
+
+        for &(ref name, ref helper) in &self.helpers {
+            handlebars.register_helper(name, Box::new(SharedHelper(Arc::clone(helper))));
+        }
     }
 
     /// Copy across any additional CSS and JavaScript files which the book
     /// has been configured to use.
-    fn copy_additional_css_and_js(
+    fn copy_custom_files(
         &self,
         html: &HtmlConfig,
         root: &Path,
         destination: &Path,
     ) -> Result<()> {
-        let custom_files = html.additional_css.iter().chain(html.additional_js.iter());
+        let custom_files = html
+            .additional_css
+            .iter()
+            .chain(html.additional_js.iter())
+            .chain(html.favicon.iter())
+            .chain(html.logo.iter());
 
-        debug!("Copying additional CSS and JS");
+        debug!("Copying additional CSS and JS, the favicon and the logo");
 
         for custom_file in custom_files {
             let input_location = root.join(custom_file);
@@ -252,6 +429,47 @@ impl HtmlHandlebars {
 
         Ok(())
     }
+
+    /// Copy across any additional files and directories the book has been
+    /// configured to include, without touching their contents.
+    fn copy_additional_resources(
+        &self,
+        html: &HtmlConfig,
+        root: &Path,
+        destination: &Path,
+    ) -> Result<()> {
+        debug!("Copying additional resources");
+
+        for resource in &html.additional_resources {
+            let input_location = root.join(resource);
+            let output_location = destination.join(resource);
+            debug!(
+                "Copying {} -> {}",
+                input_location.display(),
+                output_location.display()
+            );
+
+            if input_location.is_dir() {
+                fs::create_dir_all(&output_location)
+                    .chain_err(|| format!("Unable to create {}", output_location.display()))?;
+                utils::fs::copy_files_except_ext(&input_location, &output_location, true, &[])?;
+            } else {
+                if let Some(parent) = output_location.parent() {
+                    fs::create_dir_all(parent)
+                        .chain_err(|| format!("Unable to create {}", parent.display()))?;
+                }
+                fs::copy(&input_location, &output_location).chain_err(|| {
+                    format!(
+                        "Unable to copy {} to {}",
+                        input_location.display(),
+                        output_location.display()
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 // TODO(mattico): Remove some time after the 0.1.8 release
@@ -282,15 +500,11 @@ impl Renderer for HtmlHandlebars {
         let html_config = ctx.config.html_config().unwrap_or_default();
         let src_dir = ctx.root.join(&ctx.config.book.src);
         let destination = &ctx.destination;
+        let previous_destination = ctx.previous_destination.as_deref();
         let book = &ctx.book;
 
         trace!("render");
-        let mut handlebars = Handlebars::new();
-
-        let theme_dir = match html_config.theme {
-            Some(ref theme) => theme.to_path_buf(),
-            None => ctx.root.join("theme"),
-        };
+        let theme_dir = theme_dir(&ctx.root, &html_config);
 
         if html_config.theme.is_none()
             && maybe_wrong_theme_dir(&src_dir.join("theme")).unwrap_or(false)
@@ -302,39 +516,57 @@ impl Renderer for HtmlHandlebars {
             warn!("Please move your theme files to `./theme` for them to continue being used");
         }
 
-        let theme = theme::Theme::new(theme_dir);
-
-        debug!("Register the index handlebars template");
-        handlebars.register_template_string("index", String::from_utf8(theme.index.clone())?)?;
-
-        debug!("Register the header handlebars template");
-        handlebars.register_partial("header", String::from_utf8(theme.header.clone())?)?;
-
-        debug!("Register handlebars helpers");
-        self.register_hbs_helpers(&mut handlebars, &html_config);
-
-        let mut data = make_data(&ctx.root, &book, &ctx.config, &html_config)?;
-
-        // Print version
-        let mut print_content = String::new();
+        let theme = theme::Theme::new(&theme_dir);
+        let languages = additional_languages(&html_config, &theme_dir, &theme)?;
+        let resources = resources(&ctx.root, &theme, &html_config, &languages)?;
+        let handlebars = self.handlebars(&theme, &html_config, &resources)?;
+        let mut data = book_data(ctx, &html_config, &languages)?;
+        let cache = self.page_cache(ctx, &theme, &html_config, &resources);
 
         fs::create_dir_all(&destination)
             .chain_err(|| "Unexpected error when constructing destination path")?;
 
+        let mut items = Vec::new();
         let mut is_index = true;
         for item in book.iter() {
+            items.push((item, is_index));
+            let is_draft = match *item {
+                BookItem::Chapter(ref ch) => ch.is_draft_chapter(),
+                _ => false,
+            };
+            // the first chapter with a page is the index
+            is_index = is_index && is_draft;
+        }
+
+        // Chapters are rendered in parallel, then put back in order for the
+        // print version
+        let rendered = utils::parallel_map_mut(&mut items, |&mut (item, is_index)| {
             let ctx = RenderItemContext {
                 handlebars: &handlebars,
+                src_dir: src_dir.clone(),
                 destination: destination.to_path_buf(),
+                previous_destination: previous_destination.map(Path::to_path_buf),
                 data: data.clone(),
-                is_index: is_index,
+                is_index,
                 html_config: html_config.clone(),
+                cache: cache.as_ref(),
             };
-            self.render_item(item, ctx, &mut print_content)?;
-            is_index = false;
+            let mut print_chapter = Vec::new();
+            self.render_item(item, ctx, &mut print_chapter)
+                .map(|()| print_chapter)
+        });
+        let mut print_chapters = Vec::new();
+        for print_chapter in rendered {
+            print_chapters.extend(print_chapter?);
+        }
+        if let Some(ref cache) = cache {
+            cache.prune().chain_err(|| "Unable to clean up the page cache")?;
         }
 
+        self.render_404(&handlebars, &src_dir, destination, data.clone(), &html_config)?;
+
         // Print version
+        let print_content = render_print_content(&print_chapters);
         self.configure_print_version(&mut data, &print_content);
         if let Some(ref title) = ctx.config.book.title {
             data.insert("title".to_owned(), json!(title));
@@ -344,7 +576,15 @@ impl Renderer for HtmlHandlebars {
         debug!("Render template");
         let rendered = handlebars.render("index", &data)?;
 
-        let rendered = self.post_process(rendered, &html_config.playpen);
+        let rendered = self.post_process(rendered, &html_config);
+        let rendered = with_image_variants(
+            rendered,
+            Path::new("print.html"),
+            &src_dir,
+            destination,
+            previous_destination,
+            &html_config,
+        )?;
 
         utils::fs::write_file(&destination, "print.html", rendered.as_bytes())?;
         debug!("Creating print.html ✓");
@@ -352,25 +592,517 @@ impl Renderer for HtmlHandlebars {
         debug!("Copy static files");
         self.copy_static_files(&destination, &theme, &html_config)
             .chain_err(|| "Unable to copy across static files")?;
-        self.copy_additional_css_and_js(&html_config, &ctx.root, &destination)
+        self.copy_custom_files(&html_config, &ctx.root, &destination)
             .chain_err(|| "Unable to copy across additional CSS and JS")?;
+        for &(ref name, ref definition) in &languages {
+            utils::fs::write_file(&destination, language_path(name), definition)?;
+        }
+        resources
+            .write(&destination)
+            .chain_err(|| "Unable to write the fingerprinted CSS and JS")?;
+        self.copy_additional_resources(&html_config, &ctx.root, &destination)
+            .chain_err(|| "Unable to copy across additional resources")?;
 
         // Render search index
         #[cfg(feature = "search")]
         {
-            let search = html_config.search.unwrap_or_default();
-            if search.enable {
+            let search = html_config.search.clone().unwrap_or_default();
+            if search.enable || search.export {
                 super::search::create_files(&search, &destination, &book)?;
             }
         }
 
-        // Copy all remaining files
-        utils::fs::copy_files_except_ext(&src_dir, &destination, true, &["md"])?;
+        // Untranslated chapters of a multilingual book come from another
+        // language, so they need its files too
+        if ctx.config.book.multilingual {
+            for dir in fallback_dirs(&book) {
+                let dir = src_dir.join(dir);
+                if dir.exists() {
+                    utils::fs::copy_files_except_ext(&dir, &destination, true, &["md"])?;
+                }
+            }
+        }
+
+        // Copy all remaining files (books built in memory may not have any)
+        if src_dir.exists() {
+            utils::fs::copy_files_except_ext(&src_dir, &destination, true, &["md"])?;
+        }
+
+        write_redirects(&destination, &html_config.redirect)
+            .chain_err(|| "Unable to write the redirects")?;
+        write_sitemap(&destination, &book, &html_config)
+            .chain_err(|| "Unable to write the sitemap")?;
+
+        Ok(())
+    }
+
+    fn render_chapters(&self, ctx: &RenderContext, chapters: &[PathBuf]) -> Result<()> {
+        let html_config = ctx.config.html_config().unwrap_or_default();
+        let src_dir = ctx.root.join(&ctx.config.book.src);
+        let theme_dir = theme_dir(&ctx.root, &html_config);
+        let theme = theme::Theme::new(&theme_dir);
+        let languages = additional_languages(&html_config, &theme_dir, &theme)?;
+        let resources = resources(&ctx.root, &theme, &html_config, &languages)?;
+        let handlebars = self.handlebars(&theme, &html_config, &resources)?;
+        let data = book_data(ctx, &html_config, &languages)?;
+        let cache = self.page_cache(ctx, &theme, &html_config, &resources);
+
+        // The print page and search index need every chapter, so they're
+        // left as they are until the next full build
+        let mut print_chapters = Vec::new();
+
+        let mut is_index = true;
+        for item in ctx.book.iter() {
+            let (wanted, is_draft) = match *item {
+                BookItem::Chapter(ref ch) => match ch.path {
+                    Some(ref path) => (chapters.contains(path), false),
+                    None => (false, true),
+                },
+                _ => (false, false),
+            };
+            if wanted {
+                let item_ctx = RenderItemContext {
+                    handlebars: &handlebars,
+                    src_dir: src_dir.clone(),
+                    destination: ctx.destination.clone(),
+                    previous_destination: ctx.previous_destination.clone(),
+                    data: data.clone(),
+                    is_index,
+                    html_config: html_config.clone(),
+                    cache: cache.as_ref(),
+                };
+                self.render_item(item, item_ctx, &mut print_chapters)?;
+            }
+            is_index = is_index && is_draft;
+        }
 
         Ok(())
     }
 }
 
+/// The directories outside the source directory which chapters were loaded
+/// from, like `../en` for the chapters a translation hasn't translated yet.
+fn fallback_dirs(book: &Book) -> BTreeSet<PathBuf> {
+    let mut dirs = BTreeSet::new();
+    for item in book.iter() {
+        let source_path = match *item {
+            BookItem::Chapter(ref ch) => match ch.source_path {
+                Some(ref source_path) => source_path,
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        let mut dir = PathBuf::new();
+        for component in source_path.components() {
+            dir.push(component);
+            if component != Component::ParentDir {
+                break;
+            }
+        }
+        if dir.starts_with("..") && dir != source_path.as_path() {
+            dirs.insert(dir);
+        }
+    }
+    dirs
+}
+
+/// Write a page at each old location in `redirects` which sends readers on
+/// to the new one, keeping any `#fragment` they were linked to.
+fn write_redirects(destination: &Path, redirects: &BTreeMap<String, String>) -> Result<()> {
+    for (from, to) in redirects {
+        let from = from.trim_start_matches('/');
+        let path = destination.join(from);
+        if path.exists() {
+            bail!("Not redirecting from \"{}\" because a page already exists there", from);
+        }
+
+        let url = handlebars::html_escape(to);
+        let js_url = serde_json::to_string(to)?.replace("</", "<\\/");
+        let page = format!(
+            "<!DOCTYPE HTML>\n\
+             <html>\n\
+             <head>\n\
+             <meta charset=\"utf-8\">\n\
+             <title>Redirecting...</title>\n\
+             <meta http-equiv=\"refresh\" content=\"0; URL={url}\">\n\
+             <link rel=\"canonical\" href=\"{url}\">\n\
+             </head>\n\
+             <body>\n\
+             <p>Redirecting to <a href=\"{url}\">{url}</a>...</p>\n\
+             <script>window.location.replace({js_url} + window.location.hash);</script>\n\
+             </body>\n\
+             </html>\n",
+            url = url,
+            js_url = js_url
+        );
+
+        debug!("Redirecting {} -> {}", from, to);
+        utils::fs::write_file(destination, from, page.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// The book's `site-url` ending in a `/`, if it's a full URL rather than just
+/// a path.
+fn absolute_site_url(html_config: &HtmlConfig) -> Option<String> {
+    html_config
+        .site_url
+        .as_ref()
+        .filter(|url| url.contains("://"))
+        .map(|url| format!("{}/", url.trim_end_matches('/')))
+}
+
+/// A short description of a chapter for link previews, from the
+/// `description` in its front matter or else its first paragraph.
+fn chapter_description(ch: &Chapter) -> Option<String> {
+    const MAX_LENGTH: usize = 200;
+
+    if let Some(description) = ch.metadata.get("description").and_then(|d| d.as_str()) {
+        return Some(description.to_string());
+    }
+
+    let mut paragraph = String::new();
+    let mut in_paragraph = false;
+    for event in Parser::new(&ch.content) {
+        match event {
+            Event::Start(Tag::Paragraph) => in_paragraph = true,
+            Event::End(Tag::Paragraph) if !paragraph.trim().is_empty() => break,
+            Event::End(Tag::Paragraph) => in_paragraph = false,
+            Event::Text(ref text) if in_paragraph => paragraph.push_str(text),
+            Event::SoftBreak | Event::HardBreak if in_paragraph => paragraph.push(' '),
+            _ => {}
+        }
+    }
+
+    let paragraph = utils::collapse_whitespace(paragraph.trim()).into_owned();
+    if paragraph.is_empty() {
+        return None;
+    }
+    if paragraph.chars().count() <= MAX_LENGTH {
+        return Some(paragraph);
+    }
+
+    // Cut long paragraphs off at the end of a word
+    let cut: String = paragraph.chars().take(MAX_LENGTH).collect();
+    let cut = match cut.rfind(' ') {
+        Some(i) => &cut[..i],
+        None => &cut[..],
+    };
+    Some(format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation())))
+}
+
+/// Write a `sitemap.xml` listing every chapter, if the book's full URL is
+/// known, and the `robots.txt` if it was asked for.
+fn write_sitemap(destination: &Path, book: &Book, html_config: &HtmlConfig) -> Result<()> {
+    let site_url = absolute_site_url(html_config);
+
+    if let Some(ref site_url) = site_url {
+        let mut sitemap = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        );
+        for item in book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                if let Some(ref path) = ch.path {
+                    let path = path.with_extension("html").display().to_string();
+                    let path = utils::fs::normalize_path(&path);
+                    let url = handlebars::html_escape(&format!("{}{}", site_url, path));
+                    sitemap.push_str(&format!("  <url><loc>{}</loc></url>\n", url));
+                }
+            }
+        }
+        sitemap.push_str("</urlset>\n");
+        utils::fs::write_file(destination, "sitemap.xml", sitemap.as_bytes())?;
+        debug!("Creating sitemap.xml ✓");
+    }
+
+    if html_config.robots_txt && !destination.join("robots.txt").exists() {
+        let mut robots = String::from("User-agent: *\nAllow: /\n");
+        if let Some(ref site_url) = site_url {
+            robots.push_str(&format!("Sitemap: {}sitemap.xml\n", site_url));
+        }
+        utils::fs::write_file(destination, "robots.txt", robots.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// The theme's directory, from `output.html.theme` or `theme/` in the book.
+fn theme_dir(root: &Path, html_config: &HtmlConfig) -> PathBuf {
+    match html_config.theme {
+        Some(ref theme) => theme.to_path_buf(),
+        None => root.join("theme"),
+    }
+}
+
+/// The data shared by every page, including the scripts for the `languages`
+/// highlight.js has been extended with.
+fn book_data(
+    ctx: &RenderContext,
+    html_config: &HtmlConfig,
+    languages: &[(String, Vec<u8>)],
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let mut data = make_data(&ctx.root, &ctx.book, &ctx.config, html_config)?;
+    if !languages.is_empty() {
+        let scripts: Vec<_> = languages
+            .iter()
+            .map(|&(ref name, _)| language_path(name))
+            .collect();
+        data.insert("additional_languages".to_owned(), json!(scripts));
+    }
+    Ok(data)
+}
+
+/// Find the definitions for the extra highlight.js languages the book uses,
+/// skipping any which are already bundled with the theme's `highlight.js`.
+fn additional_languages(
+    html_config: &HtmlConfig,
+    theme_dir: &Path,
+    theme: &Theme,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let highlight_js = String::from_utf8_lossy(&theme.highlight_js);
+    let mut languages = Vec::new();
+
+    for name in &html_config.additional_languages {
+        if highlight_js.contains(&format!("registerLanguage(\"{}\"", name)) {
+            debug!("The \"{}\" language is already bundled with highlight.js", name);
+            continue;
+        }
+
+        let path = theme_dir.join("languages").join(format!("{}.js", name));
+        let definition = fs::read(&path).chain_err(|| {
+            format!(
+                "Unable to read the definition of the \"{}\" language from {}",
+                name,
+                path.display()
+            )
+        })?;
+        languages.push((name.clone(), definition));
+    }
+
+    Ok(languages)
+}
+
+fn language_path(name: &str) -> String {
+    format!("languages/{}.js", name)
+}
+
+/// The theme's stylesheets and scripts, by where they're written to.
+fn theme_resources<'a>(
+    theme: &'a Theme,
+    html_config: &HtmlConfig,
+) -> Vec<(&'static str, &'a [u8])> {
+    let mut files = vec![
+        ("css/variables.css", &theme.variables_css[..]),
+        ("css/general.css", &theme.general_css[..]),
+        ("css/chrome.css", &theme.chrome_css[..]),
+        ("css/print.css", &theme.print_css[..]),
+        ("FontAwesome/css/font-awesome.css", theme::FONT_AWESOME),
+        ("highlight.css", &theme.highlight_css[..]),
+        ("tomorrow-night.css", &theme.tomorrow_night_css[..]),
+        ("ayu-highlight.css", &theme.ayu_highlight_css[..]),
+        ("highlight.js", &theme.highlight_js[..]),
+        ("clipboard.min.js", &theme.clipboard_js[..]),
+        ("book.js", &theme.js[..]),
+    ];
+
+    // Ace is a very large dependency, so only load it when requested
+    let playpen_config = &html_config.playpen;
+    if playpen_config.editable && playpen_config.copy_js {
+        files.extend_from_slice(&[
+            ("editor.js", playpen_editor::JS),
+            ("ace.js", playpen_editor::ACE_JS),
+            ("mode-rust.js", playpen_editor::MODE_RUST_JS),
+            ("theme-dawn.js", playpen_editor::THEME_DAWN_JS),
+            ("theme-tomorrow_night.js", playpen_editor::THEME_TOMORROW_NIGHT_JS),
+        ]);
+    }
+
+    files
+}
+
+/// The names pages link to the theme's stylesheets and scripts, the extra
+/// highlight.js `languages`, the search's scripts and the book's additional
+/// CSS and JS by. `searcher.js` fetches the search index by its own name, so
+/// that's left out.
+fn resources(
+    root: &Path,
+    theme: &Theme,
+    html_config: &HtmlConfig,
+    languages: &[(String, Vec<u8>)],
+) -> Result<Resources> {
+    let mut resources = Resources::new(html_config.hash_files);
+    if !html_config.hash_files {
+        return Ok(resources);
+    }
+
+    for (file, contents) in theme_resources(theme, html_config) {
+        resources.add(file, contents);
+    }
+    for (name, definition) in languages {
+        resources.add(&language_path(name), definition);
+    }
+    #[cfg(feature = "search")]
+    {
+        let search = html_config.search.clone().unwrap_or_default();
+        if search.enable && search.copy_js {
+            resources.add("elasticlunr.min.js", theme::searcher::ELASTICLUNR_JS);
+            resources.add("mark.min.js", theme::searcher::MARK_JS);
+            resources.add("searcher.js", theme::searcher::JS);
+        }
+    }
+
+    let custom_files = html_config
+        .additional_css
+        .iter()
+        .chain(html_config.additional_js.iter())
+        .filter(|file| file.is_relative());
+    for file in custom_files {
+        let contents = fs::read(root.join(file))
+            .chain_err(|| format!("Unable to read {}", file.display()))?;
+        resources.add(&file.to_string_lossy(), &contents);
+    }
+
+    Ok(resources)
+}
+
+/// Add the data for a chapter's page, apart from its `content`, on top of the
+/// data shared by every page.
+fn insert_chapter_data(
+    ch: &Chapter,
+    ch_path: &Path,
+    data: &mut serde_json::Map<String, serde_json::Value>,
+    html_config: &HtmlConfig,
+    src_dir: &Path,
+) -> Result<()> {
+    let path = ch_path
+        .to_str()
+        .chain_err(|| "Could not convert path to str")?;
+    let filepath = ch_path.with_extension("html");
+
+    // Non-lexical lifetimes needed :'(
+    let title: String;
+    {
+        let book_title = data
+            .get("book_title")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or("");
+        title = ch.name.clone() + " - " + book_title;
+    }
+
+    data.insert("path".to_owned(), json!(path));
+    data.insert("chapter_title".to_owned(), json!(ch.name));
+    data.insert("metadata".to_owned(), json!(ch.metadata));
+    if let Some(description) = chapter_description(ch) {
+        data.insert("page_description".to_owned(), json!(description));
+    }
+    if let Some(site_url) = absolute_site_url(html_config) {
+        let page = utils::fs::normalize_path(&filepath.display().to_string());
+        data.insert("page_url".to_owned(), json!(site_url + &page));
+    }
+    data.insert("title".to_owned(), json!(title));
+    data.insert(
+        "path_to_root".to_owned(),
+        json!(utils::fs::path_to_root(ch_path)),
+    );
+
+    let source_path = match ch.source_path {
+        Some(ref source_path) => source_path,
+        None => ch_path,
+    };
+    if let Some(ref template) = html_config.edit_url_template {
+        let source_path = utils::fs::normalize_path(&source_path.display().to_string());
+        let edit_url = template.replace("{path}", &source_path);
+        data.insert("edit_url".to_owned(), json!(edit_url));
+    }
+    if html_config.git_metadata {
+        if let Some(commit) = last_commit(&src_dir.join(source_path)) {
+            data.insert("git_commit".to_owned(), commit);
+        }
+    }
+
+    Ok(())
+}
+
+/// A helper registered with `HtmlHandlebars::with_helper`, which each render
+/// gets its own handle to.
+struct SharedHelper(Arc<HelperDef>);
+
+impl HelperDef for SharedHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars,
+        ctx: &'rc Context,
+        rc: &mut handlebars::RenderContext<'reg>,
+    ) -> ::std::result::Result<Option<ScopedJson<'reg, 'rc>>, RenderError> {
+        self.0.call_inner(h, r, ctx, rc)
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars,
+        ctx: &'rc Context,
+        rc: &mut handlebars::RenderContext<'reg>,
+        out: &mut Output,
+    ) -> HelperResult {
+        self.0.call(h, r, ctx, rc, out)
+    }
+}
+
+/// Add the data for the analytics snippet, checking the `analytics` table has
+/// everything its provider needs.
+fn add_analytics(
+    data: &mut serde_json::Map<String, serde_json::Value>,
+    html_config: &HtmlConfig,
+) -> Result<()> {
+    let analytics = match (&html_config.analytics, &html_config.google_analytics) {
+        (&Some(ref analytics), &Some(_)) => {
+            warn!("Both `analytics` and `google-analytics` are set, ignoring `google-analytics`");
+            analytics.clone()
+        }
+        (&Some(ref analytics), &None) => analytics.clone(),
+        (&None, &Some(ref id)) => Analytics {
+            provider: String::from("google"),
+            id: Some(id.clone()),
+            ..Default::default()
+        },
+        (&None, &None) => return Ok(()),
+    };
+
+    match analytics.provider.as_str() {
+        "google" => {
+            let id = match analytics.id {
+                Some(id) => id,
+                None => bail!("Google Analytics needs the `id` of the site to track"),
+            };
+            data.insert("google_analytics".to_owned(), json!(id));
+        }
+        "plausible" => {
+            let domain = match analytics.domain {
+                Some(domain) => domain,
+                None => bail!("Plausible needs the `domain` the site is registered as"),
+            };
+            let script_url = analytics
+                .script_url
+                .unwrap_or_else(|| String::from(PLAUSIBLE_SCRIPT_URL));
+            data.insert(
+                "plausible".to_owned(),
+                json!({ "domain": domain, "script_url": script_url }),
+            );
+        }
+        other => bail!(
+            "Unknown analytics provider \"{}\", expected \"google\" or \"plausible\"",
+            other
+        ),
+    }
+
+    Ok(())
+}
+
 fn make_data(
     root: &Path,
     book: &Book,
@@ -381,7 +1113,33 @@ fn make_data(
     let html = config.html_config().unwrap_or_default();
 
     let mut data = serde_json::Map::new();
-    data.insert("language".to_owned(), json!("en"));
+    let language = config.book.language().to_string();
+    if config.book.multilingual {
+        let languages = config.languages()?;
+        if languages.len() > 1 {
+            let picker: Vec<_> = languages
+                .into_iter()
+                .map(|(code, lang)| {
+                    let name = lang.name.unwrap_or_else(|| code.clone());
+                    json!({ "current": code == language, "name": name, "code": code })
+                })
+                .collect();
+            data.insert("languages".to_owned(), json!(picker));
+        }
+    }
+    data.insert("language".to_owned(), json!(language));
+    data.insert(
+        "text_direction".to_owned(),
+        json!(config.book.text_direction().as_str()),
+    );
+    let ui_language = html_config.language.as_ref().unwrap_or(&language);
+    let ui = ui_strings(ui_language, &html_config.ui_strings);
+    data.insert("ui_strings_json".to_owned(), json!(script_json(&ui)?));
+    data.insert("ui".to_owned(), json!(ui));
+    data.insert(
+        "playground_endpoints_json".to_owned(),
+        json!(script_json(&html_config.playground.endpoints())?),
+    );
     data.insert(
         "book_title".to_owned(),
         json!(config.book.title.clone().unwrap_or_default()),
@@ -390,20 +1148,44 @@ fn make_data(
         "description".to_owned(),
         json!(config.book.description.clone().unwrap_or_default()),
     );
-    data.insert("favicon".to_owned(), json!("favicon.png"));
+    data.insert("authors".to_owned(), json!(config.book.authors));
+    let favicon = match html_config.favicon {
+        Some(ref favicon) => utils::fs::normalize_path(&favicon.display().to_string()),
+        None => String::from("favicon.png"),
+    };
+    data.insert("favicon".to_owned(), json!(favicon));
+    if let Some(ref logo) = html_config.logo {
+        let logo = utils::fs::normalize_path(&logo.display().to_string());
+        data.insert("logo".to_owned(), json!(logo));
+    }
+    if let Some(ref image) = html_config.social_image {
+        let image = match absolute_site_url(html_config) {
+            Some(ref site_url) if !image.contains("://") => {
+                format!("{}{}", site_url, image.trim_start_matches('/'))
+            }
+            _ => image.clone(),
+        };
+        data.insert("social_image".to_owned(), json!(image));
+    }
+    let default_theme = match html_config.default_theme {
+        Some(ref theme) => theme.to_lowercase(),
+        None => String::from("light"),
+    };
+    data.insert("default_theme".to_owned(), json!(default_theme));
     if let Some(ref livereload) = html_config.livereload_url {
         data.insert("livereload".to_owned(), json!(livereload));
     }
 
-    // Add google analytics tag
-    if let Some(ref ga) = config.html_config().and_then(|html| html.google_analytics) {
-        data.insert("google_analytics".to_owned(), json!(ga));
-    }
+    add_analytics(&mut data, html_config)?;
 
     if html.mathjax_support {
         data.insert("mathjax_support".to_owned(), json!(true));
     }
 
+    if html.page_toc {
+        data.insert("page_toc".to_owned(), json!(true));
+    }
+
     // Add check to see if there is an additional style
     if !html.additional_css.is_empty() {
         let mut css = Vec::new();
@@ -416,6 +1198,22 @@ fn make_data(
         data.insert("additional_css".to_owned(), json!(css));
     }
 
+    for &(key, files) in &[
+        ("additional_head", &html.additional_head),
+        ("additional_footer", &html.additional_footer),
+    ] {
+        if files.is_empty() {
+            continue;
+        }
+        let mut snippets = Vec::new();
+        for file in files {
+            let snippet = utils::fs::file_to_string(root.join(file))
+                .chain_err(|| format!("Unable to read {}", file.display()))?;
+            snippets.push(snippet);
+        }
+        data.insert(key.to_owned(), json!(snippets.join("\n")));
+    }
+
     // Add check to see if there is an additional script
     if !html.additional_js.is_empty() {
         let mut js = Vec::new();
@@ -467,15 +1265,19 @@ fn make_data(
                 }
 
                 chapter.insert("name".to_owned(), json!(ch.name));
-                let path = ch
-                    .path
-                    .to_str()
-                    .chain_err(|| "Could not convert path to str")?;
-                chapter.insert("path".to_owned(), json!(path));
+                if let Some(ref path) = ch.path {
+                    let path = path
+                        .to_str()
+                        .chain_err(|| "Could not convert path to str")?;
+                    chapter.insert("path".to_owned(), json!(path));
+                }
             }
             BookItem::Separator => {
                 chapter.insert("spacer".to_owned(), json!("_spacer_"));
             }
+            BookItem::PartTitle(ref title) => {
+                chapter.insert("part".to_owned(), json!(title));
+            }
         }
 
         chapters.push(chapter);
@@ -490,7 +1292,7 @@ fn make_data(
 /// Goes through the rendered HTML, making sure all header tags are wrapped in
 /// an anchor so people can link to sections directly.
 fn build_header_links(html: &str) -> String {
-    let regex = Regex::new(r"<h(\d)>(.*?)</h\d>").unwrap();
+    let regex = Regex::new(r#"<h(\d)(?: id="([^"]+)")?>(.*?)</h\d>"#).unwrap();
     let mut id_counter = HashMap::new();
 
     regex
@@ -498,28 +1300,25 @@ fn build_header_links(html: &str) -> String {
             let level = caps[1]
                 .parse()
                 .expect("Regex should ensure we only ever get numbers here");
+            let explicit_id = caps.get(2).map(|id| id.as_str());
 
-            wrap_header_with_link(level, &caps[2], &mut id_counter)
+            wrap_header_with_link(level, &caps[3], explicit_id, &mut id_counter)
         }).into_owned()
 }
 
 /// Wraps a single header tag with a link, making sure each tag gets its own
-/// unique ID by appending an auto-incremented number (if necessary).
+/// unique ID. Headers without an explicit ID get one from their content.
 fn wrap_header_with_link(
     level: usize,
     content: &str,
+    explicit_id: Option<&str>,
     id_counter: &mut HashMap<String, usize>,
 ) -> String {
-    let raw_id = utils::id_from_content(content);
-
-    let id_count = id_counter.entry(raw_id.clone()).or_insert(0);
-
-    let id = match *id_count {
-        0 => raw_id,
-        other => format!("{}-{}", raw_id, other),
+    let raw_id = match explicit_id {
+        Some(id) => id.to_string(),
+        None => utils::id_from_content(content),
     };
-
-    *id_count += 1;
+    let id = unique_id(&raw_id, id_counter);
 
     format!(
         r##"<a class="header" href="#{id}" id="{id}"><h{level}>{text}</h{level}></a>"##,
@@ -529,6 +1328,115 @@ fn wrap_header_with_link(
     )
 }
 
+/// Makes an ID unique by appending an auto-incremented number (if necessary).
+fn unique_id(raw_id: &str, id_counter: &mut HashMap<String, usize>) -> String {
+    let id_count = id_counter.entry(raw_id.to_string()).or_insert(0);
+
+    let id = match *id_count {
+        0 => raw_id.to_string(),
+        other => format!("{}-{}", raw_id, other),
+    };
+
+    *id_count += 1;
+
+    id
+}
+
+/// Puts every chapter on the one page for `print.html`.
+///
+/// Relative links are made relative to the book's root, and links to other
+/// chapters (or their headers) are turned into links within the page. Header
+/// IDs which are repeated across chapters get a different suffix on the print
+/// page than on the chapter's own page, so those are looked up too. Every
+/// chapter numbers its footnotes from 1, so their IDs get the chapter's in
+/// front of them.
+fn render_print_content(chapters: &[(String, String)]) -> String {
+    lazy_static! {
+        static ref HEADER: Regex = Regex::new(r#"<h\d(?: id="([^"]+)")?>(.*?)</h\d>"#).unwrap();
+        static ref LINK: Regex = Regex::new(r#"(href|src)="([^"]*)""#).unwrap();
+        static ref SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+        static ref FOOTNOTE_ID: Regex = Regex::new(r#"id="(footnote-[\w-]+)""#).unwrap();
+    }
+
+    let paths: HashSet<&str> = chapters.iter().map(|&(ref path, _)| path.as_str()).collect();
+    let mut ids = HashMap::new();
+    let mut print_counter = HashMap::new();
+    for &(ref path, ref content) in chapters {
+        let mut chapter_counter = HashMap::new();
+
+        for caps in HEADER.captures_iter(content) {
+            let raw_id = match caps.get(1) {
+                Some(id) => id.as_str().to_string(),
+                None => utils::id_from_content(&caps[2]),
+            };
+            let chapter_id = unique_id(&raw_id, &mut chapter_counter);
+            let print_id = unique_id(&raw_id, &mut print_counter);
+            ids.insert((path.clone(), chapter_id), print_id);
+        }
+        for caps in FOOTNOTE_ID.captures_iter(content) {
+            let print_id = format!("{}-{}", print_chapter_id(path), &caps[1]);
+            ids.insert((path.clone(), caps[1].to_string()), print_id);
+        }
+    }
+
+    let mut print_content = String::new();
+    for &(ref path, ref content) in chapters {
+        let content = LINK.replace_all(content, |caps: &Captures| {
+            let link = &caps[2];
+            if link.is_empty() || SCHEME.is_match(link) || link.starts_with('/') {
+                return caps[0].to_string();
+            }
+
+            let (target, fragment) = match link.find('#') {
+                Some(i) => (&link[..i], Some(&link[i + 1..])),
+                None => (link, None),
+            };
+            let target = if target.is_empty() {
+                path.clone()
+            } else {
+                utils::fs::resolve_relative_link(path, target)
+            };
+
+            let fixed = if &caps[1] == "href" && paths.contains(target.as_str()) {
+                match fragment {
+                    Some(fragment) => {
+                        let key = (target, fragment.to_string());
+                        format!("#{}", ids.get(&key).map_or(fragment, |id| id.as_str()))
+                    }
+                    None => format!("#{}", print_chapter_id(&target)),
+                }
+            } else {
+                match fragment {
+                    Some(fragment) => format!("{}#{}", target, fragment),
+                    None => target,
+                }
+            };
+
+            format!("{}=\"{}\"", &caps[1], fixed)
+        });
+        let content = FOOTNOTE_ID.replace_all(&content, |caps: &Captures| {
+            format!("id=\"{}-{}\"", print_chapter_id(path), &caps[1])
+        });
+
+        print_content.push_str(&format!(
+            "<div id=\"{}\" class=\"print-chapter\"></div>\n",
+            print_chapter_id(path)
+        ));
+        print_content.push_str(&content);
+    }
+
+    print_content
+}
+
+/// The ID of the spot on the print page where a chapter starts.
+fn print_chapter_id(path: &str) -> String {
+    let id: String = path
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("print-{}", id)
+}
+
 // The rust book uses annotations for rustdoc to test code snippets,
 // like the following:
 // ```rust,should_panic
@@ -542,29 +1450,121 @@ fn fix_code_blocks(html: &str) -> String {
     regex
         .replace_all(html, |caps: &Captures| {
             let before = &caps[1];
-            let classes = &caps[2].replace(",", " ");
+            let (classes, highlighted) = parse_code_attributes(&caps[2]);
             let after = &caps[3];
+            let data = if highlighted.is_empty() {
+                String::new()
+            } else {
+                format!(r#" data-hl-lines="{}""#, highlighted.join(" "))
+            };
 
             format!(
-                r#"<code{before}class="{classes}"{after}>"#,
+                r#"<code{before}class="{classes}"{data}{after}>"#,
                 before = before,
                 classes = classes,
+                data = data,
                 after = after
             )
         }).into_owned()
 }
 
-fn add_playpen_pre(html: &str, playpen_config: &Playpen) -> String {
-    let regex = Regex::new(r##"((?s)<code[^>]?class="([^"]+)".*?>(.*?)</code>)"##).unwrap();
+/// Split a code block's comma separated fence attributes into the classes to
+/// give its `<code>` tag and the line ranges listed by `hl_lines`.
+///
+/// The ranges are separated by commas too, so `hl_lines=1,3-5` highlights
+/// lines 1, 3, 4 and 5.
+fn parse_code_attributes(info: &str) -> (String, Vec<&str>) {
+    let mut classes = Vec::new();
+    let mut highlighted = Vec::new();
+    let mut in_hl_lines = false;
+
+    for attribute in info.split(',').filter(|attribute| !attribute.is_empty()) {
+        let value = if attribute.starts_with("hl_lines=") {
+            in_hl_lines = true;
+            &attribute["hl_lines=".len()..]
+        } else {
+            attribute
+        };
+
+        if in_hl_lines && is_line_range(value) {
+            highlighted.push(value);
+        } else {
+            in_hl_lines = false;
+            classes.push(attribute);
+        }
+    }
+
+    (classes.join(" "), highlighted)
+}
+
+/// Is this a line number (`3`) or an inclusive range of them (`3-5`)?
+fn is_line_range(range: &str) -> bool {
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let mut bounds = range.splitn(2, '-');
+
+    bounds.all(is_number)
+}
+
+/// Offer the smaller versions of a page's images which `[output.html.images]`
+/// asks for, where `page` is the page's path in the book.
+fn with_image_variants(
+    html: String,
+    page: &Path,
+    src_dir: &Path,
+    destination: &Path,
+    previous_destination: Option<&Path>,
+    html_config: &HtmlConfig,
+) -> Result<String> {
+    match html_config.images {
+        Some(ref images) => {
+            let page_dir = page.parent().unwrap_or_else(|| Path::new(""));
+            add_image_variants(
+                &html,
+                page_dir,
+                src_dir,
+                destination,
+                previous_destination,
+                images,
+            ).chain_err(|| format!("Unable to make the images for {}", page.display()))
+        }
+        None => Ok(html),
+    }
+}
+
+/// `value` as JSON for a `<script>`, escaped so it can't close the script.
+fn script_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(value)?
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026"))
+}
+
+fn add_playpen_pre(html: &str, playpen_config: &Playpen, playground: &Playground) -> String {
+    let regex =
+        Regex::new(r##"((?s)<code[^>]?class="([^"]+)"([^>]*)>(.*?)</code>)"##).unwrap();
+    // other languages with a playground are run as they are
+    let runnable: Vec<_> = playground
+        .endpoints()
+        .keys()
+        .filter(|language| *language != "rust")
+        .map(|language| format!("language-{}", language))
+        .collect();
+
     regex
         .replace_all(html, |caps: &Captures| {
             let text = &caps[1];
             let classes = &caps[2];
-            let code = &caps[3];
+            let attributes = &caps[3];
+            let code = &caps[4];
+            let ignored = classes.contains("ignore") || classes.contains("noplaypen");
 
-            if (classes.contains("language-rust")
-                && !classes.contains("ignore")
-                && !classes.contains("noplaypen"))
+            if classes.split_whitespace().any(|class| runnable.iter().any(|r| r == class)) {
+                if ignored {
+                    text.to_owned()
+                } else {
+                    format!("<pre class=\"playpen\">{}</pre>", text)
+                }
+            } else if !ignored && classes.contains("language-rust")
                 || classes.contains("mdbook-runnable")
             {
                 // wrap the contents in an external pre block
@@ -578,9 +1578,9 @@ fn add_playpen_pre(html: &str, playpen_config: &Playpen) -> String {
                     let (attrs, code) = partition_source(code);
 
                     format!(
-                        "<pre class=\"playpen\"><code class=\"{}\">\n# \
+                        "<pre class=\"playpen\"><code class=\"{}\"{}>\n# \
                          #![allow(unused_variables)]\n{}#fn main() {{\n{}#}}</code></pre>",
-                        classes, attrs, code
+                        classes, attributes, attrs, code
                     )
                 }
             } else {
@@ -611,17 +1611,54 @@ fn partition_source(s: &str) -> (String, String) {
     (before, after)
 }
 
+/// The date and hash of the last commit which changed a file, or `None` if
+/// `git` isn't installed or the file isn't in a repository.
+fn last_commit(path: &Path) -> Option<serde_json::Value> {
+    let (dir, file_name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(file_name)) => (dir, file_name),
+        _ => return None,
+    };
+
+    let output = Command::new("git")
+        .args(&["log", "-1", "--date=short", "--format=%cd%n%H%n%h", "--"])
+        .arg(file_name)
+        .current_dir(dir)
+        .output();
+    let output = match output {
+        Ok(ref output) if output.status.success() => String::from_utf8_lossy(&output.stdout),
+        Ok(_) | Err(_) => {
+            debug!("Unable to get the last commit for {}", path.display());
+            return None;
+        }
+    };
+
+    let mut lines = output.lines();
+    match (lines.next(), lines.next(), lines.next()) {
+        (Some(date), Some(hash), Some(short_hash)) => Some(json!({
+            "date": date,
+            "hash": hash,
+            "short_hash": short_hash,
+        })),
+        // Files which have never been committed have no history
+        _ => None,
+    }
+}
+
 struct RenderItemContext<'a> {
     handlebars: &'a Handlebars,
+    src_dir: PathBuf,
     destination: PathBuf,
+    previous_destination: Option<PathBuf>,
     data: serde_json::Map<String, serde_json::Value>,
     is_index: bool,
     html_config: HtmlConfig,
+    cache: Option<&'a PageCache>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::Builder as TempFileBuilder;
 
     #[test]
     fn original_build_header_links() {
@@ -650,6 +1687,10 @@ mod tests {
                 "<h1>Foo</h1><h3>Foo</h3>",
                 r##"<a class="header" href="#foo" id="foo"><h1>Foo</h1></a><a class="header" href="#foo-1" id="foo-1"><h3>Foo</h3></a>"##,
             ),
+            (
+                r#"<h2 id="install">Setup</h2>"#,
+                r##"<a class="header" href="#install" id="install"><h2>Setup</h2></a>"##,
+            ),
         ];
 
         for (src, should_be) in inputs {
@@ -657,4 +1698,151 @@ mod tests {
             assert_eq!(got, should_be);
         }
     }
+    #[test]
+    fn print_page_links_point_within_the_page() {
+        let chapters = vec![
+            (
+                "intro.html".to_string(),
+                "<h1>Intro</h1><p><a href=\"guide/usage.html#usage\">usage</a> \
+                 <a href=\"https://example.com/\">elsewhere</a></p>"
+                    .to_string(),
+            ),
+            (
+                "guide/usage.html".to_string(),
+                "<h1>Usage</h1><h2>Intro</h2><p><a href=\"#intro\">here</a> \
+                 <a href=\"../intro.html\">back</a> <img src=\"img/a.png\" /></p>"
+                    .to_string(),
+            ),
+        ];
+
+        let got = render_print_content(&chapters);
+
+        assert_eq!(
+            got,
+            "<div id=\"print-intro-html\" class=\"print-chapter\"></div>\n\
+             <h1>Intro</h1><p><a href=\"#usage\">usage</a> \
+             <a href=\"https://example.com/\">elsewhere</a></p>\
+             <div id=\"print-guide-usage-html\" class=\"print-chapter\"></div>\n\
+             <h1>Usage</h1><h2>Intro</h2><p><a href=\"#intro-1\">here</a> \
+             <a href=\"#print-intro-html\">back</a> <img src=\"guide/img/a.png\" /></p>"
+        );
+    }
+
+    #[test]
+    fn footnote_ids_are_unique_on_the_print_page() {
+        let footnote = |text: &str| {
+            utils::render_markdown(&format!("{}[^note]\n\n[^note]: A note.", text), false)
+        };
+        let chapters = vec![
+            ("one.html".to_string(), footnote("One")),
+            ("two.html".to_string(), footnote("Two")),
+        ];
+
+        let got = render_print_content(&chapters);
+
+        for chapter in &["one", "two"] {
+            let id = format!("print-{}-html-footnote-1", chapter);
+            let reference_id = format!("print-{}-html-footnote-reference-1-1", chapter);
+            assert!(got.contains(&format!("id=\"{}\"", id)));
+            assert!(got.contains(&format!("href=\"#{}\"", id)));
+            assert!(got.contains(&format!("id=\"{}\"", reference_id)));
+            assert!(got.contains(&format!("href=\"#{}\"", reference_id)));
+        }
+        assert!(!got.contains("\"footnote-1\"") && !got.contains("\"#footnote-1\""));
+    }
+
+    #[test]
+    fn only_languages_missing_from_highlight_js_are_loaded() {
+        let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+        utils::fs::write_file(temp.path(), "languages/protobuf.js", b"/* protobuf */").unwrap();
+        let theme = Theme::new(temp.path());
+        let mut html_config = HtmlConfig::default();
+        html_config.additional_languages = vec![String::from("scala"), String::from("protobuf")];
+
+        let got = additional_languages(&html_config, temp.path(), &theme).unwrap();
+
+        assert_eq!(got, vec![(String::from("protobuf"), b"/* protobuf */".to_vec())]);
+
+        html_config.additional_languages.push(String::from("sbt"));
+        assert!(additional_languages(&html_config, temp.path(), &theme).is_err());
+    }
+
+    #[test]
+    fn no_last_commit_outside_a_repository() {
+        let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+        utils::fs::write_file(temp.path(), "chapter_1.md", b"# Chapter 1").unwrap();
+
+        assert_eq!(last_commit(&temp.path().join("chapter_1.md")), None);
+    }
+
+    #[test]
+    fn code_block_line_highlighting() {
+        let inputs = vec![
+            (
+                r#"<pre><code class="language-scala,linenos">"#,
+                r#"<pre><code class="language-scala linenos">"#,
+            ),
+            (
+                r#"<pre><code class="language-scala,linenos,hl_lines=3-5">"#,
+                r#"<pre><code class="language-scala linenos" data-hl-lines="3-5">"#,
+            ),
+            (
+                r#"<pre><code class="language-rust,hl_lines=1,3-5,ignore">"#,
+                r#"<pre><code class="language-rust ignore" data-hl-lines="1 3-5">"#,
+            ),
+            (
+                r#"<pre><code class="language-rust,hl_lines=x-y">"#,
+                r#"<pre><code class="language-rust hl_lines=x-y">"#,
+            ),
+        ];
+
+        for (src, should_be) in inputs {
+            assert_eq!(fix_code_blocks(src), should_be);
+        }
+    }
+
+    #[test]
+    fn code_in_languages_with_a_playground_can_be_run() {
+        let mut playground = Playground::default();
+        playground
+            .languages
+            .insert(String::from("scala"), String::from("https://example.com/run"));
+        let playpen = Playpen::default();
+
+        let scala = r#"<code class="language-scala">println(1)</code>"#;
+        assert_eq!(
+            add_playpen_pre(scala, &playpen, &playground),
+            format!("<pre class=\"playpen\">{}</pre>", scala)
+        );
+        let ignored = r#"<code class="language-scala ignore">println(1)</code>"#;
+        assert_eq!(add_playpen_pre(ignored, &playpen, &playground), ignored);
+        let java = r#"<code class="language-java">f();</code>"#;
+        assert_eq!(add_playpen_pre(java, &playpen, &playground), java);
+
+        playground.languages.insert(String::from("rust"), String::new());
+        assert_eq!(playground.endpoints().keys().collect::<Vec<_>>(), vec!["scala"]);
+    }
+
+    #[test]
+    fn chapters_are_described_by_their_first_paragraph() {
+        let mut ch = Chapter::new(
+            "Intro",
+            String::from("# Intro\n\n> A quote\n\nThe *first*\nparagraph.\n\nThe second."),
+            "intro.md",
+            Vec::new(),
+        );
+        assert_eq!(chapter_description(&ch), Some(String::from("A quote")));
+
+        ch.content = format!("# Intro\n\n{}", "word ".repeat(100));
+        let description = chapter_description(&ch).unwrap();
+        assert!(description.ends_with("word…"));
+        assert!(description.chars().count() <= 201);
+
+        ch.metadata.insert("description".to_string(), json!("From the front matter"));
+        assert_eq!(chapter_description(&ch), Some(String::from("From the front matter")));
+
+        ch.metadata.clear();
+        ch.content = String::from("# Just a header");
+        assert_eq!(chapter_description(&ch), None);
+    }
 }
@@ -1,2 +1,3 @@
 pub mod navigation;
+pub mod resource;
 pub mod toc;
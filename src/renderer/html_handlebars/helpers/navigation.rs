@@ -107,6 +107,10 @@ fn render(
         json!(utils::fs::path_to_root(&base_path)),
     );
 
+    // so the buttons' tooltips are in the book's language
+    let ui = rc.evaluate_absolute(ctx, "ui", false)?.clone();
+    context.insert("ui".to_owned(), ui);
+
     chapter
         .get("name")
         .ok_or_else(|| RenderError::new("No title found for chapter in JSON data"))
@@ -0,0 +1,40 @@
+use handlebars::{
+    html_escape, Context, Handlebars, Helper, HelperDef, Output, RenderContext, RenderError,
+};
+
+use renderer::html_handlebars::resources::Resources;
+
+/// Handlebars helper linking to one of the book's stylesheets or scripts,
+/// like `{{resource "css/general.css"}}`, by its fingerprinted name if it
+/// has one.
+pub struct RenderResource {
+    pub resources: Resources,
+}
+
+impl HelperDef for RenderResource {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        ctx: &Context,
+        rc: &mut RenderContext,
+        out: &mut Output,
+    ) -> Result<(), RenderError> {
+        let file = h
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or_else(|| RenderError::new("The resource helper needs the path of a file"))?;
+        let path_to_root = rc
+            .evaluate_absolute(ctx, "path_to_root", false)?
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+
+        out.write(&html_escape(&format!(
+            "{}{}",
+            path_to_root,
+            self.resources.name(file)
+        )))?;
+        Ok(())
+    }
+}
@@ -3,7 +3,9 @@ use std::path::Path;
 
 use utils;
 
-use handlebars::{Context, Handlebars, Helper, HelperDef, Output, RenderContext, RenderError};
+use handlebars::{
+    html_escape, Context, Handlebars, Helper, HelperDef, Output, RenderContext, RenderError,
+};
 use pulldown_cmark::{html, Event, Parser, Tag};
 use serde_json;
 
@@ -11,6 +13,9 @@ use serde_json;
 #[derive(Clone, Copy)]
 pub struct RenderToc {
     pub no_section_label: bool,
+    pub section_numbering_depth: Option<usize>,
+    /// If sections can be folded, how many levels start out unfolded.
+    pub fold_level: Option<usize>,
 }
 
 impl HelperDef for RenderToc {
@@ -35,22 +40,37 @@ impl HelperDef for RenderToc {
             .ok_or_else(|| RenderError::new("Type error for `path`, string expected"))?
             .replace("\"", "");
 
+        let toggle_section = match rc.evaluate_absolute(ctx, "ui.toggle_section", false)? {
+            serde_json::Value::String(ref label) => html_escape(label),
+            _ => String::from("Toggle section"),
+        };
+
         out.write("<ol class=\"chapter\">")?;
 
         let mut current_level = 1;
 
-        for item in chapters {
+        for (i, item) in chapters.iter().enumerate() {
             // Spacer
             if item.get("spacer").is_some() {
                 out.write("<li class=\"spacer\"></li>")?;
                 continue;
             }
 
-            let level = if let Some(s) = item.get("section") {
-                s.matches('.').count()
-            } else {
-                1
-            };
+            // Part title, which always goes at the top level
+            if let Some(title) = item.get("part") {
+                while current_level > 1 {
+                    out.write("</ol>")?;
+                    out.write("</li>")?;
+                    current_level -= 1;
+                }
+                out.write("<li class=\"part-title\">")?;
+                out.write(&html_escape(title))?;
+                out.write("</li>")?;
+                continue;
+            }
+
+            let level = item_level(item);
+            let affix = level == current_level && item.get("section").is_none();
 
             if level > current_level {
                 while level > current_level {
@@ -58,20 +78,43 @@ impl HelperDef for RenderToc {
                     out.write("<ol class=\"section\">")?;
                     current_level += 1;
                 }
-                out.write("<li>")?;
             } else if level < current_level {
                 while level < current_level {
                     out.write("</ol>")?;
                     out.write("</li>")?;
                     current_level -= 1;
                 }
+            }
+
+            let has_children = chapters.get(i + 1).map_or(false, |next| {
+                next.get("spacer").is_none()
+                    && next.get("part").is_none()
+                    && item_level(next) > level
+            });
+            let foldable = self.fold_level.is_some() && has_children;
+
+            let mut classes = Vec::new();
+            if affix {
+                classes.push("affix");
+            }
+            if foldable {
+                classes.push("foldable");
+                if self.fold_level.map_or(false, |fold_level| level <= fold_level) {
+                    classes.push("expanded");
+                }
+            }
+
+            if classes.is_empty() {
                 out.write("<li>")?;
             } else {
-                out.write("<li")?;
-                if item.get("section").is_none() {
-                    out.write(" class=\"affix\"")?;
-                }
-                out.write(">")?;
+                out.write(&format!("<li class=\"{}\">", classes.join(" ")))?;
+            }
+            if foldable {
+                out.write(&format!(
+                    "<button class=\"toggle\" title=\"{0}\" aria-label=\"{0}\">\
+                     <i class=\"fa fa-angle-right\"></i></button>",
+                    toggle_section
+                ))?;
             }
 
             // Link
@@ -91,7 +134,7 @@ impl HelperDef for RenderToc {
                     out.write(&tmp)?;
                     out.write("\"")?;
 
-                    if path == &current {
+                    if *path == current {
                         out.write(" class=\"active\"")?;
                     }
 
@@ -104,7 +147,8 @@ impl HelperDef for RenderToc {
                 false
             };
 
-            if !self.no_section_label {
+            let numbered = self.section_numbering_depth.map_or(true, |depth| level <= depth);
+            if !self.no_section_label && numbered {
                 // Section does not necessarily exist
                 if let Some(section) = item.get("section") {
                     out.write("<strong aria-hidden=\"true\">")?;
@@ -149,3 +193,12 @@ impl HelperDef for RenderToc {
         Ok(())
     }
 }
+
+/// How deeply nested a chapter is, with top-level chapters at `1`.
+fn item_level(item: &BTreeMap<String, String>) -> usize {
+    if let Some(s) = item.get("section") {
+        s.matches('.').count()
+    } else {
+        1
+    }
+}
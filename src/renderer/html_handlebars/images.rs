@@ -0,0 +1,238 @@
+//! Smaller versions of a page's images, in modern formats and at several
+//! widths, which browsers can pick from instead of downloading the original.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use regex::{Captures, Regex};
+use shlex::Shlex;
+use tempfile::Builder as TempFileBuilder;
+
+use config::Images;
+use errors::*;
+
+/// Wrap each of a page's local PNG and JPEG images in a `<picture>` offering
+/// the variants `images` asks for, making any which are missing or older than
+/// the image. `page_dir` is the directory of the page, relative to both the
+/// `src` directory the images are made from and the `destination` they're
+/// written to. Variants in the `previous` build which are still up to date
+/// are copied instead of being made again.
+pub(crate) fn add_image_variants(
+    html: &str,
+    page_dir: &Path,
+    src_dir: &Path,
+    destination: &Path,
+    previous: Option<&Path>,
+    images: &Images,
+) -> Result<String> {
+    lazy_static! {
+        static ref IMG: Regex = Regex::new(r#"<img\s[^>]*?src="([^"]+)"[^>]*>"#).unwrap();
+        static ref RASTER: Regex = Regex::new(r"(?i)^([^?#]+)\.(png|jpe?g)$").unwrap();
+        static ref EXTERNAL: Regex = Regex::new(r"^([a-zA-Z][a-zA-Z0-9+.-]*:|/)").unwrap();
+    }
+
+    let mut error = None;
+    let html = IMG.replace_all(html, |caps: &Captures| {
+        let url = &caps[1];
+        let stem = match RASTER.captures(url) {
+            Some(ref raster) if !EXTERNAL.is_match(url) => raster[1].to_string(),
+            _ => return caps[0].to_string(),
+        };
+        let image = src_dir.join(page_dir).join(url);
+        if error.is_some() || !image.is_file() {
+            return caps[0].to_string();
+        }
+
+        let mut sources = String::new();
+        for format in &images.formats {
+            let mut srcset = Vec::new();
+            let widths: Vec<_> = match images.widths.len() {
+                0 => vec![None],
+                _ => images.widths.iter().cloned().map(Some).collect(),
+            };
+
+            for width in widths {
+                let variant = match width {
+                    Some(width) => format!("{}-{}.{}", stem, width, format),
+                    None => format!("{}.{}", stem, format),
+                };
+                let output = destination.join(page_dir).join(&variant);
+                let earlier = previous.map(|dir| dir.join(page_dir).join(&variant));
+                if let Err(e) = make_variant(&images.command, &image, &output, earlier, width) {
+                    error = Some(e);
+                    return caps[0].to_string();
+                }
+
+                srcset.push(match width {
+                    Some(width) => format!("{} {}w", variant, width),
+                    None => variant,
+                });
+            }
+
+            let sizes = match images.sizes {
+                Some(ref sizes) if !images.widths.is_empty() => format!(" sizes=\"{}\"", sizes),
+                _ => String::new(),
+            };
+            sources.push_str(&format!(
+                "<source type=\"image/{}\" srcset=\"{}\"{}>",
+                format,
+                srcset.join(", "),
+                sizes
+            ));
+        }
+
+        format!("<picture>{}{}</picture>", sources, &caps[0])
+    });
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(html.into_owned()),
+    }
+}
+
+/// Convert `image` into `output` with ImageMagick's `command`, shrinking it to
+/// `width` if it's any wider, unless `output` is already newer than `image`.
+/// An `earlier` variant which is newer than `image` is copied instead.
+fn make_variant(
+    command: &str,
+    image: &Path,
+    output: &Path,
+    earlier: Option<PathBuf>,
+    width: Option<u32>,
+) -> Result<()> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let up_to_date = |path: &Path| match (modified(path), modified(image)) {
+        (Some(made), Some(changed)) => made >= changed,
+        _ => false,
+    };
+    if up_to_date(output) {
+        return Ok(());
+    }
+
+    let dir = output.parent().expect("An image is always in a directory");
+    fs::create_dir_all(dir)?;
+    // ImageMagick goes by the extension, and pages being rendered at the same
+    // time might want the same variant
+    let extension = output.extension().unwrap_or_default().to_string_lossy();
+    let temp = TempFileBuilder::new()
+        .prefix(".image-")
+        .suffix(&format!(".{}", extension))
+        .tempfile_in(dir)?;
+
+    match earlier {
+        Some(ref earlier) if up_to_date(earlier) => {
+            debug!("Reusing {} from the last build", earlier.display());
+            fs::copy(earlier, temp.path())?;
+        }
+        _ => {
+            debug!("Converting {} to {}", image.display(), output.display());
+            convert(command, image, temp.path(), width)?;
+        }
+    }
+
+    // temporary files are only readable by their owner
+    fs::set_permissions(temp.path(), fs::metadata(image)?.permissions())?;
+    temp.persist(output)
+        .chain_err(|| format!("Unable to write {}", output.display()))?;
+    Ok(())
+}
+
+/// Run ImageMagick's `command` to turn `image` into `output`.
+fn convert(command: &str, image: &Path, output: &Path, width: Option<u32>) -> Result<()> {
+    let mut words = Shlex::new(command);
+    let program = match words.next() {
+        Some(program) => program,
+        None => bail!("The image conversion command is empty"),
+    };
+    let mut cmd = Command::new(&program);
+    cmd.args(words).arg(image);
+    if let Some(width) = width {
+        cmd.arg("-resize").arg(format!("{}x>", width));
+    }
+    cmd.arg(output);
+
+    let result = cmd
+        .output()
+        .chain_err(|| format!("Unable to run \"{}\" to convert the images", command))?;
+    if !result.status.success() {
+        bail!(
+            "Unable to convert {}: {}",
+            image.display(),
+            String::from_utf8_lossy(&result.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+// `cp` and `false` stand in for ImageMagick, so these are only run where
+// they exist
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use utils::fs::write_file;
+
+    #[test]
+    fn local_images_get_their_variants() {
+        let src = TempDir::new().unwrap();
+        let destination = TempDir::new().unwrap();
+        write_file(src.path(), "guide/img/shot.png", b"PNG").unwrap();
+        // `cp` stands in for ImageMagick, copying the image ($0) to the output ($1)
+        let images = Images {
+            formats: vec![String::from("avif"), String::from("webp")],
+            widths: vec![],
+            sizes: None,
+            command: String::from("sh -c 'cp $0 $1'"),
+        };
+        let html = "<p><img src=\"img/shot.png\" alt=\"A\" /> \
+                    <img src=\"https://example.com/a.png\" /> <img src=\"img/gone.jpg\" /></p>";
+
+        let got = add_image_variants(
+            html,
+            Path::new("guide"),
+            src.path(),
+            destination.path(),
+            None,
+            &images,
+        ).unwrap();
+
+        assert_eq!(
+            got,
+            "<p><picture><source type=\"image/avif\" srcset=\"img/shot.avif\">\
+             <source type=\"image/webp\" srcset=\"img/shot.webp\">\
+             <img src=\"img/shot.png\" alt=\"A\" /></picture> \
+             <img src=\"https://example.com/a.png\" /> <img src=\"img/gone.jpg\" /></p>"
+        );
+        assert!(destination.path().join("guide/img/shot.avif").exists());
+        assert!(destination.path().join("guide/img/shot.webp").exists());
+    }
+
+    #[test]
+    fn variants_from_the_last_build_are_reused() {
+        let src = TempDir::new().unwrap();
+        let previous = TempDir::new().unwrap();
+        let destination = TempDir::new().unwrap();
+        write_file(src.path(), "shot.png", b"PNG").unwrap();
+        write_file(previous.path(), "shot.webp", b"WEBP").unwrap();
+        // converting would fail, so the variant has to come from `previous`
+        let images = Images {
+            formats: vec![String::from("webp")],
+            widths: vec![],
+            sizes: None,
+            command: String::from("false"),
+        };
+
+        add_image_variants(
+            "<img src=\"shot.png\">",
+            Path::new(""),
+            src.path(),
+            destination.path(),
+            Some(previous.path()),
+            &images,
+        ).unwrap();
+
+        let variant = fs::read(destination.path().join("shot.webp")).unwrap();
+        assert_eq!(variant, b"WEBP");
+    }
+}
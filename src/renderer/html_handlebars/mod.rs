@@ -4,6 +4,10 @@ pub use self::hbs_renderer::HtmlHandlebars;
 
 mod hbs_renderer;
 mod helpers;
+mod images;
+mod page_cache;
+mod resources;
+mod ui_strings;
 
 #[cfg(feature = "search")]
 mod search;
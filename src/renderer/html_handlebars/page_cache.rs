@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_json;
+use tempfile::NamedTempFile;
+
+use config::HtmlConfig;
+use errors::*;
+use renderer::html_handlebars::resources::Resources;
+use theme::Theme;
+use utils;
+
+/// A chapter's HTML from an earlier build.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CachedPage {
+    /// The chapter's rendered markdown, which the print page is made from.
+    pub content: String,
+    /// The finished page.
+    pub page: String,
+}
+
+/// Pages rendered by earlier builds, so chapters which haven't changed don't
+/// have to be rendered again.
+///
+/// Each page is keyed on a hash of the chapter's markdown after preprocessing,
+/// which covers whichever preprocessors changed it, and the data its template
+/// is rendered with. That's combined with a hash of everything shared by every
+/// page: the mdBook version, the theme's templates, the `[output.html]` table
+/// and the fingerprinted names of the stylesheets and scripts pages link to.
+pub(crate) struct PageCache {
+    dir: PathBuf,
+    shared: String,
+    /// The entries this build has looked for, so the rest can be removed.
+    used: Mutex<HashSet<String>>,
+}
+
+impl PageCache {
+    pub fn new(
+        dir: PathBuf,
+        theme: &Theme,
+        html_config: &HtmlConfig,
+        resources: &Resources,
+    ) -> PageCache {
+        let html_config = serde_json::to_string(html_config).unwrap_or_default();
+        let shared = hash_parts(&[
+            ::MDBOOK_VERSION.as_bytes(),
+            &theme.index,
+            &theme.header,
+            html_config.as_bytes(),
+            resources.names().as_bytes(),
+        ]);
+
+        PageCache {
+            dir,
+            shared,
+            used: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The key for a chapter with this (preprocessed) markdown, whose page is
+    /// rendered with `data`.
+    pub fn key(&self, markdown: &str, data: &serde_json::Map<String, serde_json::Value>) -> String {
+        let data = serde_json::to_string(data).unwrap_or_default();
+        hash_parts(&[self.shared.as_bytes(), markdown.as_bytes(), data.as_bytes()])
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedPage> {
+        self.used.lock().unwrap().insert(key.to_owned());
+        File::open(self.path(key))
+            .ok()
+            .and_then(|f| serde_json::from_reader(f).ok())
+    }
+
+    /// Save a page. Chapters are rendered in parallel, so it's written to a
+    /// temporary file first, and then moved into place so no one reads it
+    /// half-written.
+    pub fn put(&self, key: &str, page: &CachedPage) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .chain_err(|| format!("Unable to create {}", self.dir.display()))?;
+        let mut temp = NamedTempFile::new_in(&self.dir)?;
+        serde_json::to_writer(&mut temp, page)?;
+        temp.persist(self.path(key)).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    /// Remove the pages which this build didn't look for, like ones for
+    /// chapters which have since changed or been deleted.
+    pub fn prune(&self) -> Result<()> {
+        if !self.dir.exists() {
+            return Ok(());
+        }
+
+        let used = self.used.lock().unwrap();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if !is_stale(&path, &used) {
+                continue;
+            }
+            debug!("Removing {} from the page cache", path.display());
+            fs::remove_file(&path)
+                .chain_err(|| format!("Unable to remove {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// A hash of `parts`, each followed by a NUL so one can't run into the next.
+fn hash_parts(parts: &[&[u8]]) -> String {
+    let mut data = Vec::new();
+    for part in parts {
+        data.extend_from_slice(part);
+        data.push(0);
+    }
+    utils::stable_hash(&data)
+}
+
+/// Is this a cached page which wasn't used? Anything else in the directory is
+/// left alone.
+fn is_stale(path: &Path, used: &HashSet<String>) -> bool {
+    if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+        return false;
+    }
+    match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(key) if key.chars().all(|c| c.is_ascii_hexdigit()) => !used.contains(key),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cache(dir: &TempDir, html_config: &HtmlConfig) -> PageCache {
+        let resources = Resources::default();
+        PageCache::new(dir.path().to_path_buf(), &Theme::default(), html_config, &resources)
+    }
+
+    #[test]
+    fn keys_change_with_the_chapter_and_the_config() {
+        let temp = TempDir::new().unwrap();
+        let plain = cache(&temp, &HtmlConfig::default());
+        let mut data = serde_json::Map::new();
+        data.insert("path".to_owned(), json!("intro.md"));
+
+        let key = plain.key("# Intro", &data);
+        assert_eq!(key, plain.key("# Intro", &data));
+        assert_ne!(key, plain.key("# Introduction", &data));
+
+        let mut other_data = data.clone();
+        other_data.insert("path".to_owned(), json!("other.md"));
+        assert_ne!(key, plain.key("# Intro", &other_data));
+
+        let mut html_config = HtmlConfig::default();
+        html_config.curly_quotes = true;
+        assert_ne!(key, cache(&temp, &html_config).key("# Intro", &data));
+    }
+
+    #[test]
+    fn pages_which_were_not_used_are_pruned() {
+        let temp = TempDir::new().unwrap();
+        let page = CachedPage {
+            content: String::from("<p>Hi</p>"),
+            page: String::from("<html><p>Hi</p></html>"),
+        };
+        let first = cache(&temp, &HtmlConfig::default());
+        first.put("01", &page).unwrap();
+        first.put("02", &page).unwrap();
+
+        let second = cache(&temp, &HtmlConfig::default());
+        assert_eq!(second.get("01"), Some(page));
+        assert_eq!(second.get("03"), None);
+        second.prune().unwrap();
+
+        assert!(second.path("01").exists());
+        assert!(!second.path("02").exists());
+    }
+}
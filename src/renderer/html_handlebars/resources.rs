@@ -0,0 +1,154 @@
+//! The book's stylesheets and scripts, which can also be written under names
+//! with a hash of their contents in them, like `css/general-1c2d3e4f5a6b7c8d.css`.
+//! Pages link to those instead, so a host can let browsers cache them for as
+//! long as it likes: a changed file is a new file, which readers fetch as soon
+//! as they load a page linking to it.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use regex::{Captures, Regex};
+
+use errors::*;
+use utils;
+
+/// The names pages link to the book's stylesheets and scripts by.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Resources {
+    hash_files: bool,
+    /// The fingerprinted name and contents of each file, by its own name.
+    fingerprinted: BTreeMap<String, (String, Vec<u8>)>,
+}
+
+impl Resources {
+    /// Resources which are fingerprinted if `hash_files`, or otherwise keep
+    /// their names.
+    pub fn new(hash_files: bool) -> Resources {
+        Resources {
+            hash_files,
+            fingerprinted: BTreeMap::new(),
+        }
+    }
+
+    /// Fingerprint `file`, a path relative to the root of the book, if files
+    /// are hashed. A stylesheet's `@import`s of files added before it are
+    /// changed to their fingerprinted names, so they're part of its hash.
+    pub fn add(&mut self, file: &str, contents: &[u8]) {
+        if !self.hash_files {
+            return;
+        }
+
+        let contents = if file.ends_with(".css") {
+            self.with_fingerprinted_imports(file, contents)
+        } else {
+            contents.to_vec()
+        };
+        let name = fingerprinted_name(file, &contents);
+        self.fingerprinted.insert(file.to_string(), (name, contents));
+    }
+
+    /// The name to link to `file` by.
+    pub fn name<'a>(&'a self, file: &'a str) -> &'a str {
+        match self.fingerprinted.get(file) {
+            Some((name, _)) => name,
+            None => file,
+        }
+    }
+
+    /// Write the fingerprinted copies of the files into `destination`. The
+    /// originals are left to whatever copies them across, so templates which
+    /// link to them directly keep working.
+    pub fn write(&self, destination: &Path) -> Result<()> {
+        for (name, contents) in self.fingerprinted.values() {
+            utils::fs::write_file(destination, name, contents)?;
+        }
+        Ok(())
+    }
+
+    fn with_fingerprinted_imports(&self, file: &str, contents: &[u8]) -> Vec<u8> {
+        lazy_static! {
+            static ref IMPORT: Regex = Regex::new(r#"@import\s+(['"])([^'"]+)['"]"#).unwrap();
+        }
+
+        let css = String::from_utf8_lossy(contents);
+        let dir = match file.rfind('/') {
+            Some(i) => &file[..i + 1],
+            None => "",
+        };
+        let css = IMPORT.replace_all(&css, |caps: &Captures| {
+            let imported = format!("{}{}", dir, &caps[2]);
+            match self.fingerprinted.get(&imported) {
+                // fingerprinting keeps a file in its directory
+                Some((name, _)) => {
+                    format!("@import {0}{1}{0}", &caps[1], &name[dir.len()..])
+                }
+                None => caps[0].to_string(),
+            }
+        });
+
+        css.into_owned().into_bytes()
+    }
+
+    /// Each file's fingerprinted name, one per line, for the page cache.
+    pub fn names(&self) -> String {
+        self.fingerprinted
+            .iter()
+            .map(|(file, (name, _))| format!("{} {}\n", file, name))
+            .collect()
+    }
+}
+
+/// `file` with a hash of its contents before the extension. The hash is the
+/// start of a SHA-1, so a file keeps its name from one build to the next, and
+/// from one version of mdBook to the next.
+fn fingerprinted_name(file: &str, contents: &[u8]) -> String {
+    let hash = &utils::stable_hash(contents)[..16];
+
+    let start = file.rfind('/').map(|i| i + 1).unwrap_or(0);
+    match file[start..].rfind('.') {
+        Some(dot) => format!("{}-{}{}", &file[..start + dot], hash, &file[start + dot..]),
+        None => format!("{}-{}", file, hash),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn names_are_kept_unless_files_are_hashed() {
+        let mut resources = Resources::new(false);
+        resources.add("book.js", b"console.log(1);");
+
+        assert_eq!(resources.name("book.js"), "book.js");
+    }
+
+    #[test]
+    fn fingerprints_change_with_the_contents() {
+        let mut resources = Resources::new(true);
+        resources.add("css/variables.css", b":root { --fg: black; }");
+        resources.add("css/general.css", b"@import 'variables.css';\nbody { color: var(--fg); }");
+        let variables = resources.name("css/variables.css").to_string();
+        let general = resources.name("css/general.css").to_string();
+
+        assert!(variables.starts_with("css/variables-") && variables.ends_with(".css"));
+        assert_eq!(variables, "css/variables-c8b9c692fb63fd00.css");
+        assert_eq!(resources.name("missing.js"), "missing.js");
+
+        let temp = TempDir::new().unwrap();
+        resources.write(temp.path()).unwrap();
+        let css = utils::fs::file_to_string(temp.path().join(&general)).unwrap();
+        assert_eq!(
+            css,
+            format!("@import '{}';\nbody {{ color: var(--fg); }}", &variables[4..])
+        );
+
+        // a new variables.css gives the general.css importing it a new name too
+        let mut changed = Resources::new(true);
+        changed.add("css/variables.css", b":root { --fg: navy; }");
+        changed.add("css/general.css", b"@import 'variables.css';\nbody { color: var(--fg); }");
+        assert_ne!(changed.name("css/variables.css"), variables);
+        assert_ne!(changed.name("css/general.css"), general);
+    }
+}
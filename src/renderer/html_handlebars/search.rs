@@ -9,7 +9,7 @@ use self::elasticlunr::Index;
 use pulldown_cmark::*;
 use serde_json;
 
-use book::{Book, BookItem};
+use book::{Book, BookItem, Chapter};
 use config::Search;
 use errors::*;
 use theme::searcher;
@@ -18,12 +18,23 @@ use utils;
 /// Creates all files required for search.
 pub fn create_files(search_config: &Search, destination: &Path, book: &Book) -> Result<()> {
     let mut index = Index::new(&["title", "body", "breadcrumbs"]);
-    let mut doc_urls = Vec::with_capacity(book.sections.len());
+    let mut docs = Vec::with_capacity(book.sections.len());
 
     for item in book.iter() {
-        render_item(&mut index, &search_config, &mut doc_urls, item)?;
+        render_item(&mut index, &search_config, &mut docs, item)?;
     }
 
+    if search_config.export {
+        let export = json!({ "version": 1, "documents": docs });
+        let export = serde_json::to_string_pretty(&export)?;
+        utils::fs::write_file(destination, "search-export.json", export.as_bytes())?;
+        debug!("Writing search export ✓");
+    }
+    if !search_config.enable {
+        return Ok(());
+    }
+
+    let doc_urls = docs.into_iter().map(|doc| doc.url).collect();
     let index = write_to_json(index, &search_config, doc_urls)?;
     debug!("Writing search index ✓");
     if index.len() > 10_000_000 {
@@ -46,40 +57,70 @@ pub fn create_files(search_config: &Search, destination: &Path, book: &Book) ->
     Ok(())
 }
 
-/// Uses the given arguments to construct a search document, then inserts it to the given index.
-fn add_doc(
-    index: &mut Index,
-    doc_urls: &mut Vec<String>,
-    anchor_base: &str,
-    section_id: &Option<String>,
-    items: &[&str],
-) {
-    let url = if let &Some(ref id) = section_id {
-        Cow::Owned(format!("{}#{}", anchor_base, id))
-    } else {
-        Cow::Borrowed(anchor_base)
-    };
-    let url = utils::collapse_whitespace(url.trim());
-    let doc_ref = doc_urls.len().to_string();
-    doc_urls.push(url.into());
+/// A section of a chapter, as it's searched for and as it's written to
+/// `search-export.json`.
+#[derive(Debug, PartialEq, Serialize)]
+struct SearchDoc {
+    /// The name of the chapter the section is in.
+    chapter: String,
+    url: String,
+    heading: String,
+    /// The names of the chapter's parents, then the section's parent headings
+    /// and its own heading.
+    breadcrumbs: Vec<String>,
+    body: String,
+}
+
+impl SearchDoc {
+    fn new(
+        chapter: &Chapter,
+        anchor_base: &str,
+        section_id: &Option<String>,
+        heading: &str,
+        body: &str,
+        breadcrumbs: &[String],
+    ) -> SearchDoc {
+        let url = if let &Some(ref id) = section_id {
+            Cow::Owned(format!("{}#{}", anchor_base, id))
+        } else {
+            Cow::Borrowed(anchor_base)
+        };
+        let collapse = |text: &str| utils::collapse_whitespace(text.trim()).into_owned();
+
+        SearchDoc {
+            chapter: chapter.name.clone(),
+            url: collapse(&url),
+            heading: collapse(heading),
+            breadcrumbs: breadcrumbs.iter().map(|crumb| collapse(crumb)).collect(),
+            body: collapse(body),
+        }
+    }
+}
 
-    let items = items.iter().map(|&x| utils::collapse_whitespace(x.trim()));
-    index.add_doc(&doc_ref, items);
+/// Inserts a search document into the given index.
+fn add_doc(index: &mut Index, docs: &mut Vec<SearchDoc>, doc: SearchDoc) {
+    let doc_ref = docs.len().to_string();
+    let breadcrumbs = doc.breadcrumbs.join(" » ");
+    index.add_doc(&doc_ref, &[&doc.heading, &doc.body, &breadcrumbs]);
+    docs.push(doc);
 }
 
 /// Renders markdown into flat unformatted text and adds it to the search index.
 fn render_item(
     index: &mut Index,
     search_config: &Search,
-    doc_urls: &mut Vec<String>,
+    docs: &mut Vec<SearchDoc>,
     item: &BookItem,
 ) -> Result<()> {
-    let chapter = match item {
-        &BookItem::Chapter(ref ch) => ch,
+    let (chapter, path) = match item {
+        &BookItem::Chapter(ref ch) => match ch.path {
+            Some(ref path) => (ch, path),
+            None => return Ok(()),
+        },
         _ => return Ok(()),
     };
 
-    let filepath = Path::new(&chapter.path).with_extension("html");
+    let filepath = path.with_extension("html");
     let filepath = filepath
         .to_str()
         .chain_err(|| "Could not convert HTML path to str")?;
@@ -88,7 +129,8 @@ fn render_item(
     let mut opts = Options::empty();
     opts.insert(OPTION_ENABLE_TABLES);
     opts.insert(OPTION_ENABLE_FOOTNOTES);
-    let p = Parser::new_ext(&chapter.content, opts);
+    let (events, header_ids) = utils::extract_header_ids(Parser::new_ext(&chapter.content, opts));
+    let mut header_ids = header_ids.into_iter();
 
     let mut in_header = false;
     let max_section_depth = search_config.heading_split_level as i32;
@@ -98,19 +140,26 @@ fn render_item(
     let mut breadcrumbs = chapter.parent_names.clone();
     let mut footnote_numbers = HashMap::new();
 
-    for event in p {
+    for event in events {
+        let header_id = match event {
+            Event::End(Tag::Header(_)) => header_ids.next().and_then(|id| id),
+            _ => None,
+        };
+
         match event {
             Event::Start(Tag::Header(i)) if i <= max_section_depth => {
                 if heading.len() > 0 {
                     // Section finished, the next header is following now
                     // Write the data to the index, and clear it for the next section
-                    add_doc(
-                        index,
-                        doc_urls,
+                    let doc = SearchDoc::new(
+                        chapter,
                         &anchor_base,
                         &section_id,
-                        &[&heading, &body, &breadcrumbs.join(" » ")],
+                        &heading,
+                        &body,
+                        &breadcrumbs,
                     );
+                    add_doc(index, docs, doc);
                     section_id = None;
                     heading.clear();
                     body.clear();
@@ -121,7 +170,7 @@ fn render_item(
             }
             Event::End(Tag::Header(i)) if i <= max_section_depth => {
                 in_header = false;
-                section_id = Some(utils::id_from_content(&heading));
+                section_id = Some(header_id.unwrap_or_else(|| utils::id_from_content(&heading)));
                 breadcrumbs.push(heading.clone());
             }
             Event::Start(Tag::FootnoteDefinition(name)) => {
@@ -157,13 +206,15 @@ fn render_item(
 
     if heading.len() > 0 {
         // Make sure the last section is added to the index
-        add_doc(
-            index,
-            doc_urls,
+        let doc = SearchDoc::new(
+            chapter,
             &anchor_base,
             &section_id,
-            &[&heading, &body, &breadcrumbs.join(" » ")],
+            &heading,
+            &body,
+            &breadcrumbs,
         );
+        add_doc(index, docs, doc);
     }
 
     Ok(())
@@ -0,0 +1,76 @@
+//! The text of the theme's own user interface, like the search bar's
+//! placeholder and the navigation buttons' tooltips, which is translated into
+//! the book's language where mdBook has a translation for it.
+
+use std::collections::BTreeMap;
+
+use toml;
+
+/// The built-in translations, by language.
+static UI_STRINGS: &str = include_str!("ui_strings.toml");
+
+/// The theme's strings in `language`, with `overrides` replacing any of them.
+/// Strings which haven't been translated into `language` are left in English.
+pub(crate) fn ui_strings(
+    language: &str,
+    overrides: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut translations: BTreeMap<String, BTreeMap<String, String>> =
+        toml::from_str(UI_STRINGS).expect("The built-in UI strings should be valid");
+    let mut strings = translations.remove("en").expect("The UI strings should be in English");
+
+    // "pt-BR" can make do with "pt", if that's all there is
+    let language = language.to_lowercase().replace('_', "-");
+    let primary = language.split('-').next().unwrap_or_default().to_string();
+    match translations
+        .remove(&language)
+        .or_else(|| translations.remove(&primary))
+    {
+        Some(translation) => strings.extend(translation),
+        None if primary != "en" && overrides.is_empty() => warn!(
+            "The theme hasn't been translated into \"{}\", so its text is in English",
+            language
+        ),
+        None => {}
+    }
+
+    for (name, text) in overrides {
+        if !strings.contains_key(name) {
+            warn!("\"{}\" isn't one of the theme's strings", name);
+        }
+        strings.insert(name.clone(), text.clone());
+    }
+    strings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_translation_has_only_the_english_strings() {
+        let translations: BTreeMap<String, BTreeMap<String, String>> =
+            toml::from_str(UI_STRINGS).unwrap();
+        let english: Vec<_> = translations["en"].keys().collect();
+        for (language, strings) in &translations {
+            for name in strings.keys() {
+                assert!(english.contains(&name), "{} has an unknown {}", language, name);
+            }
+        }
+    }
+
+    #[test]
+    fn strings_come_from_the_closest_translation() {
+        let none = BTreeMap::new();
+        assert_eq!(ui_strings("en", &none)["next_chapter"], "Next chapter");
+        assert_eq!(ui_strings("fr", &none)["next_chapter"], "Chapitre suivant");
+        assert_eq!(ui_strings("de_AT", &none)["next_chapter"], "Nächstes Kapitel");
+        assert_eq!(ui_strings("tlh", &none)["next_chapter"], "Next chapter");
+
+        let mut overrides = BTreeMap::new();
+        overrides.insert(String::from("next_chapter"), String::from("Onward"));
+        let strings = ui_strings("fr", &overrides);
+        assert_eq!(strings["next_chapter"], "Onward");
+        assert_eq!(strings["previous_chapter"], "Chapitre précédent");
+    }
+}
@@ -0,0 +1,227 @@
+use std::fs;
+
+use pulldown_cmark::{Alignment, Event, Options, Parser, Tag};
+use pulldown_cmark::{OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use serde_json::{self, Value};
+
+use book::BookItem;
+use errors::*;
+use renderer::{RenderContext, Renderer};
+use utils;
+
+/// A renderer which exports the book, after it has been preprocessed, as a
+/// single JSON document.
+///
+/// Unlike the `RenderContext` given to alternative backends, the export has
+/// each chapter's HTML (and optionally its parsed markdown events) so other
+/// tools don't need their own markdown parser to use it.
+#[derive(Default)]
+pub struct JsonRenderer;
+
+impl JsonRenderer {
+    /// Create a new `JsonRenderer` instance.
+    pub fn new() -> Self {
+        JsonRenderer
+    }
+}
+
+/// The `[output.json]` table.
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct JsonConfig {
+    /// Include each chapter's HTML.
+    html: bool,
+    /// Include each chapter's markdown events.
+    events: bool,
+}
+
+impl Default for JsonConfig {
+    fn default() -> JsonConfig {
+        JsonConfig {
+            html: true,
+            events: false,
+        }
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let json_config: JsonConfig = ctx
+            .config
+            .get_deserialized_opt("output.json")?
+            .unwrap_or_default();
+        let curly_quotes = ctx.config.html_config().map_or(false, |html| html.curly_quotes);
+
+        let items: Vec<_> = ctx
+            .book
+            .sections
+            .iter()
+            .map(|item| export_item(item, &json_config, curly_quotes))
+            .collect();
+        let book = json!({
+            "version": ctx.version,
+            "title": ctx.config.book.title,
+            "authors": ctx.config.book.authors,
+            "description": ctx.config.book.description,
+            "language": ctx.config.book.language(),
+            "items": items,
+        });
+
+        fs::create_dir_all(&ctx.destination)
+            .chain_err(|| "Unexpected error when constructing destination path")?;
+        let contents = serde_json::to_string_pretty(&book)?;
+        utils::fs::write_file(&ctx.destination, "book.json", contents.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+fn export_item(item: &BookItem, json_config: &JsonConfig, curly_quotes: bool) -> Value {
+    let ch = match *item {
+        BookItem::Chapter(ref ch) => ch,
+        BookItem::Separator => return json!({ "type": "separator" }),
+        BookItem::PartTitle(ref title) => return json!({ "type": "part-title", "title": title }),
+    };
+
+    let sub_items: Vec<_> = ch
+        .sub_items
+        .iter()
+        .map(|item| export_item(item, json_config, curly_quotes))
+        .collect();
+    let path = ch
+        .path
+        .as_ref()
+        .map(|path| utils::fs::normalize_path(&path.display().to_string()));
+    let mut chapter = json!({
+        "type": "chapter",
+        "name": ch.name,
+        "number": ch.number.as_ref().map(|number| number.to_string()),
+        "path": path,
+        "parent_names": ch.parent_names,
+        "metadata": ch.metadata,
+        "content": ch.content,
+        "sub_items": sub_items,
+    });
+
+    if json_config.html {
+        chapter["html"] = json!(utils::render_markdown(&ch.content, curly_quotes));
+    }
+    if json_config.events {
+        chapter["events"] = export_events(&ch.content);
+    }
+
+    chapter
+}
+
+/// Turn a chapter's markdown events into JSON, each with a `type` field
+/// saying which kind of event it is.
+fn export_events(content: &str) -> Value {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+
+    let events: Vec<_> = Parser::new_ext(content, opts)
+        .map(|event| match event {
+            Event::Start(tag) => export_tag("start", tag),
+            Event::End(tag) => export_tag("end", tag),
+            Event::Text(text) => json!({ "type": "text", "text": text }),
+            Event::Html(html) => json!({ "type": "html", "html": html }),
+            Event::InlineHtml(html) => json!({ "type": "inline-html", "html": html }),
+            Event::FootnoteReference(name) => {
+                json!({ "type": "footnote-reference", "name": name })
+            }
+            Event::SoftBreak => json!({ "type": "soft-break" }),
+            Event::HardBreak => json!({ "type": "hard-break" }),
+        }).collect();
+
+    json!(events)
+}
+
+fn export_tag(kind: &str, tag: Tag) -> Value {
+    let (name, mut details) = match tag {
+        Tag::Paragraph => ("paragraph", json!({})),
+        Tag::Rule => ("rule", json!({})),
+        Tag::Header(level) => ("header", json!({ "level": level })),
+        Tag::BlockQuote => ("block-quote", json!({})),
+        Tag::CodeBlock(info) => ("code-block", json!({ "info": info })),
+        Tag::List(start) => ("list", json!({ "start": start })),
+        Tag::Item => ("item", json!({})),
+        Tag::FootnoteDefinition(name) => ("footnote-definition", json!({ "name": name })),
+        Tag::Table(alignments) => {
+            let alignments: Vec<_> = alignments
+                .iter()
+                .map(|alignment| match *alignment {
+                    Alignment::None => Value::Null,
+                    Alignment::Left => json!("left"),
+                    Alignment::Center => json!("center"),
+                    Alignment::Right => json!("right"),
+                }).collect();
+            ("table", json!({ "alignments": alignments }))
+        }
+        Tag::TableHead => ("table-head", json!({})),
+        Tag::TableRow => ("table-row", json!({})),
+        Tag::TableCell => ("table-cell", json!({})),
+        Tag::Emphasis => ("emphasis", json!({})),
+        Tag::Strong => ("strong", json!({})),
+        Tag::Code => ("code", json!({})),
+        Tag::Link(url, title) => ("link", json!({ "url": url, "title": title })),
+        Tag::Image(url, title) => ("image", json!({ "url": url, "title": title })),
+    };
+
+    details["type"] = json!(kind);
+    details["tag"] = json!(name);
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::{Chapter, SectionNumber};
+
+    #[test]
+    fn export_a_chapter() {
+        let mut nested = Chapter::new("Nested", String::from("Hi"), "first/nested.md", Vec::new());
+        nested.number = Some(SectionNumber(vec![1, 1]));
+        let mut first = Chapter::new("First", String::from("# First"), "first.md", Vec::new());
+        first.number = Some(SectionNumber(vec![1]));
+        first.sub_items.push(BookItem::Chapter(nested));
+        let json_config = JsonConfig {
+            html: true,
+            events: true,
+        };
+
+        let got = export_item(&BookItem::Chapter(first), &json_config, false);
+
+        assert_eq!(got["number"], json!("1."));
+        assert_eq!(got["path"], json!("first.md"));
+        assert_eq!(got["html"], json!("<h1>First</h1>\n"));
+        assert_eq!(
+            got["events"],
+            json!([
+                { "type": "start", "tag": "header", "level": 1 },
+                { "type": "text", "text": "First" },
+                { "type": "end", "tag": "header", "level": 1 },
+            ])
+        );
+        assert_eq!(got["sub_items"][0]["number"], json!("1.1."));
+        assert_eq!(got["sub_items"][0]["html"], json!("<p>Hi</p>\n"));
+    }
+
+    #[test]
+    fn part_titles_and_separators() {
+        let json_config = JsonConfig::default();
+
+        assert_eq!(
+            export_item(&BookItem::PartTitle(String::from("Part I")), &json_config, false),
+            json!({ "type": "part-title", "title": "Part I" })
+        );
+        assert_eq!(
+            export_item(&BookItem::Separator, &json_config, false),
+            json!({ "type": "separator" })
+        );
+    }
+}
@@ -0,0 +1,663 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use pulldown_cmark::{Alignment, Event, Options, Parser, Tag};
+use pulldown_cmark::{OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use regex::Regex;
+use shlex::Shlex;
+
+use book::{Book, BookItem, Chapter};
+use config::BookConfig;
+use errors::*;
+use renderer::{RenderContext, Renderer};
+use utils;
+
+/// The sectioning commands for each level of nesting, starting with
+/// top-level chapters.
+const SECTIONS: &[&str] = &[
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+/// Images which `\includegraphics` can cope with.
+const GRAPHICS: &[&str] = &["png", "jpg", "jpeg", "pdf"];
+
+/// A renderer which turns the book into a LaTeX project, ready to be
+/// typeset as a PDF.
+///
+/// `SUMMARY.md` decides the structure of the document: top-level chapters
+/// become `\chapter`s, the chapters nested under them `\section`s and so
+/// on, with part titles as `\part`s. Links between chapters become
+/// cross-references, and code blocks are typeset with the `listings`
+/// package.
+#[derive(Default)]
+pub struct LatexRenderer;
+
+impl LatexRenderer {
+    /// Create a new `LatexRenderer` instance.
+    pub fn new() -> Self {
+        LatexRenderer
+    }
+}
+
+/// The `[output.latex]` table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct LatexConfig {
+    /// A command to run on `book.tex` once it's written, like `tectonic`.
+    engine: Option<String>,
+}
+
+impl Renderer for LatexRenderer {
+    fn name(&self) -> &str {
+        "latex"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let latex_config: LatexConfig = ctx
+            .config
+            .get_deserialized_opt("output.latex")?
+            .unwrap_or_default();
+        let destination = &ctx.destination;
+
+        if destination.exists() {
+            utils::fs::remove_dir_content(destination)
+                .chain_err(|| "Unable to remove stale LaTeX output")?;
+        }
+        fs::create_dir_all(destination)
+            .chain_err(|| "Unexpected error when constructing destination path")?;
+
+        // images are included relative to the document
+        let src_dir = ctx.source_dir();
+        if src_dir.exists() {
+            utils::fs::copy_files_except_ext(&src_dir, destination, true, &["md"])?;
+        }
+
+        let document = render_document(ctx, &ctx.book);
+        utils::fs::write_file(destination, "book.tex", document.as_bytes())?;
+
+        if let Some(ref engine) = latex_config.engine {
+            run_engine(engine, destination)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn run_engine(engine: &str, destination: &Path) -> Result<()> {
+    let mut words = Shlex::new(engine);
+    let program = match words.next() {
+        Some(program) => program,
+        None => bail!("The LaTeX engine command is empty"),
+    };
+
+    info!("Invoking \"{}\" on book.tex", engine);
+    let status = Command::new(&program)
+        .args(words)
+        .arg("book.tex")
+        .current_dir(destination)
+        .status()
+        .chain_err(|| format!("Unable to run the LaTeX engine, \"{}\"", program))?;
+
+    if !status.success() {
+        bail!("The LaTeX engine, \"{}\", failed", engine);
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Matter {
+    Front,
+    Main,
+    Back,
+}
+
+fn render_document(ctx: &RenderContext, book: &Book) -> String {
+    let mut doc = String::from(PREAMBLE);
+    doc.push_str(&title_page(&ctx.config.book));
+    doc.push_str("\n\\begin{document}\n\n");
+    doc.push_str("\\frontmatter\n\\maketitle\n\\tableofcontents\n\n");
+
+    let mut matter = Matter::Front;
+    for item in book.iter_with_paths() {
+        match *item.item {
+            BookItem::Chapter(ref ch) => {
+                if ch.number.is_some() && matter == Matter::Front {
+                    doc.push_str("\\mainmatter\n\n");
+                    matter = Matter::Main;
+                } else if ch.number.is_none() && matter == Matter::Main {
+                    doc.push_str("\\backmatter\n\n");
+                    matter = Matter::Back;
+                }
+                doc.push_str(&render_chapter(ch, item.depth()));
+            }
+            BookItem::PartTitle(ref title) => {
+                if matter == Matter::Front {
+                    doc.push_str("\\mainmatter\n\n");
+                    matter = Matter::Main;
+                }
+                doc.push_str(&format!("\\part{{{}}}\n\n", escape(title)));
+            }
+            BookItem::Separator => {}
+        }
+    }
+
+    doc.push_str("\\end{document}\n");
+    doc
+}
+
+/// The title page's contents, from the book's metadata, which the PDF's own
+/// metadata gets too.
+fn title_page(book: &BookConfig) -> String {
+    let title = escape(book.title.as_ref().map_or("", String::as_str));
+    let authors: Vec<_> = book.authors.iter().map(|a| escape(a)).collect();
+
+    let mut page = String::new();
+    match book.description {
+        Some(ref description) => page.push_str(&format!(
+            "\\title{{{}\\\\[1ex]\n\\large {}}}\n",
+            title,
+            escape(description)
+        )),
+        None => page.push_str(&format!("\\title{{{}}}\n", title)),
+    }
+    page.push_str(&format!("\\author{{{}}}\n", authors.join(" \\and ")));
+    page.push_str("\\date{}\n");
+
+    page.push_str(&format!(
+        "\\hypersetup{{pdftitle={{{}}}, pdfauthor={{{}}}, pdflang={{{}}}",
+        title,
+        authors.join(", "),
+        escape(book.language())
+    ));
+    if let Some(ref description) = book.description {
+        page.push_str(&format!(", pdfsubject={{{}}}", escape(description)));
+    }
+    page.push_str("}\n");
+    page
+}
+
+const PREAMBLE: &str = r"\documentclass{book}
+\usepackage{iftex}
+\ifPDFTeX
+  \usepackage[utf8]{inputenc}
+  \usepackage[T1]{fontenc}
+\else
+  \usepackage{fontspec}
+\fi
+\usepackage{graphicx}
+\usepackage{listings}
+\usepackage{xcolor}
+\usepackage[hidelinks]{hyperref}
+
+\lstset{
+  basicstyle=\ttfamily\small,
+  breaklines=true,
+  columns=fullflexible,
+  keepspaces=true,
+  frame=single,
+  backgroundcolor=\color{black!5},
+}
+
+% shrink images which are too wide for the page, but don't blow up small ones
+\makeatletter
+\def\maxwidth{\ifdim\Gin@nat@width>\linewidth\linewidth\else\Gin@nat@width\fi}
+\makeatother
+
+";
+
+fn render_chapter(ch: &Chapter, depth: usize) -> String {
+    let path = match ch.path {
+        Some(ref path) => utils::fs::normalize_path(&path.display().to_string()),
+        // draft chapters only get their heading
+        None => return format!("\\{}{{{}}}\n\n", section(depth), escape(&ch.name)),
+    };
+
+    let mut writer = LatexWriter::new(&path, depth);
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+    let (events, header_ids) = utils::extract_header_ids(Parser::new_ext(&ch.content, opts));
+    writer.header_ids = header_ids.into_iter();
+    for event in events {
+        writer.push_event(event);
+    }
+
+    let mut labels = format!("\\label{{{}}}", label(&path, None));
+    if let Some(ref title) = writer.title_label {
+        labels.push_str(&format!("\\label{{{}}}", title));
+    }
+    format!(
+        "\\{}{{{}}}{}\n\n{}",
+        section(depth),
+        escape(&ch.name),
+        labels,
+        writer.finish().trim_start()
+    )
+}
+
+fn section(level: usize) -> &'static str {
+    SECTIONS[level.min(SECTIONS.len() - 1)]
+}
+
+/// The label for a chapter, or one of its headings.
+fn label(path: &str, id: Option<&str>) -> String {
+    let sanitise = |s: &str| -> String {
+        s.chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect()
+    };
+
+    match id {
+        Some(id) => format!("{}:{}", sanitise(path), sanitise(id)),
+        None => sanitise(path),
+    }
+}
+
+/// Escape the characters LaTeX treats specially.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Rust code blocks can have lines hidden with a `#`, which shouldn't make it
+/// into the book.
+fn strip_hidden_lines(code: &str) -> String {
+    code.lines()
+        .filter(|line| {
+            let trimmed = line.trim_start();
+            trimmed != "#" && !trimmed.starts_with("# ")
+        }).map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Turns a chapter's markdown events into LaTeX.
+struct LatexWriter<'a> {
+    path: &'a str,
+    depth: usize,
+    out: String,
+    /// The chapter's `# Title` is already covered by its sectioning command,
+    /// so only its label is kept.
+    title_label: Option<String>,
+    heading: Option<Heading>,
+    code_block: Option<(String, String)>,
+    skipping_image: bool,
+    cells: usize,
+    footnote: Option<(String, String)>,
+    footnotes: HashMap<String, String>,
+    ids: HashMap<String, usize>,
+    /// The explicit ID (if any) of each heading still to come.
+    header_ids: ::std::vec::IntoIter<Option<String>>,
+}
+
+struct Heading {
+    /// The heading's text, for its ID.
+    text: String,
+    /// What was written before the heading, while the title is being dropped.
+    saved: Option<String>,
+}
+
+impl<'a> LatexWriter<'a> {
+    fn new(path: &'a str, depth: usize) -> LatexWriter<'a> {
+        LatexWriter {
+            path: path,
+            depth: depth,
+            out: String::new(),
+            title_label: None,
+            heading: None,
+            code_block: None,
+            skipping_image: false,
+            cells: 0,
+            footnote: None,
+            footnotes: HashMap::new(),
+            ids: HashMap::new(),
+            header_ids: Vec::new().into_iter(),
+        }
+    }
+
+    fn push_event(&mut self, event: Event) {
+        if self.skipping_image {
+            if let Event::End(Tag::Image(..)) = event {
+                self.skipping_image = false;
+            }
+            return;
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                if let Some((_, ref mut code)) = self.code_block {
+                    code.push_str(&text);
+                    return;
+                }
+                if let Some(ref mut heading) = self.heading {
+                    heading.text.push_str(&text);
+                }
+                self.out.push_str(&escape(&text));
+            }
+            Event::FootnoteReference(name) => {
+                self.out.push_str(&footnote_placeholder(&name));
+            }
+            Event::SoftBreak => self.out.push('\n'),
+            Event::HardBreak => self.out.push_str("\\\\\n"),
+            // there's nothing sensible to do with raw HTML
+            Event::Html(_) | Event::InlineHtml(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {}
+            Tag::Rule => self.out.push_str("\\par\\noindent\\rule{\\linewidth}{0.4pt}\n\n"),
+            Tag::Header(level) => {
+                let level = level as usize;
+                let saved = if level == 1 && self.title_label.is_none() {
+                    Some(::std::mem::replace(&mut self.out, String::new()))
+                } else {
+                    let level = self.depth + level - 1;
+                    self.out.push_str(&format!("\\{}{{", section(level)));
+                    None
+                };
+                self.heading = Some(Heading {
+                    text: String::new(),
+                    saved: saved,
+                });
+            }
+            Tag::BlockQuote => self.out.push_str("\\begin{quote}\n"),
+            Tag::CodeBlock(info) => {
+                let lang = info
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .next()
+                    .unwrap_or("")
+                    .to_string();
+                self.code_block = Some((lang, String::new()));
+            }
+            Tag::List(Some(_)) => self.out.push_str("\\begin{enumerate}\n"),
+            Tag::List(None) => self.out.push_str("\\begin{itemize}\n"),
+            Tag::Item => self.out.push_str("\\item "),
+            Tag::FootnoteDefinition(name) => {
+                let saved = ::std::mem::replace(&mut self.out, String::new());
+                self.footnote = Some((name.to_string(), saved));
+            }
+            Tag::Table(alignments) => {
+                let columns: String = alignments
+                    .iter()
+                    .map(|a| match *a {
+                        Alignment::Center => "c|",
+                        Alignment::Right => "r|",
+                        _ => "l|",
+                    }).collect();
+                self.out.push_str(&format!(
+                    "\\begin{{center}}\n\\begin{{tabular}}{{|{}}}\n\\hline\n",
+                    columns
+                ));
+            }
+            Tag::TableHead | Tag::TableRow => self.cells = 0,
+            Tag::TableCell => {
+                if self.cells > 0 {
+                    self.out.push_str(" & ");
+                }
+                self.cells += 1;
+            }
+            Tag::Emphasis => self.out.push_str("\\emph{"),
+            Tag::Strong => self.out.push_str("\\textbf{"),
+            Tag::Code => self.out.push_str("\\texttt{"),
+            Tag::Link(dest, _) => {
+                let link = self.link(&dest);
+                self.out.push_str(&link);
+            }
+            Tag::Image(dest, _) => {
+                if let Some(path) = self.graphic(&dest) {
+                    self.out
+                        .push_str(&format!("\\includegraphics[width=\\maxwidth]{{{}}}", path));
+                    self.skipping_image = true;
+                }
+                // otherwise fall back to the alt text
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.out.push_str("\n\n"),
+            Tag::Rule | Tag::Image(..) => {}
+            Tag::Header(_) => {
+                let heading = self.heading.take().expect("Headings always start first");
+                let raw_id = match self.header_ids.next() {
+                    Some(Some(id)) => id,
+                    _ => utils::id_from_content(&heading.text),
+                };
+                let count = self.ids.entry(raw_id.clone()).or_insert(0);
+                let id = match *count {
+                    0 => raw_id,
+                    other => format!("{}-{}", raw_id, other),
+                };
+                *count += 1;
+
+                let heading_label = label(self.path, Some(&id));
+                match heading.saved {
+                    Some(saved) => {
+                        self.out = saved;
+                        self.title_label = Some(heading_label);
+                    }
+                    None => self.out.push_str(&format!("}}\\label{{{}}}\n\n", heading_label)),
+                }
+            }
+            Tag::BlockQuote => self.out.push_str("\\end{quote}\n\n"),
+            Tag::CodeBlock(_) => {
+                let (lang, code) = self.code_block.take().expect("Code blocks always start first");
+                let code = if lang == "rust" {
+                    strip_hidden_lines(&code)
+                } else {
+                    code
+                };
+                self.out.push_str("\\begin{lstlisting}\n");
+                self.out.push_str(&code);
+                if !code.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.out.push_str("\\end{lstlisting}\n\n");
+            }
+            Tag::List(Some(_)) => self.out.push_str("\\end{enumerate}\n\n"),
+            Tag::List(None) => self.out.push_str("\\end{itemize}\n\n"),
+            Tag::Item => self.out.push('\n'),
+            Tag::FootnoteDefinition(_) => {
+                let (name, saved) = self.footnote.take().expect("Footnotes always start first");
+                let definition = ::std::mem::replace(&mut self.out, saved);
+                self.footnotes.insert(name, definition.trim().to_string());
+            }
+            Tag::Table(_) => {
+                self.out.push_str("\\hline\n\\end{tabular}\n\\end{center}\n\n");
+            }
+            Tag::TableHead => self.out.push_str(" \\\\\n\\hline\n"),
+            Tag::TableRow => self.out.push_str(" \\\\\n"),
+            Tag::TableCell => {}
+            Tag::Emphasis | Tag::Strong | Tag::Code | Tag::Link(..) => self.out.push('}'),
+        }
+    }
+
+    /// The start of a link, pointing at the right chapter if it's in the book.
+    fn link(&self, dest: &str) -> String {
+        lazy_static! {
+            static ref SCHEME: Regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap();
+        }
+
+        if SCHEME.is_match(dest) || dest.starts_with('/') {
+            return format!("\\href{{{}}}{{", escape_url(dest));
+        }
+
+        let (target, fragment) = match dest.find('#') {
+            Some(i) => (&dest[..i], Some(&dest[i + 1..])),
+            None => (dest, None),
+        };
+        if !target.is_empty() && !target.ends_with(".md") {
+            // a link to some other file, which LaTeX can't do much with
+            return String::from("{");
+        }
+
+        let target = if target.is_empty() {
+            self.path.to_string()
+        } else {
+            utils::fs::resolve_relative_link(self.path, target)
+        };
+        format!("\\hyperref[{}]{{", label(&target, fragment))
+    }
+
+    /// Where to find an image, if it can be included in the document.
+    fn graphic(&self, dest: &str) -> Option<String> {
+        if dest.contains("://") || dest.starts_with('/') {
+            return None;
+        }
+
+        let extension = Path::new(dest).extension()?.to_str()?.to_lowercase();
+        if GRAPHICS.contains(&extension.as_str()) {
+            Some(utils::fs::resolve_relative_link(self.path, dest))
+        } else {
+            None
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut out = self.out;
+        for (name, definition) in &self.footnotes {
+            out = out.replace(
+                &footnote_placeholder(name),
+                &format!("\\footnote{{{}}}", definition),
+            );
+        }
+        out
+    }
+}
+
+fn footnote_placeholder(name: &str) -> String {
+    format!("\u{1}footnote:{}\u{1}", name)
+}
+
+/// URLs are mostly left alone by `\href`, apart from a few characters.
+fn escape_url(url: &str) -> String {
+    url.replace('\\', "\\\\")
+        .replace('#', "\\#")
+        .replace('%', "\\%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(content: &str) -> String {
+        let ch = Chapter::new("Intro", content.to_string(), "guide/intro.md", Vec::new());
+        render_chapter(&ch, 1)
+    }
+
+    #[test]
+    fn the_title_page_has_the_books_metadata() {
+        let mut book = BookConfig {
+            title: Some(String::from("Rust & You")),
+            authors: vec![String::from("Jane Doe"), String::from("John Doe")],
+            language: Some(String::from("de")),
+            ..Default::default()
+        };
+        assert_eq!(
+            title_page(&book),
+            "\\title{Rust \\& You}\n\
+             \\author{Jane Doe \\and John Doe}\n\
+             \\date{}\n\
+             \\hypersetup{pdftitle={Rust \\& You}, pdfauthor={Jane Doe, John Doe}, pdflang={de}}\n"
+        );
+
+        book.description = Some(String::from("A guide"));
+        let got = title_page(&book);
+        assert!(got.starts_with("\\title{Rust \\& You\\\\[1ex]\n\\large A guide}\n"));
+        assert!(got.ends_with(", pdfsubject={A guide}}\n"));
+    }
+
+    #[test]
+    fn the_title_becomes_the_sectioning_command() {
+        let got = render("# Introduction\n\nSome *text* with 100% `code_here`.\n\n## Details\n");
+
+        assert_eq!(
+            got,
+            "\\section{Intro}\\label{guide-intro-md}\\label{guide-intro-md:introduction}\n\n\
+             Some \\emph{text} with 100\\% \\texttt{code\\_here}.\n\n\
+             \\subsection{Details}\\label{guide-intro-md:details}\n\n"
+        );
+    }
+
+    #[test]
+    fn headings_can_have_explicit_ids() {
+        let got = render("# Introduction\n\n## Setup {#install}\n");
+
+        assert!(got.ends_with("\\subsection{Setup}\\label{guide-intro-md:install}\n\n"));
+    }
+
+    #[test]
+    fn chapters_without_a_title() {
+        let got = render("Just text.\n");
+
+        assert_eq!(got, "\\section{Intro}\\label{guide-intro-md}\n\nJust text.\n\n");
+    }
+
+    #[test]
+    fn links_become_cross_references() {
+        let got = render(
+            "[a](../other.md#some-heading) [b](#local) [c](https://example.com/a#b) [d](a.txt)",
+        );
+
+        assert!(got.contains("\\hyperref[other-md:some-heading]{a}"));
+        assert!(got.contains("\\hyperref[guide-intro-md:local]{b}"));
+        assert!(got.contains("\\href{https://example.com/a\\#b}{c}"));
+        assert!(got.contains(" {d}"));
+    }
+
+    #[test]
+    fn code_blocks_and_images() {
+        let got = render(
+            "```rust,ignore\n# fn main() {\nlet x = 1;\n# }\n```\n\n\
+             ![diagram](img/diagram.png) ![logo](logo.svg)\n",
+        );
+
+        assert!(got.contains("\\begin{lstlisting}\nlet x = 1;\n\\end{lstlisting}"));
+        assert!(got.contains("\\includegraphics[width=\\maxwidth]{guide/img/diagram.png} logo"));
+    }
+
+    #[test]
+    fn footnotes_are_put_where_they_are_referenced() {
+        let got = render("Text[^note].\n\n[^note]: The *note*.\n");
+
+        assert_eq!(
+            got,
+            "\\section{Intro}\\label{guide-intro-md}\n\n\
+             Text\\footnote{The \\emph{note}.}.\n\n"
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let got = render("| a | b |\n|---|--:|\n| 1 | 2 |\n");
+
+        assert!(got.contains(
+            "\\begin{tabular}{|l|r|}\n\\hline\n a  &  b  \\\\\n\\hline\n 1  &  2  \\\\\n\\hline\n"
+        ));
+    }
+}
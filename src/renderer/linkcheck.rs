@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use pulldown_cmark::{OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use regex::Regex;
+use serde_json;
+use shlex::Shlex;
+
+use book::{Book, BookItem};
+use errors::*;
+use renderer::{RenderContext, Renderer};
+use utils;
+
+const DEFAULT_COMMAND: &str =
+    "curl --silent --show-error --location --max-time 30 --output /dev/null \
+     --write-out %{http_code}";
+
+/// A renderer which checks that the book's links to other sites still work,
+/// instead of writing anything out.
+///
+/// Each link is fetched by running `command` with the link's URL as its last
+/// argument, which should print the response's status code. Links to
+/// different hosts are checked in parallel, while links to the same host are
+/// checked one at a time with a delay in between, so no site gets flooded with
+/// requests. Links which worked are remembered in a cache file for a while,
+/// so they aren't fetched again on every build.
+#[derive(Default)]
+pub struct LinkCheckRenderer;
+
+impl LinkCheckRenderer {
+    /// Create a new `LinkCheckRenderer` instance.
+    pub fn new() -> Self {
+        LinkCheckRenderer
+    }
+}
+
+/// The `[output.linkcheck]` table.
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct LinkCheckConfig {
+    /// The command used to fetch a link, which should print its status code.
+    command: String,
+    /// How many hosts can be checked at once.
+    max_concurrency: usize,
+    /// The number of milliseconds to wait between requests to the same host.
+    domain_delay: u64,
+    /// Where working links are cached, relative to the book's root.
+    cache_file: PathBuf,
+    /// How many seconds a working link stays in the cache.
+    cache_timeout: u64,
+    /// Patterns for links which shouldn't be checked, like ones to hosts
+    /// known to be flaky.
+    exclude: Vec<String>,
+    /// Only warn about broken links instead of failing the build.
+    warn_only: bool,
+}
+
+impl Default for LinkCheckConfig {
+    fn default() -> LinkCheckConfig {
+        LinkCheckConfig {
+            command: String::from(DEFAULT_COMMAND),
+            max_concurrency: 8,
+            domain_delay: 500,
+            cache_file: PathBuf::from(".linkcheck-cache.json"),
+            cache_timeout: 24 * 60 * 60,
+            exclude: Vec::new(),
+            warn_only: false,
+        }
+    }
+}
+
+impl Renderer for LinkCheckRenderer {
+    fn name(&self) -> &str {
+        "linkcheck"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let config: LinkCheckConfig = ctx
+            .config
+            .get_deserialized_opt("output.linkcheck")?
+            .unwrap_or_default();
+        let exclude = config
+            .exclude
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).chain_err(|| format!("Invalid exclude pattern \"{}\"", pattern))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut links = external_links(&ctx.book);
+        links.retain(|url, _| !exclude.iter().any(|re| re.is_match(url)));
+
+        let cache_file = ctx.root.join(&config.cache_file);
+        let mut cache = load_cache(&cache_file);
+        let now = unix_time();
+        cache.retain(|_, checked| now.saturating_sub(*checked) < config.cache_timeout);
+        let unchecked: Vec<String> = links
+            .keys()
+            .filter(|url| !cache.contains_key(*url))
+            .cloned()
+            .collect();
+        info!(
+            "Checking {} external links ({} cached)",
+            unchecked.len(),
+            links.len() - unchecked.len()
+        );
+
+        let mut broken = Vec::new();
+        for (url, result) in check_all(unchecked, &config) {
+            match result {
+                Ok(()) => {
+                    cache.insert(url, now);
+                }
+                Err(problem) => broken.push(format!(
+                    "{} ({}), linked from {}",
+                    url,
+                    problem,
+                    links[&url].join(", ")
+                )),
+            }
+        }
+        broken.sort();
+
+        let contents = serde_json::to_string_pretty(&cache)?;
+        utils::fs::write_file(&ctx.root, &config.cache_file, contents.as_bytes())
+            .chain_err(|| "Unable to save the link cache")?;
+
+        if config.warn_only {
+            for link in &broken {
+                warn!("Broken link: {}", link);
+            }
+        } else if !broken.is_empty() {
+            bail!(
+                "Found {} broken external link(s):\n    {}",
+                broken.len(),
+                broken.join("\n    ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Every `http` and `https` link and image in the book, along with the
+/// chapters they're in.
+fn external_links(book: &Book) -> BTreeMap<String, Vec<String>> {
+    let mut opts = Options::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    opts.insert(OPTION_ENABLE_FOOTNOTES);
+
+    let mut links: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for item in book.iter() {
+        if let BookItem::Chapter(ref ch) = *item {
+            for event in Parser::new_ext(&ch.content, opts) {
+                let dest = match event {
+                    Event::Start(Tag::Link(dest, _)) | Event::Start(Tag::Image(dest, _)) => dest,
+                    _ => continue,
+                };
+                if !dest.starts_with("http://") && !dest.starts_with("https://") {
+                    continue;
+                }
+
+                let chapters = links.entry(dest.into_owned()).or_default();
+                if !chapters.contains(&ch.name) {
+                    chapters.push(ch.name.clone());
+                }
+            }
+        }
+    }
+
+    links
+}
+
+/// Check the links, a host at a time on each of up to `max_concurrency`
+/// threads.
+fn check_all(
+    urls: Vec<String>,
+    config: &LinkCheckConfig,
+) -> Vec<(String, ::std::result::Result<(), String>)> {
+    let mut by_host: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for url in urls {
+        by_host.entry(host(&url)).or_default().push(url);
+    }
+
+    let threads = config.max_concurrency.max(1).min(by_host.len());
+    let queue = Arc::new(Mutex::new(by_host.into_iter()));
+    let (tx, rx) = mpsc::channel();
+
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let command = config.command.clone();
+            let delay = Duration::from_millis(config.domain_delay);
+
+            thread::spawn(move || loop {
+                let urls = match queue.lock().unwrap().next() {
+                    Some((_, urls)) => urls,
+                    None => break,
+                };
+                for (i, url) in urls.into_iter().enumerate() {
+                    if i > 0 {
+                        thread::sleep(delay);
+                    }
+                    let result = check(&command, &url);
+                    tx.send((url, result)).unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let results = rx.iter().collect();
+    for handle in handles {
+        let _ = handle.join();
+    }
+    results
+}
+
+/// Fetch a link, returning what's wrong with it if it's broken.
+fn check(command: &str, url: &str) -> ::std::result::Result<(), String> {
+    debug!("Checking {}", url);
+    let mut words = Shlex::new(command);
+    let executable = match words.next() {
+        Some(e) => e,
+        None => return Err(String::from("the link check command is empty")),
+    };
+
+    let output = Command::new(&executable)
+        .args(words)
+        .arg(url)
+        .output()
+        .map_err(|e| format!("unable to run \"{}\": {}", executable, e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let status = stdout
+        .split_whitespace()
+        .next()
+        .and_then(|s| s.parse::<u16>().ok());
+
+    match status {
+        Some(status) if status / 100 == 2 => Ok(()),
+        Some(0) | None => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(stderr
+                .trim()
+                .lines()
+                .last()
+                .unwrap_or("no response")
+                .to_string())
+        }
+        Some(status) => Err(format!("status {}", status)),
+    }
+}
+
+fn host(url: &str) -> String {
+    lazy_static! {
+        static ref HOST: Regex = Regex::new(r"^[a-zA-Z]+://([^/?#]+)").unwrap();
+    }
+
+    HOST.captures(url)
+        .map(|caps| caps[1].to_lowercase())
+        .unwrap_or_default()
+}
+
+/// When each working link was last checked, in seconds since the Unix epoch.
+fn load_cache(path: &Path) -> HashMap<String, u64> {
+    File::open(path)
+        .ok()
+        .and_then(|f| serde_json::from_reader(f).ok())
+        .unwrap_or_default()
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::Chapter;
+    #[cfg(not(windows))]
+    use config::Config;
+    #[cfg(not(windows))]
+    use tempfile::TempDir;
+
+    fn book() -> Book {
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "One",
+            String::from(
+                "[a](https://good.example.com/a) [b](https://bad.example.com/) \
+                 [c](other.md) ![d](http://flaky.example.com/d.png)",
+            ),
+            "one.md",
+            Vec::new(),
+        ));
+        book.push_item(Chapter::new(
+            "Two",
+            String::from("[a](https://good.example.com/a) [b](https://good.example.com/b)"),
+            "two.md",
+            Vec::new(),
+        ));
+        book
+    }
+
+    // A shell script stands in for curl, so tests using it are only run where
+    // there's a shell. URLs with "bad" in them are broken and the rest work.
+    #[cfg(not(windows))]
+    fn context(temp: &TempDir, table: &str) -> RenderContext {
+        let command = r#"sh -c 'case "$0" in *bad*) echo 404;; *) echo 200;; esac'"#;
+        let mut config = Config::from_str(table).unwrap();
+        config.set("output.linkcheck.command", command).unwrap();
+        config.set("output.linkcheck.domain-delay", 0).unwrap();
+        RenderContext::new(temp.path(), book(), config, temp.path().join("book"))
+    }
+
+    #[test]
+    fn external_links_are_found_with_their_chapters() {
+        let links = external_links(&book());
+
+        let got: Vec<_> = links
+            .iter()
+            .map(|(url, chapters)| (url.as_str(), chapters.join(",")))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("http://flaky.example.com/d.png", String::from("One")),
+                ("https://bad.example.com/", String::from("One")),
+                ("https://good.example.com/a", String::from("One,Two")),
+                ("https://good.example.com/b", String::from("Two")),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn the_status_code_decides_whether_a_link_works() {
+        assert!(check("echo 200", "https://example.com/").is_ok());
+        assert_eq!(
+            check("echo 404", "https://example.com/"),
+            Err(String::from("status 404"))
+        );
+        assert!(check("false", "https://example.com/").is_err());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn broken_links_fail_the_build_unless_told_otherwise() {
+        let temp = TempDir::new().unwrap();
+        let err = LinkCheckRenderer::new()
+            .render(&context(&temp, ""))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("https://bad.example.com/ (status 404), linked from One"));
+
+        let ctx = context(&temp, "[output.linkcheck]\nwarn-only = true");
+        assert!(LinkCheckRenderer::new().render(&ctx).is_ok());
+
+        let ctx = context(&temp, "[output.linkcheck]\nexclude = ['bad\\.example']");
+        assert!(LinkCheckRenderer::new().render(&ctx).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn working_links_are_cached() {
+        let temp = TempDir::new().unwrap();
+        let ctx = context(&temp, "[output.linkcheck]\nwarn-only = true");
+        LinkCheckRenderer::new().render(&ctx).unwrap();
+
+        let cache = load_cache(&temp.path().join(".linkcheck-cache.json"));
+        let mut cached: Vec<_> = cache.keys().map(String::as_str).collect();
+        cached.sort();
+        assert_eq!(
+            cached,
+            vec![
+                "http://flaky.example.com/d.png",
+                "https://good.example.com/a",
+                "https://good.example.com/b"
+            ]
+        );
+    }
+}
@@ -0,0 +1,526 @@
+use std::fs;
+
+use pulldown_cmark::{Alignment, Event, Options, Parser, Tag};
+use pulldown_cmark::{OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use toml::Value;
+
+use book::{Book, BookItem, Chapter};
+use errors::*;
+use renderer::{RenderContext, Renderer};
+use utils;
+
+const DEFAULT_SECTION: &str = "1";
+
+/// A renderer which writes the book as `groff` man pages, for books
+/// documenting command line tools.
+///
+/// By default the whole book goes into a single page, with each top-level
+/// chapter as a section. With `split-chapters` each top-level chapter gets a
+/// page of its own instead.
+#[derive(Default)]
+pub struct ManRenderer;
+
+impl ManRenderer {
+    /// Create a new `ManRenderer` instance.
+    pub fn new() -> Self {
+        ManRenderer
+    }
+}
+
+/// The `[output.man]` table, apart from `section` which can be a number or a
+/// string.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct ManConfig {
+    /// The title of the combined page, instead of the book's title.
+    title: Option<String>,
+    /// Whether each top-level chapter should get its own page.
+    split_chapters: bool,
+}
+
+struct Page {
+    name: String,
+    body: String,
+}
+
+impl Renderer for ManRenderer {
+    fn name(&self) -> &str {
+        "man"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let man_config: ManConfig = ctx
+            .config
+            .get_deserialized_opt("output.man")?
+            .unwrap_or_default();
+        let section = match ctx.config.get("output.man.section") {
+            Some(&Value::String(ref section)) => section.clone(),
+            Some(&Value::Integer(section)) => section.to_string(),
+            Some(other) => bail!("The man page section should be a string, not {}", other),
+            None => String::from(DEFAULT_SECTION),
+        };
+        let book_title = ctx.config.book.title.as_ref().map_or("", String::as_str);
+        let title = man_config
+            .title
+            .as_ref()
+            .map_or(book_title, String::as_str);
+
+        let destination = &ctx.destination;
+        if destination.exists() {
+            utils::fs::remove_dir_content(destination)
+                .chain_err(|| "Unable to remove stale man pages")?;
+        }
+        fs::create_dir_all(destination)
+            .chain_err(|| "Unexpected error when constructing destination path")?;
+
+        let pages = if man_config.split_chapters {
+            split_pages(&ctx.book)
+        } else {
+            let mut header = String::new();
+            if let Some(ref description) = ctx.config.book.description {
+                header.push_str(&format!(
+                    ".SH NAME\n{} \\- {}\n",
+                    escape(title),
+                    escape(description)
+                ));
+            }
+
+            vec![Page {
+                name: page_name(title),
+                body: header + &render_items(&ctx.book),
+            }]
+        };
+
+        for page in pages {
+            let filename = format!("{}.{}", page.name, section);
+            let contents = format!(
+                "'\\\" t\n.TH \"{}\" \"{}\" \"\" \"\" \"{}\"\n{}",
+                escape(&page.name.to_uppercase()),
+                escape(&section),
+                escape(book_title),
+                page.body
+            );
+            debug!("Writing {}", filename);
+            utils::fs::write_file(destination, &filename, contents.as_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Give each top-level chapter (and the chapters nested under it) a page.
+fn split_pages(book: &Book) -> Vec<Page> {
+    let mut pages: Vec<Page> = Vec::new();
+
+    for item in book.iter_with_paths() {
+        if let BookItem::Chapter(ref ch) = *item.item {
+            if item.depth() == 0 {
+                pages.push(Page {
+                    name: page_name(&ch.name),
+                    body: String::new(),
+                });
+            }
+            if let Some(page) = pages.last_mut() {
+                page.body.push_str(&render_chapter(ch, item.depth(), true));
+            }
+        }
+    }
+
+    pages
+}
+
+/// Put the whole book on one page, with each top-level chapter as a section.
+fn render_items(book: &Book) -> String {
+    let mut body = String::new();
+
+    for item in book.iter_with_paths() {
+        match *item.item {
+            BookItem::Chapter(ref ch) => {
+                body.push_str(&render_chapter(ch, item.depth(), false))
+            }
+            BookItem::PartTitle(ref title) => body.push_str(&heading(0, &escape(title))),
+            BookItem::Separator => {}
+        }
+    }
+
+    body
+}
+
+fn page_name(title: &str) -> String {
+    let name = utils::normalize_id(title);
+    if name.is_empty() {
+        String::from("book")
+    } else {
+        name
+    }
+}
+
+/// The macro for a heading. Man pages only have two levels of headings, so
+/// anything deeper is a bold paragraph.
+fn heading(level: usize, text: &str) -> String {
+    match level {
+        0 => format!(".SH \"{}\"\n", text),
+        1 => format!(".SS \"{}\"\n", text),
+        _ => format!(".PP\n\\fB{}\\fP\n", text),
+    }
+}
+
+fn render_chapter(ch: &Chapter, depth: usize, split: bool) -> String {
+    // on a page of its own, a top-level chapter's title is on the .TH line
+    let offset = if split { 1 } else { 0 };
+
+    let mut out = String::new();
+    if depth >= offset {
+        out.push_str(&heading(depth - offset, &escape(&ch.name)));
+    }
+
+    if ch.path.is_some() {
+        let mut writer = ManWriter::new(depth, offset);
+        let mut opts = Options::empty();
+        opts.insert(OPTION_ENABLE_TABLES);
+        opts.insert(OPTION_ENABLE_FOOTNOTES);
+        let (events, _) = utils::extract_header_ids(Parser::new_ext(&ch.content, opts));
+        for event in events {
+            writer.push_event(event);
+        }
+        out.push_str(&writer.out);
+    }
+
+    out
+}
+
+/// Escape text so `groff` shows it as it is.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\e"),
+            '-' => escaped.push_str("\\-"),
+            '"' => escaped.push_str("\\(dq"),
+            '\n' | '\t' => escaped.push(' '),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Turns a chapter's markdown events into `roff`.
+struct ManWriter {
+    depth: usize,
+    offset: usize,
+    out: String,
+    /// The chapter's title has already been written from `SUMMARY.md`.
+    title_seen: bool,
+    /// What was written before the current heading.
+    heading: Option<String>,
+    dropping_title: bool,
+    code_block: bool,
+    /// Each list's next item number, for ordered lists.
+    lists: Vec<Option<usize>>,
+    /// Whether the next paragraph continues an item's first line.
+    item_start: bool,
+    links: Vec<String>,
+    table: Option<Table>,
+}
+
+#[derive(Default)]
+struct Table {
+    alignments: Vec<Alignment>,
+    head: Vec<String>,
+    rows: Vec<Vec<String>>,
+    in_head: bool,
+    /// What was written before the current cell.
+    saved: Option<String>,
+}
+
+impl ManWriter {
+    fn new(depth: usize, offset: usize) -> ManWriter {
+        ManWriter {
+            depth: depth,
+            offset: offset,
+            out: String::new(),
+            title_seen: false,
+            heading: None,
+            dropping_title: false,
+            code_block: false,
+            lists: Vec::new(),
+            item_start: false,
+            links: Vec::new(),
+            table: None,
+        }
+    }
+
+    fn push_event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.push_text(&text),
+            Event::FootnoteReference(name) => self.push_text(&format!("[{}]", name)),
+            Event::SoftBreak => self.newline(),
+            Event::HardBreak => {
+                self.newline();
+                self.out.push_str(".br\n");
+            }
+            // there's nothing sensible to do with raw HTML
+            Event::Html(_) | Event::InlineHtml(_) => {}
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if self.code_block {
+            for line in text.split_terminator('\n') {
+                self.start_line(line);
+                self.out.push_str(&escape(line));
+                self.out.push('\n');
+            }
+        } else {
+            self.start_line(text);
+            self.out.push_str(&escape(text));
+        }
+    }
+
+    /// Lines starting with a `.` or `'` would be taken as macros.
+    fn start_line(&mut self, text: &str) {
+        let at_line_start = self.out.is_empty() || self.out.ends_with('\n');
+        if at_line_start && (text.starts_with('.') || text.starts_with('\'')) {
+            self.out.push_str("\\&");
+        }
+    }
+
+    fn newline(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    fn paragraph(&mut self) {
+        self.newline();
+        if self.item_start {
+            self.item_start = false;
+        } else if self.lists.is_empty() {
+            self.out.push_str(".PP\n");
+        } else {
+            self.out.push_str(".IP \"\" 4\n");
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.paragraph(),
+            Tag::Rule => {
+                self.newline();
+                self.out.push_str(".PP\n\\l'\\n(.lu'\n");
+            }
+            Tag::Header(level) => {
+                let level = level as usize;
+                self.dropping_title = level == 1 && !self.title_seen;
+                self.title_seen = true;
+                self.newline();
+                let saved = ::std::mem::replace(&mut self.out, String::new());
+                self.heading = Some(saved);
+            }
+            Tag::BlockQuote => {
+                self.newline();
+                self.out.push_str(".RS 4\n");
+            }
+            Tag::CodeBlock(_) => {
+                self.paragraph();
+                self.out.push_str(".RS 4\n.nf\n");
+                self.code_block = true;
+            }
+            Tag::List(start) => {
+                self.newline();
+                if !self.lists.is_empty() {
+                    self.out.push_str(".RS 4\n");
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.newline();
+                let bullet = match self.lists.last_mut() {
+                    Some(&mut Some(ref mut number)) => {
+                        *number += 1;
+                        format!("\"{}.\"", *number - 1)
+                    }
+                    _ => String::from("\\(bu"),
+                };
+                self.out.push_str(&format!(".IP {} 4\n", bullet));
+                self.item_start = true;
+            }
+            Tag::FootnoteDefinition(name) => {
+                self.newline();
+                self.out.push_str(&format!(".IP \"[{}]\" 4\n", escape(&name)));
+                self.item_start = true;
+            }
+            Tag::Table(alignments) => {
+                self.table = Some(Table {
+                    alignments: alignments,
+                    ..Default::default()
+                });
+            }
+            Tag::TableHead => {
+                if let Some(ref mut table) = self.table {
+                    table.in_head = true;
+                }
+            }
+            Tag::TableRow => {
+                if let Some(ref mut table) = self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => {
+                let saved = ::std::mem::replace(&mut self.out, String::new());
+                if let Some(ref mut table) = self.table {
+                    table.saved = Some(saved);
+                }
+            }
+            Tag::Emphasis => self.out.push_str("\\fI"),
+            Tag::Strong | Tag::Code => self.out.push_str("\\fB"),
+            Tag::Link(dest, _) => self.links.push(dest.to_string()),
+            Tag::Image(..) => {}
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.newline(),
+            Tag::Rule | Tag::Image(..) | Tag::TableRow => {}
+            Tag::TableHead => {
+                if let Some(ref mut table) = self.table {
+                    table.in_head = false;
+                }
+            }
+            Tag::Header(level) => {
+                let text = ::std::mem::replace(&mut self.out, String::new());
+                self.out = self.heading.take().expect("Headings always start first");
+
+                if !self.dropping_title {
+                    let level = (self.depth + level as usize).saturating_sub(self.offset + 1);
+                    self.out.push_str(&heading(level, text.trim()));
+                }
+                self.dropping_title = false;
+            }
+            Tag::BlockQuote => {
+                self.newline();
+                self.out.push_str(".RE\n");
+            }
+            Tag::CodeBlock(_) => {
+                self.code_block = false;
+                self.newline();
+                self.out.push_str(".fi\n.RE\n");
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+                self.newline();
+                if !self.lists.is_empty() {
+                    self.out.push_str(".RE\n");
+                }
+            }
+            Tag::Item | Tag::FootnoteDefinition(_) => {
+                self.item_start = false;
+                self.newline();
+            }
+            Tag::Table(_) => {
+                let table = self.table.take().expect("Tables always start first");
+                self.newline();
+                self.out.push_str(&render_table(&table));
+            }
+            Tag::TableCell => {
+                let cell = ::std::mem::replace(&mut self.out, String::new());
+                if let Some(ref mut table) = self.table {
+                    self.out = table.saved.take().unwrap_or_default();
+                    let cell = cell.trim().to_string();
+                    if table.in_head {
+                        table.head.push(cell);
+                    } else if let Some(row) = table.rows.last_mut() {
+                        row.push(cell);
+                    }
+                }
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Code => self.out.push_str("\\fP"),
+            Tag::Link(..) => {
+                let dest = self.links.pop().expect("Links always start first");
+                // links between chapters don't mean anything in a man page
+                if dest.contains("://") || dest.starts_with("mailto:") {
+                    self.out.push_str(&format!(" <{}>", escape(&dest)));
+                }
+            }
+        }
+    }
+}
+
+/// Tables are drawn by the `tbl` preprocessor, which the `'\" t` line at the
+/// top of each page asks for.
+fn render_table(table: &Table) -> String {
+    let format: Vec<&str> = table
+        .alignments
+        .iter()
+        .map(|alignment| match *alignment {
+            Alignment::Center => "c",
+            Alignment::Right => "r",
+            _ => "l",
+        }).collect();
+    let head_format: Vec<String> = format.iter().map(|f| format!("{}b", f)).collect();
+
+    let mut out = String::from(".TS\nallbox tab(\t);\n");
+    out.push_str(&format!("{}\n{}.\n", head_format.join(" "), format.join(" ")));
+    out.push_str(&table.head.join("\t"));
+    out.push('\n');
+    for row in &table.rows {
+        out.push_str(&row.join("\t"));
+        out.push('\n');
+    }
+    out.push_str(".TE\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(content: &str, split: bool) -> String {
+        let ch = Chapter::new("Usage", content.to_string(), "usage.md", Vec::new());
+        render_chapter(&ch, 0, split)
+    }
+
+    #[test]
+    fn render_a_chapter() {
+        let src = "# Usage\n\nRun `mdbook build --open`.\n\n## Options\n\n\
+                   - *first*\n- second\n\n```\n.hidden\nmdbook -v\n```\n";
+
+        let got = render(src, false);
+
+        assert_eq!(
+            got,
+            ".SH \"Usage\"\n\
+             .PP\n\
+             Run \\fBmdbook build \\-\\-open\\fP.\n\
+             .SS \"Options\"\n\
+             .IP \\(bu 4\n\\fIfirst\\fP\n\
+             .IP \\(bu 4\nsecond\n\
+             .PP\n.RS 4\n.nf\n\\&.hidden\nmdbook \\-v\n.fi\n.RE\n"
+        );
+    }
+
+    #[test]
+    fn a_chapter_on_its_own_page_has_no_heading_of_its_own() {
+        let got = render("# Usage\n\n## Options\n\n### Details\n", true);
+
+        assert_eq!(got, ".SH \"Options\"\n.SS \"Details\"\n");
+    }
+
+    #[test]
+    fn links_and_tables() {
+        let src = "See [the docs](https://example.com) or [build](build.md).\n\n\
+                   | Flag | Meaning |\n|------|:-------:|\n| `-o` | open |\n";
+
+        let got = render(src, false);
+
+        assert!(got.contains("See the docs <https://example.com> or build.\n"));
+        assert!(got.contains(
+            ".TS\nallbox tab(\t);\nlb cb\nl c.\nFlag\tMeaning\n\\fB\\-o\\fP\topen\n.TE\n"
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+
+use book::{Book, BookItem};
+use errors::*;
+use renderer::{RenderContext, Renderer};
+use utils;
+
+/// A renderer which writes the book back out as markdown, after it has been
+/// preprocessed.
+///
+/// Each chapter is written to the same path it had in the `src` directory,
+/// alongside a `SUMMARY.md` describing the book's layout. This makes it easy
+/// to see what preprocessors did to the book, or to hand the processed
+/// markdown over to another tool.
+#[derive(Default)]
+pub struct MarkdownRenderer;
+
+impl MarkdownRenderer {
+    /// Create a new `MarkdownRenderer` instance.
+    pub fn new() -> Self {
+        MarkdownRenderer
+    }
+}
+
+impl Renderer for MarkdownRenderer {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let destination = &ctx.destination;
+
+        if destination.exists() {
+            utils::fs::remove_dir_content(destination)
+                .chain_err(|| "Unable to remove stale Markdown output")?;
+        }
+        fs::create_dir_all(destination)
+            .chain_err(|| "Unexpected error when constructing destination path")?;
+
+        for item in ctx.book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                if let Some(ref path) = ch.path {
+                    debug!("Writing {}", path.display());
+                    utils::fs::write_file(destination, path, ch.content.as_bytes())?;
+                }
+            }
+        }
+
+        let summary = render_summary(&ctx.book);
+        utils::fs::write_file(destination, "SUMMARY.md", summary.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Create a `SUMMARY.md` with the same layout as the book.
+fn render_summary(book: &Book) -> String {
+    let mut lines = vec![String::from("# Summary"), String::new()];
+
+    for item in book.iter_with_paths() {
+        match *item.item {
+            BookItem::Chapter(ref ch) => {
+                let path = ch
+                    .path
+                    .as_ref()
+                    .map(|p| utils::fs::normalize_path(&p.display().to_string()))
+                    .unwrap_or_default();
+                let link = format!("[{}]({})", ch.name, path);
+
+                if ch.number.is_some() {
+                    let indent = "    ".repeat(item.depth());
+                    lines.push(format!("{}- {}", indent, link));
+                } else {
+                    // affix chapters are paragraphs of their own
+                    push_blank_line(&mut lines);
+                    lines.push(link);
+                    lines.push(String::new());
+                }
+            }
+            BookItem::Separator => {
+                push_blank_line(&mut lines);
+                lines.push(String::from("---"));
+                lines.push(String::new());
+            }
+            BookItem::PartTitle(ref title) => {
+                push_blank_line(&mut lines);
+                lines.push(format!("# {}", title));
+                lines.push(String::new());
+            }
+        }
+    }
+
+    push_blank_line(&mut lines);
+    lines.join("\n")
+}
+
+fn push_blank_line(lines: &mut Vec<String>) {
+    if lines.last().map_or(false, |line| !line.is_empty()) {
+        lines.push(String::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use book::{parse_summary, Chapter, SectionNumber};
+
+    #[test]
+    fn the_summary_matches_the_book() {
+        let mut nested = Chapter::new("Nested", String::new(), "first/nested.md", Vec::new());
+        nested.number = Some(SectionNumber(vec![1, 1]));
+        let mut first = Chapter::new("First", String::new(), "first/index.md", Vec::new());
+        first.number = Some(SectionNumber(vec![1]));
+        first.sub_items.push(BookItem::Chapter(nested));
+        let mut draft = Chapter::new_draft("Draft", Vec::new());
+        draft.number = Some(SectionNumber(vec![2]));
+
+        let mut book = Book::new();
+        book.push_item(Chapter::new("Intro", String::new(), "intro.md", Vec::new()))
+            .push_item(BookItem::PartTitle(String::from("Part I")))
+            .push_item(first)
+            .push_item(BookItem::Separator)
+            .push_item(draft)
+            .push_item(Chapter::new("Outro", String::new(), "outro.md", Vec::new()));
+
+        let got = render_summary(&book);
+
+        assert_eq!(
+            got,
+            "# Summary\n\n[Intro](intro.md)\n\n# Part I\n\n- [First](first/index.md)\n    \
+             - [Nested](first/nested.md)\n\n---\n\n- [Draft]()\n\n[Outro](outro.md)\n"
+        );
+
+        // and it can be read back in
+        let summary = parse_summary(&got).unwrap();
+        assert_eq!(summary.prefix_chapters.len(), 1);
+        assert_eq!(summary.numbered_chapters.len(), 4);
+        assert_eq!(summary.suffix_chapters.len(), 1);
+    }
+}
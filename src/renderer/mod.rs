@@ -11,9 +11,21 @@
 //! [For Developers]: https://rust-lang-nursery.github.io/mdBook/lib/index.html
 //! [RenderContext]: struct.RenderContext.html
 
+pub use self::epub::EpubRenderer;
 pub use self::html_handlebars::HtmlHandlebars;
+pub use self::json::JsonRenderer;
+pub use self::latex::LatexRenderer;
+pub use self::linkcheck::LinkCheckRenderer;
+pub use self::man::ManRenderer;
+pub use self::markdown_renderer::MarkdownRenderer;
 
+mod epub;
 mod html_handlebars;
+mod json;
+mod latex;
+mod linkcheck;
+mod man;
+mod markdown_renderer;
 
 use serde_json;
 use shlex::Shlex;
@@ -33,10 +45,23 @@ use errors::*;
 /// 99% of users will ever use:
 ///
 /// - [HtmlHandlebars] - the built-in HTML renderer
+/// - [MarkdownRenderer] - writes the preprocessed markdown back out, mainly
+///   for debugging
+/// - [EpubRenderer] - packages the book up as an e-book
+/// - [JsonRenderer] - exports the book as JSON, for other tools to use
+/// - [LatexRenderer] - writes a LaTeX project which can be typeset as a PDF
+/// - [ManRenderer] - writes man pages, for books about command line tools
+/// - [LinkCheckRenderer] - checks that links to other sites still work
 /// - [CmdRenderer] - a generic renderer which shells out to a program to do the
 ///   actual rendering
 ///
 /// [HtmlHandlebars]: struct.HtmlHandlebars.html
+/// [MarkdownRenderer]: struct.MarkdownRenderer.html
+/// [EpubRenderer]: struct.EpubRenderer.html
+/// [JsonRenderer]: struct.JsonRenderer.html
+/// [LatexRenderer]: struct.LatexRenderer.html
+/// [ManRenderer]: struct.ManRenderer.html
+/// [LinkCheckRenderer]: struct.LinkCheckRenderer.html
 /// [CmdRenderer]: struct.CmdRenderer.html
 pub trait Renderer {
     /// The `Renderer`'s name.
@@ -45,6 +70,20 @@ pub trait Renderer {
     /// Invoke the `Renderer`, passing in all the necessary information for
     /// describing a book.
     fn render(&self, ctx: &RenderContext) -> Result<()>;
+
+    /// Update the output of an earlier build after some chapters have changed,
+    /// where `chapters` are their `path`s. `watch` and `serve` use this to
+    /// avoid rebuilding the whole book for every edit.
+    ///
+    /// Unlike `render`, this writes into the last build in place rather than
+    /// into a new directory, so until it finishes the output mixes pages from
+    /// the two builds, and a failure leaves it that way. A renderer which
+    /// can't cope with that should render the whole book.
+    ///
+    /// By default the whole book is rendered again.
+    fn render_chapters(&self, ctx: &RenderContext, _chapters: &[PathBuf]) -> Result<()> {
+        self.render(ctx)
+    }
 }
 
 /// The context provided to all renderers.
@@ -60,10 +99,16 @@ pub struct RenderContext {
     pub book: Book,
     /// The loaded configuration file.
     pub config: Config,
-    /// Where the renderer *must* put any build artefacts generated. To allow
-    /// renderers to cache intermediate results, this directory is not
-    /// guaranteed to be empty or even exist.
+    /// Where the renderer *must* put any build artefacts generated. A full
+    /// build renders into a new, empty directory, which replaces the last
+    /// build once it's finished, while rebuilding some chapters (e.g. when
+    /// `mdbook serve` sees a change) updates the last build in place. So
+    /// this directory isn't guaranteed to be empty, or even to exist.
     pub destination: PathBuf,
+    /// Where the last build is, when `destination` is a new directory which
+    /// replaces it afterwards, so its files can be reused.
+    #[serde(skip)]
+    pub(crate) previous_destination: Option<PathBuf>,
     #[serde(skip)]
     __non_exhaustive: (),
 }
@@ -81,6 +126,7 @@ impl RenderContext {
             version: ::MDBOOK_VERSION.to_string(),
             root: root.into(),
             destination: destination.into(),
+            previous_destination: None,
             __non_exhaustive: (),
         }
     }
@@ -3,6 +3,12 @@
 // Fix back button cache problem
 window.onunload = function () { };
 
+// The theme's text in the book's language, which the page gives us in
+// `ui_strings`, or the English one for pages from older themes
+function ui_string(name, english) {
+    return (window.ui_strings && window.ui_strings[name]) || english;
+}
+
 // Global variable, shared between modules
 function playpen_text(playpen) {
     let code_block = playpen.querySelector("code");
@@ -15,6 +21,20 @@ function playpen_text(playpen) {
     }
 }
 
+// The text of a code block as it's shown to the reader, without any hidden
+// lines (including the `fn main` wrapper added to playpens)
+function visible_text(pre_block) {
+    let code_block = pre_block.querySelector("code");
+
+    if (window.ace && code_block.classList.contains("editable")) {
+        return playpen_text(pre_block);
+    }
+
+    let visible = code_block.cloneNode(true);
+    Array.from(visible.querySelectorAll(".hidden")).forEach(function (hidden) { hidden.remove(); });
+    return visible.textContent.replace(/^\n+/, "");
+}
+
 (function codeSnippets() {
     // Hide Rust code lines prepended with a specific character
     var hiding_character = "#";
@@ -26,7 +46,23 @@ function playpen_text(playpen) {
         ]);
     }
 
-    var playpens = Array.from(document.querySelectorAll(".playpen"));
+    // The playground each language's code blocks are run on, which pages from
+    // older themes don't give us, leaving just the Rust playground
+    var rust_playground = "https://play.rust-lang.org/evaluate.json";
+    var playground_endpoints = window.playground_endpoints || { rust: rust_playground };
+
+    // The language of a playpen, where Rust ones which are being edited have
+    // lost their `language-rust` class
+    function playpen_language(pre_block) {
+        var language = Array.from(pre_block.querySelector("code").classList)
+            .find(function (name) { return name.startsWith("language-"); });
+        return language ? language.substring("language-".length) : "rust";
+    }
+
+    // Only the Rust playground can tell us which crates it has
+    var playpens = Array.from(document.querySelectorAll(".playpen")).filter(function (block) {
+        return playpen_language(block) === "rust" && playground_endpoints.rust === rust_playground;
+    });
     if (playpens.length > 0) {
         fetch_with_timeout("https://play.rust-lang.org/meta/crates", {
             headers: {
@@ -91,7 +127,7 @@ function playpen_text(playpen) {
         }
     }
 
-    function run_rust_code(code_block) {
+    function run_code(code_block) {
         var result_block = code_block.querySelector(".result");
         if (!result_block) {
             result_block = document.createElement('code');
@@ -101,6 +137,7 @@ function playpen_text(playpen) {
         }
 
         let text = playpen_text(code_block);
+        let language = playpen_language(code_block);
 
         var params = {
             version: "stable",
@@ -108,13 +145,15 @@ function playpen_text(playpen) {
             code: text
         };
 
-        if (text.indexOf("#![feature") !== -1) {
+        if (language !== "rust") {
+            params = { language: language, code: text };
+        } else if (text.indexOf("#![feature") !== -1) {
             params.version = "nightly";
         }
 
-        result_block.innerText = "Running...";
+        result_block.innerText = ui_string("running", "Running...");
 
-        fetch_with_timeout("https://play.rust-lang.org/evaluate.json", {
+        fetch_with_timeout(playground_endpoints[language], {
             headers: {
                 'Content-Type': "application/json",
             },
@@ -123,7 +162,9 @@ function playpen_text(playpen) {
             body: JSON.stringify(params)
         })
         .then(response => response.json())
-        .then(response => result_block.innerText = response.result)
+        .then(response => result_block.innerText = response.result !== undefined
+            ? response.result
+            : [response.stdout, response.stderr].filter(output => output).join("\n"))
         .catch(error => result_block.innerText = "Playground Communication: " + error.message);
     }
 
@@ -193,7 +234,10 @@ function playpen_text(playpen) {
 
         var buttons = document.createElement('div');
         buttons.className = 'buttons';
-        buttons.innerHTML = "<button class=\"fa fa-expand\" title=\"Show hidden lines\" aria-label=\"Show hidden lines\"></button>";
+        var label = ui_string("show_hidden_lines", "Show hidden lines");
+        buttons.innerHTML = "<button class=\"fa fa-expand\"></button>";
+        buttons.firstChild.title = label;
+        buttons.firstChild.setAttribute('aria-label', label);
 
         // add expand button
         pre_block.insertBefore(buttons, pre_block.firstChild);
@@ -204,7 +248,7 @@ function playpen_text(playpen) {
 
                 e.target.classList.remove('fa-expand');
                 e.target.classList.add('fa-compress');
-                e.target.title = 'Hide lines';
+                e.target.title = ui_string('hide_lines', 'Hide lines');
                 e.target.setAttribute('aria-label', e.target.title);
 
                 Array.from(lines).forEach(function (line) {
@@ -216,7 +260,7 @@ function playpen_text(playpen) {
 
                 e.target.classList.remove('fa-compress');
                 e.target.classList.add('fa-expand');
-                e.target.title = 'Show hidden lines';
+                e.target.title = ui_string('show_hidden_lines', 'Show hidden lines');
                 e.target.setAttribute('aria-label', e.target.title);
 
                 Array.from(lines).forEach(function (line) {
@@ -227,6 +271,67 @@ function playpen_text(playpen) {
         });
     });
 
+    // Split highlighted code into lines, closing any spans left open at the
+    // end of a line and reopening them at the start of the next one
+    function split_lines(html) {
+        var open_tags = [];
+        var lines = html.split("\n");
+        if (lines[lines.length - 1] === "") { lines.pop(); }
+
+        var result = lines.map(function (line, n) {
+            var prefix = open_tags.join("");
+            line.replace(/<(\/?)span[^>]*>/g, function (tag, closing) {
+                if (closing) { open_tags.pop(); } else { open_tags.push(tag); }
+            });
+            var suffix = open_tags.map(function () { return "</span>"; }).join("");
+            var newline = n < lines.length - 1 || html.endsWith("\n") ? "\n" : "";
+
+            return prefix + line + suffix + newline;
+        });
+
+        // Skip a blank first line, like the one an injected `fn main` starts with
+        if (result.length > 0 && result[0].replace(/<[^>]*>/g, "") === "\n") { result.shift(); }
+        return result;
+    }
+
+    function is_hidden_line(line) {
+        var stripped = line;
+        do {
+            line = stripped;
+            stripped = line.replace(/<span[^>]*><\/span>/g, "");
+        } while (stripped !== line);
+        return line.startsWith("<span class=\"hidden\">");
+    }
+
+    // Parse line ranges like "1 3-5" into the set of line numbers they cover
+    function parse_line_ranges(ranges) {
+        var numbers = new Set();
+        ranges.split(" ").filter(function (range) { return range; }).forEach(function (range) {
+            var bounds = range.split("-").map(Number);
+            var end = bounds.length > 1 ? bounds[1] : bounds[0];
+            for (var number = bounds[0]; number <= end; number++) { numbers.add(number); }
+        });
+        return numbers;
+    }
+
+    // Number and highlight lines of code blocks marked with `linenos` or
+    // `hl_lines`. Hidden lines aren't counted.
+    Array.from(document.querySelectorAll("code.linenos, code[data-hl-lines]")).forEach(function (block) {
+        var highlighted = parse_line_ranges(block.getAttribute("data-hl-lines") || "");
+        var number = 0;
+
+        block.innerHTML = split_lines(block.innerHTML).map(function (line) {
+            var classes = "line";
+            if (is_hidden_line(line)) {
+                classes += " hidden";
+            } else {
+                number += 1;
+                if (highlighted.has(number)) { classes += " highlighted"; }
+            }
+            return "<span class=\"" + classes + "\">" + line + "</span>";
+        }).join("");
+    });
+
     Array.from(document.querySelectorAll('pre code')).forEach(function (block) {
         var pre_block = block.parentNode;
         if (!pre_block.classList.contains('playpen')) {
@@ -239,7 +344,7 @@ function playpen_text(playpen) {
 
             var clipButton = document.createElement('button');
             clipButton.className = 'fa fa-copy clip-button';
-            clipButton.title = 'Copy to clipboard';
+            clipButton.title = ui_string('copy', 'Copy to clipboard');
             clipButton.setAttribute('aria-label', clipButton.title);
             clipButton.innerHTML = '<i class=\"tooltiptext\"></i>';
 
@@ -260,27 +365,35 @@ function playpen_text(playpen) {
         var runCodeButton = document.createElement('button');
         runCodeButton.className = 'fa fa-play play-button';
         runCodeButton.hidden = true;
-        runCodeButton.title = 'Run this code';
+        runCodeButton.title = ui_string('run_code', 'Run this code');
         runCodeButton.setAttribute('aria-label', runCodeButton.title);
 
         var copyCodeClipboardButton = document.createElement('button');
         copyCodeClipboardButton.className = 'fa fa-copy clip-button';
         copyCodeClipboardButton.innerHTML = '<i class="tooltiptext"></i>';
-        copyCodeClipboardButton.title = 'Copy to clipboard';
+        copyCodeClipboardButton.title = ui_string('copy', 'Copy to clipboard');
         copyCodeClipboardButton.setAttribute('aria-label', copyCodeClipboardButton.title);
 
         buttons.insertBefore(runCodeButton, buttons.firstChild);
         buttons.insertBefore(copyCodeClipboardButton, buttons.firstChild);
 
         runCodeButton.addEventListener('click', function (e) {
-            run_rust_code(pre_block);
+            run_code(pre_block);
         });
 
+        // Rust code on the Rust playground waits to hear which crates it has
         let code_block = pre_block.querySelector("code");
+        let language = playpen_language(pre_block);
+        if (!playground_endpoints[language] || code_block.classList.contains("no_run")) {
+            runCodeButton.classList.add("hidden");
+        } else if (playpens.indexOf(pre_block) === -1) {
+            runCodeButton.classList.remove("hidden");
+        }
+
         if (window.ace && code_block.classList.contains("editable")) {
             var undoChangesButton = document.createElement('button');
             undoChangesButton.className = 'fa fa-history reset-button';
-            undoChangesButton.title = 'Undo changes';
+            undoChangesButton.title = ui_string('undo_changes', 'Undo changes');
             undoChangesButton.setAttribute('aria-label', undoChangesButton.title);
 
             buttons.insertBefore(undoChangesButton, buttons.firstChild);
@@ -299,10 +412,12 @@ function playpen_text(playpen) {
     var themeToggleButton = document.getElementById('theme-toggle');
     var themePopup = document.getElementById('theme-list');
     var themeColorMetaTag = document.querySelector('meta[name="theme-color"]');
+    // Themes from before `default-theme` existed don't set `default_theme`
+    var defaultTheme = window.default_theme || 'light';
     var stylesheets = {
-        ayuHighlight: document.querySelector("[href$='ayu-highlight.css']"),
-        tomorrowNight: document.querySelector("[href$='tomorrow-night.css']"),
-        highlight: document.querySelector("[href$='highlight.css']"),
+        ayuHighlight: document.querySelector("#ayu-highlight-css, [href$='ayu-highlight.css']"),
+        tomorrowNight: document.querySelector("#tomorrow-night-css, [href$='tomorrow-night.css']"),
+        highlight: document.querySelector("#highlight-css, [href$='highlight.css']"),
     };
 
     function showThemes() {
@@ -352,7 +467,7 @@ function playpen_text(playpen) {
 
         var previousTheme;
         try { previousTheme = localStorage.getItem('mdbook-theme'); } catch (e) { }
-        if (previousTheme === null || previousTheme === undefined) { previousTheme = 'light'; }
+        if (previousTheme === null || previousTheme === undefined) { previousTheme = defaultTheme; }
 
         try { localStorage.setItem('mdbook-theme', theme); } catch (e) { }
 
@@ -364,10 +479,15 @@ function playpen_text(playpen) {
     // Set theme
     var theme;
     try { theme = localStorage.getItem('mdbook-theme'); } catch(e) { }
-    if (theme === null || theme === undefined) { theme = 'light'; }
+    if (theme === null || theme === undefined) { theme = defaultTheme; }
 
     set_theme(theme);
 
+    // Mark the book's default theme in the list
+    var defaultLabel = themePopup.querySelector(".default");
+    var defaultButton = themePopup.querySelector("button#" + defaultTheme);
+    if (defaultLabel && defaultButton) { defaultButton.append(" ", defaultLabel); }
+
     themeToggleButton.addEventListener('click', function () {
         if (themePopup.style.display === 'block') {
             hideThemes();
@@ -499,6 +619,38 @@ function playpen_text(playpen) {
         }
     }, { passive: true });
 
+    // Fold and unfold sections, remembering the ones which have been toggled
+    var folds = {};
+    try { folds = JSON.parse(localStorage.getItem('mdbook-sidebar-folds')) || {}; } catch (e) { }
+
+    function fold_key(item) {
+        var link = item.querySelector('a');
+        return link ? link.pathname : item.textContent.trim();
+    }
+
+    Array.from(sidebar.querySelectorAll('li.foldable')).forEach(function (item) {
+        var key = fold_key(item);
+        if (folds.hasOwnProperty(key)) {
+            item.classList.toggle('expanded', folds[key]);
+        }
+
+        item.querySelector('.toggle').addEventListener('click', function () {
+            folds[key] = item.classList.toggle('expanded');
+            try { localStorage.setItem('mdbook-sidebar-folds', JSON.stringify(folds)); } catch (e) { }
+        });
+    });
+
+    // Always unfold the current chapter and the sections it's in
+    var activeItem = sidebar.querySelector('a.active');
+    activeItem = activeItem && activeItem.parentElement;
+    while (activeItem) {
+        if (activeItem.classList.contains('foldable')) {
+            activeItem.classList.add('expanded');
+        }
+        var section = activeItem.parentElement.closest('ol.section');
+        activeItem = section && section.parentElement.previousElementSibling;
+    }
+
     // Scroll sidebar to current active section
     var activeSection = sidebar.querySelector(".active");
     if (activeSection) {
@@ -506,12 +658,59 @@ function playpen_text(playpen) {
     }
 })();
 
+(function pageToc() {
+    var pageToc = document.getElementById('page-toc');
+    if (!pageToc) { return; }
+
+    var headers = Array.from(document.querySelectorAll('#content main a.header'))
+        .filter(function (header) { return header.querySelector('h2, h3'); });
+    if (headers.length === 0) { return; }
+
+    var title = document.createElement('div');
+    title.className = 'page-toc-title';
+    title.textContent = ui_string('on_this_page', 'On this page');
+    var list = document.createElement('ul');
+
+    var links = headers.map(function (header) {
+        var heading = header.querySelector('h2, h3');
+        var item = document.createElement('li');
+        item.className = 'page-toc-' + heading.tagName.toLowerCase();
+
+        var link = document.createElement('a');
+        link.href = '#' + header.id;
+        link.textContent = heading.textContent;
+
+        item.appendChild(link);
+        list.appendChild(item);
+        return link;
+    });
+    pageToc.append(title, list);
+
+    // Highlight the section being read
+    function updateActive() {
+        var current = 0;
+        headers.forEach(function (header, i) {
+            if (header.getBoundingClientRect().top < 100) { current = i; }
+        });
+        links.forEach(function (link, i) { link.classList.toggle('active', i === current); });
+    }
+
+    document.addEventListener('scroll', updateActive, { passive: true });
+    updateActive();
+})();
+
 (function chapterNavigation() {
     document.addEventListener('keydown', function (e) {
         if (e.altKey || e.ctrlKey || e.metaKey || e.shiftKey) { return; }
         if (window.search && window.search.hasFocus()) { return; }
 
-        switch (e.key) {
+        // in right-to-left books the next chapter is to the left
+        var key = e.key;
+        if (document.documentElement.dir === 'rtl') {
+            key = { ArrowLeft: 'ArrowRight', ArrowRight: 'ArrowLeft' }[key] || key;
+        }
+
+        switch (key) {
             case 'ArrowRight':
                 e.preventDefault();
                 var nextButton = document.querySelector('.nav-chapters.next');
@@ -546,8 +745,8 @@ function playpen_text(playpen) {
     var clipboardSnippets = new Clipboard('.clip-button', {
         text: function (trigger) {
             hideTooltip(trigger);
-            let playpen = trigger.closest("pre");
-            return playpen_text(playpen);
+            let pre_block = trigger.closest("pre");
+            return visible_text(pre_block);
         }
     });
 
@@ -559,11 +758,11 @@ function playpen_text(playpen) {
 
     clipboardSnippets.on('success', function (e) {
         e.clearSelection();
-        showTooltip(e.trigger, "Copied!");
+        showTooltip(e.trigger, ui_string("copied", "Copied!"));
     });
 
     clipboardSnippets.on('error', function (e) {
-        showTooltip(e.trigger, "Clipboard error!");
+        showTooltip(e.trigger, ui_string("clipboard_error", "Clipboard error!"));
     });
 })();
 
@@ -598,3 +797,37 @@ function playpen_text(playpen) {
         previousScrollTop = document.scrollingElement.scrollTop;
     }, { passive: true });
 })();
+
+(function tabs() {
+    var groups = Array.from(document.querySelectorAll('.tabs'));
+
+    function select(label) {
+        groups.forEach(function (group) {
+            var buttons = Array.from(group.querySelectorAll('.tab-label'));
+            var panels = Array.from(group.children).filter(function (child) {
+                return child.classList.contains('tab-panel');
+            });
+            var index = buttons.findIndex(function (button) { return button.textContent === label; });
+
+            if (index === -1) { return; }
+
+            buttons.forEach(function (button, i) { button.classList.toggle('active', i === index); });
+            panels.forEach(function (panel, i) { panel.classList.toggle('active', i === index); });
+        });
+    }
+
+    groups.forEach(function (group) {
+        Array.from(group.querySelectorAll('.tab-label')).forEach(function (button) {
+            button.addEventListener('click', function () {
+                select(button.textContent);
+                try { localStorage.setItem('mdbook-tab', button.textContent); } catch (e) { }
+            });
+        });
+    });
+
+    var previous = null;
+    try { previous = localStorage.getItem('mdbook-tab'); } catch (e) { }
+    if (previous) {
+        select(previous);
+    }
+})();
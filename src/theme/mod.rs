@@ -16,6 +16,7 @@ pub static HEADER: &'static [u8] = include_bytes!("header.hbs");
 pub static CHROME_CSS: &'static [u8] = include_bytes!("css/chrome.css");
 pub static GENERAL_CSS: &'static [u8] = include_bytes!("css/general.css");
 pub static PRINT_CSS: &'static [u8] = include_bytes!("css/print.css");
+pub static EPUB_CSS: &'static [u8] = include_bytes!("epub.css");
 pub static VARIABLES_CSS: &'static [u8] = include_bytes!("css/variables.css");
 pub static FAVICON: &'static [u8] = include_bytes!("favicon.png");
 pub static JS: &'static [u8] = include_bytes!("book.js");
@@ -50,6 +51,7 @@ pub struct Theme {
     pub chrome_css: Vec<u8>,
     pub general_css: Vec<u8>,
     pub print_css: Vec<u8>,
+    pub epub_css: Vec<u8>,
     pub variables_css: Vec<u8>,
     pub favicon: Vec<u8>,
     pub js: Vec<u8>,
@@ -81,6 +83,7 @@ impl Theme {
                 (theme_dir.join("css/chrome.css"), &mut theme.chrome_css),
                 (theme_dir.join("css/general.css"), &mut theme.general_css),
                 (theme_dir.join("css/print.css"), &mut theme.print_css),
+                (theme_dir.join("epub.css"), &mut theme.epub_css),
                 (
                     theme_dir.join("css/variables.css"),
                     &mut theme.variables_css,
@@ -122,6 +125,7 @@ impl Default for Theme {
             chrome_css: CHROME_CSS.to_owned(),
             general_css: GENERAL_CSS.to_owned(),
             print_css: PRINT_CSS.to_owned(),
+            epub_css: EPUB_CSS.to_owned(),
             variables_css: VARIABLES_CSS.to_owned(),
             favicon: FAVICON.to_owned(),
             js: JS.to_owned(),
@@ -178,6 +182,7 @@ mod tests {
             "css/general.css",
             "css/print.css",
             "css/variables.css",
+            "epub.css",
             "book.js",
             "highlight.js",
             "tomorrow-night.css",
@@ -202,6 +207,7 @@ mod tests {
             chrome_css: Vec::new(),
             general_css: Vec::new(),
             print_css: Vec::new(),
+            epub_css: Vec::new(),
             variables_css: Vec::new(),
             favicon: Vec::new(),
             js: Vec::new(),
@@ -126,13 +126,22 @@ window.search = window.search || {};
     })();
     
     function formatSearchMetric(count, searchterm) {
+        var name, english;
         if (count == 1) {
-            return count + " search result for '" + searchterm + "':";
+            name = "one_search_result";
+            english = "1 search result for '{term}':";
         } else if (count == 0) {
-            return "No search results for '" + searchterm + "'.";
+            name = "no_search_results";
+            english = "No search results for '{term}'.";
         } else {
-            return count + " search results for '" + searchterm + "':";
+            name = "search_results";
+            english = "{count} search results for '{term}':";
         }
+        // in the book's language, if the page has the theme's strings
+        var metric = (window.ui_strings && window.ui_strings[name]) || english;
+        return metric
+            .replace("{count}", count)
+            .replace("{term}", function () { return searchterm; });
     }
     
     function formatSearchResult(result, searchterms) {
@@ -2,6 +2,7 @@ use errors::*;
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::{Component, Path, PathBuf};
+use tempfile::{Builder as TempFileBuilder, TempDir};
 
 /// Takes a path to a file and try to read the file into a String
 pub fn file_to_string<P: AsRef<Path>>(path: P) -> Result<String> {
@@ -24,6 +25,37 @@ pub fn normalize_path(path: &str) -> String {
         .collect::<String>()
 }
 
+/// Works out where a relative link in the file at `from` points to, with both
+/// paths relative to the book's root and separated by forward-slashes.
+///
+/// ```rust
+/// # extern crate mdbook;
+/// #
+/// # use mdbook::utils::fs::resolve_relative_link;
+/// #
+/// # fn main() {
+/// assert_eq!(resolve_relative_link("guide/usage.md", "../intro.md"), "intro.md");
+/// assert_eq!(resolve_relative_link("guide/usage.md", "./img/a.png"), "guide/img/a.png");
+/// # }
+/// ```
+pub fn resolve_relative_link(from: &str, link: &str) -> String {
+    let mut parts: Vec<&str> = from.split('/').collect();
+    // the file's own name
+    parts.pop();
+
+    for part in link.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
 /// Write the given data to a file, creating it first if necessary
 pub fn write_file<P: AsRef<Path>>(build_dir: &Path, filename: P, content: &[u8]) -> Result<()> {
     let path = build_dir.join(filename);
@@ -88,6 +120,14 @@ pub fn create_file(path: &Path) -> Result<File> {
     File::create(path).map_err(|e| e.into())
 }
 
+/// The entries of a directory, sorted by name so whatever is done with them
+/// happens in the same order on every build.
+pub(crate) fn read_dir_sorted(dir: &Path) -> Result<Vec<fs::DirEntry>> {
+    let mut entries = fs::read_dir(dir)?.collect::<::std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
 /// Removes all the content of a directory but not the directory itself
 pub fn remove_dir_content(dir: &Path) -> Result<()> {
     for item in fs::read_dir(dir)? {
@@ -103,6 +143,46 @@ pub fn remove_dir_content(dir: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Put the directory `new` where `dir` is, replacing whatever was there.
+///
+/// The old directory is moved aside before `new` is renamed into its place,
+/// so `dir` always holds one or the other in full, apart from the moment
+/// between the two renames. When `dir` can't be renamed (e.g. it's a mount
+/// point), its contents are replaced with `new`'s instead.
+pub(crate) fn replace_dir(new: TempDir, dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        fs::rename(new.path(), dir)?;
+        new.into_path();
+        return Ok(());
+    }
+
+    let parent = dir.parent().unwrap_or_else(|| Path::new("."));
+    let old = TempFileBuilder::new().prefix(".mdbook-old-").tempdir_in(parent)?;
+    if fs::rename(dir, old.path().join("book")).is_err() {
+        debug!("Unable to move {} aside, so copying the book into it", dir.display());
+        remove_dir_content(dir)?;
+        return copy_files_except_ext(new.path(), dir, true, &[]);
+    }
+
+    if let Err(e) = fs::rename(new.path(), dir) {
+        // put the last build back, or at least don't delete it
+        let old = old.into_path().join("book");
+        return match fs::rename(&old, dir) {
+            Ok(()) => Err(e.into()),
+            Err(rollback) => Err(Error::from(rollback).chain_err(|| {
+                format!(
+                    "Unable to move the new build into {} ({}), and the last one was left in {}",
+                    dir.display(),
+                    e,
+                    old.display()
+                )
+            })),
+        };
+    }
+    new.into_path();
+    Ok(())
+}
+
 /// Copies all files of a directory to another one except the files
 /// with the extensions given in the `ext_blacklist` array
 pub fn copy_files_except_ext(
@@ -123,8 +203,7 @@ pub fn copy_files_except_ext(
         return Ok(());
     }
 
-    for entry in fs::read_dir(from)? {
-        let entry = entry?;
+    for entry in read_dir_sorted(from)? {
         let metadata = entry.metadata()?;
 
         // If the entry is a dir and the recursive option is enabled, call itself
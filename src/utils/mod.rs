@@ -2,16 +2,27 @@
 
 pub mod fs;
 mod string;
-use errors::Error;
+use chrono::{DateTime, TimeZone, Utc};
+use errors::{Error, ErrorKind, Result, ResultExt};
 use regex::Regex;
+use sha1::Sha1;
+use shlex::Shlex;
 
 use pulldown_cmark::{
     html, Event, Options, Parser, Tag, OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES,
 };
 
+use crossbeam_utils::thread;
+use num_cpus;
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::io::Write;
+use std::mem;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
 
-pub use self::string::{take_lines, RangeArgument};
+pub use self::string::{take_anchored_lines, take_lines, RangeArgument};
 
 /// Replaces multiple consecutive whitespace characters with a single space character.
 pub fn collapse_whitespace<'a>(text: &'a str) -> Cow<'a, str> {
@@ -115,10 +126,84 @@ pub fn render_markdown(text: &str, curly_quotes: bool) -> String {
         .map(adjust_links)
         .map(|event| converter.convert(event));
 
-    html::push_html(&mut s, events);
+    // Headers with an explicit ID are written by hand to give them the ID
+    let (events, header_ids) = extract_header_ids(events);
+    let mut header_ids = header_ids.into_iter();
+    let mut has_id = false;
+    let events = events.into_iter().map(|event| match event {
+        Event::Start(Tag::Header(level)) => match header_ids.next() {
+            Some(Some(id)) => {
+                has_id = true;
+                Event::Html(Cow::from(format!("<h{} id=\"{}\">", level, id)))
+            }
+            _ => event,
+        },
+        Event::End(Tag::Header(level)) if has_id => {
+            has_id = false;
+            Event::Html(Cow::from(format!("</h{}>\n", level)))
+        }
+        _ => event,
+    });
+
+    html::push_html(&mut s, move_footnotes_to_end(events).into_iter());
     s
 }
 
+/// Pull explicit IDs, like the `install` in `## Setup {#install}`, out of the
+/// headers in a stream of markdown events.
+///
+/// The `{#...}` is removed from each header's text, and the headers' IDs are
+/// returned in the order the headers appear (`None` for headers without one).
+pub fn extract_header_ids<'a, I>(events: I) -> (Vec<Event<'a>>, Vec<Option<String>>)
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    lazy_static! {
+        static ref HEADER_ID: Regex = Regex::new(r"\s*\{#([\w.:-]+)\}\s*$").unwrap();
+    }
+
+    let mut out = Vec::new();
+    let mut ids = Vec::new();
+    let mut header_start = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Header(_)) => {
+                header_start = Some(out.len() + 1);
+                out.push(event);
+            }
+            Event::End(Tag::Header(_)) => {
+                let start = header_start.take().expect("Headers always start first");
+                // The text at the end of a header can be split over several events
+                let text_start = out[start..]
+                    .iter()
+                    .rposition(|event| match *event {
+                        Event::Text(_) => false,
+                        _ => true,
+                    }).map_or(start, |i| start + i + 1);
+                let text: String = out[text_start..]
+                    .iter()
+                    .map(|event| match *event {
+                        Event::Text(ref text) => text.as_ref(),
+                        _ => "",
+                    }).collect();
+
+                let id = HEADER_ID.captures(&text).map(|caps| caps[1].to_string());
+                if id.is_some() {
+                    let text = HEADER_ID.replace(&text, "").into_owned();
+                    out.truncate(text_start);
+                    out.push(Event::Text(Cow::from(text)));
+                }
+                ids.push(id);
+                out.push(event);
+            }
+            _ => out.push(event),
+        }
+    }
+
+    (out, ids)
+}
+
 struct EventQuoteConverter {
     enabled: bool,
     convert_text: bool,
@@ -165,6 +250,365 @@ fn clean_codeblock_headers(event: Event) -> Event {
     }
 }
 
+/// Move footnote definitions to the end of the chapter, numbering them in the
+/// order they're first referenced and linking each one back to its references.
+fn move_footnotes_to_end<'a, I>(events: I) -> Vec<Event<'a>>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut numbers: HashMap<String, usize> = HashMap::new();
+    let mut reference_counts: HashMap<usize, usize> = HashMap::new();
+    let mut definitions: Vec<(String, Vec<Event<'a>>)> = Vec::new();
+    let mut current: Option<(String, Vec<Event<'a>>)> = None;
+    let mut body = Vec::new();
+
+    for event in events {
+        let event = match event {
+            Event::FootnoteReference(name) => {
+                let next = numbers.len() + 1;
+                let number = *numbers.entry(name.into_owned()).or_insert(next);
+                let count = reference_counts.entry(number).or_insert(0);
+                *count += 1;
+
+                Event::InlineHtml(Cow::from(format!(
+                    "<sup class=\"footnote-reference\" id=\"{}\"><a href=\"#footnote-{}\">{}</a></sup>",
+                    footnote_reference_id(number, *count),
+                    number,
+                    number
+                )))
+            }
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                current = Some((name.into_owned(), Vec::new()));
+                continue;
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                definitions.extend(current.take());
+                continue;
+            }
+            event => event,
+        };
+
+        match current {
+            Some((_, ref mut definition)) => definition.push(event),
+            None => body.push(event),
+        }
+    }
+
+    // footnotes which are never referenced go after the ones which are
+    let mut numbered: Vec<_> = definitions
+        .into_iter()
+        .map(|(name, definition)| {
+            let next = numbers.len() + 1;
+            (*numbers.entry(name).or_insert(next), definition)
+        }).collect();
+    numbered.sort_by_key(|&(number, _)| number);
+    numbered.dedup_by_key(|&mut (number, _)| number);
+
+    for (number, mut definition) in numbered {
+        body.push(Event::Html(Cow::from(format!(
+            "<div class=\"footnote-definition\" id=\"footnote-{0}\">\
+             <sup class=\"footnote-definition-label\">{0}</sup>\n",
+            number
+        ))));
+
+        let count = reference_counts.get(&number).cloned().unwrap_or(0);
+        let back_links: String = (1..=count)
+            .map(|i| {
+                format!(
+                    " <a href=\"#{}\" class=\"footnote-back-link\">↩</a>",
+                    footnote_reference_id(number, i)
+                )
+            }).collect();
+
+        // put the links inside the last paragraph so they don't get a line
+        // to themselves
+        match definition.pop() {
+            Some(Event::End(Tag::Paragraph)) => {
+                definition.push(Event::InlineHtml(Cow::from(back_links)));
+                definition.push(Event::End(Tag::Paragraph));
+            }
+            last => {
+                definition.extend(last);
+                definition.push(Event::Html(Cow::from(back_links)));
+            }
+        }
+
+        body.extend(definition);
+        body.push(Event::Html(Cow::from("</div>\n")));
+    }
+
+    body
+}
+
+fn footnote_reference_id(number: usize, reference: usize) -> String {
+    format!("footnote-reference-{}-{}", number, reference)
+}
+
+/// Check whether a line of markdown opens a fenced code block, returning the
+/// fence character, the fence's length and the info string.
+pub(crate) fn parse_opening_fence(line: &str) -> Option<(char, usize, &str)> {
+    let line = line.trim_start();
+    let ch = line.chars().next()?;
+    if ch != '`' && ch != '~' {
+        return None;
+    }
+
+    let len = line.chars().take_while(|&c| c == ch).count();
+    if len < 3 {
+        return None;
+    }
+
+    Some((ch, len, line[len..].trim()))
+}
+
+/// Check whether a line of markdown closes a fenced code block which was
+/// opened with `len` repetitions of `ch`.
+fn is_closing_fence(line: &str, ch: char, len: usize) -> bool {
+    let line = line.trim_start();
+    let fence_len = line.chars().take_while(|&c| c == ch).count();
+    fence_len >= len && line[fence_len..].trim().is_empty()
+}
+
+/// A run of a chapter's lines, as split up by `split_code_blocks`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Block<'a> {
+    /// Lines outside any fenced code block, starting on line `start`
+    /// (counting from 0).
+    Text { start: usize, lines: Vec<&'a str> },
+    Code(CodeBlock<'a>),
+}
+
+impl<'a> Block<'a> {
+    /// The line the block starts on, counting from 0.
+    pub fn start(&self) -> usize {
+        match *self {
+            Block::Text { start, .. } => start,
+            Block::Code(ref block) => block.start,
+        }
+    }
+
+    /// Every line of the block, including any fences.
+    pub fn lines(&self) -> Vec<&'a str> {
+        match *self {
+            Block::Text { ref lines, .. } => lines.clone(),
+            Block::Code(ref block) => {
+                let mut lines = vec![block.opening_fence];
+                lines.extend(&block.lines);
+                lines.extend(block.closing_fence);
+                lines
+            }
+        }
+    }
+
+    /// The block as it was written.
+    pub fn to_markdown(&self) -> String {
+        self.lines().join("\n")
+    }
+}
+
+/// A fenced code block in a chapter.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CodeBlock<'a> {
+    /// The line the opening fence is on, counting from 0.
+    pub start: usize,
+    pub opening_fence: &'a str,
+    pub info: &'a str,
+    /// The lines between the fences.
+    pub lines: Vec<&'a str>,
+    /// `None` for a block which is never closed, which runs to the end of
+    /// the chapter.
+    pub closing_fence: Option<&'a str>,
+}
+
+impl<'a> CodeBlock<'a> {
+    pub fn language(&self) -> Option<&'a str> {
+        code_block_attributes(self.info).next()
+    }
+
+    /// Does the code block have this attribute, after its language?
+    pub fn has(&self, attribute: &str) -> bool {
+        code_block_attributes(self.info)
+            .skip(1)
+            .any(|attr| attr == attribute)
+    }
+
+    pub fn code(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// The line the block ends on, counting from 0.
+    pub fn end(&self) -> usize {
+        self.start + self.lines.len() + self.closing_fence.map_or(0, |_| 1)
+    }
+
+    /// The block as it was written.
+    pub fn to_markdown(&self) -> String {
+        Block::Code(self.clone()).to_markdown()
+    }
+}
+
+/// Split a chapter into its fenced code blocks and the text between them.
+/// Joining the lines of every block with `\n` gives back the chapter.
+pub(crate) fn split_code_blocks<'a>(content: &'a str) -> Vec<Block<'a>> {
+    let mut blocks = Vec::new();
+    let mut text = Vec::new();
+    let mut current: Option<(char, usize, CodeBlock)> = None;
+    let mut count = 0;
+
+    for (i, line) in content.split('\n').enumerate() {
+        count = i + 1;
+        if let Some((ch, len, mut block)) = current.take() {
+            if is_closing_fence(line, ch, len) {
+                block.closing_fence = Some(line);
+                blocks.push(Block::Code(block));
+            } else {
+                block.lines.push(line);
+                current = Some((ch, len, block));
+            }
+        } else if let Some((ch, len, info)) = parse_opening_fence(line) {
+            if !text.is_empty() {
+                let lines = mem::take(&mut text);
+                blocks.push(Block::Text {
+                    start: i - lines.len(),
+                    lines,
+                });
+            }
+            let block = CodeBlock {
+                start: i,
+                opening_fence: line,
+                info,
+                lines: Vec::new(),
+                closing_fence: None,
+            };
+            current = Some((ch, len, block));
+        } else {
+            text.push(line);
+        }
+    }
+
+    if let Some((_, _, block)) = current {
+        blocks.push(Block::Code(block));
+    }
+    if !text.is_empty() {
+        blocks.push(Block::Text {
+            start: count - text.len(),
+            lines: text,
+        });
+    }
+
+    blocks
+}
+
+/// The level and text of an ATX heading (`## Like this`).
+pub(crate) fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let line = &line[indent..];
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    if indent > 3 || level == 0 || level > 6 || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Each line of `content`, and whether it's outside any fenced code block.
+pub(crate) fn lines_outside_code(content: &str) -> Vec<(&str, bool)> {
+    let mut lines = Vec::new();
+    for block in split_code_blocks(content) {
+        let outside = matches!(block, Block::Text { .. });
+        lines.extend(block.lines().into_iter().map(|line| (line, outside)));
+    }
+    lines
+}
+
+/// Find the run of exactly `ticks` backticks which closes an inline code span.
+pub(crate) fn find_closing_backticks(text: &str, ticks: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'`' {
+            let run = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+            if run == ticks {
+                return Some(i);
+            }
+            i += run;
+        } else {
+            i += 1;
+        }
+    }
+
+    None
+}
+
+/// Apply `replace` to the parts of a chapter which aren't in fenced code
+/// blocks or inline code spans.
+pub(crate) fn replace_outside_code<F>(content: &str, mut replace: F) -> String
+where
+    F: FnMut(&str) -> String,
+{
+    split_code_blocks(content)
+        .iter()
+        .map(|block| match *block {
+            Block::Text { ref lines, .. } => {
+                replace_outside_code_spans(&lines.join("\n"), &mut replace)
+            }
+            Block::Code(_) => block.to_markdown(),
+        }).collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn replace_outside_code_spans<F>(text: &str, replace: &mut F) -> String
+where
+    F: FnMut(&str) -> String,
+{
+    let bytes = text.as_bytes();
+    let mut replaced = String::with_capacity(text.len());
+    let mut previous_end_index = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            // skip over whatever is being escaped
+            b'\\' => i += 2,
+            b'`' => {
+                let ticks = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+
+                match find_closing_backticks(&text[i + ticks..], ticks) {
+                    Some(len) => {
+                        let code_end = i + ticks + len + ticks;
+                        replaced.push_str(&replace(&text[previous_end_index..i]));
+                        replaced.push_str(&text[i..code_end]);
+                        i = code_end;
+                        previous_end_index = i;
+                    }
+                    None => i += ticks,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    replaced.push_str(&replace(&text[previous_end_index..]));
+    replaced
+}
+
+/// Split a code block's info string into its attributes (e.g. `scala` and
+/// `run` in `scala,run`). The first attribute is the code block's language.
+pub(crate) fn code_block_attributes<'a>(info: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    info.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|attr| !attr.is_empty())
+}
+
+/// Escape `s` for HTML, either as text or inside a quoted attribute.
+pub(crate) fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 fn convert_quotes_to_curly(original_text: &str) -> String {
     // We'll consider the start to be "whitespace".
     let mut preceded_by_whitespace = true;
@@ -196,6 +640,118 @@ fn convert_quotes_to_curly(original_text: &str) -> String {
         }).collect()
 }
 
+/// Run a command, writing `input` to its `stdin` and returning whatever it
+/// printed to `stdout`. Exiting with a non-zero exit code is an error.
+pub(crate) fn run_with_input(command: &str, input: &str) -> Result<String> {
+    let mut words = Shlex::new(command);
+    let executable = match words.next() {
+        Some(e) => e,
+        None => bail!("Command string was empty"),
+    };
+
+    let mut child = Command::new(executable)
+        .args(words)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .chain_err(|| format!("Unable to start \"{}\". Is it installed?", command))?;
+
+    {
+        let mut stdin = child.stdin.take().expect("Child has stdin");
+        if let Err(e) = stdin.write_all(input.as_bytes()) {
+            warn!("Error writing to \"{}\", {}", command, e);
+        }
+    }
+
+    let output = child
+        .wait_with_output()
+        .chain_err(|| format!("Error waiting for \"{}\" to finish", command))?;
+
+    if !output.status.success() {
+        bail!(ErrorKind::Subprocess(
+            format!(
+                "\"{}\" returned an error\n{}",
+                command,
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            output
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// When the book is being built. This is the current time, unless the
+/// `SOURCE_DATE_EPOCH` environment variable gives another, so building the
+/// same book twice can give identical output.
+pub(crate) fn build_time() -> Result<DateTime<Utc>> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => source_date(&epoch),
+        Err(_) => Ok(Utc::now()),
+    }
+}
+
+/// The time a `SOURCE_DATE_EPOCH` stands for, in seconds since the Unix epoch.
+fn source_date(epoch: &str) -> Result<DateTime<Utc>> {
+    let invalid = || format!("SOURCE_DATE_EPOCH should be a number of seconds, not \"{}\"", epoch);
+    let seconds: i64 = epoch.trim().parse().chain_err(invalid)?;
+    match Utc.timestamp_opt(seconds, 0).single() {
+        Some(time) => Ok(time),
+        None => bail!(invalid()),
+    }
+}
+
+/// A hash of `data`, in hex, for names which outlive a build like cache
+/// entries. Unlike `DefaultHasher`'s, it's the same whichever version of Rust
+/// mdBook was built with.
+pub(crate) fn stable_hash(data: &[u8]) -> String {
+    Sha1::from(data).digest().to_string()
+}
+
+/// Call `func` on each of the `items`, spread across a thread for each CPU,
+/// and give back what it returns in the same order as the items.
+pub fn parallel_map_mut<T, R, F>(items: &mut [T], func: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(&mut T) -> R + Sync,
+{
+    let threads = num_cpus::get().min(items.len());
+    if threads <= 1 {
+        return items.iter_mut().map(func).collect();
+    }
+
+    // each thread takes the next item as soon as it's free, so a few slow
+    // items don't hold the others up
+    let queue = Mutex::new(items.iter_mut().enumerate());
+    let func = &func;
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let next = queue.lock().unwrap().next();
+                        match next {
+                            Some((i, item)) => done.push((i, func(item))),
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
 /// Prints a "backtrace" of some `Error`.
 pub fn log_backtrace(e: &Error) {
     error!("Error: {}", e);
@@ -321,6 +877,53 @@ more text with spaces
             assert_eq!(render_markdown(input, false), expected);
             assert_eq!(render_markdown(input, true), expected);
         }
+
+        #[test]
+        fn footnotes_are_moved_to_the_end_with_back_links() {
+            let input = "A[^b] b[^a] c[^b].\n\n[^a]: First.\n\n[^b]: Second.\n\nEnd.\n";
+            let expected = "<p>A<sup class=\"footnote-reference\" id=\"footnote-reference-1-1\">\
+                            <a href=\"#footnote-1\">1</a></sup> \
+                            b<sup class=\"footnote-reference\" id=\"footnote-reference-2-1\">\
+                            <a href=\"#footnote-2\">2</a></sup> \
+                            c<sup class=\"footnote-reference\" id=\"footnote-reference-1-2\">\
+                            <a href=\"#footnote-1\">1</a></sup>.</p>\n\
+                            <p>End.</p>\n\
+                            <div class=\"footnote-definition\" id=\"footnote-1\">\
+                            <sup class=\"footnote-definition-label\">1</sup>\n\
+                            <p>Second. \
+                            <a href=\"#footnote-reference-1-1\" class=\"footnote-back-link\">↩</a> \
+                            <a href=\"#footnote-reference-1-2\" class=\"footnote-back-link\">↩</a></p>\n\
+                            </div>\n\
+                            <div class=\"footnote-definition\" id=\"footnote-2\">\
+                            <sup class=\"footnote-definition-label\">2</sup>\n\
+                            <p>First. \
+                            <a href=\"#footnote-reference-2-1\" class=\"footnote-back-link\">↩</a></p>\n\
+                            </div>\n";
+
+            assert_eq!(render_markdown(input, false), expected);
+        }
+
+        #[test]
+        fn unreferenced_footnotes_come_last() {
+            let input = "[^unused]: Never used.\n\nText[^used].\n\n[^used]: Used.\n";
+
+            let got = render_markdown(input, false);
+
+            let used = got.find("id=\"footnote-1\"").unwrap();
+            let unused = got.find("id=\"footnote-2\"").unwrap();
+            assert!(got[used..unused].contains("Used."));
+            assert!(got[unused..].contains("Never used."));
+        }
+
+        #[test]
+        fn headers_can_have_explicit_ids() {
+            assert_eq!(
+                render_markdown("## Setup {#install}\n\n## `mdbook_build` {#build_step}", false),
+                "<h2 id=\"install\">Setup</h2>\n\
+                 <h2 id=\"build_step\"><code>mdbook_build</code></h2>\n"
+            );
+            assert_eq!(render_markdown("## A {b}", false), "<h2>A {b}</h2>\n");
+        }
     }
 
     mod html_munging {
@@ -378,4 +981,99 @@ more text with spaces
             assert_eq!(convert_quotes_to_curly("\t'one'"), "\t‘one’");
         }
     }
+
+    mod replace_outside_code {
+        use super::super::replace_outside_code;
+
+        #[test]
+        fn code_blocks_and_code_spans_are_skipped() {
+            let input = "a `a` ``a ` a`` \\`a\n```\na\n```\na";
+
+            let got = replace_outside_code(input, |text| text.replace('a', "b"));
+
+            assert_eq!(got, "b `a` ``a ` a`` \\`b\n```\na\n```\nb");
+        }
+    }
+
+    mod split_code_blocks {
+        use super::super::{split_code_blocks, Block, CodeBlock};
+
+        #[test]
+        fn code_blocks_know_where_they_are() {
+            let content = "# Scala\n\n~~~scala\nval x = 1\n```\n~~~~\n\n```bash\necho hi";
+            let blocks = split_code_blocks(content);
+            assert_eq!(
+                blocks,
+                vec![
+                    Block::Text {
+                        start: 0,
+                        lines: vec!["# Scala", ""],
+                    },
+                    Block::Code(CodeBlock {
+                        start: 2,
+                        opening_fence: "~~~scala",
+                        info: "scala",
+                        lines: vec!["val x = 1", "```"],
+                        closing_fence: Some("~~~~"),
+                    }),
+                    Block::Text {
+                        start: 6,
+                        lines: vec![""],
+                    },
+                    // an unclosed code block runs to the end of the chapter
+                    Block::Code(CodeBlock {
+                        start: 7,
+                        opening_fence: "```bash",
+                        info: "bash",
+                        lines: vec!["echo hi"],
+                        closing_fence: None,
+                    }),
+                ]
+            );
+
+            match blocks[1] {
+                Block::Code(ref block) => {
+                    assert_eq!(block.language(), Some("scala"));
+                    assert_eq!(block.end(), 5);
+                    assert_eq!(block.to_markdown(), "~~~scala\nval x = 1\n```\n~~~~");
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    mod escape_html {
+        use super::super::escape_html;
+
+        #[test]
+        fn text_and_attributes_are_escaped() {
+            assert_eq!(
+                escape_html(r#"<a title="Q&A">"#),
+                "&lt;a title=&quot;Q&amp;A&quot;&gt;"
+            );
+        }
+    }
+
+    mod stable_hash {
+        use super::super::stable_hash;
+
+        #[test]
+        fn it_is_sha1() {
+            assert_eq!(
+                stable_hash(b"The quick brown fox jumps over the lazy dog"),
+                "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12"
+            );
+        }
+    }
+
+    mod source_date {
+        use super::super::source_date;
+
+        #[test]
+        fn it_is_seconds_since_the_epoch() {
+            let time = source_date("1700000000\n").unwrap();
+            assert_eq!(time.to_rfc3339(), "2023-11-14T22:13:20+00:00");
+            assert!(source_date("yesterday").is_err());
+        }
+    }
 }
@@ -9,16 +9,21 @@ mod dummy_book;
 
 use dummy_book::{assert_contains_strings, assert_doesnt_contain_strings, DummyBook};
 
-use mdbook::config::Config;
+use mdbook::book::{Book, BookItem, Chapter, SectionNumber};
+use mdbook::config::{Config, TextDirection};
+use mdbook::diagnostics::{self, Severity};
 use mdbook::errors::*;
+use mdbook::handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext};
+use mdbook::renderer::HtmlHandlebars;
 use mdbook::utils::fs::{file_to_string, write_file};
 use mdbook::MDBook;
 use select::document::Document;
 use select::predicate::{Class, Name, Predicate};
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tempfile::Builder as TempFileBuilder;
 use walkdir::{DirEntry, WalkDir};
 
@@ -397,6 +402,32 @@ fn theme_dir_overrides_work_correctly() {
     dummy_book::assert_contains_strings(built_index, &["This is a modified index.hbs!"]);
 }
 
+#[test]
+fn build_a_book_assembled_in_memory() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+
+    let mut nested = Chapter::new(
+        "Nested",
+        String::from("# Nested\n\nFrom the database"),
+        "first/nested.md",
+        vec![String::from("First")],
+    );
+    nested.number = Some(SectionNumber(vec![1, 1]));
+    let mut first = Chapter::new("First", String::from("# First"), "first/index.md", Vec::new());
+    first.number = Some(SectionNumber(vec![1]));
+    first.sub_items.push(BookItem::Chapter(nested));
+
+    let mut book = Book::new();
+    book.push_item(first);
+
+    let md = MDBook::from_book(temp.path(), Config::default(), book).unwrap();
+    md.build().unwrap();
+
+    let nested_file = temp.path().join("book").join("first").join("nested.html");
+    assert_contains_strings(&nested_file, &["From the database", "1.1.</strong> Nested"]);
+    assert!(!temp.path().join("src").exists());
+}
+
 #[cfg(feature = "search")]
 mod search {
     extern crate serde_json;
@@ -449,6 +480,31 @@ mod search {
         assert_eq!(docs[&conclusion]["body"], "I put &lt;HTML&gt; in here!");
     }
 
+    #[test]
+    fn search_index_can_be_exported_without_the_search_box() {
+        let temp = DummyBook::new().build().unwrap();
+        let mut md = MDBook::load(temp.path()).unwrap();
+        md.config.set("output.html.search.enable", false).unwrap();
+        md.config.set("output.html.search.export", true).unwrap();
+        md.build().unwrap();
+
+        assert!(!temp.path().join("book/searchindex.js").exists());
+        let export = file_to_string(temp.path().join("book/search-export.json")).unwrap();
+        let export: serde_json::Value = serde_json::from_str(&export).unwrap();
+
+        assert_eq!(export["version"], 1);
+        let summary = export["documents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|doc| doc["url"] == "first/includes.html#summary")
+            .unwrap();
+        assert_eq!(summary["chapter"], "Includes");
+        assert_eq!(summary["heading"], "Summary");
+        let breadcrumbs = serde_json::Value::from(vec!["First Chapter", "Summary"]);
+        assert_eq!(summary["breadcrumbs"], breadcrumbs);
+    }
+
     // Setting this to `true` may cause issues with `cargo watch`,
     // since it may not finish writing the fixture before the tests
     // are run again.
@@ -500,3 +556,661 @@ mod search {
         }
     }
 }
+
+#[test]
+fn additional_resources_are_copied_as_they_are() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(&temp.path().join("downloads"), "notes.md", b"# Notes").unwrap();
+    write_file(&temp.path().join("downloads/data"), "set.csv", b"a,b").unwrap();
+    write_file(temp.path(), "handout.pdf", b"%PDF").unwrap();
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config
+        .set("output.html.additional-resources", vec!["downloads", "handout.pdf"])
+        .unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_eq!(file_to_string(book.join("downloads/notes.md")).unwrap(), "# Notes");
+    assert_eq!(file_to_string(book.join("downloads/data/set.csv")).unwrap(), "a,b");
+    assert!(book.join("handout.pdf").exists());
+}
+
+#[test]
+fn chapters_link_to_their_edit_url() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config
+        .set(
+            "output.html.edit-url-template",
+            "https://github.com/org/repo/edit/master/src/{path}",
+        ).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(
+        book.join("first/nested.html"),
+        &[r#"href="https://github.com/org/repo/edit/master/src/first/nested.md""#],
+    );
+    assert_contains_strings(
+        book.join("index.html"),
+        &[r#"href="https://github.com/org/repo/edit/master/src/README.md""#],
+    );
+}
+
+#[test]
+fn default_theme_is_configurable() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.default-theme", "Navy").unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        temp.path().join("book/intro.html"),
+        &[r#"<body class="navy">"#, r#"var default_theme = "navy";"#],
+    );
+}
+
+#[test]
+fn sections_with_nested_chapters_can_be_folded() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.fold.enable", true).unwrap();
+    md.build().unwrap();
+
+    let index = temp.path().join("book/index.html");
+    assert_contains_strings(
+        &index,
+        &[r#"<li class="foldable"><button class="toggle""#, r#"first/index.html""#],
+    );
+
+    md.config.set("output.html.fold.level", 1).unwrap();
+    md.build().unwrap();
+    assert_contains_strings(&index, &[r#"<li class="foldable expanded"><button"#]);
+}
+
+#[test]
+fn page_toc_is_opt_in() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let intro = temp.path().join("book/intro.html");
+    assert_doesnt_contain_strings(&intro, &[r#"id="page-toc""#]);
+
+    md.config.set("output.html.page-toc", true).unwrap();
+    md.build().unwrap();
+    assert_contains_strings(&intro, &[r#"<nav id="page-toc" class="page-toc""#]);
+}
+
+#[test]
+fn a_404_page_is_rendered_relative_to_the_site_url() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let not_found = temp.path().join("book/404.html");
+    assert_contains_strings(
+        &not_found,
+        &[r#"<base href="/">"#, "Document not found (404)", r#"href="first/nested.html""#],
+    );
+    assert_doesnt_contain_strings(temp.path().join("book/intro.html"), &["<base"]);
+
+    write_file(&temp.path().join("src"), "404.md", b"# Gone fishing").unwrap();
+    md.config.set("output.html.site-url", "/my-book").unwrap();
+    md.build().unwrap();
+    assert_contains_strings(&not_found, &[r#"<base href="/my-book/">"#, "Gone fishing"]);
+}
+
+#[test]
+fn moved_pages_redirect_to_their_new_location() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    let mut redirects = BTreeMap::new();
+    redirects.insert("/old/nested.html", "../first/nested.html");
+    md.config.set("output.html.redirect", &redirects).unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        temp.path().join("book/old/nested.html"),
+        &[
+            r#"<meta http-equiv="refresh" content="0; URL=../first/nested.html">"#,
+            r#"window.location.replace("../first/nested.html" + window.location.hash)"#,
+        ],
+    );
+
+    // redirecting from a page which still exists would overwrite it
+    redirects.insert("intro.html", "conclusion.html");
+    md.config.set("output.html.redirect", &redirects).unwrap();
+    assert!(md.build().is_err());
+}
+
+#[test]
+fn books_with_a_full_site_url_get_a_sitemap() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.site-url", "/docs/").unwrap();
+    md.config.set("output.html.robots-txt", true).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    assert!(!book.join("sitemap.xml").exists());
+    assert_eq!(file_to_string(book.join("robots.txt")).unwrap(), "User-agent: *\nAllow: /\n");
+
+    md.config.set("output.html.site-url", "https://example.com/docs").unwrap();
+    md.build().unwrap();
+    assert_contains_strings(
+        book.join("sitemap.xml"),
+        &[
+            "<loc>https://example.com/docs/intro.html</loc>",
+            "<loc>https://example.com/docs/first/nested.html</loc>",
+        ],
+    );
+    assert_contains_strings(
+        book.join("robots.txt"),
+        &["Sitemap: https://example.com/docs/sitemap.xml"],
+    );
+}
+
+#[test]
+fn pages_have_social_media_metadata() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.site-url", "https://example.com/docs/").unwrap();
+    md.config.set("output.html.social-image", "/preview.png").unwrap();
+    md.build().unwrap();
+
+    assert_contains_strings(
+        temp.path().join("book/intro.html"),
+        &[
+            r#"<meta property="og:title" content="Introduction - "#,
+            r#"<meta property="og:description" content="Here's some interesting text...">"#,
+            r#"<meta property="og:url" content="https://example.com/docs/intro.html">"#,
+            r#"<meta property="og:image" content="https://example.com/docs/preview.png">"#,
+            r#"<meta name="twitter:card" content="summary_large_image">"#,
+        ],
+    );
+}
+
+#[test]
+fn the_favicon_and_logo_are_configurable() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(&temp.path().join("images"), "icon.svg", b"<svg/>").unwrap();
+    write_file(&temp.path().join("images"), "logo.svg", b"<svg/>").unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let nested = temp.path().join("book/first/nested.html");
+    assert_contains_strings(&nested, &[r#"href="../favicon.png""#]);
+    assert_doesnt_contain_strings(&nested, &["sidebar-logo"]);
+
+    md.config.set("output.html.favicon", "images/icon.svg").unwrap();
+    md.config.set("output.html.logo", "images/logo.svg").unwrap();
+    md.build().unwrap();
+    assert_contains_strings(
+        &nested,
+        &[
+            r#"<link rel="shortcut icon" href="../images/icon.svg">"#,
+            r#"<a href="../index.html" class="sidebar-logo"><img src="../images/logo.svg""#,
+        ],
+    );
+    assert!(temp.path().join("book/images/icon.svg").exists());
+    assert!(temp.path().join("book/images/logo.svg").exists());
+}
+
+#[test]
+fn stylesheets_and_scripts_can_be_fingerprinted() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(temp.path(), "custom.css", b"p { color: red; }").unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.additional-css", vec!["custom.css"]).unwrap();
+    md.build().unwrap();
+
+    let nested = temp.path().join("book/first/nested.html");
+    assert_contains_strings(
+        &nested,
+        &[r#"href="../css/general.css""#, r#"href="../custom.css""#],
+    );
+
+    md.config.set("output.html.hash-files", true).unwrap();
+    md.build().unwrap();
+    let page = file_to_string(&nested).unwrap();
+    let doc = Document::from(page.as_str());
+    let links: Vec<_> = doc
+        .find(Name("link"))
+        .filter_map(|link| link.attr("href"))
+        .chain(doc.find(Name("script")).filter_map(|script| script.attr("src")))
+        .filter(|url| url.starts_with("../"))
+        .collect();
+
+    for name in &["../css/general-", "../custom-", "../book-"] {
+        let url = links
+            .iter()
+            .find(|url| url.starts_with(name))
+            .expect("The file is linked to by its fingerprinted name");
+        let hash = &url[name.len()..url.rfind('.').unwrap()];
+        assert!(hash.len() == 16 && hash.chars().all(|c| c.is_digit(16)), "{}", url);
+    }
+    for url in &links {
+        assert!(temp.path().join("book/first").join(url).exists(), "{} is missing", url);
+    }
+    // templates linking to the files directly still find them
+    assert!(temp.path().join("book/css/general.css").exists());
+}
+
+#[test]
+fn html_can_be_added_to_the_head_and_footer() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(temp.path(), "head.html", b"<meta name=\"x-custom\" content=\"1\">").unwrap();
+    write_file(temp.path(), "footer.html", b"<script>track();</script>").unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.additional-head", vec!["head.html"]).unwrap();
+    md.config.set("output.html.additional-footer", vec!["footer.html"]).unwrap();
+    md.build().unwrap();
+
+    let intro = file_to_string(temp.path().join("book/intro.html")).unwrap();
+    let head = intro.find(r#"<meta name="x-custom" content="1">"#).unwrap();
+    let footer = intro.find("<script>track();</script>").unwrap();
+    assert!(head < intro.find("</head>").unwrap());
+    assert!(footer > intro.find("book.js").unwrap());
+    assert!(footer < intro.find("</body>").unwrap());
+}
+
+#[test]
+fn analytics_providers_add_their_snippet() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.google-analytics", "UA-1234").unwrap();
+    md.build().unwrap();
+
+    let intro = file_to_string(temp.path().join("book/intro.html")).unwrap();
+    assert!(intro.contains("ga('create', 'UA-1234', 'auto');"));
+    assert!(!intro.contains("data-domain"));
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.analytics.provider", "plausible").unwrap();
+    md.config.set("output.html.analytics.domain", "example.com").unwrap();
+    md.build().unwrap();
+
+    let intro = file_to_string(temp.path().join("book/intro.html")).unwrap();
+    assert!(intro.contains("setAttribute('data-domain', 'example.com')"));
+    assert!(intro.contains("'https://plausible.io/js/plausible.js'"));
+    assert!(!intro.contains("google-analytics.com"));
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    md.config.set("output.html.analytics.provider", "matomo").unwrap();
+    assert!(md.build().is_err());
+}
+
+fn shout(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut Output,
+) -> HelperResult {
+    let text = h.param(0).and_then(|p| p.value().as_str()).unwrap_or("");
+    out.write(&text.to_uppercase())?;
+    Ok(())
+}
+
+#[test]
+fn custom_templates_can_use_registered_helpers() {
+    let temp = DummyBook::new().build().unwrap();
+    let mut index = ::mdbook::theme::INDEX.to_vec();
+    index.extend_from_slice(b"\n<!-- {{shout \"made louder\"}} -->");
+    write_file(&temp.path().join("theme"), "index.hbs", &index).unwrap();
+
+    let mut md = MDBook::load(temp.path()).unwrap();
+    let mut html = HtmlHandlebars::new();
+    html.with_helper("shout", shout);
+    md.with_renderer(html);
+    md.build().unwrap();
+
+    // the customised renderer replaces the default one, rather than writing
+    // the book out a second time into `book/html`
+    assert!(!temp.path().join("book/html").exists());
+    let intro = temp.path().join("book/intro.html");
+    assert_contains_strings(intro, &["<!-- MADE LOUDER -->"]);
+}
+
+#[test]
+fn the_template_context_of_a_chapter_can_be_inspected() {
+    let temp = DummyBook::new().build().unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    let html = HtmlHandlebars::new();
+    let book = md.preprocess_book(&html).unwrap();
+    let ctx = mdbook::renderer::RenderContext::new(
+        md.root.clone(),
+        book,
+        md.config.clone(),
+        md.build_dir_for("html"),
+    );
+
+    let data = html
+        .template_context(&ctx, Path::new("first/includes.md"))
+        .unwrap();
+    assert_eq!(data["chapter_title"], "Includes");
+    assert_eq!(data["path_to_root"], "../");
+    assert_eq!(data["default_theme"], "light");
+    // the links preprocessor has already run
+    assert!(data["content"].as_str().unwrap().contains("Nested Chapter"));
+
+    let by_page = html
+        .template_context(&ctx, Path::new("first/includes.html"))
+        .unwrap();
+    assert_eq!(by_page, data);
+    assert!(html
+        .template_context(&ctx, Path::new("missing.md"))
+        .is_err());
+}
+
+#[test]
+fn rebuilding_only_renders_the_chapters_affected_by_a_change() {
+    let temp = DummyBook::new().build().unwrap();
+    let src = temp.path().join("src");
+    let nested = "# Nested Chapter\n\n{{#include snippet.txt}}\n";
+    write_file(&src, "first/nested.md", nested.as_bytes()).unwrap();
+    write_file(&src, "first/snippet.txt", b"Old snippet").unwrap();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+    // what was built is remembered beside the build, not in it
+    assert!(temp.path().join(".mdbook-build-book.hashes").exists());
+
+    // mark a page the rebuild shouldn't touch
+    let book = temp.path().join("book");
+    write_file(&book, "second.html", b"Left alone").unwrap();
+
+    write_file(&src, "first/snippet.txt", b"New snippet").unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.rebuild(&src.join("first/snippet.txt")).unwrap();
+
+    assert_contains_strings(book.join("first/nested.html"), &["New snippet"]);
+    assert_eq!(file_to_string(book.join("second.html")).unwrap(), "Left alone");
+
+    // anything else rebuilds the whole book
+    md.rebuild(&src.join("SUMMARY.md")).unwrap();
+    assert_contains_strings(book.join("second.html"), &["Second Chapter"]);
+}
+
+#[test]
+fn rebuilding_renders_chapters_another_change_affects() {
+    let temp = DummyBook::new().build().unwrap();
+    write_file(temp.path(), "book.toml", b"[preprocessor.captions]\n").unwrap();
+    let src = temp.path().join("src");
+    write_file(&src, "second.md", b"# Second Chapter\n\nSee {{#ref fig:last}}.\n").unwrap();
+    write_file(&src, "conclusion.md", b"# Conclusion\n\n![Last](last.png){#fig:last}\n")
+        .unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.build().unwrap();
+
+    let book = temp.path().join("book");
+    assert_contains_strings(book.join("second.html"), &["Figure 1"]);
+    write_file(&book, "intro.html", b"Left alone").unwrap();
+
+    // a figure before the last one renumbers it
+    let nested = "# Nested Chapter\n\n![First](first.png){#fig:first}\n";
+    write_file(&src, "first/nested.md", nested.as_bytes()).unwrap();
+    let md = MDBook::load(temp.path()).unwrap();
+    md.rebuild(&src.join("first/nested.md")).unwrap();
+
+    assert_contains_strings(book.join("second.html"), &["Figure 2"]);
+    assert_eq!(file_to_string(book.join("intro.html")).unwrap(), "Left alone");
+}
+
+#[test]
+fn unchanged_chapters_are_taken_from_the_page_cache() {
+    let temp = DummyBook::new().build().unwrap();
+    let cache_dir = temp.path().join(".mdbook-cache");
+    let build = || {
+        let mut md = MDBook::load(temp.path()).unwrap();
+        md.config.set("output.html.cache-dir", ".mdbook-cache").unwrap();
+        md.build().unwrap();
+    };
+    let cached_pages = || {
+        fs::read_dir(&cache_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>()
+    };
+    build();
+    assert_eq!(cached_pages().len(), 8);
+
+    // swap every cached page for a marker, which shows up if it's used
+    for page in cached_pages() {
+        write_file(
+            &cache_dir,
+            &page,
+            br#"{"content": "<p>Cached</p>", "page": "From the cache"}"#,
+        )
+        .unwrap();
+    }
+    build();
+    let book = temp.path().join("book");
+    assert_eq!(file_to_string(book.join("second.html")).unwrap(), "From the cache");
+    assert_contains_strings(book.join("print.html"), &["<p>Cached</p>"]);
+
+    // a changed chapter is rendered again, and its old page dropped
+    let second = "# Second Chapter\n\nRewritten\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+    build();
+    assert_contains_strings(book.join("second.html"), &["Rewritten"]);
+    assert_eq!(file_to_string(book.join("intro.html")).unwrap(), "From the cache");
+    assert_eq!(cached_pages().len(), 8);
+}
+
+#[test]
+fn problems_with_chapters_are_reported_with_their_location() {
+    let temp = DummyBook::new().build().unwrap();
+    let second = "# Second Chapter\n\nSee below.\n\n{{#include does-not-exist.md}}\n";
+    write_file(&temp.path().join("src"), "second.md", second.as_bytes()).unwrap();
+
+    diagnostics::collect();
+    MDBook::load(temp.path()).unwrap().build().unwrap();
+
+    // other tests might be emitting diagnostics at the same time
+    let failed_include = diagnostics::take_collected()
+        .into_iter()
+        .find(|d| d.file == Some(PathBuf::from("second.md")))
+        .unwrap();
+    assert_eq!(failed_include.severity, Severity::Error);
+    assert_eq!(failed_include.line, Some(5));
+    assert!(failed_include.message.contains("{{#include does-not-exist.md}}"));
+}
+
+#[test]
+fn strict_mode_fails_the_build_on_warnings() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let mut book = Book::new();
+    book.push_item(Chapter::new(
+        "Usage",
+        String::from("# Usage\n\n## Usage\n\n## Usage\n"),
+        "usage.md",
+        Vec::new(),
+    ));
+
+    let mut config = Config::default();
+    MDBook::from_book(temp.path(), config.clone(), book.clone())
+        .unwrap()
+        .build()
+        .unwrap();
+
+    config.build.strict = true;
+    let err = MDBook::from_book(temp.path(), config, book)
+        .unwrap()
+        .build()
+        .unwrap_err();
+    assert!(err.to_string().contains("strict mode"), "{}", err);
+}
+
+#[test]
+fn books_in_a_workspace_are_built_under_its_output() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let root = temp.path();
+    let config = "[book]\ntitle = \"Docs\"\n\n[workspace]\nbooks = [\"core\", \"http\"]\n";
+    write_file(root, "book.toml", config.as_bytes()).unwrap();
+    for (name, link) in &[("core", "../http/intro.md"), ("http", "../core/intro.md")] {
+        let dir = root.join(name);
+        let config = format!("[book]\ntitle = \"The {} Guide\"\n", name);
+        write_file(&dir, "book.toml", config.as_bytes()).unwrap();
+        write_file(&dir, "src/SUMMARY.md", b"- [Intro](intro.md)\n").unwrap();
+        let intro = format!("# Intro\n\n[The other guide]({})\n", link);
+        write_file(&dir, "src/intro.md", intro.as_bytes()).unwrap();
+    }
+
+    let book = MDBook::load(root).unwrap();
+    assert_eq!(book.members.len(), 2);
+    book.build().unwrap();
+
+    let landing_page = root.join("book/index.html");
+    assert_contains_strings(
+        &landing_page,
+        &[
+            "<title>Docs</title>",
+            r#"<a href="core/index.html">The core Guide</a>"#,
+            r#"<a href="http/index.html">The http Guide</a>"#,
+        ],
+    );
+    assert_contains_strings(
+        root.join("book/core/intro.html"),
+        &[r#"<a href="../http/intro.html">The other guide</a>"#],
+    );
+    assert!(root.join("book/http/index.html").exists());
+}
+
+#[test]
+fn each_language_of_a_multilingual_book_gets_its_own_directory() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let root = temp.path();
+    let config = "[book]\nmultilingual = true\n\n\
+                  [language.en]\nname = \"English\"\n\n\
+                  [language.ja]\nname = \"日本語\"\n";
+    write_file(root, "book.toml", config.as_bytes()).unwrap();
+    write_file(root, "src/en/SUMMARY.md", b"- [Intro](intro.md)\n- [Usage](usage.md)\n").unwrap();
+    write_file(root, "src/en/intro.md", b"# Intro\n\n![Logo](logo.png)\n").unwrap();
+    write_file(root, "src/en/usage.md", b"# Usage\n").unwrap();
+    write_file(root, "src/en/logo.png", b"").unwrap();
+    write_file(root, "src/ja/intro.md", "# はじめに\n".as_bytes()).unwrap();
+
+    let book = MDBook::load(root).unwrap();
+    book.build().unwrap();
+
+    assert_contains_strings(root.join("book/index.html"), &["URL=en/index.html"]);
+    assert_contains_strings(
+        root.join("book/ja/intro.html"),
+        &[
+            r#"<html lang="ja""#,
+            "はじめに",
+            r#"<option value="en">English</option>"#,
+            r#"<option value="ja" selected>日本語</option>"#,
+        ],
+    );
+    // untranslated chapters fall back to the default language
+    assert_contains_strings(root.join("book/ja/usage.html"), &[r#"id="usage""#]);
+    assert!(root.join("book/ja/logo.png").exists());
+    assert!(root.join("book/en/intro.html").exists());
+
+    let mut book = MDBook::load(root).unwrap();
+    book.select_language("ja").unwrap();
+    assert_eq!(book.members.len(), 1);
+    assert!(book.select_language("fr").is_err());
+}
+
+#[test]
+fn right_to_left_books_say_so_on_every_page() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let mut book = Book::new();
+    book.push_item(Chapter::new(
+        "مقدمة",
+        String::from("# مقدمة\n"),
+        "intro.md",
+        Vec::new(),
+    ));
+
+    let mut config = Config::default();
+    config.book.language = Some(String::from("ar"));
+    MDBook::from_book(temp.path(), config.clone(), book.clone())
+        .unwrap()
+        .build()
+        .unwrap();
+    let intro = temp.path().join("book/intro.html");
+    assert_contains_strings(
+        &intro,
+        &[
+            r#"<html lang="ar" dir="rtl""#,
+            r#"<div id="content" class="content" dir="rtl">"#,
+        ],
+    );
+
+    config.book.text_direction = Some(TextDirection::LeftToRight);
+    MDBook::from_book(temp.path(), config, book)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_contains_strings(&intro, &[r#"<html lang="ar" dir="ltr""#]);
+}
+
+#[test]
+fn the_theme_is_in_the_books_language() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let mut book = Book::new();
+    for name in &["intro", "usage"] {
+        let content = format!("# {}\n", name);
+        let path = format!("{}.md", name);
+        book.push_item(Chapter::new(name, content, path, Vec::new()));
+    }
+
+    let mut config = Config::default();
+    config.book.language = Some(String::from("fr"));
+    config.set("output.html.ui-strings.print", "Imprimer").unwrap();
+    MDBook::from_book(temp.path(), config, book)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    // the search box is only there with the search feature
+    #[cfg(feature = "search")]
+    assert_contains_strings(
+        temp.path().join("book/intro.html"),
+        &[r#"placeholder="Rechercher dans ce livre…""#],
+    );
+    assert_contains_strings(
+        temp.path().join("book/intro.html"),
+        &[
+            r#"title="Chapitre suivant""#,
+            r#"title="Imprimer""#,
+            r#""copied":"Copié !""#,
+        ],
+    );
+    assert_doesnt_contain_strings(
+        temp.path().join("book/intro.html"),
+        &["Next chapter", "Print this book"],
+    );
+}
+
+#[test]
+fn the_books_metadata_is_checked_and_goes_in_every_page() {
+    let temp = TempFileBuilder::new().prefix("mdBook").tempdir().unwrap();
+    let mut book = Book::new();
+    book.push_item(Chapter::new("Intro", String::from("# Intro\n"), "intro.md", Vec::new()));
+
+    let mut config = Config::default();
+    config.book.authors = vec![String::from("Jane Doe"), String::from("John <Doe>")];
+    config.book.description = Some(String::from("All about metadata"));
+    MDBook::from_book(temp.path(), config.clone(), book.clone())
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_contains_strings(
+        temp.path().join("book/intro.html"),
+        &[
+            r#"<meta name="author" content="Jane Doe, John &lt;Doe&gt;">"#,
+            r#"<meta name="description" content="All about metadata">"#,
+        ],
+    );
+
+    config.book.language = Some(String::from("English (US)"));
+    match MDBook::from_book(temp.path(), config, book) {
+        Err(err) => assert!(err.to_string().contains("book.language"), "{}", err),
+        Ok(_) => panic!("\"English (US)\" isn't a language tag"),
+    }
+}
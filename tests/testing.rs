@@ -1,10 +1,14 @@
 extern crate mdbook;
+extern crate tempfile;
 
 mod dummy_book;
 
 use dummy_book::DummyBook;
 
+use mdbook::book::{Book, Chapter};
+use mdbook::config::Config;
 use mdbook::MDBook;
+use tempfile::Builder as TempFileBuilder;
 
 #[test]
 fn mdbook_can_correctly_test_a_passing_book() {
@@ -21,3 +25,23 @@ fn mdbook_detects_book_with_failing_tests() {
 
     assert!(md.test(vec![]).is_err());
 }
+
+#[test]
+fn only_rust_code_blocks_are_tested_and_every_chapter_is_reported() {
+    let temp = TempFileBuilder::new().prefix("mdbook").tempdir().unwrap();
+    let content = "# Interop\n\n```scala\nval x: Int = 1\n```\n\n```\nnot rust\n```\n";
+    let passing = "# Passing\n\n```rust\n# fn main() {\nassert!(true);\n# }\n```\n";
+    let failing = "# Failing\n\n```rust\nassert!(false);\n```\n\n```rust,ignore\nnope\n```\n";
+
+    let mut book = Book::new();
+    book.push_item(Chapter::new("Interop", content.to_string(), "interop.md", Vec::new()));
+    book.push_item(Chapter::new("Failing", failing.to_string(), "failing.md", Vec::new()));
+    book.push_item(Chapter::new("Passing", passing.to_string(), "passing.md", Vec::new()));
+
+    let mut md = MDBook::from_book(temp.path(), Config::default(), book).unwrap();
+    let err = md.test(vec![]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "1 of 2 test(s) failed: failing.md (rust)"
+    );
+}